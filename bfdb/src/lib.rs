@@ -0,0 +1,407 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bfdb.
+
+bfdb is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bfdb is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Offline campaign history. bfdb ingests the stats logs written by
+//! the bflib background thread (Logs/bfstats*.jsonl.zst) and the
+//! rotated zstd save snapshots, splits them into rounds, and indexes
+//! them by round, player, objective, and time.
+
+use anyhow::{anyhow, Context, Result};
+use bflib::{
+    db::{objective::ObjectiveId, persisted::Persisted},
+    stats::{Stat, StatKind},
+};
+use chrono::prelude::*;
+use dcso3::{coalition::Side, net::Ucid};
+use fxhash::FxHashMap;
+use smallvec::{smallvec, SmallVec};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, BufRead, BufReader},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RoundId(u32);
+
+impl fmt::Display for RoundId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl RoundId {
+    pub fn new(n: u32) -> Self {
+        Self(n)
+    }
+
+    pub fn inner(&self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Round {
+    pub id: RoundId,
+    pub start: DateTime<Utc>,
+    /// the time of the RoundEnd event, or the start of the next round
+    pub end: Option<DateTime<Utc>>,
+    pub winner: Option<Side>,
+    stats: Range<usize>,
+    snapshots: BTreeMap<DateTime<Utc>, PathBuf>,
+}
+
+impl Round {
+    /// save snapshots taken during this round, oldest first
+    pub fn snapshots(&self) -> impl Iterator<Item = (&DateTime<Utc>, &Path)> {
+        self.snapshots.iter().map(|(ts, p)| (ts, p.as_path()))
+    }
+
+    fn contains(&self, ts: DateTime<Utc>) -> bool {
+        ts >= self.start && self.end.map(|end| ts < end).unwrap_or(true)
+    }
+}
+
+/// Read a stats log. A log that was being written when the server
+/// died ends in a truncated zstd frame, or a partial last line,
+/// everything before the truncation is returned. Any other error is
+/// an error.
+pub fn read_stats(path: &Path) -> Result<Vec<Stat>> {
+    let file = fs::File::open(path).with_context(|| format!("opening {path:?}"))?;
+    let file = BufReader::new(zstd::stream::Decoder::new(file)?);
+    let truncated = |r: &io::Result<String>| match r {
+        Ok(_) => false,
+        Err(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+    };
+    let mut lines = file.lines().peekable();
+    let mut stats = vec![];
+    while let Some(line) = lines.next() {
+        if truncated(&line) {
+            break;
+        }
+        let line = line.with_context(|| format!("reading {path:?}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Stat>(&line) {
+            Ok(st) => stats.push(st),
+            Err(e) if e.is_eof() && lines.peek().map(truncated).unwrap_or(true) => break,
+            Err(e) => return Err(anyhow!("decoding stat in {path:?}, {e:?}")),
+        }
+    }
+    Ok(stats)
+}
+
+pub fn read_snapshot(path: &Path) -> Result<Persisted> {
+    bflib::db::read_persisted(path)
+}
+
+/// the time a save snapshot was written
+pub fn snapshot_time(path: &Path) -> Result<DateTime<Utc>> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(DateTime::<Utc>::from(modified))
+}
+
+fn stat_ucids(kind: &StatKind) -> SmallVec<[Ucid; 2]> {
+    match kind {
+        StatKind::Capture { ucid, .. }
+        | StatKind::Repair { ucid, .. }
+        | StatKind::Action { by: ucid, .. }
+        | StatKind::Deploy { ucid, .. }
+        | StatKind::Troop { ucid, .. }
+        | StatKind::PlayerRegister { ucid, .. }
+        | StatKind::PlayerSideswitch { ucid, .. }
+        | StatKind::Slot { ucid, .. }
+        | StatKind::Takeoff { ucid, .. }
+        | StatKind::Land { ucid, .. } => smallvec![*ucid],
+        StatKind::Kill { shots, points, .. } => {
+            let mut ucids: SmallVec<[Ucid; 2]> = smallvec![];
+            let shooters = shots.shots.iter().map(|s| s.shooter_ucid);
+            let scorers = points.iter().map(|(ucid, _)| *ucid);
+            for ucid in shots.victim_ucid.into_iter().chain(shooters).chain(scorers) {
+                if !ucids.contains(&ucid) {
+                    ucids.push(ucid)
+                }
+            }
+            ucids
+        }
+        StatKind::NewRound
+        | StatKind::RoundEnd { .. }
+        | StatKind::SessionStart { .. }
+        | StatKind::SessionEnd
+        | StatKind::Objective { .. }
+        | StatKind::ObjectiveStatus { .. } => smallvec![],
+    }
+}
+
+fn stat_objective(kind: &StatKind) -> Option<ObjectiveId> {
+    match kind {
+        StatKind::Objective { id, .. }
+        | StatKind::Capture { id, .. }
+        | StatKind::Repair { id, .. }
+        | StatKind::ObjectiveStatus { id, .. } => Some(*id),
+        StatKind::NewRound
+        | StatKind::RoundEnd { .. }
+        | StatKind::SessionStart { .. }
+        | StatKind::SessionEnd
+        | StatKind::Action { .. }
+        | StatKind::Deploy { .. }
+        | StatKind::Troop { .. }
+        | StatKind::PlayerRegister { .. }
+        | StatKind::PlayerSideswitch { .. }
+        | StatKind::Slot { .. }
+        | StatKind::Takeoff { .. }
+        | StatKind::Land { .. }
+        | StatKind::Kill { .. } => None,
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Db {
+    /// every stat we know about, sorted by time
+    stats: Vec<Stat>,
+    rounds: Vec<Round>,
+    by_ucid: FxHashMap<Ucid, Vec<usize>>,
+    // objective ids are only unique within a round
+    by_objective: FxHashMap<(RoundId, ObjectiveId), Vec<usize>>,
+    names: FxHashMap<Ucid, String>,
+}
+
+impl Db {
+    /// Build the database from a set of stats and save snapshots. A
+    /// round begins with a NewRound event, stats that precede the
+    /// first NewRound form a round of their own.
+    pub fn new(
+        stats: impl IntoIterator<Item = Stat>,
+        snapshots: impl IntoIterator<Item = (DateTime<Utc>, PathBuf)>,
+    ) -> Self {
+        let mut t = Self {
+            stats: stats.into_iter().collect(),
+            ..Self::default()
+        };
+        t.stats.sort_by_key(|st| st.time);
+        for (i, st) in t.stats.iter().enumerate() {
+            let new_round = match &st.kind {
+                StatKind::NewRound => true,
+                _ => t.rounds.is_empty(),
+            };
+            if new_round {
+                if let Some(round) = t.rounds.last_mut() {
+                    round.stats.end = i;
+                    if round.end.is_none() {
+                        round.end = Some(st.time);
+                    }
+                }
+                t.rounds.push(Round {
+                    id: RoundId(t.rounds.len() as u32),
+                    start: st.time,
+                    end: None,
+                    winner: None,
+                    stats: i..i,
+                    snapshots: BTreeMap::new(),
+                });
+            }
+            let round = t.rounds.last_mut().unwrap();
+            match &st.kind {
+                StatKind::RoundEnd { winner } => {
+                    round.end = Some(st.time);
+                    round.winner = *winner;
+                }
+                StatKind::PlayerRegister { name, ucid, .. } => {
+                    t.names.insert(*ucid, name.to_string());
+                }
+                _ => (),
+            }
+            for ucid in stat_ucids(&st.kind) {
+                t.by_ucid.entry(ucid).or_default().push(i);
+            }
+            if let Some(oid) = stat_objective(&st.kind) {
+                t.by_objective.entry((round.id, oid)).or_default().push(i);
+            }
+        }
+        if let Some(round) = t.rounds.last_mut() {
+            round.stats.end = t.stats.len();
+        }
+        for (ts, path) in snapshots {
+            // snapshots taken before the first stat belong to the first round
+            let i = t
+                .rounds
+                .partition_point(|r| r.start <= ts)
+                .saturating_sub(1);
+            if let Some(round) = t.rounds.get_mut(i) {
+                round.snapshots.insert(ts, path);
+            }
+        }
+        t
+    }
+
+    /// Load everything for `sortie` from the DCS write dir. Stats
+    /// logs are read from the Logs folder, and save snapshots are the
+    /// current save file plus all of it's rotated backups and
+    /// archived rounds.
+    pub fn open(write_dir: &Path, sortie: &str) -> Result<Self> {
+        let mut stats = vec![];
+        let logs = write_dir.join("Logs");
+        if logs.is_dir() {
+            for file in fs::read_dir(&logs)? {
+                let file = file?;
+                let name = file.file_name();
+                let name = match name.to_str() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if name.starts_with("bfstats") && name.ends_with(".jsonl.zst") {
+                    stats.extend(read_stats(&file.path())?);
+                }
+            }
+        }
+        let mut snapshots = vec![];
+        for file in fs::read_dir(write_dir)? {
+            let file = file?;
+            let name = file.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Some(ts) = name.strip_prefix(sortie) {
+                let archive = ts
+                    .strip_prefix("_round")
+                    .map(|n| n.parse::<u32>().is_ok())
+                    .unwrap_or(false);
+                if ts.is_empty() || archive || ts.parse::<i64>().is_ok() {
+                    let path = file.path();
+                    snapshots.push((snapshot_time(&path)?, path));
+                }
+            }
+        }
+        Ok(Self::new(stats, snapshots))
+    }
+
+    pub fn rounds(&self) -> &[Round] {
+        &self.rounds
+    }
+
+    pub fn round(&self, id: RoundId) -> Option<&Round> {
+        self.rounds.get(id.0 as usize)
+    }
+
+    pub fn round_at(&self, ts: DateTime<Utc>) -> Option<&Round> {
+        self.rounds.iter().rev().find(|r| r.contains(ts))
+    }
+
+    /// the last name a player registered with
+    pub fn player_name(&self, ucid: &Ucid) -> Option<&str> {
+        self.names.get(ucid).map(|s| s.as_str())
+    }
+
+    /// all the stats recorded in a round, sorted by time
+    pub fn stats(&self, round: RoundId) -> &[Stat] {
+        match self.round(round) {
+            None => &[],
+            Some(round) => &self.stats[round.stats.clone()],
+        }
+    }
+
+    /// all the stats recorded between `start` (inclusive) and `end` (exclusive)
+    pub fn stats_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> &[Stat] {
+        let i = self.stats.partition_point(|st| st.time < start);
+        let j = self.stats.partition_point(|st| st.time < end);
+        &self.stats[i..j.max(i)]
+    }
+
+    fn in_round<'a>(
+        &'a self,
+        round: RoundId,
+        idx: Option<&'a Vec<usize>>,
+    ) -> impl Iterator<Item = &'a Stat> + 'a {
+        let range = self.round(round).map(|r| r.stats.clone()).unwrap_or(0..0);
+        idx.into_iter()
+            .flat_map(|v| v.iter())
+            .filter(move |i| range.contains(i))
+            .map(|i| &self.stats[*i])
+    }
+
+    /// every stat involving the player, across all rounds
+    pub fn player(&self, ucid: &Ucid) -> impl Iterator<Item = &Stat> {
+        self.by_ucid
+            .get(ucid)
+            .into_iter()
+            .flat_map(|v| v.iter())
+            .map(|i| &self.stats[*i])
+    }
+
+    pub fn player_in_round(&self, round: RoundId, ucid: &Ucid) -> impl Iterator<Item = &Stat> {
+        self.in_round(round, self.by_ucid.get(ucid))
+    }
+
+    /// objectives captured by troops the player deployed in the round
+    pub fn captures_by_player(&self, round: RoundId, ucid: &Ucid) -> impl Iterator<Item = &Stat> {
+        let ucid = *ucid;
+        self.player_in_round(round, &ucid)
+            .filter(move |st| match &st.kind {
+                StatKind::Capture { ucid: by, .. } => by == &ucid,
+                _ => false,
+            })
+    }
+
+    pub fn objective(&self, round: RoundId, oid: &ObjectiveId) -> impl Iterator<Item = &Stat> {
+        self.by_objective
+            .get(&(round, *oid))
+            .into_iter()
+            .flat_map(|v| v.iter())
+            .map(|i| &self.stats[*i])
+    }
+
+    /// the owner of the objective over time, with one entry for every
+    /// change of ownership
+    pub fn objective_ownership(
+        &self,
+        round: RoundId,
+        oid: &ObjectiveId,
+    ) -> Vec<(DateTime<Utc>, Side)> {
+        let mut timeline: Vec<(DateTime<Utc>, Side)> = vec![];
+        for st in self.objective(round, oid) {
+            let side = match &st.kind {
+                StatKind::Objective { owner, .. } => *owner,
+                StatKind::Capture { side, .. } => *side,
+                _ => continue,
+            };
+            match timeline.last() {
+                Some((_, cur)) if cur == &side => (),
+                Some(_) | None => timeline.push((st.time, side)),
+            }
+        }
+        timeline
+    }
+
+    /// the latest snapshot of the round that was taken at or before `ts`
+    pub fn snapshot_at(&self, round: RoundId, ts: DateTime<Utc>) -> Result<Option<Persisted>> {
+        let round = self
+            .round(round)
+            .ok_or_else(|| anyhow!("no such round {round}"))?;
+        match round.snapshots.range(..=ts).next_back() {
+            None => Ok(None),
+            Some((_, path)) => Ok(Some(read_snapshot(path)?)),
+        }
+    }
+
+    /// the last snapshot taken during the round
+    pub fn final_snapshot(&self, round: RoundId) -> Result<Option<Persisted>> {
+        self.snapshot_at(round, DateTime::<Utc>::MAX_UTC)
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::Result;
+use bfdb::{read_stats, Db, RoundId};
+use bflib::{db::objective::ObjectiveId, stats::Stat};
+use chrono::{prelude::*, Duration};
+use dcso3::{coalition::Side, net::Ucid};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const PILOT: &str = "0123456789abcdef0123456789abcdef";
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+fn stat(minutes: i64, kind: Value) -> Value {
+    let mut st = kind;
+    st["time"] = json!(start() + Duration::minutes(minutes));
+    st
+}
+
+fn objective(minutes: i64, id: u64, owner: &str) -> Value {
+    stat(
+        minutes,
+        json!({
+            "type": "Objective",
+            "id": id,
+            "name": format!("obj{id}"),
+            "pos": { "latitude": 42., "longitude": 41., "altitude": 0. },
+            "owner": owner,
+            "kind": "Airbase"
+        }),
+    )
+}
+
+fn capture(minutes: i64, id: u64, side: &str) -> Value {
+    stat(
+        minutes,
+        json!({ "type": "Capture", "id": id, "ucid": PILOT, "side": side, "points": 10 }),
+    )
+}
+
+/// Two rounds of a small campaign. Blue takes obj1 in the first
+/// round and wins, in the second round red takes obj0 back.
+fn campaign() -> Vec<Value> {
+    vec![
+        stat(
+            0,
+            json!({ "type": "SessionStart", "stop_time": start() + Duration::hours(4) }),
+        ),
+        objective(0, 0, "Blue"),
+        objective(0, 1, "Red"),
+        stat(
+            5,
+            json!({
+                "type": "PlayerRegister",
+                "name": "pilot",
+                "ucid": PILOT,
+                "side": "Blue",
+                "points": 0
+            }),
+        ),
+        capture(30, 1, "Blue"),
+        stat(31, json!({ "type": "RoundEnd", "winner": "Blue" })),
+        stat(32, json!({ "type": "NewRound" })),
+        objective(32, 0, "Blue"),
+        objective(32, 1, "Red"),
+        capture(60, 0, "Red"),
+    ]
+}
+
+fn stats(stats: &[Value]) -> Result<Vec<Stat>> {
+    Ok(stats
+        .iter()
+        .map(|st| serde_json::from_value(st.clone()))
+        .collect::<Result<_, _>>()?)
+}
+
+fn write_dir(name: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("bfdb-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("Logs"))?;
+    Ok(dir)
+}
+
+/// append a zstd frame holding the lines to the log at path
+fn append_frame(path: &Path, lines: &[String]) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut enc = zstd::stream::Encoder::new(file, 1)?;
+    for line in lines {
+        writeln!(enc, "{line}")?;
+    }
+    enc.finish()?;
+    Ok(())
+}
+
+fn lines(stats: &[Value]) -> Vec<String> {
+    stats.iter().map(|st| st.to_string()).collect()
+}
+
+#[test]
+fn stats_are_split_into_rounds() -> Result<()> {
+    let db = Db::new(stats(&campaign())?, []);
+    let rounds = db.rounds();
+    assert_eq!(rounds.len(), 2);
+    let (r0, r1) = (RoundId::new(0), RoundId::new(1));
+    assert_eq!(rounds[0].start, start());
+    assert_eq!(rounds[0].end, Some(start() + Duration::minutes(31)));
+    assert_eq!(rounds[0].winner, Some(Side::Blue));
+    assert_eq!(rounds[1].start, start() + Duration::minutes(32));
+    assert_eq!(rounds[1].end, None);
+    assert_eq!(rounds[1].winner, None);
+    assert_eq!(db.stats(r0).len(), 6);
+    assert_eq!(db.stats(r1).len(), 4);
+    assert!(db.stats(RoundId::new(2)).is_empty());
+    // the gap between the end of a round and the next one belongs to
+    // neither
+    let at = |m| db.round_at(start() + Duration::minutes(m)).map(|r| r.id);
+    assert_eq!(at(10), Some(r0));
+    assert_eq!(at(31), None);
+    assert_eq!(at(45), Some(r1));
+    Ok(())
+}
+
+#[test]
+fn stats_before_the_first_new_round_are_a_round() -> Result<()> {
+    let mut campaign = campaign();
+    campaign.insert(0, stat(-1, json!({ "type": "NewRound" })));
+    let db = Db::new(stats(&campaign)?, []);
+    assert_eq!(db.rounds().len(), 2);
+    assert_eq!(db.stats(RoundId::new(0)).len(), 7);
+    Ok(())
+}
+
+#[test]
+fn queries() -> Result<()> {
+    let db = Db::new(stats(&campaign())?, []);
+    let (r0, r1) = (RoundId::new(0), RoundId::new(1));
+    let ucid: Ucid = PILOT.parse()?;
+    assert_eq!(db.player_name(&ucid), Some("pilot"));
+    assert_eq!(db.player(&ucid).count(), 3);
+    assert_eq!(db.player_in_round(r0, &ucid).count(), 2);
+    assert_eq!(db.player_in_round(r1, &ucid).count(), 1);
+    assert_eq!(db.captures_by_player(r0, &ucid).count(), 1);
+    assert_eq!(db.captures_by_player(r1, &ucid).count(), 1);
+    let (o0, o1): (ObjectiveId, ObjectiveId) = ("0".parse()?, "1".parse()?);
+    let t = |m| start() + Duration::minutes(m);
+    assert_eq!(
+        db.objective_ownership(r0, &o1),
+        vec![(t(0), Side::Red), (t(30), Side::Blue)]
+    );
+    assert_eq!(db.objective_ownership(r0, &o0), vec![(t(0), Side::Blue)]);
+    // objective ids are reused by the next round
+    assert_eq!(
+        db.objective_ownership(r1, &o0),
+        vec![(t(32), Side::Blue), (t(60), Side::Red)]
+    );
+    assert_eq!(db.objective(r1, &o1).count(), 1);
+    assert_eq!(db.stats_between(t(30), t(32)).len(), 2);
+    assert!(db.stats_between(t(32), t(30)).is_empty());
+    Ok(())
+}
+
+#[test]
+fn open_reads_every_log() -> Result<()> {
+    let dir = write_dir("open")?;
+    let campaign = campaign();
+    append_frame(&dir.join("Logs/bfstats.jsonl.zst"), &lines(&campaign[..6]))?;
+    append_frame(
+        &dir.join("Logs/bfstats_1.jsonl.zst"),
+        &lines(&campaign[6..]),
+    )?;
+    fs::write(dir.join("Logs/dcs.log"), "not stats")?;
+    let db = Db::open(&dir, "test")?;
+    assert_eq!(db.rounds().len(), 2);
+    assert_eq!(db.stats(RoundId::new(0)).len(), 6);
+    assert_eq!(db.stats(RoundId::new(1)).len(), 4);
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn truncated_logs_are_read_up_to_the_truncation() -> Result<()> {
+    let dir = write_dir("truncated")?;
+    let campaign = campaign();
+    let path = dir.join("Logs/bfstats.jsonl.zst");
+    append_frame(&path, &lines(&campaign[..4]))?;
+    // the server died while writing the next frame
+    append_frame(&path, &lines(&campaign[4..]))?;
+    let len = fs::metadata(&path)?.len();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&path)?
+        .set_len(len - 8)?;
+    assert_eq!(read_stats(&path)?.len(), 4);
+    // or after writing part of a line
+    let path = dir.join("Logs/bfstats_1.jsonl.zst");
+    let mut partial = lines(&campaign[..4]);
+    partial.push(String::from(r#"{"type":"NewRo"#));
+    append_frame(&path, &partial)?;
+    assert_eq!(read_stats(&path)?.len(), 4);
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn corrupt_logs_are_errors() -> Result<()> {
+    let dir = write_dir("corrupt")?;
+    let campaign = campaign();
+    // a partial line is only expected at the end of the log
+    let path = dir.join("Logs/bfstats.jsonl.zst");
+    let mut partial = lines(&campaign[..2]);
+    partial.push(String::from(r#"{"type":"NewRo"#));
+    partial.extend(lines(&campaign[2..4]));
+    append_frame(&path, &partial)?;
+    assert!(read_stats(&path).is_err());
+    let path = dir.join("Logs/bfstats_1.jsonl.zst");
+    let mut bad = lines(&campaign[..2]);
+    bad.push(String::from(r#"{"type":"NoSuchStat"}"#));
+    append_frame(&path, &bad)?;
+    assert!(read_stats(&path).is_err());
+    let path = dir.join("Logs/bfstats_2.jsonl.zst");
+    fs::write(&path, "not zstd at all")?;
+    assert!(read_stats(&path).is_err());
+    assert!(Db::open(&dir, "test").is_err());
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
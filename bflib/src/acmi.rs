@@ -0,0 +1,409 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Tacview ACMI flight recording of the campaign. The sim thread
+//! samples the position of tracked objects into records, the
+//! background thread turns them, along with the stats stream, into
+//! an ACMI text file. Each session gets it's own zstd compressed file.
+
+use crate::{
+    bg::rotate_log,
+    cfg::{UnitTag, UnitTags},
+    db::{group::UnitId, objective::ObjectiveId},
+    stats::{Stat, StatKind},
+};
+use anyhow::Result;
+use chrono::prelude::*;
+use compact_str::{format_compact, CompactString};
+use dcso3::{
+    azumith3d,
+    coalition::Side,
+    coord::{Coord, LLPos},
+    net::Ucid,
+    object::DcsOid,
+    radians_to_degrees,
+    weapon::ClassWeapon,
+    LuaVec3, Position3, String,
+};
+use fxhash::FxHashMap;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// weapons in flight are sampled at most this often (Milliseconds)
+pub const WEAPON_SAMPLE_INTERVAL: i64 = 2000;
+
+/// The Tacview object type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcmiType {
+    FixedWing,
+    Rotorcraft,
+    Vehicle,
+    Infantry,
+    Missile,
+}
+
+impl AcmiType {
+    pub fn from_tags(tags: UnitTags) -> Self {
+        if tags.contains(UnitTag::Helicopter) {
+            Self::Rotorcraft
+        } else if tags.contains(UnitTag::Aircraft) {
+            Self::FixedWing
+        } else if tags.contains(UnitTag::Infantry) {
+            Self::Infantry
+        } else {
+            Self::Vehicle
+        }
+    }
+
+    fn tags(&self) -> &'static str {
+        match self {
+            Self::FixedWing => "Air+FixedWing",
+            Self::Rotorcraft => "Air+Rotorcraft",
+            Self::Vehicle => "Ground+Vehicle",
+            Self::Infantry => "Ground+Light+Human+Infantry",
+            Self::Missile => "Weapon+Missile",
+        }
+    }
+}
+
+/// The identity of a recorded object
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AcmiKey {
+    Unit(UnitId),
+    Player(Ucid),
+    Weapon(DcsOid<ClassWeapon>),
+}
+
+/// The properties of a recorded object. They are written when the
+/// object first appears, and again if they change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Props {
+    pub typ: AcmiType,
+    pub name: String,
+    pub pilot: Option<String>,
+    pub group: Option<String>,
+    pub side: Side,
+}
+
+#[derive(Debug, Clone)]
+pub enum Record {
+    Sample {
+        time: DateTime<Utc>,
+        key: AcmiKey,
+        pos: LLPos,
+        heading: f64,
+        props: Props,
+    },
+    Remove {
+        time: DateTime<Utc>,
+        key: AcmiKey,
+    },
+}
+
+impl Record {
+    pub fn sample(
+        coord: &Coord,
+        time: DateTime<Utc>,
+        key: AcmiKey,
+        position: &Position3,
+        props: Props,
+    ) -> Result<Self> {
+        Ok(Self::Sample {
+            time,
+            key,
+            pos: coord.lo_to_ll(LuaVec3(position.p.0))?,
+            heading: radians_to_degrees(azumith3d(position.x.0)),
+            props,
+        })
+    }
+}
+
+/// escape the characters that are special in ACMI property values
+pub fn escape(s: &str) -> CompactString {
+    let mut res = CompactString::new("");
+    for c in s.chars() {
+        match c {
+            ',' | '\\' => {
+                res.push('\\');
+                res.push(c)
+            }
+            '\n' => res.push(' '),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+fn color(side: Side) -> &'static str {
+    match side {
+        Side::Red => "Red",
+        Side::Blue => "Blue",
+        Side::Neutral => "Grey",
+    }
+}
+
+struct AcmiObjective {
+    id: u64,
+    name: String,
+    pos: LLPos,
+    owner: Side,
+    airbase: bool,
+}
+
+pub struct AcmiLog {
+    path: PathBuf,
+    file: Option<zstd::stream::Encoder<'static, fs::File>>,
+    start: Option<DateTime<Utc>>,
+    frame: f64,
+    next_id: u64,
+    objects: FxHashMap<AcmiKey, (u64, Props)>,
+    objectives: FxHashMap<ObjectiveId, AcmiObjective>,
+}
+
+impl AcmiLog {
+    pub fn new(write_dir: &Path) -> Self {
+        Self {
+            path: write_dir.join("Logs").join("bftrack.txt.acmi.zst"),
+            file: None,
+            start: None,
+            frame: 0.,
+            next_id: 1,
+            objects: FxHashMap::default(),
+            objectives: FxHashMap::default(),
+        }
+    }
+
+    fn id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn write_objective(
+        file: &mut zstd::stream::Encoder<'static, fs::File>,
+        obj: &AcmiObjective,
+    ) -> Result<()> {
+        let typ = if obj.airbase {
+            "Ground+Static+Aerodrome"
+        } else {
+            "Ground+Static+Building"
+        };
+        writeln!(
+            file,
+            "{:x},T={:.7}|{:.7}|{:.1},Type={typ},Name={},Coalition={},Color={}",
+            obj.id,
+            obj.pos.longitude,
+            obj.pos.latitude,
+            obj.pos.altitude,
+            escape(&obj.name),
+            obj.owner,
+            color(obj.owner)
+        )?;
+        Ok(())
+    }
+
+    /// open a new recording starting at time, and write the header
+    /// along with the objectives
+    fn file(
+        &mut self,
+        time: DateTime<Utc>,
+    ) -> Result<&mut zstd::stream::Encoder<'static, fs::File>> {
+        if self.file.is_none() {
+            rotate_log(&self.path, "bftrack", "txt.acmi.zst");
+            let file = fs::File::options()
+                .write(true)
+                .truncate(true)
+                .create(true)
+                .open(&self.path)?;
+            let mut file = zstd::stream::Encoder::new(file, 9)?;
+            let start = *self.start.get_or_insert(time);
+            writeln!(file, "FileType=text/acmi/tacview")?;
+            writeln!(file, "FileVersion=2.2")?;
+            writeln!(
+                file,
+                "0,ReferenceTime={}",
+                start.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )?;
+            writeln!(file, "0,DataSource=bfnext")?;
+            for obj in self.objectives.values() {
+                Self::write_objective(&mut file, obj)?
+            }
+            // every object's properties must be written again in the new file
+            self.objects.clear();
+            self.frame = 0.;
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// start a new frame if time is later than the current frame
+    fn frame(&mut self, time: DateTime<Utc>) -> Result<()> {
+        let start = *self.start.get_or_insert(time);
+        let t = (time - start).num_milliseconds() as f64 / 1000.;
+        // opening a new file resets the frame
+        self.file(time)?;
+        if t > self.frame {
+            self.frame = t;
+            writeln!(self.file(time)?, "#{t:.2}")?;
+        }
+        Ok(())
+    }
+
+    /// finish the current recording, the next write will rotate the file
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.finish()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // flush the block so the file is readable up to this point even if we crash
+        if let Some(file) = self.file.as_mut() {
+            file.flush()?
+        }
+        Ok(())
+    }
+
+    /// Track sessions and objectives from the stats stream. Nothing
+    /// is written unless a recording is in progress.
+    pub fn stats(&mut self, stats: &[Stat]) -> Result<()> {
+        for st in stats {
+            match &st.kind {
+                StatKind::SessionStart { .. } => {
+                    self.finish()?;
+                    self.start = Some(st.time);
+                    self.objects.clear();
+                    self.objectives.clear();
+                    self.next_id = 1;
+                }
+                StatKind::Objective {
+                    id,
+                    name,
+                    pos,
+                    owner,
+                    kind,
+                } => {
+                    let obj = AcmiObjective {
+                        id: self.id(),
+                        name: if name.is_empty() {
+                            String::from(kind.name())
+                        } else {
+                            name.clone()
+                        },
+                        pos: *pos,
+                        owner: *owner,
+                        airbase: kind.is_airbase(),
+                    };
+                    if let Some(file) = self.file.as_mut() {
+                        Self::write_objective(file, &obj)?
+                    }
+                    self.objectives.insert(*id, obj);
+                }
+                StatKind::Capture { id, side, .. } => {
+                    let obj = match self.objectives.get_mut(id) {
+                        Some(obj) if obj.owner != *side => obj,
+                        Some(_) | None => continue,
+                    };
+                    obj.owner = *side;
+                    if self.file.is_some() {
+                        let (oid, name) = (obj.id, escape(&obj.name));
+                        self.frame(st.time)?;
+                        let file = self.file(st.time)?;
+                        writeln!(file, "{oid:x},Coalition={side},Color={}", color(*side))?;
+                        writeln!(file, "0,Event=Bookmark|{name} captured by {side}")?;
+                    }
+                }
+                StatKind::NewRound
+                | StatKind::RoundEnd { .. }
+                | StatKind::SessionEnd
+                | StatKind::Repair { .. }
+                | StatKind::Action { .. }
+                | StatKind::Deploy { .. }
+                | StatKind::Troop { .. }
+                | StatKind::ObjectiveStatus { .. }
+                | StatKind::PlayerRegister { .. }
+                | StatKind::PlayerSideswitch { .. }
+                | StatKind::Slot { .. }
+                | StatKind::Takeoff { .. }
+                | StatKind::Land { .. }
+                | StatKind::Kill { .. } => (),
+            }
+        }
+        self.flush()
+    }
+
+    pub fn write(&mut self, records: Vec<Record>) -> Result<()> {
+        for rec in records {
+            match rec {
+                Record::Sample {
+                    time,
+                    key,
+                    pos,
+                    heading,
+                    props,
+                } => {
+                    self.frame(time)?;
+                    let (id, changed) = match self.objects.get_mut(&key) {
+                        Some((id, cur)) if *cur == props => (*id, false),
+                        Some((id, cur)) => {
+                            *cur = props.clone();
+                            (*id, true)
+                        }
+                        None => {
+                            let id = self.id();
+                            self.objects.insert(key, (id, props.clone()));
+                            (id, true)
+                        }
+                    };
+                    let mut line = format_compact!(
+                        "{id:x},T={:.7}|{:.7}|{:.1}|||{:.1}",
+                        pos.longitude,
+                        pos.latitude,
+                        pos.altitude,
+                        heading
+                    );
+                    if changed {
+                        line.push_str(&format_compact!(
+                            ",Type={},Name={},Coalition={},Color={}",
+                            props.typ.tags(),
+                            escape(&props.name),
+                            props.side,
+                            color(props.side)
+                        ));
+                        if let Some(pilot) = &props.pilot {
+                            line.push_str(&format_compact!(",Pilot={}", escape(pilot)));
+                        }
+                        if let Some(group) = &props.group {
+                            line.push_str(&format_compact!(",Group={}", escape(group)));
+                        }
+                    }
+                    writeln!(self.file(time)?, "{line}")?;
+                }
+                Record::Remove { time, key } => {
+                    if let Some((id, _)) = self.objects.remove(&key) {
+                        self.frame(time)?;
+                        writeln!(self.file(time)?, "-{id:x}")?;
+                    }
+                }
+            }
+        }
+        self.flush()
+    }
+}
@@ -15,25 +15,28 @@ for more details.
 */
 
 use crate::{
+    audit::{AuditEntry, AuditKind, AuditQuery},
     bg::Task,
-    cfg::Cfg,
+    cfg::{Cfg, CfgReload},
     db::{
         group::{DeployKind, GroupId},
         objective::ObjectiveId,
-        Db, Set,
+        round, Db, Set,
     },
     msgq::MsgTyp,
     return_lives,
     spawnctx::{SpawnCtx, SpawnLoc},
+    stats::StatKind,
     Context,
 };
 use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
 use chrono::{prelude::*, Duration};
-use compact_str::format_compact;
+use compact_str::{format_compact, CompactString};
 use dcso3::{
     coalition::Side,
     degrees_to_radians,
-    net::{DcsLuaEnvironment, Net, PlayerId, Ucid},
+    env::miz::Miz,
+    net::{DcsLuaEnvironment, Net, PlayerId, SlotId, Ucid},
     object::DcsObject,
     pointing_towards2,
     trigger::{MarkId, Trigger},
@@ -44,7 +47,7 @@ use dcso3::{
 };
 use enumflags2::BitFlags;
 use fxhash::FxHashMap;
-use log::warn;
+use log::{error, info, warn};
 use mlua::Value;
 use parking_lot::{Condvar, Mutex};
 use regex::{Regex, RegexBuilder};
@@ -141,8 +144,12 @@ pub enum AdminCommand {
     Remark {
         objective: String,
     },
+    Reload,
     Reset,
     Shutdown,
+    Audit {
+        query: AuditQuery,
+    },
 }
 
 impl AdminCommand {
@@ -172,10 +179,150 @@ impl AdminCommand {
             "delete <groupid>: delete deployed group, now with 100% less mess",
             "deslot <player>: force <player> to spectators",
             "remark <obj>: force refresh the markup on objective",
+            "reload: reload the config file, applying changes that are safe to make while running",
             "reset: shutdown the server and reset the campaign state",
-            "shutdown: shutdown the server"
+            "shutdown: shutdown the server",
+            "audit [player|n]: show the last n entries of the audit journal, or the last entries involving player"
         ]
     }
+
+    fn help_name(h: &'static str) -> &'static str {
+        h.split([' ', ':']).next().unwrap_or(h)
+    }
+
+    /// the name of every command that can be granted by an admin role
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::help().iter().map(|h| Self::help_name(h))
+    }
+
+    /// the help lines of the commands the admin is allowed to run
+    pub fn help_for<'a>(cfg: &'a Cfg, ucid: &'a Ucid) -> impl Iterator<Item = &'static str> + 'a {
+        Self::help()
+            .iter()
+            .copied()
+            .filter(|h| cfg.admin_may(ucid, Self::help_name(h)))
+    }
+
+    /// the name of the command as it is typed, and as it appears in
+    /// admin roles
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Help => "help",
+            Self::ReduceInventory { .. } => "reduce",
+            Self::TransferSupply { .. } => "transfer",
+            Self::LogisticsTickNow => "tick",
+            Self::LogisticsDeliverNow => "deliver",
+            Self::Repair { .. } => "repair",
+            Self::Tim { .. } => "tim",
+            Self::Spawn { .. } => "spawn",
+            Self::SideSwitch { .. } => "switch",
+            Self::Ban { .. } => "ban",
+            Self::Unban { .. } => "unban",
+            Self::Kick { .. } => "kick",
+            Self::Connected => "connected",
+            Self::Banned => "banned",
+            Self::Search { .. } => "search",
+            Self::LogWarehouse { .. } => "log-warehouse",
+            Self::Logdesc => "log-desc",
+            Self::ResetLives { .. } => "reset-lives",
+            Self::AddAdmin { .. } => "add-admin",
+            Self::RemoveAdmin { .. } => "remove-admin",
+            Self::Balance { .. } => "balance",
+            Self::SetPoints { .. } => "set-points",
+            Self::Delete { .. } => "delete",
+            Self::Deslot { .. } => "deslot",
+            Self::Remark { .. } => "remark",
+            Self::Reload => "reload",
+            Self::Reset => "reset",
+            Self::Shutdown => "shutdown",
+            Self::Audit { .. } => "audit",
+        }
+    }
+
+    /// the arguments of the command as they are typed, leaving out
+    /// the target player
+    fn args(&self) -> CompactString {
+        match self {
+            Self::ReduceInventory { airbase, amount } => format_compact!("{airbase} {amount}"),
+            Self::TransferSupply { from, to } => format_compact!("{from} {to}"),
+            Self::Repair { airbase } => format_compact!("{airbase}"),
+            Self::Tim { key, size } => format_compact!("{key} {size}"),
+            Self::Spawn { key } => format_compact!("{key}"),
+            Self::SideSwitch { side, .. } => format_compact!("{side}"),
+            Self::Ban { until: None, .. } => CompactString::from("forever"),
+            Self::Ban {
+                until: Some(until), ..
+            } => format_compact!("until {}", until.format("%Y-%m-%d %H:%M:%S")),
+            Self::Search { expr } => format_compact!("{}", expr.as_str()),
+            Self::LogWarehouse { kind, airbase } => {
+                let kind = match kind {
+                    WarehouseKind::Objective => "objective",
+                    WarehouseKind::DCS => "dcs",
+                };
+                format_compact!("{kind} {airbase}")
+            }
+            Self::SetPoints { amount, .. } => format_compact!("{amount}"),
+            Self::Delete { group } => format_compact!("{group}"),
+            Self::Remark { objective } => format_compact!("{objective}"),
+            Self::Audit {
+                query: AuditQuery::Last(n),
+            } => format_compact!("{n}"),
+            Self::Audit {
+                query: AuditQuery::Player(player, _),
+            } => format_compact!("{player}"),
+            Self::Help
+            | Self::LogisticsTickNow
+            | Self::LogisticsDeliverNow
+            | Self::Unban { .. }
+            | Self::Kick { .. }
+            | Self::Connected
+            | Self::Banned
+            | Self::Logdesc
+            | Self::ResetLives { .. }
+            | Self::AddAdmin { .. }
+            | Self::RemoveAdmin { .. }
+            | Self::Balance { .. }
+            | Self::Deslot { .. }
+            | Self::Reload
+            | Self::Reset
+            | Self::Shutdown => CompactString::new(""),
+        }
+    }
+
+    /// the player the command acts on, if any
+    fn target(&self) -> Option<&String> {
+        match self {
+            Self::SideSwitch { player, .. }
+            | Self::Ban { player, .. }
+            | Self::Unban { player }
+            | Self::Kick { player }
+            | Self::ResetLives { player }
+            | Self::AddAdmin { player }
+            | Self::RemoveAdmin { player }
+            | Self::Balance { player }
+            | Self::SetPoints { player, .. }
+            | Self::Deslot { player } => Some(player),
+            Self::Help
+            | Self::ReduceInventory { .. }
+            | Self::TransferSupply { .. }
+            | Self::LogisticsTickNow
+            | Self::LogisticsDeliverNow
+            | Self::Repair { .. }
+            | Self::Tim { .. }
+            | Self::Spawn { .. }
+            | Self::Connected
+            | Self::Banned
+            | Self::Search { .. }
+            | Self::LogWarehouse { .. }
+            | Self::Logdesc
+            | Self::Delete { .. }
+            | Self::Remark { .. }
+            | Self::Reload
+            | Self::Reset
+            | Self::Shutdown
+            | Self::Audit { .. } => None,
+        }
+    }
 }
 
 impl FromStr for AdminCommand {
@@ -300,8 +447,14 @@ impl FromStr for AdminCommand {
             Ok(Self::Remark {
                 objective: s.into(),
             })
+        } else if s.trim() == "reload" {
+            Ok(Self::Reload)
         } else if s == "reset" {
             Ok(Self::Reset)
+        } else if s == "audit" || s.starts_with("audit ") {
+            Ok(Self::Audit {
+                query: AuditQuery::parse(&s["audit".len()..], 10),
+            })
         } else {
             bail!("unknown command {s}")
         }
@@ -533,15 +686,105 @@ fn admin_sideswitch(ctx: &mut Context, side: Side, name: String) -> Result<()> {
 }
 
 fn with_mut_cfg<F: FnOnce(&mut Cfg) -> Result<()>>(ctx: &mut Context, f: F) -> Result<()> {
-    {
-        let cfg = Arc::make_mut(&mut ctx.db.ephemeral.cfg);
-        f(cfg)?
-    }
-    let cfg = Arc::clone(&ctx.db.ephemeral.cfg);
+    // don't clobber changes on disk that are waiting for a restart,
+    // edit the pending config and take the live fields from it
+    let cfg = match ctx.pending_cfg.as_mut() {
+        None => {
+            f(Arc::make_mut(&mut ctx.db.ephemeral.cfg))?;
+            Arc::clone(&ctx.db.ephemeral.cfg)
+        }
+        Some(pending) => {
+            f(pending)?;
+            let (live, _) = ctx.db.ephemeral.cfg.merge_live(pending.clone())?;
+            ctx.db.ephemeral.cfg = Arc::new(live);
+            Arc::new(pending.clone())
+        }
+    };
     ctx.do_bg_task(Task::SaveConfig(ctx.miz_state_path.clone(), cfg));
     Ok(())
 }
 
+/// Re-read the config file and apply everything that can be changed
+/// while the mission is running.
+pub(super) fn reload_config(ctx: &mut Context, lua: MizLua) -> Result<CfgReload> {
+    let new = Cfg::load(&ctx.miz_state_path).context("loading config")?;
+    let (merged, report) = ctx.db.ephemeral.cfg.merge_live(new.clone())?;
+    if report.is_empty() {
+        return Ok(report);
+    }
+    let old_banned = ctx.db.ephemeral.cfg.banned.clone();
+    let miz = Miz::singleton(lua)?;
+    ctx.db
+        .reload_cfg(&miz, &ctx.idx, merged)
+        .context("validating config")?;
+    ctx.pending_cfg = if report.needs_restart.is_empty() {
+        None
+    } else {
+        Some(new)
+    };
+    let menus = [
+        "rules",
+        "cargo",
+        "deployables",
+        "troops",
+        "actions",
+        "points",
+    ];
+    if menus.iter().any(|f| report.applied(f)) {
+        let slots = ctx
+            .db
+            .instanced_players()
+            .filter_map(|(_, p, _)| p.current_slot.as_ref().map(|(slot, _)| *slot))
+            .collect::<SmallVec<[SlotId; 64]>>();
+        ctx.menu_init_queue.extend(slots);
+    }
+    if report.applied("banned") {
+        let net = Net::singleton(lua)?;
+        for (ucid, (until, _)) in &ctx.db.ephemeral.cfg.banned {
+            if !old_banned.contains_key(ucid) {
+                if let Some(id) = ctx.connected.id_by_ucid.get(ucid) {
+                    let msg = match until {
+                        None => format_compact!("you are banned forever"),
+                        Some(ts) => format_compact!("you are banned until {}", ts),
+                    };
+                    net.kick(*id, msg.into())?;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// reload the config because the file changed and tell any connected
+/// admins what happened
+pub(super) fn reload_config_and_notify(ctx: &mut Context, lua: MizLua) {
+    let msg = match reload_config(ctx, lua) {
+        Ok(report) if report.is_empty() => return,
+        Ok(report) => {
+            info!("config file changed, {report}");
+            format_compact!("config file changed, {report}")
+        }
+        Err(e) => {
+            error!("config file changed but could not be reloaded {e:?}");
+            format_compact!("config file changed but could not be reloaded {e}")
+        }
+    };
+    let admins = ctx
+        .db
+        .ephemeral
+        .cfg
+        .admins
+        .keys()
+        .filter_map(|ucid| ctx.connected.id_by_ucid.get(ucid).copied())
+        .collect::<SmallVec<[PlayerId; 8]>>();
+    for id in admins {
+        ctx.db
+            .ephemeral
+            .msgs()
+            .send(MsgTyp::Chat(Some(id)), msg.clone())
+    }
+}
+
 fn admin_ban(
     ctx: &mut Context,
     lua: MizLua,
@@ -555,7 +798,7 @@ fn admin_ban(
         .map(|p| p.name.clone())
         .unwrap_or_else(|| name.clone());
     with_mut_cfg(ctx, |cfg| {
-        cfg.banned.insert(ucid.clone(), (until, name));
+        cfg.banned.insert(ucid.clone(), (until, name.clone()));
         Ok(())
     })?;
     if let Some(id) = ctx.connected.id_by_ucid.get(&ucid) {
@@ -659,10 +902,23 @@ fn admin_reset_lives(ctx: &mut Context, player: &String) -> Result<()> {
     ctx.db.player_reset_lives(&ucid)
 }
 
-pub(super) fn admin_shutdown(ctx: &mut Context, lua: MizLua, reset: bool) -> Result<()> {
+/// Save or reset the state and exit. If audit is specified it is
+/// taken and recorded as successful once everything that can fail
+/// before the journal is flushed has succeeded.
+pub(super) fn admin_shutdown(
+    ctx: &mut Context,
+    lua: MizLua,
+    reset: bool,
+    audit: &mut Option<AuditEntry>,
+) -> Result<()> {
     let wait = Arc::new((Mutex::new(false), Condvar::new()));
     if reset {
-        ctx.do_bg_task(Task::ResetState(ctx.miz_state_path.clone()))
+        let archive = round::archive_path(&ctx.miz_state_path, ctx.db.persisted.round)?;
+        ctx.do_bg_task(Task::ArchiveState(archive, ctx.db.persisted.clone()));
+        ctx.do_bg_task(Task::ResetState(ctx.miz_state_path.clone()));
+        if ctx.db.persisted.winner.is_none() {
+            ctx.db.ephemeral.stat(StatKind::RoundEnd { winner: None })
+        }
     } else {
         return_lives(lua, ctx, DateTime::<Utc>::MAX_UTC);
         ctx.do_bg_task(Task::SaveState(
@@ -670,6 +926,12 @@ pub(super) fn admin_shutdown(ctx: &mut Context, lua: MizLua, reset: bool) -> Res
             ctx.db.persisted.clone(),
         ));
     }
+    ctx.db.ephemeral.stat(StatKind::SessionEnd);
+    if let Some(mut entry) = audit.take() {
+        entry.result = Ok(if reset { "resetting" } else { "shutting down" }.into());
+        ctx.db.ephemeral.audit(entry)
+    }
+    ctx.flush_stats();
     ctx.do_bg_task(Task::Sync(Arc::clone(&wait)));
     let &(ref lock, ref cvar) = &*wait;
     let mut synced = lock.lock();
@@ -688,8 +950,8 @@ fn add_admin(ctx: &mut Context, player: &String) -> Result<()> {
         .ok_or_else(|| anyhow!("missing info for admin {ucid}"))?
         .name
         .clone();
-    with_mut_cfg(ctx, move |cfg| {
-        cfg.admins.insert(ucid, name);
+    with_mut_cfg(ctx, |cfg| {
+        cfg.admins.insert(ucid, name.clone());
         Ok(())
     })
 }
@@ -698,6 +960,7 @@ fn remove_admin(ctx: &mut Context, player: &String) -> Result<()> {
     let ucid = get_player_ucid(ctx, player)?;
     with_mut_cfg(ctx, |cfg| {
         cfg.admins.remove(&ucid);
+        cfg.admin_assignments.remove(&ucid);
         Ok(())
     })
 }
@@ -728,7 +991,10 @@ fn delete(ctx: &mut Context, id: &GroupId) -> Result<()> {
         DeployKind::Crate { .. }
         | DeployKind::Deployed { .. }
         | DeployKind::Troop { .. }
-        | DeployKind::Action { .. } => ctx.db.delete_group(id),
+        | DeployKind::Action { .. }
+        | DeployKind::Pilot { .. }
+        | DeployKind::Convoy { .. }
+        | DeployKind::Offensive { .. } => ctx.db.delete_group(id),
     }
 }
 
@@ -752,20 +1018,77 @@ fn remark(ctx: &mut Context, objective: &String) -> Result<()> {
     Ok(())
 }
 
+pub(super) fn audit_entry(ctx: &Context, id: PlayerId, cmd: &AdminCommand) -> AuditEntry {
+    let mut entry = AuditEntry::new(AuditKind::Admin, cmd.name());
+    entry.issuer = ctx
+        .connected
+        .get(&id)
+        .map(|ifo| (ifo.ucid, ifo.name.clone()));
+    entry.target = cmd.target().and_then(|player| {
+        let ucid = get_player_ucid(ctx, player).ok()?;
+        let name = ctx
+            .db
+            .player(&ucid)
+            .map(|p| p.name.clone())
+            .unwrap_or_else(|| player.clone());
+        Some((ucid, name))
+    });
+    entry.args = cmd.args().as_str().into();
+    entry
+}
+
+/// the replies to an admin command, summarized for the audit journal
+fn audit_result(replies: &[CompactString]) -> String {
+    let mut res = replies
+        .iter()
+        .take(3)
+        .map(|r| r.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    if replies.len() > 3 {
+        res.push_str(&format!("; and {} more", replies.len() - 3));
+    }
+    res.into()
+}
+
 pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<()> {
+    for (id, msg) in ctx.audit_replies.lock().drain(..) {
+        ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg)
+    }
     let mut cmds = mem::take(&mut ctx.admin_commands);
     for (id, cmd) in cmds.drain(..) {
+        let mut entry = Some(audit_entry(ctx, id, &cmd));
+        let mut replies: SmallVec<[CompactString; 4]> = smallvec![];
+        let mut failed = false;
         macro_rules! reply {
-            ($($arg:expr),+) => {
-                ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), format_compact!($($arg),+))
-            }
+            ($($arg:expr),+) => {{
+                let msg = format_compact!($($arg),+);
+                replies.push(msg.clone());
+                ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg)
+            }}
+        }
+        macro_rules! fail {
+            ($($arg:expr),+) => {{
+                failed = true;
+                reply!($($arg),+)
+            }}
+        }
+        macro_rules! finish {
+            () => {{
+                if let Some(mut entry) = entry.take() {
+                    let res = audit_result(&replies);
+                    entry.result = if failed { Err(res) } else { Ok(res) };
+                    ctx.db.ephemeral.audit(entry);
+                }
+            }};
         }
         macro_rules! airbase {
             ($name:expr) => {
                 match get_airbase(&ctx.db, $name) {
                     Ok(oid) => oid,
                     Err(e) => {
-                        reply!("{e:?}");
+                        fail!("{e:?}");
+                        finish!();
                         continue;
                     }
                 }
@@ -778,7 +1101,7 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<()> {
                     .db
                     .admin_reduce_inventory(lua, airbase!(&airbase), amount)
                 {
-                    Err(e) => reply!("reduce inventory failed: {:?}", e),
+                    Err(e) => fail!("reduce inventory failed: {:?}", e),
                     Ok(()) => reply!("inventory reduced"),
                 }
             }
@@ -786,7 +1109,7 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<()> {
                 let from = airbase!(&from);
                 let to = airbase!(&to);
                 match ctx.db.transfer_supplies(lua, from, to) {
-                    Err(e) => reply!("transfer inventory failed {:?}", e),
+                    Err(e) => fail!("transfer inventory failed {:?}", e),
                     Ok(()) => reply!("transfer complete. disconnect"),
                 }
             }
@@ -801,7 +1124,7 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<()> {
             AdminCommand::Repair { airbase } => {
                 match ctx.db.repair_objective(airbase!(&airbase), Utc::now()) {
                     Ok(()) => reply!("repaired {airbase}"),
-                    Err(e) => reply!("failed to repair {e:?}"),
+                    Err(e) => fail!("failed to repair {e:?}"),
                 }
             }
             AdminCommand::Tim { key, size } => {
@@ -824,27 +1147,27 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<()> {
             }
             AdminCommand::Spawn { key } => {
                 if let Err(e) = admin_spawn(ctx, lua, id, key) {
-                    reply!("could not spawn {:?}", e)
+                    fail!("could not spawn {:?}", e)
                 }
             }
             AdminCommand::SideSwitch { side, player } => {
                 if let Err(e) = admin_sideswitch(ctx, side, player.clone()) {
-                    reply!("could not sideswitch {:?}", e)
+                    fail!("could not sideswitch {:?}", e)
                 } else {
                     reply!("{player} sideswitched to {side}")
                 }
             }
             AdminCommand::Ban { player, until } => match admin_ban(ctx, lua, until, &player) {
                 Ok(()) => reply!("{player} banned until {:?}", until),
-                Err(e) => reply!("could not ban {player}, {:?}", e),
+                Err(e) => fail!("could not ban {player}, {:?}", e),
             },
             AdminCommand::Unban { player } => match admin_unban(ctx, &player) {
                 Ok(()) => reply!("{player} unbanned"),
-                Err(e) => reply!("could not unban {}, {:?}", player, e),
+                Err(e) => fail!("could not unban {}, {:?}", player, e),
             },
             AdminCommand::Kick { player } => match admin_kick(ctx, lua, &player) {
                 Ok(()) => reply!("{player} kicked"),
-                Err(e) => reply!("could not kick {player}, {:?}", e),
+                Err(e) => fail!("could not kick {player}, {:?}", e),
             },
             AdminCommand::Banned => {
                 for (ucid, name, until) in admin_list_banned(ctx) {
@@ -867,57 +1190,68 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<()> {
             AdminCommand::LogWarehouse { kind, airbase } => {
                 match ctx.db.admin_log_inventory(lua, kind, airbase!(&airbase)) {
                     Ok(()) => reply!("{airbase} inventory logged"),
-                    Err(e) => reply!("could not log {airbase} inventory {:?}", e),
+                    Err(e) => fail!("could not log {airbase} inventory {:?}", e),
                 }
             }
             AdminCommand::Logdesc => match ctx.connected.get(&id) {
                 None => reply!("no player {id}"),
                 Some(ifo) => match admin_log_desc(ctx, lua, &ifo.ucid) {
                     Ok(()) => reply!("{} desc logged", ifo.ucid),
-                    Err(e) => reply!("could not log admin desc {:?}", e),
+                    Err(e) => fail!("could not log admin desc {:?}", e),
                 },
             },
             AdminCommand::ResetLives { player } => match admin_reset_lives(ctx, &player) {
                 Ok(()) => reply!("{player} lives reset"),
-                Err(e) => reply!("could not reset {player} lives {:?}", e),
+                Err(e) => fail!("could not reset {player} lives {:?}", e),
             },
-            AdminCommand::Shutdown => match admin_shutdown(ctx, lua, false) {
+            AdminCommand::Reload => match reload_config(ctx, lua) {
+                Ok(report) => reply!("config reloaded, {report}"),
+                Err(e) => fail!("could not reload config, nothing changed {e:?}"),
+            },
+            // the journal is flushed during shutdown, so these record
+            // their own entry once they know they will succeed
+            AdminCommand::Shutdown => match admin_shutdown(ctx, lua, false, &mut entry) {
                 Ok(()) => reply!("shutting down"),
-                Err(e) => reply!("failed to shutdown {:?}", e),
+                Err(e) => fail!("failed to shutdown {:?}", e),
             },
             AdminCommand::AddAdmin { player } => match add_admin(ctx, &player) {
                 Ok(()) => reply!("{player} is now an admin"),
-                Err(e) => reply!("failed to make {player} an admin {e:?}"),
+                Err(e) => fail!("failed to make {player} an admin {e:?}"),
             },
             AdminCommand::RemoveAdmin { player } => match remove_admin(ctx, &player) {
                 Ok(()) => reply!("{player} is no longer an admin"),
-                Err(e) => reply!("failed to remove {player} from the admin list {e:?}"),
+                Err(e) => fail!("failed to remove {player} from the admin list {e:?}"),
             },
             AdminCommand::Balance { player } => match balance(ctx, &player) {
                 Ok(b) => reply!("{player}'s balance is {b}"),
-                Err(e) => reply!("could not get {player}'s balance {e:?}"),
+                Err(e) => fail!("could not get {player}'s balance {e:?}"),
             },
             AdminCommand::SetPoints { amount, player } => match set_points(ctx, &player, amount) {
                 Ok(()) => reply!("{player}'s points set to {amount}"),
-                Err(e) => reply!("could not set {player}'s points {e:?}"),
+                Err(e) => fail!("could not set {player}'s points {e:?}"),
             },
             AdminCommand::Delete { group } => match delete(ctx, &group) {
                 Ok(()) => reply!("{group} deleted"),
-                Err(e) => reply!("could not delete group {e:?}"),
+                Err(e) => fail!("could not delete group {e:?}"),
             },
             AdminCommand::Deslot { player } => match deslot(ctx, &player) {
                 Ok(()) => reply!("{player} deslotted"),
-                Err(e) => reply!("could not deslot {player} {e:?}"),
+                Err(e) => fail!("could not deslot {player} {e:?}"),
             },
             AdminCommand::Remark { objective } => match remark(ctx, &objective) {
                 Ok(()) => reply!("{objective} remark queued"),
-                Err(e) => reply!("could not remark {objective} {e:?}"),
+                Err(e) => fail!("could not remark {objective} {e:?}"),
             },
-            AdminCommand::Reset => match admin_shutdown(ctx, lua, true) {
+            AdminCommand::Reset => match admin_shutdown(ctx, lua, true, &mut entry) {
                 Ok(()) => reply!("the state has been reset"),
-                Err(e) => reply!("the state could not be reset {e:?}"),
+                Err(e) => fail!("the state could not be reset {e:?}"),
             },
+            AdminCommand::Audit { query } => {
+                let replies = Arc::clone(&ctx.audit_replies);
+                ctx.do_bg_task(Task::AuditQuery(query, id, replies))
+            }
         }
+        finish!();
     }
     ctx.admin_commands = cmds;
     Ok(())
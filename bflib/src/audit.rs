@@ -0,0 +1,202 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! The audit journal. Every admin command, automated punishment, and
+//! config save is appended to a json lines file that is never
+//! rotated, so disputes can be settled long after the logs are gone.
+
+use anyhow::Result;
+use chrono::prelude::*;
+use compact_str::{format_compact, CompactString};
+use dcso3::{net::Ucid, String};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditKind {
+    /// an admin ran a command
+    Admin,
+    /// the server punished a player automatically
+    Punishment,
+    /// the config file was written
+    ConfigSave,
+    /// the server did something on it's own schedule, e.g. a ban expired
+    System,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    pub kind: AuditKind,
+    /// the admin who did it, None if the server did it on it's own
+    pub issuer: Option<(Ucid, String)>,
+    /// what was done, the command name for admin commands
+    pub action: String,
+    /// the player it was done to, if any
+    pub target: Option<(Ucid, String)>,
+    pub args: String,
+    pub result: Result<String, String>,
+}
+
+impl AuditEntry {
+    pub fn new(kind: AuditKind, action: &str) -> Self {
+        Self {
+            time: Utc::now(),
+            kind,
+            issuer: None,
+            action: action.into(),
+            target: None,
+            args: String::default(),
+            result: Ok(String::default()),
+        }
+    }
+
+    /// true if the player, a ucid or part of a name, issued or was the target of this entry
+    pub fn involves(&self, player: &str) -> bool {
+        let player = player.to_lowercase();
+        [&self.issuer, &self.target].into_iter().any(|p| match p {
+            None => false,
+            Some((ucid, name)) => {
+                format_compact!("{ucid}") == player || name.to_lowercase().contains(&player)
+            }
+        })
+    }
+
+    /// a one line summary suitable for chat
+    pub fn summary(&self) -> CompactString {
+        let who = |p: &Option<(Ucid, String)>| match p {
+            None => CompactString::from("server"),
+            Some((ucid, name)) => format_compact!("{name}({ucid})"),
+        };
+        let mut res = format_compact!(
+            "{} {} {}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            who(&self.issuer),
+            self.action
+        );
+        if self.target.is_some() {
+            res.push_str(&format_compact!(" {}", who(&self.target)));
+        }
+        if !self.args.is_empty() {
+            res.push_str(&format_compact!(" {}", self.args));
+        }
+        match &self.result {
+            Ok(s) if s.is_empty() => (),
+            Ok(s) => res.push_str(&format_compact!(": {s}")),
+            Err(e) => res.push_str(&format_compact!(": failed {e}")),
+        }
+        res
+    }
+}
+
+/// Which entries to return from the journal
+#[derive(Debug, Clone)]
+pub enum AuditQuery {
+    /// the last n entries
+    Last(usize),
+    /// the last n entries that involve the player
+    Player(String, usize),
+}
+
+impl AuditQuery {
+    pub fn parse(s: &str, default: usize) -> Self {
+        let s = s.trim();
+        match s.parse::<usize>() {
+            Ok(n) => Self::Last(n),
+            Err(_) if s.is_empty() => Self::Last(default),
+            Err(_) => Self::Player(s.into(), default),
+        }
+    }
+}
+
+pub fn journal_path(write_dir: &Path) -> PathBuf {
+    write_dir.join("Logs").join("bfaudit.jsonl")
+}
+
+/// read the entries matching query from the journal, oldest first
+pub fn query(path: &Path, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+    let (player, n) = match query {
+        AuditQuery::Last(n) => (None, *n),
+        AuditQuery::Player(player, n) => (Some(player), *n),
+    };
+    let mut res = vec![];
+    if !path.exists() {
+        return Ok(res);
+    }
+    for (i, line) in BufReader::new(fs::File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // a crash mid write leaves a truncated line, it shouldn't hide
+        // the rest of the journal
+        let entry: AuditEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!(
+                    "skipping bad audit journal line {} in {path:?}: {e:?}",
+                    i + 1
+                );
+                continue;
+            }
+        };
+        if player.map(|p| entry.involves(p)).unwrap_or(true) {
+            res.push(entry)
+        }
+    }
+    Ok(res.split_off(res.len().saturating_sub(n)))
+}
+
+pub(crate) struct AuditJournal {
+    path: PathBuf,
+    file: Option<fs::File>,
+}
+
+impl AuditJournal {
+    pub(crate) fn new(write_dir: &Path) -> Self {
+        Self {
+            path: journal_path(write_dir),
+            file: None,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn write(&mut self, entries: Vec<AuditEntry>) -> Result<()> {
+        if self.file.is_none() {
+            let file = fs::File::options()
+                .append(true)
+                .create(true)
+                .open(&self.path)?;
+            self.file = Some(file)
+        }
+        let file = self.file.as_mut().unwrap();
+        for entry in entries {
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
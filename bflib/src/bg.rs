@@ -14,11 +14,20 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
-use crate::{cfg::Cfg, db::persisted::Persisted, Perf};
+use crate::{
+    acmi::{AcmiLog, Record},
+    audit::{self, AuditEntry, AuditJournal, AuditKind, AuditQuery},
+    cfg::Cfg,
+    db::persisted::Persisted,
+    stats::{Stat, StatKind},
+    status::{self, Latest, Status},
+    Perf,
+};
 use anyhow::{anyhow, Result};
 use bytes::{BufMut, Bytes, BytesMut};
 use chrono::prelude::*;
 use compact_str::{format_compact, CompactString};
+use dcso3::net::PlayerId;
 use fxhash::FxHashMap;
 use log::error;
 use once_cell::sync::OnceCell;
@@ -26,16 +35,23 @@ use parking_lot::{Condvar, Mutex};
 use simplelog::{LevelFilter, WriteLogger};
 use std::{
     cell::RefCell,
-    env, fs, io,
+    env, fs,
+    io::{self, Write},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
+    time::{Duration, SystemTime},
 };
 use tokio::{
     fs::File,
     io::AsyncWriteExt,
     runtime::Builder,
     sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time,
 };
 
 struct LogHandle(UnboundedSender<Task>);
@@ -137,7 +153,7 @@ fn rotate(path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn save(path: &Path, encoded: Bytes) -> Result<()> {
+fn write_tmp(path: &Path, encoded: Bytes) -> Result<PathBuf> {
     use std::fs::File;
     let mut tmp = PathBuf::from(path);
     tmp.set_extension("tmp");
@@ -149,6 +165,11 @@ fn save(path: &Path, encoded: Bytes) -> Result<()> {
     let mut file = zstd::stream::Encoder::new(file, 9)?.auto_finish();
     io::copy(&mut &*encoded, &mut file)?;
     drop(file);
+    Ok(tmp)
+}
+
+fn save(path: &Path, encoded: Bytes) -> Result<()> {
+    let tmp = write_tmp(path, encoded)?;
     if let Err(e) = rotate(path) {
         error!("failed to rotate backup files {e:?}")
     }
@@ -156,6 +177,12 @@ fn save(path: &Path, encoded: Bytes) -> Result<()> {
     Ok(())
 }
 
+fn archive(path: &Path, encoded: Bytes) -> Result<()> {
+    let tmp = write_tmp(path, encoded)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
 impl io::Write for LogHandle {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         LOGBUF.with(|lbuf| {
@@ -176,27 +203,152 @@ impl io::Write for LogHandle {
 #[derive(Debug)]
 pub(super) enum Task {
     SaveState(PathBuf, Persisted),
+    ArchiveState(PathBuf, Persisted),
     ResetState(PathBuf),
     SaveConfig(PathBuf, Arc<Cfg>),
     WriteLog(Bytes),
     LogPerf(Perf),
+    Stats(Vec<Stat>),
+    Acmi(Vec<Record>),
+    Audit(Vec<AuditEntry>),
+    AuditQuery(AuditQuery, PlayerId, AuditReplies),
+    WatchConfig(PathBuf, Arc<AtomicBool>),
     Sync(Arc<(Mutex<bool>, Condvar)>),
+    ServeStatus(u16),
+    Status(Box<Status>),
 }
 
-async fn background_loop(write_dir: PathBuf, mut rx: UnboundedReceiver<Task>) {
-    let log_path = write_dir.join("Logs").join("bfnext.txt");
-    if log_path.exists() {
-        let mut rotate_path = log_path.clone();
+/// results of audit queries waiting to be sent to the admin who asked
+pub(crate) type AuditReplies = Arc<Mutex<Vec<(PlayerId, CompactString)>>>;
+
+pub(crate) fn rotate_log(path: &Path, name: &str, ext: &str) {
+    if path.exists() {
+        let mut rotate_path = PathBuf::from(path);
         let ts = Utc::now()
             .to_rfc3339_opts(SecondsFormat::Secs, true)
             .chars()
             .filter(|c| c != &'-' && c != &':')
             .collect::<String>();
-        rotate_path.set_file_name(format_compact!("bfnext{ts}.txt"));
-        if let Err(e) = fs::rename(&log_path, &rotate_path) {
+        rotate_path.set_file_name(format_compact!("{name}{ts}.{ext}"));
+        // more than one rotation can happen in the same second, don't
+        // clobber the earlier one
+        let mut n = 1;
+        while rotate_path.exists() {
+            rotate_path.set_file_name(format_compact!("{name}{ts}_{n}.{ext}"));
+            n += 1;
+        }
+        if let Err(e) = fs::rename(path, &rotate_path) {
             error!("could not rotate log file to {:?} {:?}", rotate_path, e)
         }
     }
+}
+
+struct StatsLog {
+    path: PathBuf,
+    file: Option<zstd::stream::Encoder<'static, fs::File>>,
+    /// the open file already holds a SessionStart
+    session: bool,
+}
+
+impl StatsLog {
+    fn new(write_dir: &Path) -> Self {
+        Self {
+            path: write_dir.join("Logs").join("bfstats.jsonl.zst"),
+            file: None,
+            session: false,
+        }
+    }
+
+    fn file(&mut self) -> Result<&mut zstd::stream::Encoder<'static, fs::File>> {
+        if self.file.is_none() {
+            rotate_log(&self.path, "bfstats", "jsonl.zst");
+            let file = fs::File::options()
+                .write(true)
+                .truncate(true)
+                .create(true)
+                .open(&self.path)?;
+            self.file = Some(zstd::stream::Encoder::new(file, 9)?);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// finish the current zstd frame, the next write will rotate the file
+    fn finish(&mut self) -> Result<()> {
+        self.session = false;
+        if let Some(file) = self.file.take() {
+            file.finish()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, stats: Vec<Stat>) -> Result<()> {
+        for st in stats {
+            // each session gets it's own stats file. Stats that came
+            // before the first SessionStart, e.g. NewRound, belong to
+            // the session that is starting, not the last one.
+            if let StatKind::SessionStart { .. } = &st.kind {
+                if self.session {
+                    self.finish()?
+                }
+                self.session = true;
+            }
+            let file = self.file()?;
+            serde_json::to_writer(&mut *file, &st)?;
+            file.write_all(b"\n")?;
+        }
+        // flush the block so the file is readable up to this point even if we crash
+        if let Some(file) = self.file.as_mut() {
+            file.flush()?
+        }
+        Ok(())
+    }
+}
+
+/// polls the config file for changes made outside of bflib
+struct CfgWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    changed: Arc<AtomicBool>,
+}
+
+impl CfgWatch {
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    fn new(path: PathBuf, changed: Arc<AtomicBool>) -> Self {
+        Self {
+            modified: Self::modified(&path),
+            path,
+            changed,
+        }
+    }
+
+    /// we wrote the file ourselves, don't report it as a change
+    fn reset(&mut self) {
+        self.modified = Self::modified(&self.path);
+    }
+
+    fn check(&mut self) {
+        let modified = Self::modified(&self.path);
+        if modified.is_some() && modified != self.modified {
+            self.modified = modified;
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+async fn background_loop(write_dir: PathBuf, mut rx: UnboundedReceiver<Task>) {
+    let log_path = write_dir.join("Logs").join("bfnext.txt");
+    rotate_log(&log_path, "bfnext", "txt");
+    let mut stats = StatsLog::new(&write_dir);
+    let mut acmi = AcmiLog::new(&write_dir);
+    let mut journal = AuditJournal::new(&write_dir);
+    let mut cfg_watch: Option<CfgWatch> = None;
+    let latest: Latest = Arc::new(Mutex::new(None));
+    // the server outlives mission restarts unless the port changes
+    let mut status_server: Option<(u16, JoinHandle<()>)> = None;
+    let mut cfg_poll = time::interval(Duration::from_secs(5));
     let mut log_file = File::options()
         .create(true)
         .write(true)
@@ -204,7 +356,19 @@ async fn background_loop(write_dir: PathBuf, mut rx: UnboundedReceiver<Task>) {
         .open(log_path)
         .await
         .unwrap();
-    while let Some(msg) = rx.recv().await {
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = cfg_poll.tick() => {
+                if let Some(w) = cfg_watch.as_mut() {
+                    w.check()
+                }
+                continue;
+            }
+        };
         match msg {
             Task::SaveState(path, db) => {
                 let encoded = match encode(&db) {
@@ -220,17 +384,81 @@ async fn background_loop(write_dir: PathBuf, mut rx: UnboundedReceiver<Task>) {
                     Err(e) => error!("failed to save state to {path:?}, {e:?}"),
                 }
             },
+            Task::ArchiveState(path, db) => {
+                let res = encode(&db).and_then(|encoded| archive(&path, encoded));
+                if let Err(e) = res {
+                    error!("failed to archive state to {path:?}, {e:?}")
+                }
+            }
             Task::ResetState(path) => match fs::remove_file(&path) {
                 Ok(()) => (),
                 Err(e) => error!("failed to reset state {path:?}, {e:?}"),
             },
-            Task::SaveConfig(path, cfg) => match cfg.save(&path) {
-                Ok(()) => (),
-                Err(e) => error!("failed to save config {e:?}"),
-            },
+            Task::SaveConfig(path, cfg) => {
+                let mut entry = AuditEntry::new(AuditKind::ConfigSave, "save-config");
+                entry.args = format_compact!("{}", path.display()).into();
+                match cfg.save(&path) {
+                    Ok(()) => (),
+                    Err(e) => {
+                        error!("failed to save config {e:?}");
+                        entry.result = Err(format_compact!("{e}").into());
+                    }
+                }
+                if let Err(e) = journal.write(vec![entry]) {
+                    error!("failed to write audit journal {e:?}")
+                }
+                if let Some(w) = cfg_watch.as_mut() {
+                    w.reset()
+                }
+            }
+            Task::WatchConfig(path, changed) => cfg_watch = Some(CfgWatch::new(path, changed)),
             Task::WriteLog(mut buf) => log_file.write_all_buf(&mut buf).await.unwrap(),
             Task::LogPerf(perf) => perf.log(),
+            Task::ServeStatus(port) => match &status_server {
+                Some((cur, _)) if *cur == port => (),
+                _ => {
+                    if let Some((_, server)) = status_server.take() {
+                        server.abort()
+                    }
+                    *latest.lock() = None;
+                    let server = tokio::spawn(status::serve(port, Arc::clone(&latest)));
+                    status_server = Some((port, server))
+                }
+            },
+            Task::Status(st) => *latest.lock() = Some(Arc::from(st)),
+            Task::Stats(st) => {
+                if let Err(e) = acmi.stats(&st) {
+                    error!("failed to record stats in the acmi {e:?}")
+                }
+                if let Err(e) = stats.write(st) {
+                    error!("failed to write stats {e:?}")
+                }
+            }
+            Task::Acmi(records) => {
+                if let Err(e) = acmi.write(records) {
+                    error!("failed to write acmi {e:?}")
+                }
+            }
+            Task::Audit(entries) => {
+                if let Err(e) = journal.write(entries) {
+                    error!("failed to write audit journal {e:?}")
+                }
+            }
+            Task::AuditQuery(query, id, replies) => {
+                let msgs = match audit::query(journal.path(), &query) {
+                    Err(e) => vec![format_compact!("could not read the audit journal {e}")],
+                    Ok(entries) if entries.is_empty() => vec![format_compact!("no entries")],
+                    Ok(entries) => entries.iter().map(|e| e.summary()).collect(),
+                };
+                replies.lock().extend(msgs.into_iter().map(|m| (id, m)));
+            }
             Task::Sync(a) => {
+                if let Err(e) = stats.finish() {
+                    error!("failed to finish stats file {e:?}")
+                }
+                if let Err(e) = acmi.finish() {
+                    error!("failed to finish acmi file {e:?}")
+                }
                 let &(ref lock, ref cvar) = &*a;
                 let mut synced = lock.lock();
                 *synced = true;
@@ -173,8 +173,13 @@ fn default_red_deployables() -> Vec<Deployable> {
                 max_drop_speed: 13,
             }),
             logistics: None,
-            ewr: Some(DeployableEwr { range: 30000 }),
+            ewr: Some(DeployableEwr {
+                range: 30000,
+                antenna_height: 20,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "SA 11 Buk".into()],
@@ -218,8 +223,13 @@ fn default_red_deployables() -> Vec<Deployable> {
                 max_drop_speed: 13,
             }),
             logistics: None,
-            ewr: Some(DeployableEwr { range: 60000 }),
+            ewr: Some(DeployableEwr {
+                range: 60000,
+                antenna_height: 25,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "SA15 Tor".into()],
@@ -238,8 +248,13 @@ fn default_red_deployables() -> Vec<Deployable> {
             }],
             repair_crate: None,
             logistics: None,
-            ewr: Some(DeployableEwr { range: 20000 }),
+            ewr: Some(DeployableEwr {
+                range: 20000,
+                antenna_height: 10,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "SA8 Osa".into()],
@@ -260,6 +275,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["AAA".into(), "ZU23 Emplacement".into()],
@@ -280,6 +296,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["AAA".into(), "Shilka".into()],
@@ -300,6 +317,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["AAA".into(), "Tunguska".into()],
@@ -320,6 +338,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["IR SAMs".into(), "SA13 Strela".into()],
@@ -340,6 +359,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "SPH 2S19 Msta 152MM".into()],
@@ -360,6 +380,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "T72".into()],
@@ -383,6 +404,7 @@ fn default_red_deployables() -> Vec<Deployable> {
                 range: 8000,
                 nolos: false,
             }),
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "BMP3".into()],
@@ -406,6 +428,7 @@ fn default_red_deployables() -> Vec<Deployable> {
                 range: 8000,
                 nolos: false,
             }),
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "Ammo Truck".into()],
@@ -426,6 +449,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["EWRs".into(), "1L13".into()],
@@ -444,8 +468,13 @@ fn default_red_deployables() -> Vec<Deployable> {
             }],
             repair_crate: None,
             logistics: None,
-            ewr: Some(DeployableEwr { range: 500000 }),
+            ewr: Some(DeployableEwr {
+                range: 500000,
+                antenna_height: 20,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["FARP".into()],
@@ -476,6 +505,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             }),
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
     ]
 }
@@ -499,8 +529,13 @@ fn default_blue_deployables() -> Vec<Deployable> {
             }],
             repair_crate: None,
             logistics: None,
-            ewr: Some(DeployableEwr { range: 20000 }),
+            ewr: Some(DeployableEwr {
+                range: 20000,
+                antenna_height: 10,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "Hawk System".into()],
@@ -552,8 +587,13 @@ fn default_blue_deployables() -> Vec<Deployable> {
                 max_drop_speed: 13,
             }),
             logistics: None,
-            ewr: Some(DeployableEwr { range: 60000 }),
+            ewr: Some(DeployableEwr {
+                range: 60000,
+                antenna_height: 25,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["IR SAMs".into(), "Avenger".into()],
@@ -574,6 +614,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["IR SAMs".into(), "Linebacker".into()],
@@ -594,6 +635,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["AAA".into(), "Flakpanzergepard".into()],
@@ -614,6 +656,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["AAA".into(), "Vulkan".into()],
@@ -634,6 +677,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "Firtina 155MM".into()],
@@ -654,6 +698,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "M2A2 Bradley".into()],
@@ -677,6 +722,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
                 range: 8000,
                 nolos: false,
             }),
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "2A6M Leopard".into()],
@@ -700,6 +746,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
                 range: 8000,
                 nolos: false,
             }),
+            counter_battery: None,
         },
         Deployable {
             path: vec!["Ground Units".into(), "Ammo Truck".into()],
@@ -720,6 +767,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             logistics: None,
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["EWRs".into(), "AN/FPS-117".into()],
@@ -738,8 +786,13 @@ fn default_blue_deployables() -> Vec<Deployable> {
             }],
             repair_crate: None,
             logistics: None,
-            ewr: Some(DeployableEwr { range: 500000 }),
+            ewr: Some(DeployableEwr {
+                range: 500000,
+                antenna_height: 20,
+                notch: 0,
+            }),
             jtac: None,
+            counter_battery: None,
         },
         Deployable {
             path: vec!["FARP".into()],
@@ -770,10 +823,70 @@ fn default_blue_deployables() -> Vec<Deployable> {
             }),
             ewr: None,
             jtac: None,
+            counter_battery: None,
         },
     ]
 }
 
+fn default_admin_roles() -> FxHashMap<String, FxHashSet<String>> {
+    let role = |cmds: &[&str]| FxHashSet::from_iter(cmds.iter().map(|c| String::from(*c)));
+    FxHashMap::from_iter([
+        (
+            "moderator".into(),
+            role(&[
+                "kick",
+                "ban",
+                "unban",
+                "deslot",
+                "switch",
+                "connected",
+                "banned",
+                "search",
+                "reset-lives",
+                "audit",
+            ]),
+        ),
+        (
+            "logistics".into(),
+            role(&[
+                "tick",
+                "deliver",
+                "transfer",
+                "reduce",
+                "repair",
+                "log-warehouse",
+            ]),
+        ),
+        ("campaign".into(), role(&["*"])),
+    ])
+}
+
+fn default_radar_cross_section() -> FxHashMap<Vehicle, RcsClass> {
+    FxHashMap::from_iter([
+        ("F-16C_50".into(), RcsClass::Small),
+        ("M-2000C".into(), RcsClass::Small),
+        ("JF-17".into(), RcsClass::Small),
+        ("F-5E-3".into(), RcsClass::Small),
+        ("MiG-21Bis".into(), RcsClass::Small),
+        ("L-39C".into(), RcsClass::Small),
+        ("L-39ZA".into(), RcsClass::Small),
+        ("Yak-52".into(), RcsClass::Small),
+        ("TF-51D".into(), RcsClass::Small),
+        ("SA342L".into(), RcsClass::Small),
+        ("SA342M".into(), RcsClass::Small),
+        ("Ka-50".into(), RcsClass::Small),
+        ("Ka-50_3".into(), RcsClass::Small),
+        ("AH-64D_BLK_II".into(), RcsClass::Small),
+        ("UH-1H".into(), RcsClass::Small),
+        ("F-14A-135-GR".into(), RcsClass::Large),
+        ("F-14B".into(), RcsClass::Large),
+        ("F-15C".into(), RcsClass::Large),
+        ("F-15ESE".into(), RcsClass::Large),
+        ("Su-27".into(), RcsClass::Large),
+        ("Mi-8MT".into(), RcsClass::Large),
+    ])
+}
+
 fn default_life_types() -> FxHashMap<Vehicle, LifeType> {
     FxHashMap::from_iter([
         ("FA-18C_hornet".into(), LifeType::Standard),
@@ -1184,7 +1297,11 @@ fn default_red_actions() -> IndexMap<String, Action, FxBuildHasher> {
                 penalty: Some(100),
                 limit: None,
                 kind: ActionKind::Awacs(AwacsCfg {
-                    ewr: DeployableEwr { range: 400000 },
+                    ewr: DeployableEwr {
+                        range: 400000,
+                        antenna_height: 0,
+                        notch: 30,
+                    },
                     plane: AiPlaneCfg {
                         kind: AiPlaneKind::FixedWing,
                         duration: Some(8),
@@ -1457,7 +1574,11 @@ fn default_blue_actions() -> IndexMap<String, Action, FxBuildHasher> {
                         speed: 200.,
                         freq: Some(264000000)
                     },
-                    ewr: DeployableEwr { range: 400000 },
+                    ewr: DeployableEwr {
+                        range: 400000,
+                        antenna_height: 0,
+                        notch: 30,
+                    },
                 }),
             },
         ),
@@ -1727,6 +1848,12 @@ impl Default for Cfg {
                 "f279deb7a6b62c96a78eca3ddb2bd8d0".parse().unwrap(),
                 "REAPER 32 | EvilKipper".into(),
             )]),
+            admin_roles: default_admin_roles(),
+            admin_assignments: FxHashMap::from_iter([(
+                "f279deb7a6b62c96a78eca3ddb2bd8d0".parse().unwrap(),
+                FxHashSet::from_iter(["campaign".into()]),
+            )]),
+            admin_default_roles: Some(FxHashSet::default()),
             banned: FxHashMap::default(),
             max_msgs_per_second: 3,
             repair_time: 1800,
@@ -1782,6 +1909,7 @@ impl Default for Cfg {
                 (LifeType::Recon, (6, 21600)),
             ]),
             life_types: default_life_types(),
+            radar_cross_section: default_radar_cross_section(),
             actions: FxHashMap::from_iter([
                 (Side::Red, default_red_actions()),
                 (Side::Blue, default_blue_actions()),
@@ -1818,6 +1946,15 @@ impl Default for Cfg {
             ]),
             jtac_priority: default_jtac_priority(),
             extra_fixed_wing_objectives: FxHashSet::default(),
+            win: None,
+            status_port: None,
+            acmi: false,
+            csar: None,
+            capture: None,
+            convoy: None,
+            offensive: None,
+            front_line: None,
+            iads: None,
         }
     }
 }
@@ -14,7 +14,8 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
-use anyhow::{anyhow, Context, Result};
+use crate::admin::AdminCommand;
+use anyhow::{anyhow, bail, Context, Result};
 use chrono::prelude::*;
 use compact_str::format_compact;
 use dcso3::{coalition::Side, controller::AltType, net::Ucid, String};
@@ -69,8 +70,18 @@ impl Vehicle {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Rule {
-    Whitelist { allowed: FxHashMap<Ucid, String> },
-    Blacklist { denied: FxHashMap<Ucid, String> },
+    Whitelist {
+        allowed: FxHashMap<Ucid, String>,
+        /// members of these squadrons are also allowed
+        #[serde(default)]
+        squadrons: FxHashSet<String>,
+    },
+    Blacklist {
+        denied: FxHashMap<Ucid, String>,
+        /// members of these squadrons are also denied
+        #[serde(default)]
+        squadrons: FxHashSet<String>,
+    },
     AlwaysAllowed,
     NeverAllowed,
 }
@@ -82,10 +93,19 @@ impl Default for Rule {
 }
 
 impl Rule {
-    pub fn check(&self, ucid: &Ucid) -> bool {
+    /// check if the player, who may be a member of squadron, is allowed
+    pub fn check(&self, ucid: &Ucid, squadron: Option<&String>) -> bool {
+        let member = |squadrons: &FxHashSet<String>| match squadron {
+            Some(sq) => squadrons.contains(sq),
+            None => false,
+        };
         match self {
-            Self::Whitelist { allowed } => allowed.contains_key(ucid),
-            Self::Blacklist { denied } => !denied.contains_key(&ucid),
+            Self::Whitelist { allowed, squadrons } => {
+                allowed.contains_key(ucid) || member(squadrons)
+            }
+            Self::Blacklist { denied, squadrons } => {
+                !denied.contains_key(&ucid) && !member(squadrons)
+            }
             Self::AlwaysAllowed => true,
             Self::NeverAllowed => false,
         }
@@ -94,15 +114,18 @@ impl Rule {
     #[allow(dead_code)]
     pub fn blacklist(&mut self, ucid: Ucid, name: String) {
         match self {
-            Self::Blacklist { denied } => {
+            Self::Blacklist { denied, .. } => {
                 denied.insert(ucid, name);
             }
-            Self::Whitelist { allowed } => {
+            Self::Whitelist { allowed, .. } => {
                 allowed.remove(&ucid);
             }
             Self::AlwaysAllowed => {
                 let denied = FxHashMap::from_iter([(ucid, name)]);
-                *self = Self::Blacklist { denied };
+                *self = Self::Blacklist {
+                    denied,
+                    squadrons: FxHashSet::default(),
+                };
             }
             Self::NeverAllowed => (),
         }
@@ -111,15 +134,18 @@ impl Rule {
     #[allow(dead_code)]
     pub fn whitelist(&mut self, ucid: Ucid, name: String) {
         match self {
-            Self::Blacklist { denied } => {
+            Self::Blacklist { denied, .. } => {
                 denied.remove(&ucid);
             }
-            Self::Whitelist { allowed } => {
+            Self::Whitelist { allowed, .. } => {
                 allowed.insert(ucid, name);
             }
             Self::NeverAllowed => {
                 let allowed = FxHashMap::from_iter([(ucid, name)]);
-                *self = Self::Whitelist { allowed };
+                *self = Self::Whitelist {
+                    allowed,
+                    squadrons: FxHashSet::default(),
+                };
             }
             Self::AlwaysAllowed => (),
         }
@@ -315,12 +341,68 @@ pub struct DeployableLogistics {
     pub barracks_template: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+fn default_antenna_height() -> u32 {
+    10
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct DeployableEwr {
-    /// range for likely detection (Meters)
+    /// range for likely detection of a medium radar cross section
+    /// target (Meters)
     pub range: u32,
-    // CR estokes: Actual radar simulation ...
+    /// the height of the antenna above the unit, used to compute the
+    /// radar horizon (Meters)
+    #[serde(default = "default_antenna_height")]
+    pub antenna_height: u32,
+    /// targets below the radar moving slower than this toward or
+    /// away from it are lost in ground clutter. 0 disables the
+    /// doppler notch. (Meters / Second)
+    #[serde(default)]
+    pub notch: u32,
+}
+
+impl Default for DeployableEwr {
+    fn default() -> Self {
+        Self {
+            range: 0,
+            antenna_height: default_antenna_height(),
+            notch: 0,
+        }
+    }
+}
+
+/// How visible an aircraft is to radar
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RcsClass {
+    /// low observable aircraft
+    Stealth,
+    /// small fighters, trainers, and helicopters
+    Small,
+    /// most fighters and attack aircraft
+    #[default]
+    Medium,
+    /// bombers, tankers, and transports
+    Large,
+}
+
+impl RcsClass {
+    /// a representative radar cross section for the class (Square Meters)
+    pub fn rcs(&self) -> f64 {
+        match self {
+            Self::Stealth => 0.01,
+            Self::Small => 1.,
+            Self::Medium => 5.,
+            Self::Large => 50.,
+        }
+    }
+
+    /// The fraction of a radar's range at which this class is
+    /// detected. Detection range goes with the fourth root of the
+    /// radar cross section.
+    pub fn range_factor(&self) -> f64 {
+        (self.rcs() / Self::Medium.rcs()).powf(0.25)
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -334,6 +416,18 @@ pub struct DeployableJtac {
     pub nolos: bool,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeployableCounterBattery {
+    /// enemy artillery firing within this range of the radar is
+    /// located (Meters)
+    pub range: u32,
+    /// if non zero, friendly artillery in range of a located battery
+    /// will automatically fire this many rounds back at it
+    #[serde(default)]
+    pub return_fire: u8,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Deployable {
@@ -361,6 +455,9 @@ pub struct Deployable {
     pub ewr: Option<DeployableEwr>,
     /// Is this unit a jtac
     pub jtac: Option<DeployableJtac>,
+    /// Is this unit a counter battery radar
+    #[serde(default)]
+    pub counter_battery: Option<DeployableCounterBattery>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -450,6 +547,177 @@ pub struct PointsCfg {
     pub tk_window: u32,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoldCfg {
+    /// the percentage of all objectives a side must own
+    pub percent: u8,
+    /// how many hours the side must own them for
+    pub hours: u32,
+}
+
+/// How troops capture an objective over time
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureCfg {
+    /// how long troops must hold an objective without opposition to
+    /// capture it (Seconds)
+    pub time: u32,
+    /// how long it takes for all capture progress to be lost once the
+    /// troops are gone (Seconds)
+    pub decay: u32,
+    /// warn both sides when the capture will complete in this much
+    /// time (Seconds)
+    pub warning: u32,
+}
+
+/// How a round can end. The first side to meet any of the
+/// conditions wins.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WinCfg {
+    /// a side wins when it owns every airbase
+    #[serde(default)]
+    pub all_airbases: bool,
+    /// a side wins when it holds a share of the objectives for long enough
+    #[serde(default)]
+    pub hold: Option<HoldCfg>,
+    /// a side loses when none of it's logistics hubs have any supply left
+    #[serde(default)]
+    pub supply_exhausted: bool,
+}
+
+/// Combat search and rescue of ejected player pilots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsarCfg {
+    /// The name of the downed pilot group for each side
+    pub template: FxHashMap<Side, String>,
+    /// how much a pilot weighs as cargo (Kg)
+    pub weight: u32,
+    /// how long a downed pilot will wait to be picked up (Seconds)
+    pub lifetime: u32,
+    /// points for bringing a friendly pilot home
+    pub rescue_points: u32,
+    /// points for bringing an enemy pilot home
+    pub capture_points: u32,
+}
+
+/// Logistics transfers carried by ground convoys on the road network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConvoyCfg {
+    /// The name of the supply convoy group for each side
+    pub template: FxHashMap<Side, String>,
+    /// transfers between objectives closer than this are delivered
+    /// directly (Meters)
+    pub min_distance: u32,
+    /// how fast the convoy drives (Meters / Second)
+    pub speed: f64,
+    /// how long a convoy may be on the road before whatever cargo it
+    /// still carries is delivered anyway (Minutes)
+    pub max_age: u32,
+}
+
+/// AI ground attacks launched from well supplied objectives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OffensiveCfg {
+    /// The name of the attack group for each side
+    pub template: FxHashMap<Side, String>,
+    /// an objective must have at least this much supply to attack (Percent)
+    pub min_supply: u8,
+    /// an objective must have at least this much logi to attack (Percent)
+    pub min_logi: u8,
+    /// an objective attacks the closest enemy objective within this range (Meters)
+    pub range: u32,
+    /// how often an objective may attack (Minutes)
+    pub freq: u32,
+    /// how many groups an attack sends
+    pub groups: u32,
+    /// the equipment each group dispatched takes from the objective's warehouse
+    #[serde(default)]
+    pub cost: FxHashMap<String, u32>,
+    /// how fast attack groups drive (Meters / Second)
+    pub speed: f64,
+    /// attack groups that haven't taken their target after this long
+    /// are withdrawn (Minutes)
+    pub lifetime: u32,
+    /// at most this many attack groups per side may be in the field
+    pub max_groups: u32,
+    /// A list of (players, factor) sorted by players. The number of
+    /// groups in an attack is multiplied, and the time between attacks
+    /// is divided, by the factor for the number of players on the side,
+    /// interpolated between entries. A factor of 0 stops attacks.
+    #[serde(default)]
+    pub population_scale: Vec<(u32, f32)>,
+}
+
+impl OffensiveCfg {
+    /// the scale factor for a side with this many players
+    pub fn scale(&self, players: u32) -> f32 {
+        let mut prev: Option<(u32, f32)> = None;
+        for (n, f) in self.population_scale.iter().copied() {
+            if players <= n {
+                return match prev {
+                    None => f,
+                    Some((pn, pf)) => pf + (f - pf) * (players - pn) as f32 / (n - pn) as f32,
+                };
+            }
+            prev = Some((n, f));
+        }
+        prev.map(|(_, f)| f).unwrap_or(1.)
+    }
+}
+
+/// A front line computed from the positions of each side's
+/// objectives. The front runs where the distance to the closest
+/// objective of each side, less that objective's influence radius, is
+/// the same. Logistics deployables may not be unpacked on the enemy
+/// side of the front.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrontLineCfg {
+    /// The front is traced on a grid with cells this size (Meters)
+    pub resolution: u32,
+    /// The influence radius of an airbase (Meters)
+    pub airbase: u32,
+    /// The influence radius of a fob (Meters)
+    pub fob: u32,
+    /// The influence radius of a farp (Meters)
+    pub farp: u32,
+    /// The influence radius of a logistics hub (Meters)
+    pub logistics: u32,
+    /// shade each side's territory on the F10 map
+    #[serde(default)]
+    pub shade: bool,
+    /// if specified, objectives within this distance of the front
+    /// are always threatened (Meters)
+    #[serde(default)]
+    pub threatened_distance: Option<u32>,
+}
+
+/// Integrated air defense. SAM sites keep their radars off until the
+/// side's radar picture shows a hostile aircraft close enough to
+/// engage, and shut down when an anti radiation missile is fired at
+/// them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IadsCfg {
+    /// a site turns on its radars when a hostile track is within this
+    /// distance of it (Meters)
+    pub engagement_range: u32,
+    /// while a site with a search radar is emitting it contributes
+    /// tracks to its side's radar picture out to this range (Meters)
+    pub search_range: u32,
+    /// sites without a search radar of their own also turn on while
+    /// an emitting search radar site is within this distance (Meters)
+    pub share_range: u32,
+    /// how long a site stays dark after an anti radiation missile is
+    /// fired at it (Seconds)
+    pub arm_shutdown: u32,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum AiPlaneKind {
     FixedWing,
@@ -608,6 +876,21 @@ pub struct Cfg {
     /// ucids in this list are able to run admin commands
     #[serde(default)]
     pub admins: FxHashMap<Ucid, String>,
+    /// named sets of admin commands, e.g. "moderator": ["kick", "ban"].
+    /// "*" stands for every command.
+    #[serde(default)]
+    pub admin_roles: FxHashMap<String, FxHashSet<String>>,
+    /// the roles held by each admin. An admin who isn't listed here
+    /// holds the admin_default_roles.
+    #[serde(default)]
+    pub admin_assignments: FxHashMap<Ucid, FxHashSet<String>>,
+    /// the roles held by admins who aren't listed in
+    /// admin_assignments, including admins made with add-admin. If
+    /// empty such admins can't run any command. If absent, as in
+    /// configs written before roles existed, they can run every
+    /// command.
+    #[serde(default)]
+    pub admin_default_roles: Option<FxHashSet<String>>,
     /// ucids in this list are banned
     #[serde(default)]
     pub banned: FxHashMap<Ucid, (Option<DateTime<Utc>>, String)>,
@@ -673,6 +956,10 @@ pub struct Cfg {
     pub max_crates: Option<u32>,
     /// the life types different vehicles use
     pub life_types: FxHashMap<Vehicle, LifeType>,
+    /// the radar cross section class of aircraft. Aircraft that are
+    /// not listed are Medium.
+    #[serde(default)]
+    pub radar_cross_section: FxHashMap<Vehicle, RcsClass>,
     /// the life reset configuration for each life type. A pair
     /// of number of lives per reset, and reset time in seconds.
     pub default_lives: FxHashMap<LifeType, (u8, u32)>,
@@ -703,10 +990,99 @@ pub struct Cfg {
     /// a port.
     #[serde(default)]
     pub extra_fixed_wing_objectives: FxHashSet<String>,
+    /// how the round can be won. If not specified the round never
+    /// ends on it's own.
+    #[serde(default)]
+    pub win: Option<WinCfg>,
+    /// if specified, serve a read only json view of the mission
+    /// state on this port on localhost
+    #[serde(default)]
+    pub status_port: Option<u16>,
+    /// if true record a Tacview ACMI of every tracked object, one
+    /// file per session in Logs/bftrack.txt.acmi.zst
+    #[serde(default)]
+    pub acmi: bool,
+    /// if specified, ejected pilots wait in the field to be rescued
+    /// by friendly helicopters or captured by the enemy
+    #[serde(default)]
+    pub csar: Option<CsarCfg>,
+    /// if specified, troops must hold an objective for a time while
+    /// no living defenders are in it to capture it. Otherwise capture
+    /// is immediate.
+    #[serde(default)]
+    pub capture: Option<CaptureCfg>,
+    /// if specified, supplies moved between distant objectives travel
+    /// by road in convoys that can be interdicted
+    #[serde(default)]
+    pub convoy: Option<ConvoyCfg>,
+    /// if specified, objectives with enough supply periodically send
+    /// AI ground attacks against nearby enemy objectives
+    #[serde(default)]
+    pub offensive: Option<OffensiveCfg>,
+    /// if specified, draw the front line between the sides on the F10
+    /// map
+    #[serde(default)]
+    pub front_line: Option<FrontLineCfg>,
+    /// if specified, SAM sites operate as an integrated air defense
+    /// network instead of emitting all the time
+    #[serde(default)]
+    pub iads: Option<IadsCfg>,
+}
+
+/// What changed when a config file was reloaded into a running mission
+#[derive(Debug, Clone, Default)]
+pub struct CfgReload {
+    /// fields that changed and were applied to the live config
+    pub applied: Vec<&'static str>,
+    /// fields that changed but will only take effect after a restart
+    pub needs_restart: Vec<&'static str>,
+}
+
+impl CfgReload {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.needs_restart.is_empty()
+    }
+
+    pub fn applied(&self, field: &'static str) -> bool {
+        self.applied.contains(&field)
+    }
+}
+
+impl fmt::Display for CfgReload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+        if !self.applied.is_empty() {
+            write!(f, "applied: {}", self.applied.join(", "))?;
+        }
+        if !self.needs_restart.is_empty() {
+            if !self.applied.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "requires a restart: {}", self.needs_restart.join(", "))?;
+        }
+        Ok(())
+    }
 }
 
 impl Cfg {
-    fn path(miz_state_path: &Path) -> PathBuf {
+    /// true if ucid is an admin holding a role that grants command
+    pub fn admin_may(&self, ucid: &Ucid, command: &str) -> bool {
+        if !self.admins.contains_key(ucid) {
+            return false;
+        }
+        let roles = match (self.admin_assignments.get(ucid), &self.admin_default_roles) {
+            (Some(roles), _) | (None, Some(roles)) => roles,
+            (None, None) => return true,
+        };
+        roles.iter().any(|role| match self.admin_roles.get(role) {
+            None => false,
+            Some(cmds) => cmds.contains("*") || cmds.contains(command),
+        })
+    }
+
+    pub fn path(miz_state_path: &Path) -> PathBuf {
         let mut path = PathBuf::from(miz_state_path);
         let file_name = path
             .file_name()
@@ -740,9 +1116,42 @@ impl Cfg {
         };
         let cfg: Self = serde_json::from_reader(file)
             .map_err(|e| anyhow!("failed to decode cfg file {:?}, {:?}", path, e))?;
+        cfg.validate()
+            .with_context(|| format_compact!("invalid cfg file {:?}", path))?;
         Ok(cfg)
     }
 
+    /// Check the values that serde can't
+    pub fn validate(&self) -> Result<()> {
+        for (role, cmds) in &self.admin_roles {
+            for cmd in cmds {
+                if cmd.as_str() != "*" && !AdminCommand::names().any(|n| n == cmd.as_str()) {
+                    bail!("admin role {role} grants unknown command {cmd}")
+                }
+            }
+        }
+        let unknown = |roles: &FxHashSet<String>| {
+            roles
+                .iter()
+                .find(|r| !self.admin_roles.contains_key(*r))
+                .cloned()
+        };
+        for (ucid, roles) in &self.admin_assignments {
+            if let Some(role) = unknown(roles) {
+                bail!("admin {ucid} is assigned unknown role {role}")
+            }
+        }
+        if let Some(role) = self.admin_default_roles.as_ref().and_then(unknown) {
+            bail!("admin_default_roles has unknown role {role}")
+        }
+        if let Some(hold) = self.win.as_ref().and_then(|w| w.hold.as_ref()) {
+            if hold.percent > 100 {
+                bail!("win.hold.percent must be at most 100, not {}", hold.percent)
+            }
+        }
+        Ok(())
+    }
+
     pub fn save(&self, miz_state_path: &Path) -> Result<()> {
         let mut path = Self::path(miz_state_path);
         path.set_extension("bak");
@@ -756,4 +1165,95 @@ impl Cfg {
         fs::rename(&path, Self::path(miz_state_path)).context("moving new file into place")?;
         Ok(())
     }
+
+    /// Compare a freshly loaded config with the live one. Returns a
+    /// config containing every change that can be applied while the
+    /// mission is running, with everything else kept as it is live,
+    /// and a report of what changed.
+    pub fn merge_live(&self, mut new: Cfg) -> Result<(Cfg, CfgReload)> {
+        fn changed<T: serde::Serialize>(a: &T, b: &T) -> Result<bool> {
+            Ok(serde_json::to_value(a)? != serde_json::to_value(b)?)
+        }
+        for actions in new.actions.values_mut() {
+            actions.sort_by(|name0, _, name1, _| name0.cmp(name1));
+        }
+        let mut report = CfgReload::default();
+        macro_rules! live {
+            ($($field:ident),+) => {
+                $(
+                    if changed(&self.$field, &new.$field)? {
+                        report.applied.push(stringify!($field))
+                    }
+                )+
+            }
+        }
+        macro_rules! restart {
+            ($($field:ident),+) => {
+                $(
+                    if changed(&self.$field, &new.$field)? {
+                        report.needs_restart.push(stringify!($field));
+                        new.$field = self.$field.clone();
+                    }
+                )+
+            }
+        }
+        live!(
+            admins,
+            admin_roles,
+            admin_assignments,
+            admin_default_roles,
+            banned,
+            rules,
+            name_filter,
+            max_msgs_per_second,
+            points,
+            repair_time,
+            repair_crate,
+            logistics_exclusion,
+            unit_cull_distance,
+            ground_vehicle_cull_distance,
+            cull_after,
+            slow_timed_events_freq,
+            threatened_distance,
+            threatened_cooldown,
+            crate_load_distance,
+            crate_spread,
+            artillery_mission_range,
+            side_switches,
+            max_crates,
+            default_lives,
+            radar_cross_section,
+            actions,
+            cargo,
+            crate_template,
+            deployables,
+            troops,
+            airborne_jtacs,
+            jtac_priority,
+            win,
+            csar,
+            capture,
+            acmi,
+            convoy,
+            offensive,
+            front_line,
+            iads
+        );
+        // these are baked into the spawned units, slots, and
+        // warehouses when the mission starts
+        restart!(
+            shutdown,
+            warehouse,
+            life_types,
+            unit_classification,
+            extra_fixed_wing_objectives,
+            status_port
+        );
+        for lt in new.life_types.values() {
+            if !new.default_lives.contains_key(lt) {
+                bail!("default_lives is missing life type {lt}")
+            }
+        }
+        Ok((new, report))
+    }
 }
@@ -115,10 +115,21 @@ fn admin_command(ctx: &mut Context, id: PlayerId, cmd: &str) {
             format_compact!("parse error {:?}", e),
         ),
         Ok(AdminCommand::Help) => {
-            for cmd in AdminCommand::help() {
-                ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), *cmd);
+            let cfg = Arc::clone(&ctx.db.ephemeral.cfg);
+            for cmd in AdminCommand::help_for(&cfg, &ifo.ucid) {
+                ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), cmd);
             }
         }
+        Ok(cmd) if !ctx.db.ephemeral.cfg.admin_may(&ifo.ucid, cmd.name()) => {
+            info!("denied admin command {:?} from {:?}", cmd, ifo);
+            let mut entry = admin::audit_entry(ctx, id, &cmd);
+            entry.result = Err("not allowed by the admin's roles".into());
+            ctx.db.ephemeral.audit(entry);
+            ctx.db.ephemeral.msgs().send(
+                MsgTyp::Chat(Some(id)),
+                format_compact!("your admin roles don't allow {}", cmd.name()),
+            )
+        }
         Ok(cmd) => {
             info!("queueing admin command {:?} from {:?}", cmd, ifo);
             ctx.admin_commands.push((id, cmd))
@@ -161,6 +172,18 @@ fn balance_command(ctx: &mut Context, id: PlayerId) {
     }
 }
 
+fn stats_command(ctx: &mut Context, id: PlayerId) {
+    if let Some(ifo) = ctx.connected.get(&id) {
+        let msgs = match ctx.db.career(&ifo.ucid) {
+            Some(career) => career.summary(),
+            None => vec![format_compact!("You don't have any stats yet")],
+        };
+        for msg in msgs {
+            ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg)
+        }
+    }
+}
+
 fn transfer_command(ctx: &mut Context, id: PlayerId, s: &str) {
     macro_rules! reply {
         ($msg:tt) => {
@@ -187,6 +210,105 @@ fn transfer_command(ctx: &mut Context, id: PlayerId, s: &str) {
     }
 }
 
+const SQUADRON_HELP: &[&str] = &[
+    " -squadron: show your squadron",
+    " -squadron list: list all squadrons",
+    " -squadron info <name>: show a squadron",
+    " -squadron create <tag> <name>: found a squadron, your name must start with tag",
+    " -squadron join <name>: join a squadron, your name must start with it's tag",
+    " -squadron leave: leave your squadron",
+    " -squadron deposit <amount>: move points to the squadron pool",
+    " -squadron promote|demote|kick <player>: officers only, manage the roster",
+    " -squadron pool open|close: officers only, open or close the shared pool",
+];
+
+fn squadron_command(ctx: &mut Context, id: PlayerId, s: &str) {
+    macro_rules! reply {
+        ($msg:expr) => {
+            ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), $msg)
+        };
+    }
+    let ucid = match ctx.connected.get(&id) {
+        Some(ifo) => ifo.ucid,
+        None => return,
+    };
+    let s = s.trim();
+    let (cmd, arg) = s.split_once(' ').unwrap_or((s, ""));
+    let arg = arg.trim();
+    let res = match cmd {
+        "" => {
+            let msgs = match ctx.db.squadron_of(&ucid) {
+                Some((name, sq)) => sq.summary(name),
+                None => vec![format_compact!("You are not in a squadron")],
+            };
+            for msg in msgs {
+                reply!(msg)
+            }
+            Ok(())
+        }
+        "list" => {
+            let msgs = ctx
+                .db
+                .squadrons()
+                .map(|(name, sq)| {
+                    format_compact!("{name} [{}] {} members", sq.tag, sq.members.len())
+                })
+                .collect::<Vec<_>>();
+            if msgs.is_empty() {
+                reply!("There are no squadrons")
+            }
+            for msg in msgs {
+                reply!(msg)
+            }
+            Ok(())
+        }
+        "info" => ctx.db.squadron_name(arg).map(|name| {
+            let msgs = ctx.db.squadrons().find(|(n, _)| **n == name).map(|(n, sq)| sq.summary(n));
+            for msg in msgs.unwrap_or_default() {
+                reply!(msg)
+            }
+        }),
+        "create" => match arg.split_once(' ') {
+            None => Err(anyhow!("create expected a tag and a name")),
+            Some((tag, name)) => ctx.db.create_squadron(&ucid, name, tag),
+        },
+        "join" => ctx
+            .db
+            .join_squadron(&ucid, arg)
+            .map(|()| reply!(format_compact!("you joined {arg}"))),
+        "leave" => ctx
+            .db
+            .leave_squadron(&ucid)
+            .map(|()| reply!("you left your squadron")),
+        "deposit" => match arg.parse::<u32>() {
+            Err(e) => Err(anyhow!("deposit expected a number {e:?}")),
+            Ok(amount) => ctx.db.deposit_to_squadron(&ucid, amount),
+        },
+        "pool" => match arg {
+            "open" => ctx.db.set_squadron_pool(&ucid, true),
+            "close" => ctx.db.set_squadron_pool(&ucid, false),
+            _ => Err(anyhow!("pool expected open or close")),
+        },
+        "promote" | "demote" | "kick" => match admin::get_player_ucid(ctx, arg) {
+            Err(e) => Err(e),
+            Ok(target) if cmd == "kick" => ctx.db.kick_from_squadron(&ucid, &target),
+            Ok(target) => ctx
+                .db
+                .set_squadron_officer(&ucid, &target, cmd == "promote"),
+        }
+        .map(|()| reply!(format_compact!("{cmd} {arg} done"))),
+        _ => {
+            for msg in SQUADRON_HELP {
+                reply!(*msg)
+            }
+            Ok(())
+        }
+    };
+    if let Err(e) = res {
+        reply!(format_compact!("squadron {cmd} failed: {e}"))
+    }
+}
+
 fn delete_command(ctx: &mut Context, id: PlayerId, s: &str) {
     macro_rules! reply {
         ($msg:tt) => {
@@ -211,6 +333,9 @@ fn delete_command(ctx: &mut Context, id: PlayerId, s: &str) {
                     }
                     DeployKind::Action { .. } => reply!("can't delete an action group"),
                     DeployKind::Objective => reply!("can't delete an objective group"),
+                    DeployKind::Pilot { .. } => reply!("can't delete a downed pilot"),
+                    DeployKind::Convoy { .. } => reply!("can't delete a supply convoy"),
+                    DeployKind::Offensive { .. } => reply!("can't delete an attack group"),
                     DeployKind::Crate { .. } => match ctx.db.delete_group(&id) {
                         Err(e) => reply!("could not delete group {id} {e:?}"),
                         Ok(()) => reply!("deleted {id}"),
@@ -385,8 +510,10 @@ fn help_command(ctx: &mut Context, id: PlayerId) {
         " -lives: display your current lives",
         " -time: how long until server restart",
         " -balance: show your points balance",
+        " -stats: show your career statistics",
         " -transfer <amount> <player>: transfer points to another player",
         " -delete <groupid>: delete a group you deployed for a partial refund",
+        " -squadron <command>: manage squadrons, -squadron help for details",
         " -action <name> <args>: perform an action, -action help for a list of actions",
         " -help: show this help message",
     ] {
@@ -428,12 +555,18 @@ pub(super) fn process(
     } else if msg.starts_with("-balance") {
         balance_command(ctx, id);
         Ok("".into())
+    } else if msg.eq_ignore_ascii_case("-stats") {
+        stats_command(ctx, id);
+        Ok("".into())
     } else if let Some(s) = msg.strip_prefix("-transfer ") {
         transfer_command(ctx, id, s);
         Ok("".into())
     } else if let Some(s) = msg.strip_prefix("-delete ") {
         delete_command(ctx, id, s);
         Ok("".into())
+    } else if let Some(s) = msg.strip_prefix("-squadron") {
+        squadron_command(ctx, id, s);
+        Ok("".into())
     } else if msg.starts_with("-help") {
         help_command(ctx, id);
         Ok("".into())
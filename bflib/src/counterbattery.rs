@@ -0,0 +1,162 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Counter battery radars locate enemy artillery when it fires, tell
+//! their side where it is, and optionally task friendly artillery to
+//! shoot back.
+
+use crate::{
+    cfg::{DeployableCounterBattery, UnitTag},
+    db::{group::GroupId, Db},
+    jtac,
+};
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+use compact_str::format_compact;
+use dcso3::{
+    coalition::Side, coord::Coord, land::Land, object::DcsObject, trigger::MarkId, unit::Unit,
+    LuaVec2, LuaVec3, MizLua, Vector2, Vector3,
+};
+use fxhash::FxHashMap;
+use log::warn;
+
+/// a located battery is not reported again until this long after
+/// the last report (Seconds)
+const REPORT_INTERVAL: i64 = 60;
+
+/// how long a located battery stays marked on the F10 map (Seconds)
+const MARK_LIFETIME: i64 = 600;
+
+/// Find the first radar that isn't on side and is within range of
+/// artillery firing from pos. Returns the side that located the
+/// artillery and the radar that located it.
+pub fn locate<'a, I>(
+    radars: I,
+    side: Side,
+    pos: Vector2,
+) -> Option<(Side, &'a DeployableCounterBattery)>
+where
+    I: IntoIterator<Item = (Vector2, Side, &'a DeployableCounterBattery)>,
+{
+    radars.into_iter().find_map(|(radar_pos, radar_side, cb)| {
+        let range = cb.range as f64;
+        if radar_side != side
+            && na::distance_squared(&radar_pos.into(), &pos.into()) <= range * range
+        {
+            Some((radar_side, cb))
+        } else {
+            None
+        }
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Located {
+    mark: MarkId,
+    reported: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CounterBattery {
+    located: FxHashMap<GroupId, Located>,
+}
+
+impl CounterBattery {
+    /// Called for every shot. If the shooter is ground artillery
+    /// within range of an enemy counter battery radar then it is
+    /// reported to the radar's side and friendly artillery in range
+    /// may return fire.
+    pub fn shot(
+        &mut self,
+        lua: MizLua,
+        db: &mut Db,
+        now: DateTime<Utc>,
+        shooter: &Unit,
+    ) -> Result<()> {
+        let uid = match db.ephemeral.get_uid_by_object_id(&shooter.object_id()?) {
+            Some(uid) => *uid,
+            None => return Ok(()),
+        };
+        let unit = db.unit(&uid)?;
+        if !unit.tags.contains(UnitTag::Artillery) {
+            return Ok(());
+        }
+        let (gid, pos, typ) = (unit.group, unit.pos, unit.typ.clone());
+        if let Some(l) = self.located.get(&gid) {
+            if now - l.reported < Duration::seconds(REPORT_INTERVAL) {
+                return Ok(());
+            }
+        }
+        let (side, cb) = match locate(db.counter_battery_radars(), unit.side, pos) {
+            Some((side, cb)) => (side, *cb),
+            None => return Ok(()),
+        };
+        let mut returning = 0;
+        if cb.return_fire > 0 {
+            for arty in db.artillery_near_point(side, pos) {
+                match jtac::fire_mission(db, lua, &arty, pos, cb.return_fire) {
+                    Ok(()) => returning += 1,
+                    Err(e) => warn!("could not return fire with {arty}: {e:?}"),
+                }
+            }
+        }
+        let elevation = Land::singleton(lua)?.get_height(LuaVec2(pos))?;
+        let coord = Coord::singleton(lua)?;
+        let ll = coord.lo_to_ll(LuaVec3(Vector3::new(pos.x, elevation, pos.y)))?;
+        let mgrs = coord.ll_to_mgrs(ll.latitude, ll.longitude)?;
+        let location = format_compact!(
+            "{} {} {:05} {:05}",
+            mgrs.utm_zone,
+            mgrs.mgrs_digraph,
+            mgrs.easting as u32,
+            mgrs.northing as u32
+        );
+        let msgs = db.ephemeral.msgs();
+        let mut msg = format_compact!(
+            "COUNTER BATTERY RADAR LOCATED ENEMY ARTILLERY\n{typ} firing from {location}"
+        );
+        if returning > 0 {
+            msg.push_str(&format_compact!(
+                "\n{returning} friendly batteries returning fire"
+            ));
+        }
+        msgs.panel_to_side(10, false, side, msg);
+        if let Some(l) = self.located.remove(&gid) {
+            msgs.delete_mark(l.mark)
+        }
+        let mark = msgs.mark_to_side(side, pos, true, format_compact!("Enemy artillery {typ}"));
+        self.located.insert(
+            gid,
+            Located {
+                mark,
+                reported: now,
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove the marks of batteries that haven't been located recently
+    pub fn expire(&mut self, db: &mut Db, now: DateTime<Utc>) {
+        self.located.retain(|_, l| {
+            if now - l.reported < Duration::seconds(MARK_LIFETIME) {
+                true
+            } else {
+                db.ephemeral.msgs().delete_mark(l.mark);
+                false
+            }
+        })
+    }
+}
@@ -15,6 +15,7 @@ use crate::{
     objective,
     perf::PerfInner,
     spawnctx::{SpawnCtx, SpawnLoc},
+    stats::StatKind,
     unit,
 };
 use anyhow::{anyhow, bail, Context, Ok, Result};
@@ -286,18 +287,19 @@ impl Db {
             _ => cmd.action.cost,
         };
         if let Some(ucid) = ucid.as_ref() {
-            if !self.ephemeral.cfg.rules.actions.check(ucid) {
+            if !self.check_rule(&self.ephemeral.cfg.rules.actions, ucid) {
                 bail!("you are not authorized for actions")
             }
+            let points = self.spendable_points(ucid);
             match self.persisted.players.get(ucid) {
                 None => bail!("unknown player {ucid}"),
                 Some(player) => {
-                    if cost > 0 && player.points < cost as i32 {
+                    if cost > 0 && points < cost as i32 {
                         bail!(
                             "{ucid}({}) this action costs {} points and you have {} points",
                             player.name,
                             cost,
-                            player.points
+                            points
                         )
                     }
                     if side != player.side {
@@ -322,6 +324,10 @@ impl Db {
             }
         }
         let name = cmd.name.clone();
+        let stat = ucid.map(|by| StatKind::Action {
+            by,
+            action: cmd.action.clone(),
+        });
         match cmd.args {
             ActionArgs::Awacs(args) => self
                 .awacs(perf, spctx, idx, side, ucid.clone(), name, cmd.action, args)
@@ -387,11 +393,10 @@ impl Db {
             },
         }
         if let Some(ucid) = ucid.as_ref() {
-            self.adjust_points(
-                ucid,
-                -(cost as i32),
-                &format!("perform action {}", cmd.name),
-            );
+            self.spend_points(ucid, cost, &format!("perform action {}", cmd.name));
+        }
+        if let Some(stat) = stat {
+            self.ephemeral.stat(stat)
         }
         *self
             .ephemeral
@@ -816,7 +821,12 @@ impl Db {
         let max_dist = match &group.origin {
             DeployKind::Deployed { .. } => args.cfg.deployable,
             DeployKind::Troop { .. } => args.cfg.troop,
-            DeployKind::Action { .. } | DeployKind::Crate { .. } | DeployKind::Objective => 0,
+            DeployKind::Action { .. }
+            | DeployKind::Crate { .. }
+            | DeployKind::Objective
+            | DeployKind::Pilot { .. }
+            | DeployKind::Convoy { .. }
+            | DeployKind::Offensive { .. } => 0,
         };
         if max_dist == 0 {
             bail!("you can't move this type of unit")
@@ -843,7 +853,10 @@ impl Db {
                 | DeployKind::Crate { .. }
                 | DeployKind::Objective
                 | DeployKind::Troop { .. }
-                | DeployKind::Deployed { .. } => (),
+                | DeployKind::Deployed { .. }
+                | DeployKind::Pilot { .. }
+                | DeployKind::Convoy { .. }
+                | DeployKind::Offensive { .. } => (),
             }
         }
         let land = Land::singleton(spctx.lua())?;
@@ -1527,7 +1540,10 @@ impl Db {
             DeployKind::Crate { .. }
             | DeployKind::Deployed { .. }
             | DeployKind::Objective
-            | DeployKind::Troop { .. } => bail!("not a race tracker"),
+            | DeployKind::Troop { .. }
+            | DeployKind::Pilot { .. }
+            | DeployKind::Convoy { .. }
+            | DeployKind::Offensive { .. } => bail!("not a race tracker"),
         };
         let responsible = player
             .as_ref()
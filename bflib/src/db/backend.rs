@@ -0,0 +1,133 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::{
+    logistics::{sync_obj_to_warehouse, sync_warehouse_to_obj},
+    objective::Objective,
+};
+use crate::landcache::LandCache;
+use anyhow::{anyhow, Context, Result};
+use dcso3::{
+    airbase::{Airbase, ClassAirbase},
+    coalition::Side,
+    group::{ClassGroup, Group},
+    land::Land,
+    object::{DcsObject, DcsOid},
+    MizLua, Vector3,
+};
+
+/// The things the campaign logic needs from the game while it is
+/// running. The Db's periodic tasks run against this so they can be
+/// driven by DCS or by the simulator.
+pub trait Backend {
+    /// true if the terrain does not block the line of sight between p0
+    /// and p1, which are dist meters apart
+    fn is_visible(&mut self, dist: f64, p0: Vector3, p1: Vector3) -> Result<bool>;
+
+    /// turn the AI of a spawned group on or off
+    fn set_ai_enabled(&mut self, group: &DcsOid<ClassGroup>, enabled: bool) -> Result<()>;
+
+    /// change the coalition of the airbase belonging to an objective
+    fn set_airbase_coalition(
+        &mut self,
+        airbase: Option<&DcsOid<ClassAirbase>>,
+        side: Side,
+    ) -> Result<()>;
+
+    /// read the inventory of the objective's warehouse into the objective
+    fn sync_warehouse_to_objective(
+        &mut self,
+        airbase: Option<&DcsOid<ClassAirbase>>,
+        obj: &mut Objective,
+    ) -> Result<()>;
+
+    /// write the inventory of the objective into the objective's warehouse
+    fn sync_objective_to_warehouse(
+        &mut self,
+        airbase: Option<&DcsOid<ClassAirbase>>,
+        obj: &Objective,
+    ) -> Result<()>;
+}
+
+pub(crate) struct DcsBackend<'a, 'lua> {
+    lua: MizLua<'lua>,
+    land: Land<'lua>,
+    landcache: &'a mut LandCache,
+}
+
+impl<'a, 'lua> DcsBackend<'a, 'lua> {
+    pub(crate) fn new(lua: MizLua<'lua>, landcache: &'a mut LandCache) -> Result<Self> {
+        Ok(Self {
+            lua,
+            land: Land::singleton(lua)?,
+            landcache,
+        })
+    }
+
+    fn airbase(&self, airbase: Option<&DcsOid<ClassAirbase>>) -> Result<Airbase<'lua>> {
+        let id = airbase.ok_or_else(|| anyhow!("objective has no airbase"))?;
+        Airbase::get_instance(self.lua, id).context("getting airbase")
+    }
+}
+
+impl<'a, 'lua> Backend for DcsBackend<'a, 'lua> {
+    fn is_visible(&mut self, dist: f64, p0: Vector3, p1: Vector3) -> Result<bool> {
+        self.landcache.is_visible(&self.land, dist, p0, p1)
+    }
+
+    fn set_ai_enabled(&mut self, group: &DcsOid<ClassGroup>, enabled: bool) -> Result<()> {
+        Group::get_instance(self.lua, group)
+            .context("getting group")?
+            .get_controller()
+            .context("get controller")?
+            .set_on_off(enabled)
+            .context("enable/disable ai")
+    }
+
+    fn set_airbase_coalition(
+        &mut self,
+        airbase: Option<&DcsOid<ClassAirbase>>,
+        side: Side,
+    ) -> Result<()> {
+        self.airbase(airbase)?
+            .set_coalition(side)
+            .context("setting airbase coalition")
+    }
+
+    fn sync_warehouse_to_objective(
+        &mut self,
+        airbase: Option<&DcsOid<ClassAirbase>>,
+        obj: &mut Objective,
+    ) -> Result<()> {
+        let warehouse = self
+            .airbase(airbase)?
+            .get_warehouse()
+            .context("getting warehouse")?;
+        sync_warehouse_to_obj(obj, &warehouse)
+    }
+
+    fn sync_objective_to_warehouse(
+        &mut self,
+        airbase: Option<&DcsOid<ClassAirbase>>,
+        obj: &Objective,
+    ) -> Result<()> {
+        let warehouse = self
+            .airbase(airbase)?
+            .get_warehouse()
+            .context("getting warehouse")?;
+        sync_obj_to_warehouse(obj, &warehouse)
+    }
+}
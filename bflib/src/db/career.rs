@@ -0,0 +1,192 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::{Db, Map};
+use crate::cfg::Vehicle;
+use chrono::prelude::*;
+use compact_str::{format_compact, CompactString};
+use dcso3::{net::Ucid, object::DcsOid, unit::ClassUnit, String};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Kills {
+    pub total: u32,
+    pub by_weapon: Map<String, u32>,
+    pub by_victim: Map<String, u32>,
+}
+
+impl Kills {
+    fn add(&mut self, weapon: Option<&String>, victim: Option<&String>) {
+        self.total += 1;
+        if let Some(weapon) = weapon {
+            *self.by_weapon.get_or_default_cow(weapon.clone()) += 1;
+        }
+        if let Some(victim) = victim {
+            *self.by_victim.get_or_default_cow(victim.clone()) += 1;
+        }
+    }
+}
+
+/// A player's statistics over every round they have played. Careers
+/// are kept apart from the player record so that they carry over
+/// into the next round.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Career {
+    pub sorties: u32,
+    pub takeoffs: u32,
+    pub landings: u32,
+    pub deaths: u32,
+    pub ejections: u32,
+    pub air_kills: Kills,
+    pub ground_kills: Kills,
+    pub crates_delivered: u32,
+    pub troops_deployed: u32,
+    pub captures: u32,
+    pub repairs: u32,
+    /// seconds in the air by airframe
+    pub time_in_air: Map<Vehicle, u64>,
+}
+
+fn top(m: &Map<String, u32>, n: usize) -> CompactString {
+    let mut v = m.into_iter().collect::<Vec<_>>();
+    v.sort_by(|(_, c0), (_, c1)| c1.cmp(c0));
+    let mut res = CompactString::new("");
+    for (i, (name, count)) in v.into_iter().take(n).enumerate() {
+        if i > 0 {
+            res.push_str(", ");
+        }
+        let _ = write!(res, "{name} {count}");
+    }
+    res
+}
+
+impl Career {
+    /// a short summary suitable for the chat window
+    pub fn summary(&self) -> Vec<CompactString> {
+        let flight_time = self.time_in_air.into_iter().fold(0, |acc, (_, s)| acc + s);
+        let mut msgs = vec![
+            format_compact!(
+                "sorties {}, takeoffs {}, landings {}, deaths {}, ejections {}",
+                self.sorties,
+                self.takeoffs,
+                self.landings,
+                self.deaths,
+                self.ejections
+            ),
+            format_compact!(
+                "air kills {}, ground kills {}",
+                self.air_kills.total,
+                self.ground_kills.total
+            ),
+        ];
+        for (name, kills) in [("air", &self.air_kills), ("ground", &self.ground_kills)] {
+            if kills.total > 0 {
+                msgs.push(format_compact!(
+                    "{name} kills by weapon: {}",
+                    top(&kills.by_weapon, 3)
+                ));
+                msgs.push(format_compact!(
+                    "{name} kills by type: {}",
+                    top(&kills.by_victim, 3)
+                ));
+            }
+        }
+        msgs.push(format_compact!(
+            "crates delivered {}, troops deployed {}, captures {}, repairs {}",
+            self.crates_delivered,
+            self.troops_deployed,
+            self.captures,
+            self.repairs
+        ));
+        msgs.push(format_compact!(
+            "time in air {}h {}m",
+            flight_time / 3600,
+            (flight_time % 3600) / 60
+        ));
+        for (typ, secs) in &self.time_in_air {
+            msgs.push(format_compact!(
+                "  {} {}h {}m",
+                typ.0,
+                secs / 3600,
+                (secs % 3600) / 60
+            ));
+        }
+        msgs
+    }
+}
+
+impl Db {
+    pub fn career(&self, ucid: &Ucid) -> Option<&Career> {
+        self.persisted.careers.get(ucid)
+    }
+
+    pub(super) fn career_mut(&mut self, ucid: &Ucid) -> &mut Career {
+        self.ephemeral.dirty();
+        self.persisted.careers.get_or_default_cow(*ucid)
+    }
+
+    pub(super) fn record_kill(
+        &mut self,
+        ucid: &Ucid,
+        air: bool,
+        weapon: Option<&String>,
+        victim: Option<&String>,
+    ) {
+        let career = self.career_mut(ucid);
+        if air {
+            career.air_kills.add(weapon, victim)
+        } else {
+            career.ground_kills.add(weapon, victim)
+        }
+    }
+
+    /// add the time since the player took off to their time in air,
+    /// returns true if they were flying
+    pub(super) fn end_flight(&mut self, ucid: &Ucid, now: DateTime<Utc>) -> bool {
+        let inst = self
+            .persisted
+            .players
+            .get_mut_cow(ucid)
+            .and_then(|p| p.current_slot.as_mut())
+            .and_then(|(_, inst)| inst.as_mut());
+        if let Some(inst) = inst {
+            if let Some(since) = inst.took_off.take() {
+                let secs = (now - since).num_seconds().max(0) as u64;
+                let typ = inst.typ.clone();
+                *self.career_mut(ucid).time_in_air.get_or_default_cow(typ) += secs;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The player flying unit id was lost, either by dying or by
+    /// ejecting. Only the first loss of a unit is counted.
+    pub fn player_lost(&mut self, id: &DcsOid<ClassUnit>, ejected: bool, now: DateTime<Utc>) {
+        if let Some(ucid) = self.ephemeral.player_in_unit(id).copied() {
+            self.end_flight(&ucid, now);
+            let career = self.career_mut(&ucid);
+            if ejected {
+                career.ejections += 1
+            } else {
+                career.deaths += 1
+            }
+        }
+    }
+}
@@ -15,6 +15,7 @@ for more details.
 */
 
 use super::{
+    csar::Pilot,
     ephemeral::DeployableIndex,
     group::{GroupId, SpawnedGroup},
     objective::{Objective, ObjectiveId, ObjectiveKind},
@@ -25,6 +26,7 @@ use crate::{
     db::group::DeployKind,
     group, maybe, objective,
     spawnctx::{SpawnCtx, SpawnLoc},
+    stats::StatKind,
     unit, unit_mut,
 };
 use anyhow::{anyhow, bail, Result};
@@ -93,6 +95,8 @@ impl fmt::Display for Unpakistan {
 pub struct Cargo {
     pub troops: SmallVec<[(Ucid, Option<ObjectiveId>, Troop); 1]>,
     pub crates: SmallVec<[(ObjectiveId, Crate); 1]>,
+    #[serde(default)]
+    pub pilots: SmallVec<[Pilot; 1]>,
 }
 
 impl Cargo {
@@ -100,12 +104,16 @@ impl Cargo {
         self.troops.len()
     }
 
+    pub fn num_pilots(&self) -> usize {
+        self.pilots.len()
+    }
+
     pub fn num_crates(&self) -> usize {
         self.crates.len()
     }
 
     pub fn num_total(&self) -> usize {
-        self.num_crates() + self.num_troops()
+        self.num_crates() + self.num_troops() + self.num_pilots()
     }
 
     pub fn weight(&self) -> i64 {
@@ -113,9 +121,13 @@ impl Cargo {
             .crates
             .iter()
             .fold(0, |acc, (_, cr)| acc + cr.weight as i64);
-        self.troops
+        let tr = self
+            .troops
             .iter()
-            .fold(cr, |acc, (_, _, tr)| acc + tr.weight as i64)
+            .fold(cr, |acc, (_, _, tr)| acc + tr.weight as i64);
+        self.pilots
+            .iter()
+            .fold(tr, |acc, pilot| acc + pilot.weight as i64)
     }
 }
 
@@ -298,7 +310,10 @@ impl Db {
                 DeployKind::Deployed { .. }
                 | DeployKind::Troop { .. }
                 | DeployKind::Objective
-                | DeployKind::Action { .. } => {
+                | DeployKind::Action { .. }
+                | DeployKind::Pilot { .. }
+                | DeployKind::Convoy { .. }
+                | DeployKind::Offensive { .. } => {
                     bail!("group {:?} is listed in crates but isn't a crate", gid)
                 }
             };
@@ -586,7 +601,10 @@ impl Db {
                             | DeployKind::Crate { .. }
                             | DeployKind::Objective
                             | DeployKind::Troop { .. }
-                            | DeployKind::Action { .. } => (),
+                            | DeployKind::Action { .. }
+                            | DeployKind::Pilot { .. }
+                            | DeployKind::Convoy { .. }
+                            | DeployKind::Offensive { .. } => (),
                         }
                     }
                     if let Some(gid) = group_to_repair {
@@ -621,6 +639,11 @@ impl Db {
             logistics: bool,
             iter: F,
         ) -> bool {
+            if let Some(front) = db.ephemeral.front_line.as_ref() {
+                if logistics && front.side_at(centroid) != side {
+                    return true;
+                }
+            }
             let excl_dist_sq = (db.ephemeral.cfg.logistics_exclusion as f64).powi(2);
             db.persisted.objectives.into_iter().any(|(oid, obj)| {
                 let mut check = false;
@@ -769,9 +792,21 @@ impl Db {
                 } else {
                     self.repair_one_logi_step(st.side, Utc::now(), oid)?;
                     self.delete_group(base_repairs.keys().next().unwrap())?;
-                    if let Some(amount) = self.ephemeral.cfg.points.map(|p| p.logistics_repair) {
-                        self.adjust_points(&st.ucid, amount as i32, "for logistics repair");
-                    }
+                    let amount = self
+                        .ephemeral
+                        .cfg
+                        .points
+                        .map(|p| p.logistics_repair)
+                        .unwrap_or(0);
+                    self.adjust_points(&st.ucid, amount as i32, "for logistics repair");
+                    let career = self.career_mut(&st.ucid);
+                    career.repairs += 1;
+                    career.crates_delivered += 1;
+                    self.ephemeral.stat(StatKind::Repair {
+                        id: oid,
+                        ucid: st.ucid,
+                        points: amount as usize,
+                    });
                     let obj = objective!(self, oid)?;
                     return Ok(Unpakistan::RepairedBase(obj.name.clone(), obj.logi()));
                 }
@@ -794,6 +829,7 @@ impl Db {
                 {
                     self.transfer_supplies(lua, from, to)?;
                     self.delete_group(&gid)?;
+                    self.career_mut(&st.ucid).crates_delivered += 1;
                     if let Some(amount) = self.ephemeral.cfg.points.map(|p| p.logistics_transfer) {
                         self.adjust_points(&st.ucid, amount as i32, "for supply transfer");
                     }
@@ -828,12 +864,19 @@ impl Db {
                         Err(e) => reasons.push(format_compact!("{e}")),
                         Ok(()) => match &spec.logistics {
                             Some(parts) => {
+                                let mut n = 0;
                                 for cr in have.values().flat_map(|c| c.iter()) {
-                                    self.delete_group(&cr.group)?
+                                    self.delete_group(&cr.group)?;
+                                    n += 1;
                                 }
+                                self.career_mut(&st.ucid).crates_delivered += n;
                                 let oid =
                                     self.add_farp(&spctx, idx, st.side, centroid, &spec, parts)?;
                                 self.adjust_points(&st.ucid, -(spec.cost as i32), "for farp spawn");
+                                self.ephemeral.stat(StatKind::Deploy {
+                                    ucid: st.ucid,
+                                    deployable: spec.clone(),
+                                });
                                 let name = objective!(self, oid)?.name.clone();
                                 return Ok(Unpakistan::UnpackedFarp(name));
                             }
@@ -856,14 +899,21 @@ impl Db {
                                     BitFlags::empty(),
                                     None,
                                 )?;
+                                let mut n = 0;
                                 for cr in have.values().flat_map(|c| c.iter()) {
-                                    self.delete_group(&cr.group)?
+                                    self.delete_group(&cr.group)?;
+                                    n += 1;
                                 }
+                                self.career_mut(&st.ucid).crates_delivered += n;
                                 self.adjust_points(
                                     &st.ucid,
                                     -(spec.cost as i32),
                                     &format_compact!("for {dep} unpack"),
                                 );
+                                self.ephemeral.stat(StatKind::Deploy {
+                                    ucid: st.ucid,
+                                    deployable: spec.clone(),
+                                });
                                 return Ok(Unpakistan::Unpacked(dep));
                             }
                         },
@@ -887,6 +937,9 @@ impl Db {
                     for cr in &have {
                         self.delete_group(&cr.group)?
                     }
+                    let career = self.career_mut(&st.ucid);
+                    career.repairs += 1;
+                    career.crates_delivered += have.len() as u32;
                     self.ephemeral.push_spawn(gid);
                     self.ephemeral.dirty();
                     return Ok(Unpakistan::Repaired(dep));
@@ -1155,6 +1208,11 @@ impl Db {
                 .push((ucid, origin, troop_cfg));
             return Err(e);
         }
+        self.career_mut(&ucid).troops_deployed += 1;
+        self.ephemeral.stat(StatKind::Troop {
+            ucid,
+            troop: troop_cfg.clone(),
+        });
         Ok(troop_cfg)
     }
 
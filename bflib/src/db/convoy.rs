@@ -0,0 +1,427 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Supply convoys. When enabled, logistics transfers between distant
+//! objectives are loaded onto a ground convoy that drives the road
+//! network from the supplier to the destination. The cargo is only
+//! delivered if the convoy arrives, and every truck that is destroyed
+//! on the way takes it's share of the cargo with it.
+
+use super::{
+    group::{DeployKind, GroupId},
+    logistics::{Transfer, TransferItem},
+    objective::ObjectiveId,
+    Db, Map,
+};
+use crate::{
+    cfg::UnitTag,
+    group, objective, objective_mut,
+    perf::PerfInner,
+    spawnctx::{SpawnCtx, SpawnLoc},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{prelude::*, Duration};
+use compact_str::format_compact;
+use dcso3::{
+    azumith2d_to,
+    controller::{ActionTyp, AltType, MissionPoint, PointType, Task, VehicleFormation},
+    env::miz::MizIndex,
+    land::{Land, RoadType},
+    warehouse::LiquidType,
+    LuaVec2, MizLua, String, Vector2,
+};
+use log::{error, warn};
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+/// Waypoints closer together than this are merged (Meters)
+const WAYPOINT_SPACING: f64 = 2000.;
+
+/// What a convoy carries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConvoyCargo {
+    pub equipment: Map<String, u32>,
+    pub liquids: Map<LiquidType, u32>,
+}
+
+impl ConvoyCargo {
+    fn add(&mut self, item: &TransferItem, amount: u32) {
+        match item {
+            TransferItem::Equipment(name) => {
+                *self.equipment.get_or_default_cow(name.clone()) += amount
+            }
+            TransferItem::Liquid(name) => *self.liquids.get_or_default_cow(*name) += amount,
+        }
+    }
+
+    fn items(&self) -> impl Iterator<Item = (TransferItem, u32)> + '_ {
+        self.equipment
+            .into_iter()
+            .map(|(name, n)| (TransferItem::Equipment(name.clone()), *n))
+            .chain(
+                self.liquids
+                    .into_iter()
+                    .map(|(name, n)| (TransferItem::Liquid(*name), *n)),
+            )
+    }
+
+    pub fn total(&self) -> u32 {
+        self.items().map(|(_, n)| n).sum()
+    }
+}
+
+impl Db {
+    /// queue a transfer to go by road, transfers between the same
+    /// objectives share a convoy. The cargo has already been taken
+    /// from the source, so it is saved until the convoy is spawned.
+    pub(super) fn load_convoy(&mut self, tr: &Transfer) {
+        self.persisted
+            .pending_convoys
+            .get_or_default_cow(tr.source)
+            .get_or_default_cow(tr.target)
+            .add(&tr.item, tr.amount);
+        self.ephemeral.dirty();
+    }
+
+    /// true if a transfer between the two objectives should go by road
+    pub(super) fn convoy_wanted(&self, from: &ObjectiveId, to: &ObjectiveId) -> Result<bool> {
+        let min_distance = match &self.ephemeral.cfg.convoy {
+            Some(cfg) => cfg.min_distance as f64,
+            None => return Ok(false),
+        };
+        let from = objective!(self, from)?.zone.pos();
+        let to = objective!(self, to)?.zone.pos();
+        Ok(na::distance_squared(&from.into(), &to.into()) >= min_distance.powi(2))
+    }
+
+    /// true if a convoy between the two objectives is already loaded
+    pub(super) fn convoy_on_road(&self, from: &ObjectiveId, to: &ObjectiveId) -> bool {
+        self.persisted
+            .convoys
+            .into_iter()
+            .filter_map(|gid| self.persisted.groups.get(gid))
+            .any(|g| match &g.origin {
+                DeployKind::Convoy { from: f, to: t, .. } => f == from && t == to,
+                _ => false,
+            })
+    }
+
+    /// Take the convoys loaded by the last logistics tick. Used by the
+    /// simulator, which spawns them itself.
+    pub fn take_pending_convoys(&mut self) -> Vec<(ObjectiveId, ObjectiveId, ConvoyCargo)> {
+        let pending = std::mem::take(&mut self.persisted.pending_convoys);
+        if pending.len() > 0 {
+            self.ephemeral.dirty();
+        }
+        pending
+            .into_iter()
+            .flat_map(|(from, to)| {
+                to.into_iter()
+                    .map(|(to, cargo)| (*from, *to, cargo.clone()))
+            })
+            .collect()
+    }
+
+    fn deliver_cargo(&mut self, oid: &ObjectiveId, cargo: &ConvoyCargo) -> Result<()> {
+        let obj = objective_mut!(self, oid)?;
+        for (item, n) in cargo.items() {
+            item.credit(obj, n)
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    fn convoy_speed(&self) -> Result<f64> {
+        self.ephemeral
+            .cfg
+            .convoy
+            .as_ref()
+            .map(|c| c.speed)
+            .ok_or_else(|| anyhow!("convoys are not enabled"))
+    }
+
+    /// a route along the road network from pos to the objective
+    pub(super) fn road_mission<'lua>(
+        &self,
+        lua: MizLua<'lua>,
+        pos: Vector2,
+        to: &ObjectiveId,
+        speed: f64,
+    ) -> Result<Vec<MissionPoint<'lua>>> {
+        let dest = objective!(self, to)?.zone.pos();
+        let land = Land::singleton(lua)?;
+        let path = land.find_path_on_roads(RoadType::Road, LuaVec2(pos), LuaVec2(dest))?;
+        let mut points: Vec<Vector2> = vec![];
+        for p in path {
+            let p = p?.0;
+            match points.last() {
+                Some(last) if na::distance(&(*last).into(), &p.into()) < WAYPOINT_SPACING => (),
+                Some(_) | None => points.push(p),
+            }
+        }
+        if points.len() < 2 {
+            bail!("no road to {}", objective!(self, to)?.name)
+        }
+        points.push(dest);
+        // leave the road at the last road point and drive straight
+        // into the objective
+        let leave_road = points.len() - 2;
+        points
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let formation = if i < leave_road {
+                    VehicleFormation::OnRoad
+                } else {
+                    VehicleFormation::OffRoad
+                };
+                Ok(MissionPoint {
+                    action: Some(ActionTyp::Ground(formation)),
+                    airdrome_id: None,
+                    helipad: None,
+                    typ: PointType::TurningPoint,
+                    link_unit: None,
+                    pos: LuaVec2(p),
+                    alt: land.get_height(LuaVec2(p))?,
+                    alt_typ: Some(AltType::BARO),
+                    time_re_fu_ar: None,
+                    eta: None,
+                    eta_locked: None,
+                    speed,
+                    speed_locked: Some(true),
+                    name: None,
+                    task: Box::new(Task::ComboTask(vec![])),
+                })
+            })
+            .collect()
+    }
+
+    fn spawn_convoy(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        (from, to, cargo): &(ObjectiveId, ObjectiveId, ConvoyCargo),
+        now: DateTime<Utc>,
+    ) -> Result<GroupId> {
+        let (from, to) = (*from, *to);
+        let obj = objective!(self, from)?;
+        let side = obj.owner;
+        let template = self
+            .ephemeral
+            .cfg
+            .convoy
+            .as_ref()
+            .and_then(|c| c.template.get(&side))
+            .ok_or_else(|| anyhow!("no convoy template for {side}"))?
+            .clone();
+        let speed = self.convoy_speed()?;
+        let mission = self.road_mission(spctx.lua(), obj.zone.pos(), &to, speed)?;
+        let (p0, p1) = (mission[0].pos.0, mission[1].pos.0);
+        let location = SpawnLoc::AtPos {
+            pos: p0,
+            offset_direction: (p0 - p1).normalize(),
+            group_heading: azumith2d_to(p0, p1),
+        };
+        let origin = DeployKind::Convoy {
+            from,
+            to,
+            cargo: cargo.clone(),
+            time: now,
+        };
+        let gid = self.add_group(
+            spctx,
+            idx,
+            side,
+            location,
+            &template,
+            origin,
+            UnitTag::Driveable.into(),
+        )?;
+        self.ephemeral
+            .spawn_group(
+                perf,
+                &self.persisted,
+                idx,
+                spctx,
+                group!(self, gid)?,
+                mission,
+            )
+            .context("spawning convoy")?;
+        let msg = format_compact!(
+            "supply convoy {gid} is on the road from {} to {}",
+            objective!(self, from)?.name,
+            objective!(self, to)?.name
+        );
+        self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+        Ok(gid)
+    }
+
+    /// Put the convoys loaded by the last logistics tick on the
+    /// road. If a convoy can't be spawned, e.g. because there is no
+    /// road to the destination, it's cargo is delivered directly.
+    pub fn spawn_convoys(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        for convoy in self.take_pending_convoys() {
+            if let Err(e) = self.spawn_convoy(perf, spctx, idx, &convoy, now) {
+                let (from, to, cargo) = convoy;
+                warn!("delivering supplies from {from} to {to} directly, {e:?}");
+                if let Err(e) = self.deliver_cargo(&to, &cargo) {
+                    error!("failed to deliver supplies from {from} to {to}, {e:?}")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// respawn a convoy after a restart and send it on from where it stopped
+    pub(super) fn respawn_convoy(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        gid: GroupId,
+    ) -> Result<()> {
+        let to = match &group!(self, gid)?.origin {
+            DeployKind::Convoy { to, .. } => *to,
+            _ => bail!("{gid} is not a convoy"),
+        };
+        if self.ephemeral.cfg.convoy.is_none() {
+            // check_convoys will deliver it
+            return Ok(());
+        }
+        let speed = self.convoy_speed()?;
+        self.respawn_on_road(perf, spctx, idx, gid, &to, speed)
+    }
+
+    /// respawn a group that was driving to an objective and send it
+    /// on from where it stopped
+    pub(super) fn respawn_on_road(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        gid: GroupId,
+        to: &ObjectiveId,
+        speed: f64,
+    ) -> Result<()> {
+        let pos = self.group_center(&gid)?;
+        let mission = match self.road_mission(spctx.lua(), pos, to, speed) {
+            Ok(mission) => mission,
+            Err(e) => {
+                warn!("group {gid} can't continue on the road, {e:?}");
+                vec![]
+            }
+        };
+        self.ephemeral.spawn_group(
+            perf,
+            &self.persisted,
+            idx,
+            spctx,
+            group!(self, gid)?,
+            mission,
+        )?;
+        Ok(())
+    }
+
+    /// Deliver the cargo of every convoy that has reached it's
+    /// destination in proportion to the trucks that survived. A
+    /// convoy that hasn't arrived after max_age, or whose destination
+    /// has been captured by the enemy, is lost. If convoys have been
+    /// turned off every convoy on the road is delivered as if it had
+    /// arrived.
+    pub fn check_convoys(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let max_age = self
+            .ephemeral
+            .cfg
+            .convoy
+            .as_ref()
+            .map(|cfg| Duration::minutes(cfg.max_age as i64));
+        let convoys: SmallVec<[GroupId; 16]> =
+            self.persisted.convoys.into_iter().copied().collect();
+        for gid in convoys {
+            let group = group!(self, gid)?;
+            let (from, to, cargo, time) = match &group.origin {
+                DeployKind::Convoy {
+                    from,
+                    to,
+                    cargo,
+                    time,
+                } => (*from, *to, cargo.clone(), *time),
+                _ => continue,
+            };
+            let side = group.side;
+            let dst = objective!(self, to)?;
+            let names = format_compact!(
+                "supply convoy {gid} from {} to {}",
+                objective!(self, from)?.name,
+                dst.name
+            );
+            if dst.owner != side {
+                let msg = format_compact!("{names} was lost, it's destination has fallen");
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+                self.delete_group(&gid)?;
+                continue;
+            }
+            let arrived = group
+                .units
+                .into_iter()
+                .filter_map(|uid| self.persisted.units.get(uid))
+                .any(|u| !u.dead && dst.zone.contains(u.pos));
+            match max_age {
+                None => (),
+                Some(_) if arrived => (),
+                Some(max_age) if now - time < max_age => continue,
+                Some(_) => {
+                    let msg = format_compact!("{names} was lost, it never arrived");
+                    self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+                    self.delete_group(&gid)?;
+                    continue;
+                }
+            }
+            let (alive, total) = self.group_health(&gid)?;
+            let share = |n: u32| ((n as u64 * alive as u64) / total.max(1) as u64) as u32;
+            let delivered = ConvoyCargo {
+                equipment: cargo
+                    .equipment
+                    .into_iter()
+                    .map(|(name, n)| (name.clone(), share(*n)))
+                    .collect(),
+                liquids: cargo
+                    .liquids
+                    .into_iter()
+                    .map(|(name, n)| (*name, share(*n)))
+                    .collect(),
+            };
+            let msg = format_compact!(
+                "{names} delivered {} of {} supplies",
+                delivered.total(),
+                cargo.total()
+            );
+            self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            if let Err(e) = self.deliver_cargo(&to, &delivered) {
+                error!("failed to deliver cargo of convoy {gid} {e:?}")
+            }
+            self.delete_group(&gid)?
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Combat search and rescue. A player who ejects waits in the field
+//! as a downed pilot group. A helicopter of either side can pick them
+//! up as troop cargo. Bringing a friendly pilot to a friendly
+//! objective returns their life, bringing an enemy pilot back takes
+//! them prisoner, both are worth points.
+
+use super::{
+    group::{DeployKind, GroupId},
+    Db,
+};
+use crate::{
+    cfg::LifeType,
+    maybe,
+    spawnctx::{SpawnCtx, SpawnLoc},
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{prelude::*, Duration};
+use compact_str::{format_compact, CompactString};
+use dcso3::{
+    coord::{Coord, LLPos},
+    env::miz::MizIndex,
+    net::{SlotId, Ucid},
+    object::{ClassObject, DcsOid},
+    trigger::Trigger,
+    unit::ClassUnit,
+    LuaVec3, MizLua, String, Vector2, Vector3,
+};
+use enumflags2::BitFlags;
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+/// A pilot being carried by a helicopter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pilot {
+    pub player: Ucid,
+    pub life_type: Option<LifeType>,
+    pub weight: u32,
+}
+
+fn format_ll(pos: &LLPos) -> CompactString {
+    fn ddm(v: f64, pos: char, neg: char, width: usize) -> CompactString {
+        let hemi = if v < 0. { neg } else { pos };
+        let v = v.abs();
+        let deg = v.trunc();
+        let min = (v - deg) * 60.;
+        format_compact!("{hemi} {:0width$}°{:06.3}'", deg as u32, min)
+    }
+    format_compact!(
+        "{} {}",
+        ddm(pos.latitude, 'N', 'S', 2),
+        ddm(pos.longitude, 'E', 'W', 3)
+    )
+}
+
+impl Db {
+    /// Remember which player the ejected pilot object belongs to, so
+    /// they can be put in the field when they land.
+    pub fn pilot_ejected(&mut self, unit: &DcsOid<ClassUnit>, pilot: DcsOid<ClassObject>) {
+        if self.ephemeral.cfg.csar.is_none() {
+            return;
+        }
+        if let Some(ucid) = self.ephemeral.player_in_unit(unit).copied() {
+            let life_type = self.persisted.players.get(&ucid).and_then(|p| p.airborne);
+            self.ephemeral
+                .ejected_pilots
+                .insert(pilot, (ucid, life_type));
+        }
+    }
+
+    /// The ejected pilot object has landed at pos. Spawn a downed
+    /// pilot group in it's place and tell their side where they are.
+    /// Returns None if the pilot doesn't belong to a player.
+    pub fn pilot_landed(
+        &mut self,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        pilot: &DcsOid<ClassObject>,
+        pos: Vector2,
+        now: DateTime<Utc>,
+    ) -> Result<Option<GroupId>> {
+        let (ucid, life_type) = match self.ephemeral.ejected_pilots.remove(pilot) {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        let template = match &self.ephemeral.cfg.csar {
+            Some(csar) => csar.template.clone(),
+            None => return Ok(None),
+        };
+        let player = maybe!(self.persisted.players, ucid, "player")?;
+        let name = player.name.clone();
+        let side = player.side;
+        let template = template
+            .get(&side)
+            .ok_or_else(|| anyhow!("no downed pilot template for {side}"))?;
+        let spawnpos = SpawnLoc::AtPos {
+            pos,
+            offset_direction: Vector2::new(1., 0.),
+            group_heading: 0.,
+        };
+        let dk = DeployKind::Pilot {
+            player: ucid,
+            life_type,
+            time: now,
+        };
+        let gid = self.add_and_queue_group(
+            spctx,
+            idx,
+            side,
+            spawnpos,
+            template,
+            dk,
+            BitFlags::empty(),
+            None,
+        )?;
+        let ll =
+            Coord::singleton(spctx.lua())?.lo_to_ll(LuaVec3(Vector3::new(pos.x, 0., pos.y)))?;
+        let msg = format_compact!(
+            "{name} is down at {}, pick them up and bring them home",
+            format_ll(&ll)
+        );
+        self.ephemeral.msgs().panel_to_side(20, false, side, msg);
+        Ok(Some(gid))
+    }
+
+    /// Pick up a downed pilot within loading distance of
+    /// slot. Returns the pilot's name, and whether they are friendly.
+    pub fn pickup_pilot(
+        &mut self,
+        lua: MizLua,
+        idx: &MizIndex,
+        slot: &SlotId,
+    ) -> Result<(String, bool)> {
+        let weight = match &self.ephemeral.cfg.csar {
+            Some(csar) => csar.weight,
+            None => bail!("search and rescue is not enabled"),
+        };
+        let (cargo_capacity, side, unit_name) = self.unit_cargo_cfg(lua, idx, slot)?;
+        let unit = self.ephemeral.slot_instance_unit(lua, slot)?;
+        if unit.in_air()? {
+            bail!("you must land to pick up a pilot")
+        }
+        let pos = unit.get_point()?;
+        let point = Vector2::new(pos.x, pos.z);
+        let max_dist = (self.ephemeral.cfg.crate_load_distance as f64).powi(2);
+        let (gid, player, life_type) = self
+            .persisted
+            .pilots
+            .into_iter()
+            .filter_map(|gid| self.persisted.groups.get(gid).map(|g| (*gid, g)))
+            .find_map(|(gid, g)| {
+                if let DeployKind::Pilot {
+                    player, life_type, ..
+                } = &g.origin
+                {
+                    let in_range = g
+                        .units
+                        .into_iter()
+                        .filter_map(|uid| self.persisted.units.get(uid))
+                        .any(|u| na::distance_squared(&u.pos.into(), &point.into()) <= max_dist);
+                    if in_range {
+                        return Some((gid, *player, *life_type));
+                    }
+                }
+                None
+            })
+            .ok_or_else(|| anyhow!("no downed pilots in range"))?;
+        let rescuer = *self
+            .ephemeral
+            .player_in_slot(slot)
+            .ok_or_else(|| anyhow!("no player in slot {slot:?}"))?;
+        let cargo = self.ephemeral.cargo.entry(*slot).or_default();
+        // pilots are limited by the troop slots, but don't count as troops
+        if cargo_capacity.troop_slots as usize <= cargo.num_pilots()
+            || cargo_capacity.total_slots as usize <= cargo.num_total()
+        {
+            bail!("you already have a full load onboard")
+        }
+        let pilot = Pilot {
+            player,
+            life_type,
+            weight,
+        };
+        cargo.pilots.push(pilot.clone());
+        Trigger::singleton(lua)?
+            .action()?
+            .set_unit_internal_cargo(unit_name, cargo.weight())?;
+        self.persisted
+            .carried_pilots
+            .get_or_default_cow(rescuer)
+            .push(pilot);
+        self.ephemeral.dirty();
+        self.delete_group(&gid)?;
+        let pilot = maybe!(self.persisted.players, player, "player")?;
+        Ok((pilot.name.clone(), pilot.side == side))
+    }
+
+    /// give back a life taken from a pilot who was rescued
+    pub(super) fn return_pilot_life(&mut self, ucid: &Ucid, life_type: LifeType) -> bool {
+        let max = match self.ephemeral.cfg.default_lives.get(&life_type) {
+            Some((n, _)) => *n,
+            None => return false,
+        };
+        let player = match self.persisted.players.get_mut_cow(ucid) {
+            Some(player) => player,
+            None => return false,
+        };
+        match player.lives.get_mut_cow(&life_type) {
+            None => false,
+            Some((_, n)) => {
+                *n += 1;
+                if *n >= max {
+                    player.lives.remove_cow(&life_type);
+                }
+                self.ephemeral.dirty();
+                true
+            }
+        }
+    }
+
+    /// If slot has landed at a friendly objective then hand over any
+    /// pilots it is carrying. Friendly pilots get their life back,
+    /// enemy pilots are taken prisoner.
+    pub fn deliver_pilots(&mut self, lua: MizLua, slot: &SlotId, pos: Vector2) -> Result<()> {
+        if self
+            .ephemeral
+            .cargo
+            .get(slot)
+            .map(|c| c.pilots.is_empty())
+            .unwrap_or(true)
+        {
+            return Ok(());
+        }
+        let ucid = *self
+            .ephemeral
+            .player_in_slot(slot)
+            .ok_or_else(|| anyhow!("no player in slot {slot:?}"))?;
+        let rescuer = maybe!(self.persisted.players, ucid, "player")?;
+        let (rescuer_name, side) = (rescuer.name.clone(), rescuer.side);
+        let at_home = self
+            .persisted
+            .objectives
+            .into_iter()
+            .any(|(_, obj)| obj.owner == side && obj.zone.contains(pos));
+        if !at_home {
+            return Ok(());
+        }
+        let (rescue_points, capture_points) = match &self.ephemeral.cfg.csar {
+            Some(csar) => (csar.rescue_points, csar.capture_points),
+            None => (0, 0),
+        };
+        let pilots: SmallVec<[Pilot; 2]> = match self.ephemeral.cargo.get_mut(slot) {
+            Some(cargo) => cargo.pilots.drain(..).collect(),
+            None => SmallVec::new(),
+        };
+        self.persisted.carried_pilots.remove_cow(&ucid);
+        self.ephemeral.dirty();
+        for pilot in pilots {
+            let (name, pilot_side) = match self.persisted.players.get(&pilot.player) {
+                Some(p) => (p.name.clone(), p.side),
+                None => continue,
+            };
+            if pilot_side == side {
+                let msg = format_compact!("{rescuer_name} rescued {name}");
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+                let returned = pilot
+                    .life_type
+                    .map(|lt| self.return_pilot_life(&pilot.player, lt))
+                    .unwrap_or(false);
+                if returned {
+                    let msg = format_compact!("{rescuer_name} brought you home, life returned");
+                    self.ephemeral
+                        .panel_to_player(&self.persisted, 10, &pilot.player, msg);
+                }
+                self.adjust_points(
+                    &ucid,
+                    rescue_points as i32,
+                    &format_compact!("for rescuing {name}"),
+                );
+            } else {
+                let msg = format_compact!("{rescuer_name} took enemy pilot {name} prisoner");
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+                let msg = format_compact!("{name} was taken prisoner by {rescuer_name}");
+                self.ephemeral
+                    .msgs()
+                    .panel_to_side(10, false, pilot_side, msg);
+                self.adjust_points(
+                    &ucid,
+                    capture_points as i32,
+                    &format_compact!("for capturing {name}"),
+                );
+            }
+        }
+        let unit = self.ephemeral.slot_instance_unit(lua, slot)?;
+        let weight = self
+            .ephemeral
+            .cargo
+            .get(slot)
+            .map(|c| c.weight())
+            .unwrap_or(0);
+        Trigger::singleton(lua)?
+            .action()?
+            .set_unit_internal_cargo(unit.get_name()?, weight)?;
+        Ok(())
+    }
+
+    /// Remove downed pilots who have waited longer than the csar
+    /// lifetime to be picked up
+    pub fn expire_pilots(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let lifetime = self
+            .ephemeral
+            .cfg
+            .csar
+            .as_ref()
+            .map(|csar| csar.lifetime)
+            .unwrap_or(0);
+        let lifetime = Duration::seconds(lifetime as i64);
+        let expired: SmallVec<[(GroupId, Ucid); 4]> = self
+            .persisted
+            .pilots
+            .into_iter()
+            .filter_map(
+                |gid| match self.persisted.groups.get(gid).map(|g| &g.origin) {
+                    Some(DeployKind::Pilot { player, time, .. }) if now - *time >= lifetime => {
+                        Some((*gid, *player))
+                    }
+                    _ => None,
+                },
+            )
+            .collect();
+        for (gid, ucid) in expired {
+            self.delete_group(&gid)?;
+            if let Some(player) = self.persisted.players.get(&ucid) {
+                let side = player.side;
+                let msg = format_compact!("{} was not rescued in time", player.name);
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            }
+        }
+        Ok(())
+    }
+}
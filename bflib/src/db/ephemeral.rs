@@ -16,20 +16,24 @@ for more details.
 
 use super::{
     cargo::Cargo,
+    frontline::FrontLine,
     group::{GroupId, SpawnedGroup, SpawnedUnit, UnitId},
-    markup::ObjectiveMarkup,
+    markup::{FrontLineMarkup, ObjectiveMarkup},
     objective::{Objective, ObjectiveId},
     persisted::Persisted,
 };
 use crate::{
+    acmi::{AcmiKey, Record},
+    audit::AuditEntry,
     cfg::{
         ActionKind, AiPlaneCfg, AwacsCfg, BomberCfg, Cfg, Crate, Deployable, DeployableCfg,
-        DeployableLogistics, DroneCfg, Troop, UnitTag, Vehicle, WarehouseConfig,
+        DeployableLogistics, DroneCfg, LifeType, Troop, UnitTag, Vehicle, WarehouseConfig,
     },
     maybe,
     msgq::MsgQ,
     perf::{record_perf, PerfInner},
     spawnctx::{Despawn, SpawnCtx, Spawned},
+    stats::{Stat, StatKind},
 };
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::prelude::*;
@@ -42,7 +46,7 @@ use dcso3::{
     env::miz::{self, GroupKind, Miz, MizIndex},
     group::ClassGroup,
     net::{SlotId, Ucid},
-    object::{DcsObject, DcsOid},
+    object::{ClassObject, DcsObject, DcsOid},
     static_object::ClassStatic,
     trigger::MarkId,
     unit::{ClassUnit, Unit},
@@ -121,6 +125,8 @@ pub struct Ephemeral {
     pub(super) deployable_idx: FxHashMap<Side, Arc<DeployableIndex>>,
     pub(super) group_marks: FxHashMap<GroupId, MarkId>,
     objective_markup: FxHashMap<ObjectiveId, ObjectiveMarkup>,
+    pub(super) front_line: Option<FrontLine>,
+    front_line_markup: FrontLineMarkup,
     pub(super) object_id_by_uid: FxHashMap<UnitId, DcsOid<ClassUnit>>,
     pub(super) uid_by_object_id: FxHashMap<DcsOid<ClassUnit>, UnitId>,
     pub(super) object_id_by_slot: FxHashMap<SlotId, DcsOid<ClassUnit>>,
@@ -130,6 +136,9 @@ pub struct Ephemeral {
     pub(super) uid_by_static: FxHashMap<DcsOid<ClassStatic>, UnitId>,
     pub(super) airbase_by_oid: FxHashMap<ObjectiveId, DcsOid<ClassAirbase>>,
     pub(super) slot_info: FxHashMap<SlotId, SlotInfo>,
+    pub(super) ejected_pilots: FxHashMap<DcsOid<ClassObject>, (Ucid, Option<LifeType>)>,
+    pub(super) pending_offensives: Vec<(ObjectiveId, ObjectiveId, u32)>,
+    pub(super) last_offensive: FxHashMap<ObjectiveId, DateTime<Utc>>,
     used_pad_templates: FxHashSet<String>,
     force_to_spectators: BTreeMap<DateTime<Utc>, SmallVec<[Ucid; 1]>>,
     pub(super) units_able_to_move: IndexSet<UnitId, FxBuildHasher>,
@@ -144,6 +153,9 @@ pub struct Ephemeral {
     despawnq: VecDeque<(GroupId, Despawn)>,
     sync_warehouse: Vec<(ObjectiveId, Vehicle)>,
     pub(super) msgs: MsgQ,
+    stats: Vec<Stat>,
+    acmi: Vec<Record>,
+    audit: Vec<AuditEntry>,
 }
 
 impl Default for Ephemeral {
@@ -156,6 +168,8 @@ impl Default for Ephemeral {
             deployable_idx: FxHashMap::default(),
             group_marks: FxHashMap::default(),
             objective_markup: FxHashMap::default(),
+            front_line: None,
+            front_line_markup: FrontLineMarkup::default(),
             object_id_by_uid: FxHashMap::default(),
             uid_by_object_id: FxHashMap::default(),
             object_id_by_slot: FxHashMap::default(),
@@ -165,6 +179,9 @@ impl Default for Ephemeral {
             uid_by_static: FxHashMap::default(),
             airbase_by_oid: FxHashMap::default(),
             slot_info: FxHashMap::default(),
+            ejected_pilots: FxHashMap::default(),
+            pending_offensives: Vec::default(),
+            last_offensive: FxHashMap::default(),
             used_pad_templates: FxHashSet::default(),
             force_to_spectators: BTreeMap::default(),
             units_able_to_move: IndexSet::default(),
@@ -178,6 +195,9 @@ impl Default for Ephemeral {
             despawnq: VecDeque::default(),
             sync_warehouse: Vec::default(),
             msgs: MsgQ::default(),
+            stats: Vec::default(),
+            acmi: Vec::default(),
+            audit: Vec::default(),
             logistics_stage: LogiStage::default(),
         }
     }
@@ -218,6 +238,24 @@ impl Ephemeral {
         }
     }
 
+    pub(super) fn set_front_line(&mut self, front: Option<FrontLine>) {
+        // most captures don't move the line enough to change what is drawn
+        let unchanged = match (&self.front_line, &front) {
+            (None, None) => true,
+            (Some(old), Some(new)) => {
+                old.lines() == new.lines() && old.territory() == new.territory()
+            }
+            (Some(_), None) | (None, Some(_)) => false,
+        };
+        if !unchanged {
+            mem::take(&mut self.front_line_markup).remove(&mut self.msgs);
+            if let Some(front) = &front {
+                self.front_line_markup = FrontLineMarkup::new(&mut self.msgs, front);
+            }
+        }
+        self.front_line = front;
+    }
+
     pub fn push_sync_warehouse(&mut self, oid: ObjectiveId, vehicle: Vehicle) {
         self.sync_warehouse.push((oid, vehicle));
     }
@@ -251,6 +289,17 @@ impl Ephemeral {
         }
     }
 
+    /// Drop everything waiting to be spawned or despawned. Returns the
+    /// number of spawns and despawns dropped.
+    pub(super) fn clear_spawn_queues(&mut self) -> (usize, usize) {
+        let spawns = self.spawnq.len() + self.delayspawnq.values().map(|q| q.len()).sum::<usize>();
+        let despawns = self.despawnq.len();
+        self.spawnq.clear();
+        self.delayspawnq.clear();
+        self.despawnq.clear();
+        (spawns, despawns)
+    }
+
     pub fn process_spawn_queue(
         &mut self,
         perf: &mut PerfInner,
@@ -328,6 +377,34 @@ impl Ephemeral {
         &mut self.msgs
     }
 
+    pub fn stat(&mut self, kind: StatKind) {
+        self.stats.push(Stat::new(kind))
+    }
+
+    pub fn take_stats(&mut self) -> Vec<Stat> {
+        mem::take(&mut self.stats)
+    }
+
+    /// record to the acmi if recording is enabled
+    pub fn acmi(&mut self, record: Record) {
+        if self.cfg.acmi {
+            self.acmi.push(record)
+        }
+    }
+
+    pub fn take_acmi(&mut self) -> Vec<Record> {
+        mem::take(&mut self.acmi)
+    }
+
+    /// append an entry to the audit journal
+    pub fn audit(&mut self, entry: AuditEntry) {
+        self.audit.push(entry)
+    }
+
+    pub fn take_audit(&mut self) -> Vec<AuditEntry> {
+        mem::take(&mut self.audit)
+    }
+
     pub fn get_uid_by_object_id(&self, id: &DcsOid<ClassUnit>) -> Option<&UnitId> {
         self.uid_by_object_id.get(id)
     }
@@ -553,6 +630,10 @@ impl Ephemeral {
                 }
             }
             info!("deslotting player {ucid}");
+            self.acmi(Record::Remove {
+                time: Utc::now(),
+                key: AcmiKey::Player(ucid),
+            });
             if let Some(player) = per.players.get(&ucid) {
                 if !player.changing_slots && !player.jtac_or_spectators {
                     info!("queuing force player {ucid} to spectators");
@@ -589,6 +670,10 @@ impl Ephemeral {
             None => match self.uid_by_object_id.remove(id) {
                 Some(uid) => {
                     self.object_id_by_uid.remove(&uid);
+                    self.acmi(Record::Remove {
+                        time: Utc::now(),
+                        key: AcmiKey::Unit(uid),
+                    });
                     (uid, None)
                 }
                 None => {
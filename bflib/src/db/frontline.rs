@@ -0,0 +1,374 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! The front line between the sides. Each objective claims the
+//! ground around it, and the front is the boundary of the resulting
+//! additively weighted voronoi diagram, traced with marching squares
+//! over a grid covering every objective.
+
+use super::{
+    objective::{Objective, ObjectiveId, ObjectiveKind},
+    Db, Map,
+};
+use crate::cfg::FrontLineCfg;
+use dcso3::{coalition::Side, Vector2};
+use fxhash::FxHashMap;
+use log::warn;
+use smallvec::{smallvec, SmallVec};
+
+/// the grid is never more than this many cells on a side, the cell
+/// size grows instead
+const MAX_CELLS: f64 = 256.;
+
+/// the line is simplified until it can be drawn with at most this
+/// many line marks
+const MAX_LINE_MARKS: usize = 256;
+
+/// the line and the shading together never use more than this many marks
+const MAX_MARKS: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+struct Site {
+    pos: Vector2,
+    influence: f64,
+    side: Side,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrontLine {
+    sites: Vec<Site>,
+    segments: Vec<(Vector2, Vector2)>,
+    lines: Vec<Vec<Vector2>>,
+    territory: Vec<(Side, [Vector2; 4])>,
+}
+
+fn influence(cfg: &FrontLineCfg, kind: &ObjectiveKind) -> f64 {
+    let r = match kind {
+        ObjectiveKind::Airbase => cfg.airbase,
+        ObjectiveKind::Fob => cfg.fob,
+        ObjectiveKind::Farp { .. } => cfg.farp,
+        ObjectiveKind::Logistics => cfg.logistics,
+    };
+    r as f64
+}
+
+fn segment_distance(p: Vector2, (a, b): (Vector2, Vector2)) -> f64 {
+    let ab = b - a;
+    let len = ab.norm_squared();
+    let t = if len == 0. {
+        0.
+    } else {
+        ((p - a).dot(&ab) / len).clamp(0., 1.)
+    };
+    (p - (a + ab * t)).norm()
+}
+
+/// Join the marching squares segments into polylines. The two cells
+/// on either side of an edge compute the same crossing point, so
+/// segments that meet share an endpoint exactly.
+fn join(segments: &[(Vector2, Vector2)]) -> Vec<Vec<Vector2>> {
+    let key = |p: &Vector2| (p.x.to_bits(), p.y.to_bits());
+    let mut ends: FxHashMap<(u64, u64), SmallVec<[usize; 2]>> = FxHashMap::default();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        ends.entry(key(a)).or_default().push(i);
+        ends.entry(key(b)).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    // follow unused segments from the end of line, pushing the far end of each
+    let walk = |used: &mut Vec<bool>, line: &mut Vec<Vector2>| loop {
+        let p = *line.last().unwrap();
+        let next = ends[&key(&p)].iter().copied().find(|i| !used[*i]);
+        match next {
+            None => break,
+            Some(i) => {
+                used[i] = true;
+                let (a, b) = segments[i];
+                line.push(if key(&a) == key(&p) { b } else { a })
+            }
+        }
+    };
+    let mut lines = vec![];
+    for i in 0..segments.len() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let (a, b) = segments[i];
+        let mut forward = vec![a, b];
+        walk(&mut used, &mut forward);
+        let mut backward = vec![a];
+        walk(&mut used, &mut backward);
+        backward.reverse();
+        backward.pop();
+        backward.extend(forward);
+        lines.push(backward)
+    }
+    lines
+}
+
+/// Douglas Peucker simplification, every point dropped is within
+/// tolerance of the simplified line
+fn simplify(line: &[Vector2], tolerance: f64) -> Vec<Vector2> {
+    if line.len() < 3 {
+        return line.to_vec();
+    }
+    let mut keep = vec![false; line.len()];
+    keep[0] = true;
+    keep[line.len() - 1] = true;
+    let mut stack = vec![(0, line.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let seg = (line[first], line[last]);
+        let far = (first + 1..last)
+            .map(|i| (i, segment_distance(line[i], seg)))
+            .fold(None, |m: Option<(usize, f64)>, (i, d)| match m {
+                Some((_, md)) if md >= d => m,
+                _ => Some((i, d)),
+            });
+        if let Some((i, d)) = far {
+            if d > tolerance {
+                keep[i] = true;
+                stack.push((first, i));
+                stack.push((i, last));
+            }
+        }
+    }
+    line.iter()
+        .zip(keep)
+        .filter_map(|(p, keep)| keep.then_some(*p))
+        .collect()
+}
+
+fn line_marks(lines: &[Vec<Vector2>]) -> usize {
+    lines.iter().map(|l| l.len().saturating_sub(1)).sum()
+}
+
+impl FrontLine {
+    /// Compute the front from the objectives. Returns None unless
+    /// both sides own at least one objective.
+    pub fn compute(cfg: &FrontLineCfg, objectives: &Map<ObjectiveId, Objective>) -> Option<Self> {
+        let sites: Vec<Site> = objectives
+            .into_iter()
+            .filter(|(_, obj)| obj.owner != Side::Neutral)
+            .map(|(_, obj)| Site {
+                pos: obj.zone.pos(),
+                influence: influence(cfg, &obj.kind),
+                side: obj.owner,
+            })
+            .collect();
+        if !sites.iter().any(|s| s.side == Side::Red) || !sites.iter().any(|s| s.side == Side::Blue)
+        {
+            return None;
+        }
+        let margin = sites.iter().fold(0., |m: f64, s| m.max(s.influence)) + cfg.resolution as f64;
+        let (min, max) = sites.iter().fold(
+            (Vector2::repeat(f64::MAX), Vector2::repeat(f64::MIN)),
+            |(min, max), s| (min.inf(&s.pos), max.sup(&s.pos)),
+        );
+        let min = min - Vector2::repeat(margin);
+        let size = max + Vector2::repeat(margin) - min;
+        let cell = (cfg.resolution.max(1) as f64).max(size.max() / MAX_CELLS);
+        let cols = (size.x / cell).ceil() as usize;
+        let rows = (size.y / cell).ceil() as usize;
+        let mut front = FrontLine {
+            sites,
+            ..Default::default()
+        };
+        let vertex = |(i, j): (usize, usize)| min + Vector2::new(i as f64, j as f64) * cell;
+        let field: Vec<f64> = (0..=rows)
+            .flat_map(|j| (0..=cols).map(move |i| (i, j)))
+            .map(|v| front.balance(vertex(v)))
+            .collect();
+        let f = |(i, j): (usize, usize)| field[j * (cols + 1) + i];
+        for j in 0..rows {
+            for i in 0..cols {
+                // the corners going around the cell, edge k joins corner k to k + 1
+                let c = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+                let mut crossings: SmallVec<[Vector2; 4]> = smallvec![];
+                for k in 0..4 {
+                    // always interpolate an edge in the same direction so
+                    // both cells that share it get exactly the same point
+                    let (a, b) = (c[k], c[(k + 1) % 4]);
+                    let (a, b) = if (a.1, a.0) < (b.1, b.0) {
+                        (a, b)
+                    } else {
+                        (b, a)
+                    };
+                    let (fa, fb) = (f(a), f(b));
+                    if (fa > 0.) != (fb > 0.) {
+                        let t = fa / (fa - fb);
+                        crossings.push(vertex(a) + (vertex(b) - vertex(a)) * t);
+                    }
+                }
+                match crossings.len() {
+                    2 => front.segments.push((crossings[0], crossings[1])),
+                    4 => {
+                        // a saddle, the center decides which corners are connected
+                        let center = vertex(c[0]) + Vector2::repeat(cell / 2.);
+                        if (front.balance(center) > 0.) == (f(c[0]) > 0.) {
+                            front.segments.push((crossings[0], crossings[1]));
+                            front.segments.push((crossings[2], crossings[3]));
+                        } else {
+                            front.segments.push((crossings[3], crossings[0]));
+                            front.segments.push((crossings[1], crossings[2]));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let joined = join(&front.segments);
+        let mut tolerance = cell / 2.;
+        front.lines = joined.iter().map(|l| simplify(l, tolerance)).collect();
+        while line_marks(&front.lines) > MAX_LINE_MARKS && tolerance < size.max() {
+            tolerance *= 2.;
+            front.lines = joined.iter().map(|l| simplify(l, tolerance)).collect();
+        }
+        if line_marks(&front.lines) > MAX_LINE_MARKS {
+            // too many separate pieces, keep the longest ones
+            let len = |l: &Vec<Vector2>| l.windows(2).map(|w| (w[1] - w[0]).norm()).sum::<f64>();
+            front.lines.sort_by(|a, b| len(b).total_cmp(&len(a)));
+            let mut marks = 0;
+            front.lines.retain(|l| {
+                marks += l.len() - 1;
+                marks <= MAX_LINE_MARKS
+            });
+        }
+        if cfg.shade {
+            // each row of cells is split into runs of cells belonging
+            // to the same side, and runs that are the same in
+            // consecutive rows are joined into one quad
+            let mut territory = vec![];
+            let side_of_cell =
+                |i: usize, j: usize| front.side_at(vertex((i, j)) + Vector2::repeat(cell / 2.));
+            let mut quad = |(side, i0, i1, j0): (Side, usize, usize, usize), j1: usize| {
+                if side != Side::Neutral {
+                    let corners = [
+                        vertex((i0, j0)),
+                        vertex((i1, j0)),
+                        vertex((i1, j1)),
+                        vertex((i0, j1)),
+                    ];
+                    territory.push((side, corners))
+                }
+            };
+            let mut open: Vec<(Side, usize, usize, usize)> = vec![];
+            for j in 0..rows {
+                let mut runs: Vec<(Side, usize, usize, usize)> = vec![];
+                let mut start = 0;
+                let mut side = side_of_cell(0, j);
+                for i in 1..=cols {
+                    let next = if i < cols {
+                        Some(side_of_cell(i, j))
+                    } else {
+                        None
+                    };
+                    if next != Some(side) {
+                        let j0 = match open
+                            .iter()
+                            .position(|(s, i0, i1, _)| *s == side && *i0 == start && *i1 == i)
+                        {
+                            Some(n) => open.swap_remove(n).3,
+                            None => j,
+                        };
+                        runs.push((side, start, i, j0));
+                        if let Some(next) = next {
+                            start = i;
+                            side = next;
+                        }
+                    }
+                }
+                for run in open.drain(..) {
+                    quad(run, j)
+                }
+                open = runs;
+            }
+            for run in open {
+                quad(run, rows)
+            }
+            if territory.len() + line_marks(&front.lines) > MAX_MARKS {
+                warn!(
+                    "shading the front line would take {} marks, skipping it",
+                    territory.len()
+                )
+            } else {
+                front.territory = territory;
+            }
+        }
+        Some(front)
+    }
+
+    /// The weighted distance to red's closest objective minus the
+    /// weighted distance to blue's. Positive on blue's side of the
+    /// front, negative on red's.
+    fn balance(&self, pos: Vector2) -> f64 {
+        let (mut red, mut blue) = (f64::MAX, f64::MAX);
+        for site in &self.sites {
+            let d = (site.pos - pos).norm() - site.influence;
+            match site.side {
+                Side::Red => red = red.min(d),
+                Side::Blue => blue = blue.min(d),
+                Side::Neutral => (),
+            }
+        }
+        red - blue
+    }
+
+    /// The side whose territory pos is in
+    pub fn side_at(&self, pos: Vector2) -> Side {
+        let b = self.balance(pos);
+        if b > 0. {
+            Side::Blue
+        } else if b < 0. {
+            Side::Red
+        } else {
+            Side::Neutral
+        }
+    }
+
+    /// The distance from pos to the closest point on the front (Meters)
+    pub fn distance(&self, pos: Vector2) -> f64 {
+        self.segments
+            .iter()
+            .fold(f64::MAX, |d, seg| d.min(segment_distance(pos, *seg)))
+    }
+
+    /// The simplified polylines that are drawn on the map
+    pub fn lines(&self) -> &[Vec<Vector2>] {
+        &self.lines
+    }
+
+    pub fn territory(&self) -> &[(Side, [Vector2; 4])] {
+        &self.territory
+    }
+}
+
+impl Db {
+    /// Recompute the front line from the current objective owners
+    /// and redraw it
+    pub(super) fn update_front_line(&mut self) {
+        let front = self
+            .ephemeral
+            .cfg
+            .front_line
+            .as_ref()
+            .and_then(|cfg| FrontLine::compute(cfg, &self.persisted.objectives));
+        self.ephemeral.set_front_line(front)
+    }
+
+    pub fn front_line(&self) -> Option<&FrontLine> {
+        self.ephemeral.front_line.as_ref()
+    }
+}
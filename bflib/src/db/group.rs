@@ -15,11 +15,13 @@ for more details.
 */
 
 use super::{
+    convoy::ConvoyCargo,
     objective::{ObjGroupClass, ObjectiveId},
     Db, Set,
 };
 use crate::{
-    cfg::{Action, ActionKind, Crate, Deployable, Troop, UnitTag, UnitTags, Vehicle},
+    acmi::{AcmiKey, AcmiType, Props, Record},
+    cfg::{Action, ActionKind, Crate, Deployable, LifeType, Troop, UnitTag, UnitTags, Vehicle},
     group, group_by_name, group_health, group_mut,
     spawnctx::{Despawn, SpawnCtx, SpawnLoc},
     unit, unit_by_name, unit_mut, Connected,
@@ -30,6 +32,7 @@ use compact_str::{format_compact, CompactString};
 use dcso3::{
     atomic_id, azumith3d, centroid2d, centroid3d, change_heading,
     coalition::Side,
+    coord::Coord,
     env::miz::{Group, GroupKind, MizIndex},
     group::GroupCategory,
     land::{Land, SurfaceType},
@@ -86,6 +89,27 @@ pub enum DeployKind {
         #[serde(default)]
         origin: Option<ObjectiveId>,
     },
+    /// An ejected player waiting to be picked up
+    Pilot {
+        player: Ucid,
+        /// the life the player lost when they took off, if any
+        life_type: Option<LifeType>,
+        time: DateTime<Utc>,
+    },
+    /// A supply convoy on the road between two objectives
+    Convoy {
+        from: ObjectiveId,
+        to: ObjectiveId,
+        /// what the convoy carries when all it's trucks are alive
+        cargo: ConvoyCargo,
+        time: DateTime<Utc>,
+    },
+    /// An AI attack group sent from one objective against another
+    Offensive {
+        from: ObjectiveId,
+        to: ObjectiveId,
+        time: DateTime<Utc>,
+    },
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -299,6 +323,35 @@ impl Db {
                         .mark_to_side(group.side, group_center, true, msg),
                 )
             }
+            DeployKind::Pilot { player, .. } => {
+                let name = self.persisted.players[player].name.clone();
+                let msg = format_compact!("downed pilot {name} {gid}");
+                Some(
+                    self.ephemeral
+                        .msgs
+                        .mark_to_side(group.side, group_center, true, msg),
+                )
+            }
+            DeployKind::Convoy { from, to, .. } => {
+                let from = &self.persisted.objectives[from].name;
+                let to = &self.persisted.objectives[to].name;
+                let msg = format_compact!("supply convoy {gid} from {from} to {to}");
+                Some(
+                    self.ephemeral
+                        .msgs
+                        .mark_to_side(group.side, group_center, true, msg),
+                )
+            }
+            DeployKind::Offensive { from, to, .. } => {
+                let from = &self.persisted.objectives[from].name;
+                let to = &self.persisted.objectives[to].name;
+                let msg = format_compact!("attack group {gid} from {from} on {to}");
+                Some(
+                    self.ephemeral
+                        .msgs
+                        .mark_to_side(group.side, group_center, true, msg),
+                )
+            }
         };
         if let Some(id) = id {
             self.ephemeral.group_marks.insert(*gid, id);
@@ -338,6 +391,9 @@ impl Db {
                 if spec.ewr.is_some() {
                     self.persisted.ewrs.remove_cow(gid);
                 }
+                if spec.counter_battery.is_some() {
+                    self.persisted.counter_batteries.remove_cow(gid);
+                }
             }
             DeployKind::Troop { spec, .. } => {
                 self.persisted.troops.remove_cow(gid);
@@ -345,7 +401,17 @@ impl Db {
                     self.persisted.jtacs.remove_cow(gid);
                 }
             }
+            DeployKind::Pilot { .. } => {
+                self.persisted.pilots.remove_cow(gid);
+            }
+            DeployKind::Convoy { .. } => {
+                self.persisted.convoys.remove_cow(gid);
+            }
+            DeployKind::Offensive { .. } => {
+                self.persisted.offensives.remove_cow(gid);
+            }
         }
+        self.persisted.jtac_settings.remove_cow(gid);
         if let Some(id) = self.ephemeral.group_marks.remove(gid) {
             self.ephemeral.msgs.delete_mark(id);
         }
@@ -362,6 +428,10 @@ impl Db {
                 self.persisted.units_by_name.remove_cow(&unit.name);
                 units.push(unit.name);
             }
+            self.ephemeral.acmi(Record::Remove {
+                time: Utc::now(),
+                key: AcmiKey::Unit(*uid),
+            });
         }
         self.ephemeral.dirty();
         match group.kind {
@@ -684,6 +754,14 @@ impl Db {
             self.persisted.units.insert_cow(uid, spawned_unit);
             self.persisted.units_by_name.insert_cow(unit_name, uid);
         }
+        self.insert_group(spawned)
+    }
+
+    /// Add a group whose units are already in the db
+    pub(super) fn insert_group(&mut self, mut spawned: SpawnedGroup) -> Result<GroupId> {
+        let gid = spawned.id;
+        let side = spawned.side;
+        let group_name = spawned.name.clone();
         match &mut spawned.origin {
             DeployKind::Objective => (),
             DeployKind::Action { spec, .. } => {
@@ -704,6 +782,9 @@ impl Db {
                 if spec.ewr.is_some() {
                     self.persisted.ewrs.insert_cow(gid);
                 }
+                if spec.counter_battery.is_some() {
+                    self.persisted.counter_batteries.insert_cow(gid);
+                }
             }
             DeployKind::Troop { spec, .. } => {
                 self.persisted.troops.insert_cow(gid);
@@ -711,6 +792,15 @@ impl Db {
                     self.persisted.jtacs.insert_cow(gid);
                 }
             }
+            DeployKind::Pilot { .. } => {
+                self.persisted.pilots.insert_cow(gid);
+            }
+            DeployKind::Convoy { .. } => {
+                self.persisted.convoys.insert_cow(gid);
+            }
+            DeployKind::Offensive { .. } => {
+                self.persisted.offensives.insert_cow(gid);
+            }
         }
         self.persisted.groups.insert_cow(gid, spawned);
         self.persisted.groups_by_name.insert_cow(group_name, gid);
@@ -808,6 +898,11 @@ impl Db {
                 uid
             }
         };
+        self.unit_died(uid, now)
+    }
+
+    /// Update the campaign state after a unit has been killed
+    pub(super) fn unit_died(&mut self, uid: UnitId, now: DateTime<Utc>) -> Result<()> {
         match self.persisted.units.get_mut_cow(&uid) {
             None => error!("unit_dead: missing unit {:?}", uid),
             Some(unit) => {
@@ -823,6 +918,9 @@ impl Db {
                 if self.persisted.deployed.contains(&gid)
                     || self.persisted.troops.contains(&gid)
                     || self.persisted.crates.contains(&gid)
+                    || self.persisted.pilots.contains(&gid)
+                    || self.persisted.convoys.contains(&gid)
+                    || self.persisted.offensives.contains(&gid)
                 {
                     if self.group_health(&gid)?.0 == 0 {
                         match &group!(self, gid)?.origin {
@@ -842,10 +940,21 @@ impl Db {
                                 let msg = format_compact!("for the death of {gid} which was deployed by {owner} and moved by you");
                                 self.adjust_points(&ucid, p, &msg)
                             }
+                            DeployKind::Convoy { from, to, .. } => {
+                                let from = &self.persisted.objectives[from].name;
+                                let to = &self.persisted.objectives[to].name;
+                                let msg = format_compact!(
+                                    "supply convoy {gid} from {from} to {to} was destroyed"
+                                );
+                                let side = group!(self, gid)?.side;
+                                self.ephemeral.msgs().panel_to_side(10, false, side, msg)
+                            }
                             DeployKind::Troop { .. }
                             | DeployKind::Deployed { .. }
                             | DeployKind::Action { .. }
                             | DeployKind::Crate { .. }
+                            | DeployKind::Pilot { .. }
+                            | DeployKind::Offensive { .. }
                             | DeployKind::Objective => (),
                         }
                         self.delete_group(&gid)?
@@ -951,6 +1060,7 @@ impl Db {
     ) -> Result<Vec<DcsOid<ClassUnit>>> {
         let mut unit: Option<Unit> = None;
         let mut moved: SmallVec<[GroupId; 16]> = smallvec![];
+        let mut moved_units: SmallVec<[UnitId; 16]> = smallvec![];
         let mut dead: Vec<DcsOid<ClassUnit>> = vec![];
         for uid in units {
             let id = match self.ephemeral.object_id_by_uid.get(&uid) {
@@ -979,6 +1089,7 @@ impl Db {
             let spunit = unit_mut!(self, uid)?;
             if (spunit.position.p.0 - pos.p.0).magnitude_squared() > 1.0 {
                 moved.push(spunit.group);
+                moved_units.push(*uid);
                 spunit.moved = Some(now);
                 spunit.position = pos;
                 spunit.pos = Vector2::new(pos.p.x, pos.p.z);
@@ -998,6 +1109,36 @@ impl Db {
             self.ephemeral.dirty();
             self.mark_group(&gid)?;
         }
+        self.record_unit_tracks(lua, now, &moved_units)?;
         Ok(dead)
     }
+
+    fn record_unit_tracks(
+        &mut self,
+        lua: MizLua,
+        now: DateTime<Utc>,
+        uids: &[UnitId],
+    ) -> Result<()> {
+        if !self.ephemeral.cfg.acmi || uids.is_empty() {
+            return Ok(());
+        }
+        let coord = Coord::singleton(lua)?;
+        for uid in uids {
+            let unit = unit!(self, uid)?;
+            let props = Props {
+                typ: AcmiType::from_tags(unit.tags),
+                name: unit.typ.0.clone(),
+                pilot: None,
+                group: self
+                    .persisted
+                    .groups
+                    .get(&unit.group)
+                    .map(|g| g.name.clone()),
+                side: unit.side,
+            };
+            let record = Record::sample(&coord, now, AcmiKey::Unit(*uid), &unit.position, props)?;
+            self.ephemeral.acmi(record)
+        }
+        Ok(())
+    }
 }
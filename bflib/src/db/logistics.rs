@@ -15,6 +15,7 @@ for more details.
 */
 
 use super::{
+    backend::Backend,
     ephemeral::{Equipment, LogiStage, Production},
     objective::{Objective, ObjectiveId},
     Db, Map, Set,
@@ -37,7 +38,7 @@ use dcso3::{
     world::World,
     MizLua, String, Vector2,
 };
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use log::{error, warn};
 use serde_derive::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
@@ -99,41 +100,68 @@ impl SubAssign<u32> for Inventory {
 }
 
 #[derive(Debug, Clone)]
-enum TransferItem {
+pub(super) enum TransferItem {
     Equipment(String),
     Liquid(LiquidType),
 }
 
+impl TransferItem {
+    pub(super) fn credit(&self, obj: &mut Objective, amount: u32) {
+        match self {
+            TransferItem::Equipment(name) => {
+                obj.warehouse
+                    .equipment
+                    .get_or_default_cow(name.clone())
+                    .stored += amount
+            }
+            TransferItem::Liquid(name) => {
+                obj.warehouse
+                    .liquids
+                    .get_or_default_cow(name.clone())
+                    .stored += amount
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-struct Transfer {
-    source: ObjectiveId,
-    target: ObjectiveId,
-    amount: u32,
-    item: TransferItem,
+pub(super) struct Transfer {
+    pub(super) source: ObjectiveId,
+    pub(super) target: ObjectiveId,
+    pub(super) amount: u32,
+    pub(super) item: TransferItem,
 }
 
 impl Transfer {
-    fn execute(&self, db: &mut Db) -> Result<()> {
+    fn debit(&self, db: &mut Db) -> Result<()> {
         let src = objective_mut!(db, self.source)?;
         match &self.item {
             TransferItem::Equipment(name) => src.warehouse.equipment[name].stored -= self.amount,
             TransferItem::Liquid(name) => src.warehouse.liquids[name].stored -= self.amount,
         }
+        Ok(())
+    }
+
+    fn execute(&self, db: &mut Db) -> Result<()> {
+        self.debit(db)?;
         let dst = objective_mut!(db, self.target)?;
-        match &self.item {
-            TransferItem::Equipment(name) => {
-                dst.warehouse
-                    .equipment
-                    .get_or_default_cow(name.clone())
-                    .stored += self.amount
-            }
-            TransferItem::Liquid(name) => {
-                dst.warehouse
-                    .liquids
-                    .get_or_default_cow(name.clone())
-                    .stored += self.amount
-            }
+        self.item.credit(dst, self.amount);
+        Ok(())
+    }
+
+    /// Execute the transfer, or if it should go by road load it onto
+    /// a convoy. A transfer is dropped if a convoy is already on the
+    /// road between the same objectives, it will be reconsidered on
+    /// the next tick.
+    fn dispatch(&self, db: &mut Db) -> Result<()> {
+        if !db.convoy_wanted(&self.source, &self.target)? {
+            return self.execute(db);
         }
+        if db.convoy_on_road(&self.source, &self.target) {
+            return Ok(());
+        }
+        self.debit(db)?;
+        db.load_convoy(self);
         Ok(())
     }
 }
@@ -154,7 +182,30 @@ pub struct Warehouse {
     pub(super) destination: Set<ObjectiveId>,
 }
 
-fn sync_obj_to_warehouse(obj: &Objective, warehouse: &warehouse::Warehouse) -> Result<()> {
+impl Warehouse {
+    pub fn equipment(&self) -> &Map<String, Inventory> {
+        &self.equipment
+    }
+
+    pub fn liquids(&self) -> &Map<LiquidType, Inventory> {
+        &self.liquids
+    }
+
+    /// the logistics hub that supplies this warehouse
+    pub fn supplier(&self) -> Option<ObjectiveId> {
+        self.supplier
+    }
+
+    /// the objectives this warehouse supplies if it is a logistics hub
+    pub fn destination(&self) -> &Set<ObjectiveId> {
+        &self.destination
+    }
+}
+
+pub(super) fn sync_obj_to_warehouse(
+    obj: &Objective,
+    warehouse: &warehouse::Warehouse,
+) -> Result<()> {
     for (item, inv) in &obj.warehouse.equipment {
         warehouse
             .set_item(item.clone(), inv.stored)
@@ -168,7 +219,10 @@ fn sync_obj_to_warehouse(obj: &Objective, warehouse: &warehouse::Warehouse) -> R
     Ok(())
 }
 
-fn sync_warehouse_to_obj(obj: &mut Objective, warehouse: &warehouse::Warehouse) -> Result<()> {
+pub(super) fn sync_warehouse_to_obj(
+    obj: &mut Objective,
+    warehouse: &warehouse::Warehouse,
+) -> Result<()> {
     for (name, inv) in obj.warehouse.equipment.iter_mut_cow() {
         inv.stored = warehouse.get_item_count(name.clone())?;
     }
@@ -262,10 +316,16 @@ impl Db {
     pub(super) fn init_warehouses(&mut self, lua: MizLua) -> Result<()> {
         self.init_resource_map(lua)
             .context("initializing resource map")?;
+        self.fill_warehouses();
+        Ok(())
+    }
+
+    /// stock every objective to capacity with what its owner produces
+    pub(super) fn fill_warehouses(&mut self) {
         let cfg = &self.ephemeral.cfg;
         let whcfg = match cfg.warehouse.as_ref() {
             Some(cfg) => cfg,
-            None => return Ok(()),
+            None => return,
         };
         for side in Side::ALL {
             let production = match self.ephemeral.production_by_side.get(&side) {
@@ -301,7 +361,6 @@ impl Db {
             }
         }
         self.ephemeral.dirty();
-        Ok(())
     }
 
     pub(super) fn setup_warehouses_after_load(&mut self, lua: MizLua) -> Result<()> {
@@ -450,9 +509,9 @@ impl Db {
         self.persisted.logistics_ticks_since_delivery = u32::MAX;
     }
 
-    pub fn logistics_step(
+    pub fn logistics_step<B: Backend>(
         &mut self,
-        lua: MizLua,
+        backend: &mut B,
         perf: &mut PerfInner,
         ts: DateTime<Utc>,
     ) -> Result<()> {
@@ -483,7 +542,10 @@ impl Db {
                 LogiStage::SyncFromWarehouses { objectives } => match objectives.pop() {
                     Some(oid) => {
                         let start_ts = Utc::now();
-                        if let Err(e) = self.sync_warehouse_to_objective(lua, oid) {
+                        let airbase = self.ephemeral.airbase_by_oid.get(&oid);
+                        let res = objective_mut!(self, oid)
+                            .and_then(|obj| backend.sync_warehouse_to_objective(airbase, obj));
+                        if let Err(e) = res {
                             error!("failed to sync objective {oid} from warehouse {:?}", e)
                         }
                         record_perf(&mut perf.logistics_sync_from, start_ts);
@@ -503,6 +565,9 @@ impl Db {
                             }
                             record_perf(&mut perf.logistics_distribute, sts);
                         }
+                        if let Err(e) = self.plan_offensives(ts) {
+                            error!("failed to plan offensives {:?}", e)
+                        }
                         let objectives = self
                             .persisted
                             .objectives
@@ -516,7 +581,10 @@ impl Db {
                     None => self.ephemeral.logistics_stage = LogiStage::Complete { last_tick: ts },
                     Some(oid) => {
                         let start_ts = Utc::now();
-                        if let Err(e) = self.sync_objective_to_warehouse(lua, oid) {
+                        let airbase = self.ephemeral.airbase_by_oid.get(&oid);
+                        let res = objective!(self, oid)
+                            .and_then(|obj| backend.sync_objective_to_warehouse(airbase, obj));
+                        if let Err(e) = res {
                             error!("failed to sync objective {oid} to warehouse {:?}", e)
                         }
                         record_perf(&mut perf.logistics_sync_to, start_ts);
@@ -528,7 +596,7 @@ impl Db {
         Ok(())
     }
 
-    pub(super) fn capture_warehouse(&mut self, lua: MizLua, oid: ObjectiveId) -> Result<()> {
+    pub(super) fn capture_warehouse(&mut self, oid: ObjectiveId) -> Result<()> {
         let whcfg = match self.ephemeral.cfg.warehouse.as_ref() {
             Some(cfg) => cfg,
             None => return Ok(()),
@@ -542,9 +610,15 @@ impl Db {
             Some(q) => Arc::clone(q),
             None => return Ok(()),
         };
-        let map = warehouse::Warehouse::get_resource_map(lua).context("getting resource map")?;
         let hub = obj.kind.is_hub();
-        map.for_each(|name, _| {
+        // only items one of the sides produces can be in a warehouse
+        let names = production
+            .equipment
+            .keys()
+            .chain(other_production.equipment.keys())
+            .cloned()
+            .collect::<FxHashSet<String>>();
+        for name in names {
             match production.equipment.get(&name) {
                 Some(equip) => {
                     let aircraft = equip.category.is_aircraft();
@@ -565,8 +639,7 @@ impl Db {
                     }
                 }
             }
-            Ok(())
-        })?;
+        }
         for name in LiquidType::ALL {
             match production.liquids.get(&name) {
                 Some(qty) => {
@@ -772,7 +845,7 @@ impl Db {
             schedule_transfers!(TransferItem::Liquid, liquids, get_liquids);
         }
         for tr in transfers.drain(..) {
-            tr.execute(self)
+            tr.dispatch(self)
                 .with_context(|| format_compact!("executing transfer {:?}", tr))?
         }
         self.balance_logistics_hubs()
@@ -858,7 +931,7 @@ impl Db {
             schedule_transfers!(TransferItem::Equipment, equipment, get_equipment);
             schedule_transfers!(TransferItem::Liquid, liquids, get_liquids);
             for tr in transfers.drain(..) {
-                tr.execute(self)
+                tr.dispatch(self)
                     .with_context(|| format_compact!("executing transfer {:?}", tr))?
             }
             self.ephemeral.dirty();
@@ -15,15 +15,16 @@ for more details.
 */
 
 use super::{
+    frontline::FrontLine,
     objective::{Objective, ObjectiveKind, Zone},
     persisted::Persisted,
 };
 use crate::{cfg::Cfg, msgq::MsgQ};
-use compact_str::format_compact;
+use compact_str::{format_compact, CompactString};
 use dcso3::{
     coalition::Side,
-    trigger::{ArrowSpec, CircleSpec, LineType, MarkId, QuadSpec, SideFilter, TextSpec},
-    Color, LuaVec3, Vector3,
+    trigger::{ArrowSpec, CircleSpec, LineSpec, LineType, MarkId, QuadSpec, SideFilter, TextSpec},
+    Color, LuaVec3, Vector2, Vector3,
 };
 use smallvec::SmallVec;
 
@@ -35,6 +36,7 @@ pub(super) struct ObjectiveMarkup {
     logi: u8,
     supply: u8,
     fuel: u8,
+    capture: Option<(Side, u8, bool)>,
     name: String,
     owner_ring: MarkId,
     capturable_ring: MarkId,
@@ -43,6 +45,34 @@ pub(super) struct ObjectiveMarkup {
     supply_connections: SmallVec<[MarkId; 8]>,
 }
 
+fn capture_state(obj: &Objective) -> Option<(Side, u8, bool)> {
+    obj.capture().map(|c| {
+        (
+            c.side,
+            (c.progress * 100.).clamp(0., 100.) as u8,
+            c.contested,
+        )
+    })
+}
+
+fn label_text(name: &str, obj: &Objective) -> CompactString {
+    let mut v = format_compact!(
+        "{}\nHealth: {}\nLogi: {}\nSupply: {}\nFuel: {}",
+        name,
+        obj.health,
+        obj.logi,
+        obj.supply,
+        obj.fuel
+    );
+    if let Some((side, pct, contested)) = capture_state(obj) {
+        v.push_str(&format_compact!("\nCapture: {pct}% {side}"));
+        if contested {
+            v.push_str(" contested");
+        }
+    }
+    v
+}
+
 fn text_color(side: Side, a: f32) -> Color {
     match side {
         Side::Red => Color::red(a),
@@ -60,6 +90,7 @@ impl ObjectiveMarkup {
             logi: _,
             supply: _,
             fuel: _,
+            capture: _,
             name: _,
             owner_ring,
             capturable_ring,
@@ -93,10 +124,12 @@ impl ObjectiveMarkup {
                 Color::yellow(if self.threatened { 0.75 } else { 0. }),
             );
         }
+        let capture = capture_state(obj);
         if self.health != obj.health
             || self.logi != obj.logi
             || self.supply != obj.supply
             || self.fuel != obj.fuel
+            || self.capture != capture
         {
             if self.logi != obj.logi {
                 msgq.set_markup_color(
@@ -108,15 +141,8 @@ impl ObjectiveMarkup {
             self.logi = obj.logi;
             self.supply = obj.supply;
             self.fuel = obj.fuel;
-            let v = format_compact!(
-                "{}\nHealth: {}\nLogi: {}\nSupply: {}\nFuel: {}",
-                self.name,
-                obj.health,
-                obj.logi,
-                obj.supply,
-                obj.fuel
-            );
-            msgq.set_markup_text(self.label, v.into());
+            self.capture = capture;
+            msgq.set_markup_text(self.label, label_text(&self.name, obj).into());
         }
     }
 
@@ -135,6 +161,7 @@ impl ObjectiveMarkup {
         t.logi = obj.logi;
         t.supply = obj.supply;
         t.fuel = obj.fuel;
+        t.capture = capture_state(obj);
         t.name = format_compact!("{} {}", obj.name, obj.kind.name()).into();
         let opos = obj.zone.pos();
         let pos3 = Vector3::new(opos.x, 0., opos.y);
@@ -228,15 +255,7 @@ impl ObjectiveMarkup {
                 fill_color: Color::black(0.),
                 font_size: 10,
                 read_only: true,
-                text: format_compact!(
-                    "{}\nHealth: {}\nLogi: {}\nSupply: {}\nFuel: {}",
-                    t.name,
-                    obj.health,
-                    obj.logi,
-                    obj.supply,
-                    obj.fuel
-                )
-                .into(),
+                text: label_text(&t.name, obj).into(),
             },
         );
         match obj.kind {
@@ -275,3 +294,62 @@ impl ObjectiveMarkup {
         t
     }
 }
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct FrontLineMarkup(Vec<MarkId>);
+
+fn lua_vec(p: Vector2) -> LuaVec3 {
+    LuaVec3(Vector3::new(p.x, 0., p.y))
+}
+
+impl FrontLineMarkup {
+    pub(super) fn remove(self, msgq: &mut MsgQ) {
+        for id in self.0 {
+            msgq.delete_mark(id)
+        }
+    }
+
+    pub(super) fn new(msgq: &mut MsgQ, front: &FrontLine) -> Self {
+        let mut t = FrontLineMarkup::default();
+        for (side, [p0, p1, p2, p3]) in front.territory() {
+            let id = MarkId::new();
+            msgq.quad_to_all(
+                SideFilter::All,
+                id,
+                QuadSpec {
+                    p0: lua_vec(*p0),
+                    p1: lua_vec(*p1),
+                    p2: lua_vec(*p2),
+                    p3: lua_vec(*p3),
+                    color: Color::white(0.),
+                    fill_color: text_color(*side, 0.1),
+                    line_type: LineType::NoLine,
+                    read_only: true,
+                },
+                None,
+            );
+            t.0.push(id);
+        }
+        for (start, end) in front
+            .lines()
+            .iter()
+            .flat_map(|l| l.windows(2).map(|w| (w[0], w[1])))
+        {
+            let id = MarkId::new();
+            msgq.line_to_all(
+                SideFilter::All,
+                id,
+                LineSpec {
+                    start: lua_vec(start),
+                    end: lua_vec(end),
+                    color: Color::black(0.8),
+                    line_type: LineType::Solid,
+                    read_only: true,
+                },
+                None,
+            );
+            t.0.push(id);
+        }
+        t
+    }
+}
@@ -0,0 +1,114 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Saves record the version of the schema they were written
+//! with. An older save is upgraded one version at a time, as json,
+//! before it is decoded, so a change to the format only needs to
+//! know how to upgrade from the version right before it.
+
+use super::{objective::Zone, persisted::Persisted};
+use anyhow::{anyhow, bail, Context, Result};
+use compact_str::format_compact;
+use dcso3::Vector2;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::io::Read;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The schema version of a save. New saves are always written with
+/// the current version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SchemaVersion(pub u32);
+
+impl Default for SchemaVersion {
+    fn default() -> Self {
+        Self(SCHEMA_VERSION)
+    }
+}
+
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a save from version n to version n + 1
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [zone_from_pos_radius];
+
+/// Before zones objectives were circles described by a pos and a
+/// radius. Saves from the transition may have a zone that was never
+/// set, in which case the pos and radius are authoritative.
+fn zone_from_pos_radius(save: &mut Map<String, Value>) -> Result<()> {
+    let objectives = match save.get_mut("objectives") {
+        None => return Ok(()),
+        Some(o) => o
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("objectives is not a map"))?,
+    };
+    for (id, obj) in objectives.iter_mut() {
+        let obj = obj
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("objective {id} is not an object"))?;
+        let pos = obj.remove("pos").unwrap_or(Value::Null);
+        let radius = obj.remove("radius").unwrap_or(Value::Null);
+        let unset = match obj.get("zone") {
+            None => true,
+            Some(zone) => serde_json::from_value::<Zone>(zone.clone())
+                .with_context(|| format_compact!("decoding zone of objective {id}"))?
+                .eq(&Zone::default()),
+        };
+        if unset {
+            let pos: Option<Vector2> = serde_json::from_value(pos)
+                .with_context(|| format_compact!("decoding pos of objective {id}"))?;
+            let radius: Option<f64> = serde_json::from_value(radius)
+                .with_context(|| format_compact!("decoding radius of objective {id}"))?;
+            let zone = Zone::Circle {
+                pos: pos.unwrap_or_default(),
+                radius: radius.unwrap_or(0.),
+            };
+            obj.insert("zone".into(), serde_json::to_value(zone)?);
+        }
+    }
+    Ok(())
+}
+
+/// Upgrade a save to the current schema version
+pub fn migrate(save: &mut Value) -> Result<()> {
+    let save = save
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("save is not an object"))?;
+    let version = match save.get("version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("invalid save version {v}"))?,
+    };
+    if version > SCHEMA_VERSION {
+        bail!("save version {version} is newer than the supported version {SCHEMA_VERSION}")
+    }
+    for v in version..SCHEMA_VERSION {
+        MIGRATIONS[v as usize](save)
+            .with_context(|| format_compact!("migrating save from version {v}"))?;
+        save.insert("version".into(), json!(v + 1));
+    }
+    Ok(())
+}
+
+/// Decode a save of any supported version
+pub fn decode<R: Read>(r: R) -> Result<Persisted> {
+    let mut save: Value = serde_json::from_reader(r)?;
+    migrate(&mut save)?;
+    Ok(serde_json::from_value(save)?)
+}
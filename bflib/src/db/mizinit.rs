@@ -15,6 +15,7 @@ for more details.
 */
 
 use super::{
+    backend::DcsBackend,
     ephemeral::SlotInfo,
     group::{DeployKind, GroupId},
     objective::ObjGroup,
@@ -92,7 +93,6 @@ impl Db {
         } else {
             bail!("invalid objective type for {name}, expected AB, FO, of LO")
         };
-        let id = ObjectiveId::new();
         let zone = match zone.typ()? {
             TriggerZoneTyp::Quad(points) => Zone::Quad {
                 pos: centroid2d([points.p0.0, points.p1.0, points.p2.0, points.p3.0]),
@@ -103,13 +103,23 @@ impl Db {
                 radius,
             },
         };
+        self.add_objective(name, kind, owner, zone);
+        Ok(())
+    }
+
+    pub(super) fn add_objective(
+        &mut self,
+        name: String,
+        kind: ObjectiveKind,
+        owner: Side,
+        zone: Zone,
+    ) -> ObjectiveId {
+        let id = ObjectiveId::new();
         let obj = Objective {
             id,
             spawned: false,
             enabled: false,
             threatened: false,
-            pos: None,
-            radius: None,
             zone,
             name: name.clone(),
             kind,
@@ -123,6 +133,7 @@ impl Db {
             last_threatened_ts: Utc::now(),
             warehouse: Warehouse::default(),
             last_activate: DateTime::<Utc>::default(),
+            capture: None,
             // initialized by load
             threat_pos3: Vector3::default(),
         };
@@ -131,7 +142,7 @@ impl Db {
         }
         self.persisted.objectives.insert_cow(id, obj);
         self.persisted.objectives_by_name.insert_cow(name, id);
-        Ok(())
+        id
     }
 
     /// Objective groups are trigger zones with the first character set to G. They are then a template
@@ -327,17 +338,6 @@ impl Db {
         spctx: &SpawnCtx,
     ) -> Result<()> {
         debug!("init slots");
-        // check for objectives using the old pos + radius format and convert them to zone
-        for (_id, obj) in self.persisted.objectives.iter_mut_cow() {
-            if obj.zone == Zone::default() {
-                let pos = obj.pos.unwrap_or_else(Vector2::default);
-                let radius = obj.radius.unwrap_or(0.);
-                obj.zone = Zone::Circle { pos, radius };
-                obj.pos = None;
-                obj.radius = None;
-                self.ephemeral.dirty = true;
-            }
-        }
         for side in Side::ALL {
             let coa = miz.coalition(side)?;
             for country in coa.countries()? {
@@ -369,6 +369,9 @@ impl Db {
             for gid in &self.persisted.troops {
                 self.ephemeral.push_spawn(*gid);
             }
+            for gid in &self.persisted.pilots {
+                self.ephemeral.push_spawn(*gid);
+            }
             let actions: SmallVec<[GroupId; 16]> =
                 SmallVec::from_iter(self.persisted.actions.into_iter().map(|g| *g));
             debug!("respawn actions");
@@ -377,6 +380,22 @@ impl Db {
                     error!("failed to respawn action {e:?}");
                 }
             }
+            let convoys: SmallVec<[GroupId; 16]> =
+                SmallVec::from_iter(self.persisted.convoys.into_iter().map(|g| *g));
+            debug!("respawn convoys");
+            for gid in convoys {
+                if let Err(e) = self.respawn_convoy(perf, spctx, idx, gid) {
+                    error!("failed to respawn convoy {e:?}");
+                }
+            }
+            let offensives: SmallVec<[GroupId; 16]> =
+                SmallVec::from_iter(self.persisted.offensives.into_iter().map(|g| *g));
+            debug!("respawn offensives");
+            for gid in offensives {
+                if let Err(e) = self.respawn_offensive(perf, spctx, idx, gid) {
+                    error!("failed to respawn attack group {e:?}");
+                }
+            }
             debug!("respawning farps");
             for (_, obj) in self.persisted.objectives.iter_mut_cow() {
                 let pos = obj.zone.pos();
@@ -421,6 +440,7 @@ impl Db {
             for (_, obj) in &self.persisted.objectives {
                 self.ephemeral.create_objective_markup(&self.persisted, obj)
             }
+            self.update_front_line();
             Ok(())
         };
         mark_deployed_and_logistics().context("marking deployed and logistics")?;
@@ -431,7 +451,10 @@ impl Db {
                     DeployKind::Crate { .. } => (),
                     DeployKind::Deployed { .. }
                     | DeployKind::Troop { .. }
-                    | DeployKind::Action { .. } => {
+                    | DeployKind::Action { .. }
+                    | DeployKind::Pilot { .. }
+                    | DeployKind::Convoy { .. }
+                    | DeployKind::Offensive { .. } => {
                         self.ephemeral
                             .units_potentially_close_to_enemies
                             .insert(*uid);
@@ -472,7 +495,25 @@ impl Db {
             }
         }
         queue_check_close_enemies().context("queuing unit pos checks")?;
-        self.cull_or_respawn_objectives(spctx.lua(), landcache, Utc::now())
+        // rescued pilots were carried by aircraft that didn't survive
+        // the restart, give them back their lives like airborne players
+        let carried = self
+            .persisted
+            .carried_pilots
+            .into_iter()
+            .flat_map(|(_, pilots)| pilots.iter().cloned())
+            .collect::<Vec<_>>();
+        if !carried.is_empty() {
+            self.persisted.carried_pilots = Map::new();
+            self.ephemeral.dirty = true;
+        }
+        for pilot in carried {
+            if let Some(lt) = pilot.life_type {
+                self.return_pilot_life(&pilot.player, lt);
+            }
+        }
+        let mut backend = DcsBackend::new(spctx.lua(), landcache)?;
+        self.cull_or_respawn_objectives(&mut backend, Utc::now())
             .context("initial cull or respawn")?;
         Ok(())
     }
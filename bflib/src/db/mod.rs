@@ -15,11 +15,14 @@ for more details.
 */
 
 extern crate nalgebra as na;
-use self::{group::DeployKind, persisted::Persisted};
+use self::{
+    group::{DeployKind, GroupId, UnitId},
+    persisted::Persisted,
+};
 use crate::{
     cfg::{
-        Action, ActionKind, AwacsCfg, Cfg, Deployable, DeployableEwr, DeployableJtac, DroneCfg,
-        Troop,
+        Action, ActionKind, AwacsCfg, Cfg, Deployable, DeployableCounterBattery, DeployableEwr,
+        DeployableJtac, DroneCfg, Troop, UnitTags,
     },
     db::ephemeral::Ephemeral,
     jtac::JtId,
@@ -29,20 +32,40 @@ use dcso3::{
     centroid3d,
     coalition::Side,
     env::miz::{Miz, MizIndex},
-    Vector3,
+    Vector2, Vector3,
 };
-use std::{fs::File, path::Path};
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+use std::{fs::File, mem, path::Path, sync::Arc};
 
 pub mod actions;
+pub mod backend;
+pub mod career;
 pub mod cargo;
+pub mod convoy;
+pub mod csar;
 pub mod ephemeral;
+pub mod frontline;
 pub mod group;
 pub mod logistics;
 pub mod markup;
+pub mod migrate;
 pub mod mizinit;
 pub mod objective;
+pub mod offensive;
 pub mod persisted;
 pub mod player;
+pub mod round;
+pub mod sim;
+pub mod squadron;
+
+/// Read a save file, upgrading it to the current schema version
+pub fn read_persisted(path: &Path) -> Result<Persisted> {
+    let file =
+        File::open(path).map_err(|e| anyhow!("failed to open save file {:?}, {:?}", path, e))?;
+    let file = zstd::stream::Decoder::new(file)?;
+    migrate::decode(file).map_err(|e| anyhow!("failed to decode save file {:?}, {:?}", path, e))
+}
 
 pub type Map<K, V> = immutable_chunkmap::map::Map<K, V, 256>;
 pub type Set<K> = immutable_chunkmap::set::Set<K, 256>;
@@ -55,6 +78,18 @@ pub struct JtDesc {
     pub air: bool,
 }
 
+/// The settings a jtac's operators have chosen, kept across restarts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JtacSettings {
+    pub code: u16,
+    pub filter: UnitTags,
+    pub autoshift: bool,
+    pub ir_pointer: bool,
+    /// the unit that was manually selected as the target
+    #[serde(default)]
+    pub target: Option<UnitId>,
+}
+
 #[macro_export]
 macro_rules! maybe {
     ($t:expr, $id:expr, $name:expr) => {
@@ -175,11 +210,10 @@ pub struct Db {
 
 impl Db {
     pub fn load(miz: &Miz, idx: &MizIndex, path: &Path) -> Result<Self> {
-        let file = File::open(&path)
-            .map_err(|e| anyhow!("failed to open save file {:?}, {:?}", path, e))?;
-        let file = zstd::stream::Decoder::new(file)?;
-        let persisted: Persisted = serde_json::from_reader(file)
-            .map_err(|e| anyhow!("failed to decode save file {:?}, {:?}", path, e))?;
+        let persisted = read_persisted(path)?;
+        if let Err(e) = persisted.validate() {
+            error!("the save file {path:?} is inconsistent, {e:?}")
+        }
         let mut db = Db {
             persisted,
             ephemeral: Ephemeral::default(),
@@ -188,6 +222,19 @@ impl Db {
         Ok(db)
     }
 
+    /// Replace the live config with a reloaded one. If the new config
+    /// doesn't validate the live config is left as it was.
+    pub fn reload_cfg(&mut self, miz: &Miz, idx: &MizIndex, cfg: Cfg) -> Result<()> {
+        let deployable_idx = mem::take(&mut self.ephemeral.deployable_idx);
+        let live = Arc::clone(&self.ephemeral.cfg);
+        if let Err(e) = self.ephemeral.set_cfg(miz, idx, cfg) {
+            self.ephemeral.deployable_idx = deployable_idx;
+            self.ephemeral.cfg = live;
+            return Err(e);
+        }
+        Ok(())
+    }
+
     pub fn maybe_snapshot(&mut self) -> Option<Persisted> {
         if self.ephemeral.take_dirty() {
             Some(self.persisted.clone())
@@ -200,7 +247,12 @@ impl Db {
         self.persisted.ewrs.into_iter().filter_map(|gid| {
             let group = self.persisted.groups.get(gid)?;
             match &group.origin {
-                DeployKind::Crate { .. } | DeployKind::Objective | DeployKind::Troop { .. } => None,
+                DeployKind::Crate { .. }
+                | DeployKind::Objective
+                | DeployKind::Troop { .. }
+                | DeployKind::Pilot { .. }
+                | DeployKind::Convoy { .. }
+                | DeployKind::Offensive { .. } => None,
                 DeployKind::Action {
                     spec:
                         Action {
@@ -226,6 +278,37 @@ impl Db {
         })
     }
 
+    pub fn counter_battery_radars(
+        &self,
+    ) -> impl Iterator<Item = (Vector2, Side, &DeployableCounterBattery)> {
+        self.persisted
+            .counter_batteries
+            .into_iter()
+            .filter_map(|gid| {
+                let group = self.persisted.groups.get(gid)?;
+                match &group.origin {
+                    DeployKind::Deployed {
+                        spec:
+                            Deployable {
+                                counter_battery: Some(cb),
+                                ..
+                            },
+                        ..
+                    } => Some((self.group_center(gid).ok()?, group.side, cb)),
+                    _ => None,
+                }
+            })
+    }
+
+    pub fn jtac_settings(&self, gid: &GroupId) -> Option<&JtacSettings> {
+        self.persisted.jtac_settings.get(gid)
+    }
+
+    pub fn save_jtac_settings(&mut self, gid: GroupId, settings: JtacSettings) {
+        self.persisted.jtac_settings.insert_cow(gid, settings);
+        self.ephemeral.dirty();
+    }
+
     pub fn jtacs<'a>(&'a self) -> impl Iterator<Item = JtDesc> + 'a {
         self.persisted
             .jtacs
@@ -277,6 +360,9 @@ impl Db {
                     | DeployKind::Action { .. }
                     | DeployKind::Objective
                     | DeployKind::Troop { .. }
+                    | DeployKind::Pilot { .. }
+                    | DeployKind::Convoy { .. }
+                    | DeployKind::Offensive { .. }
                     | DeployKind::Deployed { .. } => None,
                 }
             })
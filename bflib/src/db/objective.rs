@@ -15,22 +15,22 @@ for more details.
 */
 
 use super::{
+    backend::Backend,
     ephemeral::LogiStage,
     group::{DeployKind, GroupId, SpawnedUnit, UnitId},
     logistics::{Inventory, Warehouse},
     Db, Map, Set,
 };
 use crate::{
-    cfg::{Deployable, DeployableLogistics, UnitTag},
-    group, group_health, group_mut,
-    landcache::LandCache,
-    maybe, objective, objective_mut,
+    cfg::{CaptureCfg, Deployable, DeployableLogistics, UnitTag},
+    group, group_health, group_mut, maybe, objective, objective_mut,
     spawnctx::{Despawn, SpawnCtx, SpawnLoc},
+    stats::StatKind,
     unit, unit_mut,
 };
 use anyhow::{anyhow, Context, Result};
 use chrono::{prelude::*, Duration};
-use compact_str::format_compact;
+use compact_str::{format_compact, CompactString};
 use dcso3::{
     airbase::Airbase,
     atomic_id, azumith2d_to, centroid2d,
@@ -38,7 +38,7 @@ use dcso3::{
     coord::Coord,
     cvt_err,
     env::miz::{GroupKind, MizIndex},
-    group::Group,
+    group::GroupCategory,
     land::Land,
     net::Ucid,
     object::DcsObject,
@@ -273,14 +273,28 @@ impl Zone {
     }
 }
 
+/// The capturing troops in each objective as (side, player, origin, group)
+type CaptureTroops =
+    FxHashMap<ObjectiveId, Vec<(Side, Option<Ucid>, Option<ObjectiveId>, GroupId)>>;
+
+/// The progress of troops capturing an objective
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    /// the side that is capturing the objective
+    pub side: Side,
+    /// from 0 to 1, the objective is captured at 1
+    pub progress: f64,
+    /// defenders are present, progress is paused
+    pub contested: bool,
+    /// both sides have been warned the capture is about to complete
+    pub warned: bool,
+    pub last_update: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Objective {
     pub id: ObjectiveId,
     pub name: String,
-    // deprecated, remove after transition
-    pub(super) pos: Option<Vector2>,
-    // deprecated, remove after transition
-    pub(super) radius: Option<f64>,
     pub owner: Side,
     pub(super) kind: ObjectiveKind,
     pub(super) groups: Map<Side, Set<GroupId>>,
@@ -297,6 +311,8 @@ pub struct Objective {
     pub(super) warehouse: Warehouse,
     #[serde(default)]
     pub(super) zone: Zone,
+    #[serde(default)]
+    pub(super) capture: Option<Capture>,
     #[serde(skip)]
     pub(super) spawned: bool,
     #[serde(skip)]
@@ -325,10 +341,38 @@ impl Objective {
         self.logi == 0
     }
 
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
+    }
+
     pub fn owner(&self) -> Side {
         self.owner
     }
 
+    pub fn kind(&self) -> &ObjectiveKind {
+        &self.kind
+    }
+
+    pub fn pos(&self) -> Vector2 {
+        self.zone.pos()
+    }
+
+    pub fn zone(&self) -> &Zone {
+        &self.zone
+    }
+
+    pub fn supply(&self) -> u8 {
+        self.supply
+    }
+
+    pub fn fuel(&self) -> u8 {
+        self.fuel
+    }
+
+    pub fn warehouse(&self) -> &Warehouse {
+        &self.warehouse
+    }
+
     pub fn is_farp(&self) -> bool {
         match &self.kind {
             ObjectiveKind::Farp { .. } => true,
@@ -462,6 +506,7 @@ impl Db {
         self.persisted.farps.remove_cow(oid);
         self.ephemeral.airbase_by_oid.remove(oid);
         self.ephemeral.remove_objective_markup(oid);
+        self.update_front_line();
         self.ephemeral.dirty();
         Ok(())
     }
@@ -570,8 +615,6 @@ impl Db {
                 spec: spec.clone(),
                 pad_template: pad_template.clone(),
             },
-            pos: None,
-            radius: None,
             zone: Zone::Circle { pos, radius: 2000. },
             owner: side,
             health: 100,
@@ -585,6 +628,7 @@ impl Db {
             last_threatened_ts: now,
             last_change_ts: now,
             last_activate: DateTime::<Utc>::default(),
+            capture: None,
             threat_pos3,
         };
         let oid = obj.id;
@@ -618,10 +662,42 @@ impl Db {
         };
         self.ephemeral
             .create_objective_markup(&self.persisted, objective!(self, oid)?);
+        self.update_front_line();
+        self.objective_stat(spctx.lua(), &oid)
+            .context("recording farp stat")?;
         self.ephemeral.dirty();
         Ok(oid)
     }
 
+    fn objective_stat(&mut self, lua: MizLua, oid: &ObjectiveId) -> Result<()> {
+        let obj = objective!(self, oid)?;
+        let pos = obj.zone.pos();
+        let pos = Coord::singleton(lua)?.lo_to_ll(LuaVec3(Vector3::new(pos.x, 0., pos.y)))?;
+        let kind = StatKind::Objective {
+            id: *oid,
+            name: obj.name.clone(),
+            pos,
+            owner: obj.owner,
+            kind: obj.kind.clone(),
+        };
+        self.ephemeral.stat(kind);
+        Ok(())
+    }
+
+    /// record the position, kind, and owner of every objective in the stats log
+    pub fn stat_objectives(&mut self, lua: MizLua) -> Result<()> {
+        let oids = self
+            .persisted
+            .objectives
+            .into_iter()
+            .map(|(oid, _)| *oid)
+            .collect::<SmallVec<[ObjectiveId; 64]>>();
+        for oid in oids {
+            self.objective_stat(lua, &oid)?
+        }
+        Ok(())
+    }
+
     pub(super) fn update_objective_status(
         &mut self,
         oid: &ObjectiveId,
@@ -634,7 +710,16 @@ impl Db {
             obj.health = health;
             obj.logi = logi;
             obj.last_change_ts = now;
-            (obj.kind.clone(), health, logi)
+            let st = StatKind::ObjectiveStatus {
+                id: *oid,
+                health,
+                logi,
+                supply: obj.supply,
+                fuel: obj.fuel,
+            };
+            let kind = obj.kind.clone();
+            self.ephemeral.stat(st);
+            (kind, health, logi)
         };
         if let ObjectiveKind::Farp { .. } = &kind {
             if logi == 0 {
@@ -702,13 +787,11 @@ impl Db {
         Ok(())
     }
 
-    pub fn cull_or_respawn_objectives(
+    pub fn cull_or_respawn_objectives<B: Backend>(
         &mut self,
-        lua: MizLua,
-        landcache: &mut LandCache,
+        backend: &mut B,
         now: DateTime<Utc>,
     ) -> Result<(SmallVec<[ObjectiveId; 4]>, SmallVec<[ObjectiveId; 4]>)> {
-        let land = Land::singleton(lua)?;
         let players = self
             .ephemeral
             .players_by_slot
@@ -762,10 +845,11 @@ impl Db {
             }
             Ok::<_, anyhow::Error>(())
         };
-        let mut check_close_players = |obj: &Objective,
-                                       pos3: Vector3,
-                                       spawn: &mut bool,
-                                       threat: &mut bool| {
+        let check_close_players = |backend: &mut B,
+                                   obj: &Objective,
+                                   pos3: Vector3,
+                                   spawn: &mut bool,
+                                   threat: &mut bool| {
             for (side, pos, v, typ) in &players {
                 if obj.owner != *side {
                     let threat_dist = (cfg.threatened_distance[typ] as f64).powi(2);
@@ -787,7 +871,7 @@ impl Db {
                         *spawn = true;
                     }
                     if dist <= threat_dist {
-                        if landcache.is_visible(&land, dist.sqrt(), pos3, pos.0)? {
+                        if backend.is_visible(dist.sqrt(), pos3, pos.0)? {
                             *threat = true;
                         }
                     }
@@ -798,11 +882,25 @@ impl Db {
         let mut became_threatened: SmallVec<[ObjectiveId; 4]> = smallvec![];
         let mut became_clear: SmallVec<[ObjectiveId; 4]> = smallvec![];
         let cooldown = Duration::seconds(self.ephemeral.cfg.threatened_cooldown as i64);
+        let near_front: FxHashSet<ObjectiveId> = match (
+            cfg.front_line.as_ref().and_then(|c| c.threatened_distance),
+            &self.ephemeral.front_line,
+        ) {
+            (Some(dist), Some(front)) => self
+                .persisted
+                .objectives
+                .into_iter()
+                .filter(|(_, obj)| front.distance(obj.zone.pos()) <= dist as f64)
+                .map(|(oid, _)| *oid)
+                .collect(),
+            _ => FxHashSet::default(),
+        };
         for (oid, obj) in self.persisted.objectives.iter_mut_cow() {
             let mut spawn = false;
             let mut is_threatened = false;
             let pos3 = obj.threat_pos3;
-            if let Err(e) = check_close_players(obj, pos3, &mut spawn, &mut is_threatened) {
+            if let Err(e) = check_close_players(backend, obj, pos3, &mut spawn, &mut is_threatened)
+            {
                 error!("failed to check for close players {} {e}", obj.id)
             }
             if let Err(e) = check_close_units(
@@ -814,6 +912,7 @@ impl Db {
             ) {
                 error!("failed to check close units {} {e}", obj.id)
             }
+            is_threatened |= near_front.contains(oid);
             if spawn {
                 obj.last_activate = now;
             }
@@ -882,18 +981,9 @@ impl Db {
                 obj.enabled = spawn;
                 for gid in obj.groups.get(&obj.owner).unwrap_or(&Set::new()) {
                     if let Some(oid) = self.ephemeral.object_id_by_gid.get(gid) {
-                        let group = match Group::get_instance(lua, oid) {
-                            Ok(group) => group,
-                            Err(e) => {
-                                warn!("could not get group {gid} {e:?}");
-                                continue;
-                            }
-                        };
-                        group
-                            .get_controller()
-                            .context("get controller")?
-                            .set_on_off(spawn)
-                            .context("enable/disable ai")?
+                        if let Err(e) = backend.set_ai_enabled(oid, spawn) {
+                            warn!("could not enable/disable ai for group {gid} {e:?}")
+                        }
                     }
                 }
             }
@@ -1011,13 +1101,12 @@ impl Db {
         cap
     }
 
-    pub fn check_capture(
+    pub fn check_capture<B: Backend>(
         &mut self,
-        lua: MizLua,
+        backend: &mut B,
         now: DateTime<Utc>,
     ) -> Result<SmallVec<[(Side, ObjectiveId); 1]>> {
-        let mut captured: FxHashMap<ObjectiveId, Vec<(Side, Ucid, Option<ObjectiveId>, GroupId)>> =
-            FxHashMap::default();
+        let mut captured: CaptureTroops = FxHashMap::default();
         for (oid, obj) in &self.persisted.objectives {
             if obj.captureable() {
                 for gid in &self.persisted.troops {
@@ -1035,92 +1124,129 @@ impl Db {
                                 .filter_map(|uid| self.persisted.units.get(uid))
                                 .any(|u| obj.zone.contains(u.pos));
                             if in_range {
-                                captured
-                                    .entry(*oid)
-                                    .or_default()
-                                    .push((group.side, *player, *origin, *gid));
+                                captured.entry(*oid).or_default().push((
+                                    group.side,
+                                    Some(*player),
+                                    *origin,
+                                    *gid,
+                                ));
                             }
                         }
                         DeployKind::Crate { .. }
                         | DeployKind::Deployed { .. }
                         | DeployKind::Objective
                         | DeployKind::Action { .. }
-                        | DeployKind::Troop { .. } => (),
+                        | DeployKind::Troop { .. }
+                        | DeployKind::Pilot { .. }
+                        | DeployKind::Convoy { .. }
+                        | DeployKind::Offensive { .. } => (),
+                    }
+                }
+                for gid in &self.persisted.offensives {
+                    let group = group!(self, gid)?;
+                    if let DeployKind::Offensive { from, .. } = &group.origin {
+                        let in_range = group
+                            .units
+                            .into_iter()
+                            .filter_map(|uid| self.persisted.units.get(uid))
+                            .any(|u| !u.dead && obj.zone.contains(u.pos));
+                        if in_range {
+                            captured.entry(*oid).or_default().push((
+                                group.side,
+                                None,
+                                Some(*from),
+                                *gid,
+                            ));
+                        }
                     }
                 }
             }
         }
+        let completed: SmallVec<[ObjectiveId; 1]> = match self.ephemeral.cfg.capture {
+            Some(cfg) => self.advance_captures(&cfg, &captured, now)?,
+            None => captured
+                .iter()
+                .filter(|(_, gids)| match gids.first() {
+                    None => false,
+                    Some((side, _, _, _)) => gids.iter().all(|(s, _, _, _)| side == s),
+                })
+                .map(|(oid, _)| *oid)
+                .collect(),
+        };
         let mut actually_captured = smallvec![];
-        for (oid, gids) in captured {
+        for oid in completed {
+            let gids = captured.remove(&oid).unwrap_or_default();
             let (side, _, _, _) = gids.first().ok_or_else(|| anyhow!("no guid"))?;
-            if gids.iter().all(|(s, _, _, _)| side == s) {
-                let obj = objective_mut!(self, oid)?;
-                let name = obj.name.clone();
-                let previous_owner = obj.owner;
-                let new_owner = *side;
-                obj.spawned = false;
-                obj.threatened = true;
-                obj.last_threatened_ts = now;
-                obj.last_activate = now;
-                obj.owner = new_owner;
-                actually_captured.push((*side, oid));
-                for gid in obj.groups.get(&obj.owner).unwrap_or(&Set::new()) {
-                    for uid in &group!(self, gid)?.units {
-                        if !self.ephemeral.object_id_by_uid.contains_key(uid) {
-                            unit_mut!(self, uid)?.dead = true;
-                        }
+            let obj = objective_mut!(self, oid)?;
+            let name = obj.name.clone();
+            let previous_owner = obj.owner;
+            let new_owner = *side;
+            obj.spawned = false;
+            obj.threatened = true;
+            obj.last_threatened_ts = now;
+            obj.last_activate = now;
+            obj.owner = new_owner;
+            obj.capture = None;
+            actually_captured.push((*side, oid));
+            for gid in obj.groups.get(&obj.owner).unwrap_or(&Set::new()) {
+                for uid in &group!(self, gid)?.units {
+                    if !self.ephemeral.object_id_by_uid.contains_key(uid) {
+                        unit_mut!(self, uid)?.dead = true;
                     }
                 }
-                for gid in obj.groups.get(&obj.owner.opposite()).unwrap_or(&Set::new()) {
-                    for uid in &group!(self, gid)?.units {
-                        if self.ephemeral.object_id_by_uid.contains_key(uid) {
-                            self.ephemeral
-                                .units_potentially_close_to_enemies
-                                .insert(*uid);
-                        }
+            }
+            for gid in obj.groups.get(&obj.owner.opposite()).unwrap_or(&Set::new()) {
+                for uid in &group!(self, gid)?.units {
+                    if self.ephemeral.object_id_by_uid.contains_key(uid) {
+                        self.ephemeral
+                            .units_potentially_close_to_enemies
+                            .insert(*uid);
                     }
                 }
-                let abid = self
-                    .ephemeral
-                    .airbase_by_oid
-                    .get(&oid)
-                    .ok_or_else(|| anyhow!("no airbase for objective {}", obj.name))?;
-                let airbase =
-                    Airbase::get_instance(lua, abid).context("getting captured airbase")?;
-                airbase
-                    .set_coalition(*side)
-                    .context("setting airbase coalition")?;
-                self.repair_one_logi_step(*side, now, oid)
-                    .context("repairing captured airbase logi")?;
-                self.repair_services(*side, now, oid)
-                    .context("repairing captured airbase services")?;
-                self.capture_warehouse(lua, oid)
-                    .context("capturing warehouse")?;
-                self.setup_supply_lines().context("setup supply lines")?;
-                self.deliver_supplies_from_logistics_hubs()
-                    .context("delivering supplies")?;
-                let mut ucids: SmallVec<[Ucid; 4]> = smallvec![];
-                for (_, ucid, troop_origin, gid) in gids {
-                    self.delete_group(&gid)
-                        .context("deleting capturing troops")?;
+            }
+            backend
+                .set_airbase_coalition(self.ephemeral.airbase_by_oid.get(&oid), *side)
+                .with_context(|| format_compact!("setting the coalition of {name}"))?;
+            self.repair_one_logi_step(*side, now, oid)
+                .context("repairing captured airbase logi")?;
+            self.repair_services(*side, now, oid)
+                .context("repairing captured airbase services")?;
+            self.capture_warehouse(oid).context("capturing warehouse")?;
+            self.setup_supply_lines().context("setup supply lines")?;
+            self.deliver_supplies_from_logistics_hubs()
+                .context("delivering supplies")?;
+            let mut ucids: SmallVec<[Ucid; 4]> = smallvec![];
+            for (_, ucid, troop_origin, gid) in gids {
+                self.delete_group(&gid)
+                    .context("deleting capturing troops")?;
+                if let Some(ucid) = ucid {
                     if previous_owner != new_owner || troop_origin != Some(oid) {
                         if !ucids.contains(&ucid) {
                             ucids.push(ucid);
                         }
                     }
                 }
-                if let Some(points) = self.ephemeral.cfg.points.as_ref() {
-                    let ppp = (points.capture as f32 / ucids.len() as f32).ceil() as i32;
-                    for ucid in ucids {
-                        self.adjust_points(&ucid, ppp, &format!("for capturing {name}"));
-                    }
-                }
-                let obj = objective!(self, oid)?;
-                self.ephemeral.create_objective_markup(&self.persisted, obj);
-                self.ephemeral.dirty();
             }
+            let ppp = match self.ephemeral.cfg.points.as_ref() {
+                None => 0,
+                Some(points) => (points.capture as f32 / ucids.len() as f32).ceil() as i32,
+            };
+            for ucid in ucids {
+                self.adjust_points(&ucid, ppp, &format!("for capturing {name}"));
+                self.career_mut(&ucid).captures += 1;
+                self.ephemeral.stat(StatKind::Capture {
+                    id: oid,
+                    ucid,
+                    side: new_owner,
+                    points: ppp as usize,
+                });
+            }
+            let obj = objective!(self, oid)?;
+            self.ephemeral.create_objective_markup(&self.persisted, obj);
+            self.ephemeral.dirty();
         }
         if actually_captured.len() > 0 {
+            self.update_front_line();
             self.ephemeral.logistics_stage = LogiStage::SyncToWarehouses {
                 objectives: self
                     .persisted
@@ -1133,6 +1259,139 @@ impl Db {
         Ok(actually_captured)
     }
 
+    /// true if living ground units of the side opposing attacker are
+    /// inside the zone of obj
+    fn capture_defended(&self, obj: &Objective, attacker: Side) -> bool {
+        let defender = attacker.opposite();
+        let alive = |gid: &GroupId| match self.persisted.groups.get(gid) {
+            Some(group) if group.side == defender && group.kind == Some(GroupCategory::Ground) => {
+                group
+                    .units
+                    .into_iter()
+                    .filter_map(|uid| self.persisted.units.get(uid))
+                    .any(|u| !u.dead && obj.zone.contains(u.pos))
+            }
+            Some(_) | None => false,
+        };
+        let garrison = obj.owner == defender
+            && obj
+                .groups
+                .get(&defender)
+                .map(|gids| gids.into_iter().any(alive))
+                .unwrap_or(false);
+        garrison
+            || self.persisted.deployed.into_iter().any(alive)
+            || self.persisted.troops.into_iter().any(alive)
+            || self.persisted.offensives.into_iter().any(alive)
+    }
+
+    /// Advance the timed capture of every objective with capturing
+    /// troops in it, or with capture progress left over from troops
+    /// that are gone. Returns the objectives whose capture is complete.
+    fn advance_captures(
+        &mut self,
+        cfg: &CaptureCfg,
+        attackers: &CaptureTroops,
+        now: DateTime<Utc>,
+    ) -> Result<SmallVec<[ObjectiveId; 1]>> {
+        fn rate(dt: f64, total: u32) -> f64 {
+            if total == 0 {
+                1.
+            } else {
+                dt / total as f64
+            }
+        }
+        let oids: SmallVec<[ObjectiveId; 16]> = self
+            .persisted
+            .objectives
+            .into_iter()
+            .filter(|(oid, obj)| obj.capture.is_some() || attackers.contains_key(*oid))
+            .map(|(oid, _)| *oid)
+            .collect();
+        let mut completed = smallvec![];
+        for oid in oids {
+            let obj = objective!(self, oid)?;
+            let name = obj.name.clone();
+            let mut sides: SmallVec<[Side; 2]> = smallvec![];
+            for (side, _, _, _) in attackers.get(&oid).map(|v| &v[..]).unwrap_or(&[]) {
+                if !sides.contains(side) {
+                    sides.push(*side)
+                }
+            }
+            let mut msgs: SmallVec<[CompactString; 2]> = smallvec![];
+            let capture = if !obj.captureable() {
+                None
+            } else if sides.is_empty() {
+                obj.capture.and_then(|mut c| {
+                    let dt = (now - c.last_update).num_milliseconds() as f64 / 1000.;
+                    c.progress -= rate(dt, cfg.decay);
+                    c.last_update = now;
+                    c.contested = false;
+                    if c.progress > 0. {
+                        Some(c)
+                    } else {
+                        msgs.push(format_compact!(
+                            "{} troops are no longer capturing {name}",
+                            c.side
+                        ));
+                        None
+                    }
+                })
+            } else {
+                let attacker = obj
+                    .capture
+                    .map(|c| c.side)
+                    .filter(|s| sides.contains(s))
+                    .unwrap_or(sides[0]);
+                let contested = sides.len() > 1 || self.capture_defended(obj, attacker);
+                let mut c = match obj.capture {
+                    Some(c) if c.side == attacker => c,
+                    Some(_) | None => {
+                        msgs.push(format_compact!("{attacker} troops are capturing {name}"));
+                        Capture {
+                            side: attacker,
+                            progress: 0.,
+                            contested: false,
+                            warned: false,
+                            last_update: now,
+                        }
+                    }
+                };
+                let dt = (now - c.last_update).num_milliseconds() as f64 / 1000.;
+                c.last_update = now;
+                if contested {
+                    if !c.contested {
+                        msgs.push(format_compact!(
+                            "the {attacker} capture of {name} is contested by defenders"
+                        ));
+                    }
+                } else {
+                    c.progress = (c.progress + rate(dt, cfg.time)).min(1.);
+                    let remaining = (1. - c.progress) * cfg.time as f64;
+                    if c.progress >= 1. {
+                        completed.push(oid);
+                    } else if !c.warned && remaining <= cfg.warning as f64 {
+                        c.warned = true;
+                        msgs.push(format_compact!(
+                            "{attacker} troops will capture {name} in {} seconds",
+                            remaining.ceil() as u32
+                        ));
+                    }
+                }
+                c.contested = contested;
+                Some(c)
+            };
+            if capture != obj.capture {
+                objective_mut!(self, oid)?.capture = capture;
+                self.ephemeral.dirty();
+            }
+            for msg in msgs {
+                self.ephemeral.msgs().panel_to_all(10, false, msg);
+            }
+        }
+        Ok(completed)
+    }
+
     pub fn update_objectives_markup(&mut self) -> Result<()> {
         for (_, obj) in &self.persisted.objectives {
             self.ephemeral.update_objective_markup(&self.persisted, obj)
@@ -0,0 +1,320 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! AI ground offensives. Objectives with enough supply and logi
+//! periodically send attack groups against the closest enemy
+//! objective in range. Each group costs warehouse inventory, drives
+//! to it's target by road, and captures it under the same rules as
+//! player troops. The size and frequency of attacks scale with the
+//! number of players on the side.
+
+use super::{
+    group::{DeployKind, GroupId},
+    objective::ObjectiveId,
+    Db,
+};
+use crate::{
+    cfg::UnitTag,
+    group, objective, objective_mut,
+    perf::PerfInner,
+    spawnctx::{SpawnCtx, SpawnLoc},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{prelude::*, Duration};
+use compact_str::format_compact;
+use dcso3::{azumith2d_to, coalition::Side, env::miz::MizIndex};
+use log::{error, warn};
+use smallvec::SmallVec;
+use std::{cmp::min, sync::Arc};
+
+/// scale factors are clamped to at least this so the time between
+/// attacks stays representable
+const MIN_SCALE: f32 = 0.001;
+
+impl Db {
+    /// the number of players in a slot on the side
+    fn side_population(&self, side: Side) -> u32 {
+        self.ephemeral
+            .players_by_slot
+            .values()
+            .filter(|ucid| {
+                self.persisted
+                    .players
+                    .get(ucid)
+                    .map(|p| p.side == side)
+                    .unwrap_or(false)
+            })
+            .count() as u32
+    }
+
+    fn offensive_groups(&self, side: Side) -> u32 {
+        self.persisted
+            .offensives
+            .into_iter()
+            .filter_map(|gid| self.persisted.groups.get(gid))
+            .filter(|g| g.side == side)
+            .count() as u32
+    }
+
+    /// Decide which objectives attack this logistics tick, and take
+    /// the cost of the attack from their warehouses. The attacks are
+    /// spawned later by spawn_offensives.
+    pub(super) fn plan_offensives(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let cfg = Arc::clone(&self.ephemeral.cfg);
+        let cfg = match &cfg.offensive {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        let range = (cfg.range as f64).powi(2);
+        for side in Side::ALL {
+            let factor = cfg.scale(self.side_population(side));
+            if factor <= 0. {
+                continue;
+            }
+            let factor = factor.max(MIN_SCALE);
+            let interval = Duration::seconds((cfg.freq as f32 * 60. / factor) as i64);
+            let size = ((cfg.groups as f32 * factor).round() as u32).max(1);
+            let mut in_field = self.offensive_groups(side);
+            let oids: SmallVec<[ObjectiveId; 64]> = self
+                .persisted
+                .objectives
+                .into_iter()
+                .filter(|(_, obj)| {
+                    obj.owner == side && obj.supply >= cfg.min_supply && obj.logi >= cfg.min_logi
+                })
+                .map(|(oid, _)| *oid)
+                .collect();
+            for oid in oids {
+                if in_field >= cfg.max_groups {
+                    break;
+                }
+                match self.ephemeral.last_offensive.get(&oid) {
+                    Some(last) if now - *last >= interval => (),
+                    Some(_) => continue,
+                    None => {
+                        self.ephemeral.last_offensive.insert(oid, now);
+                        continue;
+                    }
+                }
+                let obj = objective!(self, oid)?;
+                let pos = obj.zone.pos();
+                let target = Self::objective_near_point(&self.persisted.objectives, pos, |o| {
+                    o.owner != side
+                        && na::distance_squared(&o.zone.pos().into(), &pos.into()) <= range
+                })
+                .map(|(_, _, o)| o.id);
+                let target = match target {
+                    Some(target) => target,
+                    None => continue,
+                };
+                let affordable = cfg
+                    .cost
+                    .iter()
+                    .filter(|(_, cost)| **cost > 0)
+                    .map(|(name, cost)| obj.get_equipment(name).stored / cost)
+                    .min()
+                    .unwrap_or(u32::MAX);
+                let groups = min(min(size, cfg.max_groups - in_field), affordable);
+                if groups == 0 {
+                    continue;
+                }
+                let obj = objective_mut!(self, oid)?;
+                for (name, cost) in &cfg.cost {
+                    if let Some(inv) = obj.warehouse.equipment.get_mut_cow(name) {
+                        *inv -= cost * groups
+                    }
+                }
+                in_field += groups;
+                self.ephemeral.last_offensive.insert(oid, now);
+                self.ephemeral
+                    .pending_offensives
+                    .push((oid, target, groups));
+                self.ephemeral.dirty();
+            }
+        }
+        Ok(())
+    }
+
+    /// Take the attacks planned by the last logistics tick as
+    /// (from, to, groups). Used by the simulator, which spawns them
+    /// itself.
+    pub fn take_pending_offensives(&mut self) -> Vec<(ObjectiveId, ObjectiveId, u32)> {
+        std::mem::take(&mut self.ephemeral.pending_offensives)
+    }
+
+    fn offensive_speed(&self) -> Result<f64> {
+        self.ephemeral
+            .cfg
+            .offensive
+            .as_ref()
+            .map(|c| c.speed)
+            .ok_or_else(|| anyhow!("offensives are not enabled"))
+    }
+
+    /// put the cost of groups that never launched back in the warehouse
+    fn refund_offensive(&mut self, oid: ObjectiveId, groups: u32) -> Result<()> {
+        let cfg = Arc::clone(&self.ephemeral.cfg);
+        let cfg = match &cfg.offensive {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        let obj = objective_mut!(self, oid)?;
+        for (name, cost) in &cfg.cost {
+            if let Some(inv) = obj.warehouse.equipment.get_mut_cow(name) {
+                *inv += cost * groups
+            }
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// spawn the groups of an attack, spawned counts the groups that
+    /// made it into the mission even if a later one fails
+    fn spawn_offensive(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        (from, to, groups): (ObjectiveId, ObjectiveId, u32),
+        now: DateTime<Utc>,
+        spawned: &mut u32,
+    ) -> Result<()> {
+        let obj = objective!(self, from)?;
+        let (side, pos) = (obj.owner, obj.zone.pos());
+        let template = self
+            .ephemeral
+            .cfg
+            .offensive
+            .as_ref()
+            .and_then(|c| c.template.get(&side))
+            .ok_or_else(|| anyhow!("no attack group template for {side}"))?
+            .clone();
+        let speed = self.offensive_speed()?;
+        for _ in 0..groups {
+            let mission = self.road_mission(spctx.lua(), pos, &to, speed)?;
+            let (p0, p1) = (mission[0].pos.0, mission[1].pos.0);
+            let location = SpawnLoc::AtPos {
+                pos: p0,
+                offset_direction: (p0 - p1).normalize(),
+                group_heading: azumith2d_to(p0, p1),
+            };
+            let origin = DeployKind::Offensive {
+                from,
+                to,
+                time: now,
+            };
+            let gid = self.add_group(
+                spctx,
+                idx,
+                side,
+                location,
+                &template,
+                origin,
+                UnitTag::Driveable.into(),
+            )?;
+            self.ephemeral
+                .spawn_group(
+                    perf,
+                    &self.persisted,
+                    idx,
+                    spctx,
+                    group!(self, gid)?,
+                    mission,
+                )
+                .context("spawning attack group")?;
+            *spawned += 1;
+        }
+        let msg = format_compact!(
+            "{} is attacking {} with {groups} groups",
+            objective!(self, from)?.name,
+            objective!(self, to)?.name
+        );
+        self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+        Ok(())
+    }
+
+    /// Spawn the attacks planned by the last logistics tick
+    pub fn spawn_offensives(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        for attack in self.take_pending_offensives() {
+            let mut spawned = 0;
+            if let Err(e) = self.spawn_offensive(perf, spctx, idx, attack, now, &mut spawned) {
+                warn!("could not launch attack {attack:?}, {e:?}");
+                let (from, _, groups) = attack;
+                if let Err(e) = self.refund_offensive(from, groups - spawned) {
+                    error!("could not refund attack {attack:?}, {e:?}")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// respawn an attack group after a restart and send it on from where it stopped
+    pub(super) fn respawn_offensive(
+        &mut self,
+        perf: &mut PerfInner,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        gid: GroupId,
+    ) -> Result<()> {
+        let to = match &group!(self, gid)?.origin {
+            DeployKind::Offensive { to, .. } => *to,
+            _ => bail!("{gid} is not an attack group"),
+        };
+        if self.ephemeral.cfg.offensive.is_none() {
+            // check_offensives will call it off
+            return Ok(());
+        }
+        let speed = self.offensive_speed()?;
+        self.respawn_on_road(perf, spctx, idx, gid, &to, speed)
+    }
+
+    /// Withdraw attack groups whose target is already friendly, or
+    /// that have been in the field longer than their lifetime. If
+    /// offensives have been turned off every attack is called off.
+    pub fn check_offensives(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let lifetime = self
+            .ephemeral
+            .cfg
+            .offensive
+            .as_ref()
+            .map(|cfg| Duration::minutes(cfg.lifetime as i64));
+        let gids: SmallVec<[GroupId; 16]> =
+            self.persisted.offensives.into_iter().copied().collect();
+        for gid in gids {
+            let group = group!(self, gid)?;
+            let (to, time) = match &group.origin {
+                DeployKind::Offensive { to, time, .. } => (*to, *time),
+                _ => continue,
+            };
+            let side = group.side;
+            let target = objective!(self, to)?;
+            if target.owner == side {
+                self.delete_group(&gid)?;
+            } else if lifetime.map(|l| now - time >= l).unwrap_or(true) {
+                let msg = format_compact!("the attack on {} has been called off", target.name);
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+                self.delete_group(&gid)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -15,16 +15,27 @@ for more details.
 */
 
 use super::{
+    career::Career,
+    convoy::ConvoyCargo,
+    csar::Pilot,
     group::{GroupId, SpawnedGroup, SpawnedUnit, UnitId},
+    migrate::SchemaVersion,
     objective::{Objective, ObjectiveId},
     player::Player,
-    Map, Set,
+    squadron::Squadron,
+    JtacSettings, Map, Set,
 };
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+use compact_str::{format_compact, CompactString};
 use dcso3::{coalition::Side, net::Ucid, String};
 use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Persisted {
+    #[serde(default)]
+    pub version: SchemaVersion,
     pub groups: Map<GroupId, SpawnedGroup>,
     pub units: Map<UnitId, SpawnedUnit>,
     pub groups_by_name: Map<String, GroupId>,
@@ -38,6 +49,14 @@ pub struct Persisted {
     pub ewrs: Set<GroupId>,
     #[serde(default)]
     pub actions: Set<GroupId>,
+    #[serde(default)]
+    pub pilots: Set<GroupId>,
+    #[serde(default)]
+    pub convoys: Set<GroupId>,
+    #[serde(default)]
+    pub offensives: Set<GroupId>,
+    #[serde(default)]
+    pub counter_batteries: Set<GroupId>,
     pub objectives: Map<ObjectiveId, Objective>,
     pub objectives_by_name: Map<String, ObjectiveId>,
     pub objectives_by_group: Map<GroupId, ObjectiveId>,
@@ -48,10 +67,229 @@ pub struct Persisted {
     pub nukes_used: u32,
     #[serde(default)]
     pub logistics_ticks_since_delivery: u32,
+    #[serde(default)]
+    pub round: u32,
+    /// the side that won the round, once it is over
+    #[serde(default)]
+    pub winner: Option<Side>,
+    /// when each side started holding enough objectives to meet the
+    /// hold win condition
+    #[serde(default)]
+    pub holding: Map<Side, DateTime<Utc>>,
+    #[serde(default)]
+    pub careers: Map<Ucid, Career>,
+    #[serde(default)]
+    pub squadrons: Map<String, Squadron>,
+    #[serde(default)]
+    pub jtac_settings: Map<GroupId, JtacSettings>,
+    /// supplies loaded onto convoys by the last logistics tick that
+    /// aren't on the road yet, by source then destination
+    #[serde(default)]
+    pub pending_convoys: Map<ObjectiveId, Map<ObjectiveId, ConvoyCargo>>,
+    /// rescued pilots on board each player's aircraft, by the player
+    /// carrying them
+    #[serde(default)]
+    pub carried_pilots: Map<Ucid, SmallVec<[Pilot; 1]>>,
 }
 
 impl Persisted {
     pub fn players(&self) -> &Map<Ucid, Player> {
         &self.players
     }
+
+    /// Check that the indexes agree with the data they index
+    pub fn validate(&self) -> Result<()> {
+        let mut errors: Vec<CompactString> = vec![];
+        macro_rules! check {
+            ($cond:expr, $($msg:tt)+) => {
+                if !$cond {
+                    errors.push(format_compact!($($msg)+))
+                }
+            };
+        }
+        for (gid, group) in &self.groups {
+            check!(group.id == *gid, "group {gid} has id {}", group.id);
+            check!(
+                self.groups_by_name.get(&group.name) == Some(gid),
+                "group {gid} {} is not indexed by name",
+                group.name
+            );
+            check!(
+                self.groups_by_side
+                    .get(&group.side)
+                    .map(|s| s.contains(gid))
+                    .unwrap_or(false),
+                "group {gid} is not indexed by side"
+            );
+            for uid in &group.units {
+                check!(
+                    self.units
+                        .get(uid)
+                        .map(|u| u.group == *gid)
+                        .unwrap_or(false),
+                    "group {gid} unit {uid} is missing or belongs to another group"
+                );
+            }
+        }
+        for (name, gid) in &self.groups_by_name {
+            check!(
+                self.groups
+                    .get(gid)
+                    .map(|g| &g.name == name)
+                    .unwrap_or(false),
+                "group name {name} indexes missing or renamed group {gid}"
+            );
+        }
+        for (side, gids) in &self.groups_by_side {
+            for gid in gids {
+                check!(
+                    self.groups
+                        .get(gid)
+                        .map(|g| g.side == *side)
+                        .unwrap_or(false),
+                    "{side} indexes missing or other side group {gid}"
+                );
+            }
+        }
+        for (uid, unit) in &self.units {
+            check!(unit.id == *uid, "unit {uid} has id {}", unit.id);
+            check!(
+                self.units_by_name.get(&unit.name) == Some(uid),
+                "unit {uid} {} is not indexed by name",
+                unit.name
+            );
+            check!(
+                self.groups
+                    .get(&unit.group)
+                    .map(|g| g.units.contains(uid))
+                    .unwrap_or(false),
+                "unit {uid} is not in it's group {}",
+                unit.group
+            );
+        }
+        for (name, uid) in &self.units_by_name {
+            check!(
+                self.units
+                    .get(uid)
+                    .map(|u| &u.name == name)
+                    .unwrap_or(false),
+                "unit name {name} indexes missing or renamed unit {uid}"
+            );
+        }
+        for (oid, obj) in &self.objectives {
+            check!(obj.id == *oid, "objective {oid} has id {}", obj.id);
+            check!(
+                self.objectives_by_name.get(&obj.name) == Some(oid),
+                "objective {oid} {} is not indexed by name",
+                obj.name
+            );
+            for (_, gids) in &obj.groups {
+                for gid in gids {
+                    check!(
+                        self.objectives_by_group.get(gid) == Some(oid),
+                        "objective {oid} group {gid} is not indexed"
+                    );
+                    check!(
+                        self.groups.get(gid).is_some(),
+                        "objective {oid} group {gid} is missing"
+                    );
+                }
+            }
+        }
+        for (name, oid) in &self.objectives_by_name {
+            check!(
+                self.objectives
+                    .get(oid)
+                    .map(|o| &o.name == name)
+                    .unwrap_or(false),
+                "objective name {name} indexes missing or renamed objective {oid}"
+            );
+        }
+        for (gid, oid) in &self.objectives_by_group {
+            check!(
+                self.objectives
+                    .get(oid)
+                    .map(|o| o.groups.into_iter().any(|(_, gids)| gids.contains(gid)))
+                    .unwrap_or(false),
+                "group {gid} indexes missing objective {oid} or one that doesn't own it"
+            );
+        }
+        for (name, set) in [
+            ("deployed", &self.deployed),
+            ("crates", &self.crates),
+            ("troops", &self.troops),
+            ("jtacs", &self.jtacs),
+            ("ewrs", &self.ewrs),
+            ("actions", &self.actions),
+            ("pilots", &self.pilots),
+            ("convoys", &self.convoys),
+            ("offensives", &self.offensives),
+            ("counter_batteries", &self.counter_batteries),
+        ] {
+            for gid in set {
+                check!(
+                    self.groups.get(gid).is_some(),
+                    "{name} group {gid} is missing"
+                );
+            }
+        }
+        for (gid, _) in &self.jtac_settings {
+            check!(
+                self.jtacs.contains(gid),
+                "jtac settings for {gid} which is not a jtac"
+            );
+        }
+        for (from, to) in &self.pending_convoys {
+            for oid in [from].into_iter().chain(to.into_iter().map(|(oid, _)| oid)) {
+                check!(
+                    self.objectives.get(oid).is_some(),
+                    "pending convoy objective {oid} is missing"
+                );
+            }
+        }
+        for (name, set) in [
+            ("farps", &self.farps),
+            ("logistics_hubs", &self.logistics_hubs),
+        ] {
+            for oid in set {
+                check!(
+                    self.objectives.get(oid).is_some(),
+                    "{name} objective {oid} is missing"
+                );
+            }
+        }
+        let mut members: Set<Ucid> = Set::new();
+        for (name, sq) in &self.squadrons {
+            for (ucid, _) in &sq.members {
+                check!(
+                    !members.contains(ucid),
+                    "{ucid} is a member of {name} and another squadron"
+                );
+                members.insert_cow(*ucid);
+            }
+            for ucid in &sq.officers {
+                check!(
+                    sq.members.get(ucid).is_some(),
+                    "squadron {name} officer {ucid} is not a member"
+                );
+            }
+        }
+        for (ucid, pilots) in &self.carried_pilots {
+            check!(
+                self.players.get(ucid).is_some(),
+                "pilots carried by missing player {ucid}"
+            );
+            for pilot in pilots {
+                check!(
+                    self.players.get(&pilot.player).is_some(),
+                    "{ucid} is carrying missing player {}",
+                    pilot.player
+                );
+            }
+        }
+        if !errors.is_empty() {
+            bail!(errors.join("\n"))
+        }
+        Ok(())
+    }
 }
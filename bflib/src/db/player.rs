@@ -20,9 +20,12 @@ use super::{
     Db, Map, Set,
 };
 use crate::{
-    cfg::{LifeType, PointsCfg, UnitTag, Vehicle},
+    acmi::{AcmiKey, AcmiType, Props, Record},
+    audit::{AuditEntry, AuditKind},
+    cfg::{LifeType, UnitTag, Vehicle},
     maybe, maybe_mut, objective_mut,
     shots::Dead,
+    stats::StatKind,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::{prelude::*, Duration};
@@ -30,6 +33,7 @@ use compact_str::{format_compact, CompactString};
 use dcso3::{
     airbase::Airbase,
     coalition::Side,
+    coord::Coord,
     net::{SlotId, Ucid},
     object::{DcsObject, DcsOid},
     unit::{ClassUnit, Unit},
@@ -71,6 +75,7 @@ pub struct InstancedPlayer {
     pub in_air: bool,
     pub landed_at_objective: Option<ObjectiveId>,
     pub moved: Option<DateTime<Utc>>,
+    pub took_off: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,9 +104,12 @@ pub struct Player {
 
 impl Db {
     pub fn player_deslot(&mut self, ucid: &Ucid) {
+        self.end_flight(ucid, Utc::now());
         if let Some(player) = self.persisted.players.get_mut_cow(ucid) {
             player.airborne = None;
             if let Some((slot, _)) = player.current_slot.take() {
+                // any pilots they were carrying are lost with the aircraft
+                self.persisted.carried_pilots.remove_cow(ucid);
                 let _ = self
                     .ephemeral
                     .player_deslot(&self.persisted, &slot, Some(*ucid));
@@ -201,7 +209,11 @@ impl Db {
                                 origin: _,
                             } => Some(*player),
                             DeployKind::Action { player, .. } => player.clone(),
-                            DeployKind::Crate { .. } | DeployKind::Objective => None,
+                            DeployKind::Crate { .. }
+                            | DeployKind::Objective
+                            | DeployKind::Pilot { .. }
+                            | DeployKind::Convoy { .. }
+                            | DeployKind::Offensive { .. } => None,
                         })
                 }
             }
@@ -219,21 +231,29 @@ impl Db {
             .slot_info
             .get(&slot)
             .ok_or_else(|| anyhow!("could not find slot {:?}", slot))?;
-        let player = self
-            .ephemeral
-            .players_by_slot
-            .get(&slot)
-            .and_then(|ucid| self.persisted.players.get_mut_cow(ucid))
-            .ok_or_else(|| anyhow!("could not find player in slot {:?}", slot))?;
         let life_type = match self.ephemeral.cfg.life_types.get(&sifo.typ) {
             None => bail!("no life type for vehicle {:?}", sifo.typ),
             Some(typ) => *typ,
         };
+        let ucid = *self
+            .ephemeral
+            .players_by_slot
+            .get(&slot)
+            .ok_or_else(|| anyhow!("could not find player in slot {:?}", slot))?;
+        let aircraft = sifo.typ.clone();
+        self.ephemeral.stat(StatKind::Takeoff { ucid, aircraft });
+        self.career_mut(&ucid).takeoffs += 1;
+        let player = self
+            .persisted
+            .players
+            .get_mut_cow(&ucid)
+            .ok_or_else(|| anyhow!("could not find player {ucid}"))?;
         let (_, player_lives) = player.lives.get_or_insert_cow(life_type, || {
             (time, self.ephemeral.cfg.default_lives[&life_type].0)
         });
         if let Some((_, Some(inst))) = &mut player.current_slot {
             inst.landed_at_objective = None;
+            inst.took_off = Some(time);
         }
         let is_on_owned_objective = self
             .persisted
@@ -257,21 +277,44 @@ impl Db {
         }
     }
 
-    pub fn land(&mut self, slot: SlotId, position: Vector2) -> Option<LifeType> {
+    pub fn land(
+        &mut self,
+        slot: SlotId,
+        position: Vector2,
+        now: DateTime<Utc>,
+    ) -> Option<LifeType> {
         let sifo = match self.ephemeral.slot_info.get(&slot) {
             Some(sifo) => sifo,
             None => return None,
         };
-        let player = match self
-            .ephemeral
-            .players_by_slot
-            .get(&slot)
-            .and_then(|ucid| self.persisted.players.get_mut_cow(ucid))
-        {
-            Some(player) => player,
+        let ucid = match self.ephemeral.players_by_slot.get(&slot) {
+            Some(ucid) => *ucid,
             None => return None,
         };
         let life_type = self.ephemeral.cfg.life_types[&sifo.typ];
+        // land is retried until a life is returned, only count the
+        // landing once
+        if self.end_flight(&ucid, now) {
+            self.career_mut(&ucid).landings += 1;
+        }
+        let returned = self.return_life_on_land(&ucid, life_type, position);
+        self.ephemeral.stat(StatKind::Land {
+            ucid,
+            life_returned: returned.is_some(),
+        });
+        returned
+    }
+
+    fn return_life_on_land(
+        &mut self,
+        ucid: &Ucid,
+        life_type: LifeType,
+        position: Vector2,
+    ) -> Option<LifeType> {
+        let player = match self.persisted.players.get_mut_cow(ucid) {
+            Some(player) => player,
+            None => return None,
+        };
         let (_, player_lives) = match player.lives.get_mut_cow(&life_type) {
             Some(l) => l,
             None => return None,
@@ -334,6 +377,7 @@ impl Db {
         slot: SlotId,
         ucid: &Ucid,
     ) -> SlotAuth {
+        let squadron = self.squadron_of(ucid).map(|(name, _)| name.clone());
         let player = match self.persisted.players.get_mut_cow(ucid) {
             Some(player) => player,
             None => {
@@ -345,6 +389,11 @@ impl Db {
         };
         if slot.is_spectator() {
             player.jtac_or_spectators = true;
+            self.ephemeral.stat(StatKind::Slot {
+                ucid: *ucid,
+                slot,
+                aircraft: None,
+            });
             return SlotAuth::Yes;
         }
         if slot_side != player.side {
@@ -355,6 +404,11 @@ impl Db {
             SlotId::Instructor(_, _) => {
                 if self.ephemeral.cfg.admins.contains_key(ucid) {
                     player.jtac_or_spectators = true;
+                    self.ephemeral.stat(StatKind::Slot {
+                        ucid: *ucid,
+                        slot,
+                        aircraft: None,
+                    });
                     SlotAuth::Yes
                 } else {
                     SlotAuth::Denied
@@ -363,8 +417,13 @@ impl Db {
             SlotId::ArtilleryCommander(_, _)
             | SlotId::ForwardObserver(_, _)
             | SlotId::Observer(_, _) => {
-                if self.ephemeral.cfg.rules.ca.check(ucid) {
+                if self.ephemeral.cfg.rules.ca.check(ucid, squadron.as_ref()) {
                     player.jtac_or_spectators = true;
+                    self.ephemeral.stat(StatKind::Slot {
+                        ucid: *ucid,
+                        slot,
+                        aircraft: None,
+                    });
                     SlotAuth::Yes
                 } else {
                     SlotAuth::Denied
@@ -467,6 +526,13 @@ impl Db {
                         player_team_kills: Map::new(),
                     },
                 );
+                let points = self.persisted.players[&ucid].points;
+                self.ephemeral.stat(StatKind::PlayerRegister {
+                    name,
+                    ucid,
+                    side,
+                    points: max(0, points) as usize,
+                });
                 self.ephemeral.dirty();
                 Ok(())
             }
@@ -476,6 +542,8 @@ impl Db {
     pub fn force_sideswitch_player(&mut self, ucid: &Ucid, side: Side) -> Result<()> {
         let player = maybe_mut!(self.persisted.players, ucid, "no such player")?;
         player.side = side;
+        self.ephemeral
+            .stat(StatKind::PlayerSideswitch { ucid: *ucid, side });
         self.ephemeral.dirty();
         Ok(())
     }
@@ -498,6 +566,8 @@ impl Db {
                         None => (),
                     }
                     player.side = side;
+                    self.ephemeral
+                        .stat(StatKind::PlayerSideswitch { ucid: *ucid, side });
                     self.ephemeral.dirty();
                     Ok(())
                 }
@@ -512,6 +582,7 @@ impl Db {
         ids: impl IntoIterator<Item = &'a Ucid>,
     ) -> Result<Vec<DcsOid<ClassUnit>>> {
         let mut dead: Vec<DcsOid<ClassUnit>> = vec![];
+        let mut moved: SmallVec<[Ucid; 16]> = smallvec![];
         let mut unit: Option<Unit> = None;
         for ucid in ids {
             if let Some(player) = self.persisted.players.get_mut_cow(ucid) {
@@ -529,6 +600,7 @@ impl Db {
                                     inst.velocity = instance.get_velocity()?.0;
                                     inst.in_air = instance.in_air()?;
                                     inst.moved = Some(now);
+                                    moved.push(*ucid);
                                 }
                                 unit = Some(instance);
                             }
@@ -543,9 +615,50 @@ impl Db {
                 }
             }
         }
+        self.record_player_tracks(lua, now, &moved)?;
         Ok(dead)
     }
 
+    fn record_player_tracks(
+        &mut self,
+        lua: MizLua,
+        now: DateTime<Utc>,
+        ucids: &[Ucid],
+    ) -> Result<()> {
+        if !self.ephemeral.cfg.acmi || ucids.is_empty() {
+            return Ok(());
+        }
+        let coord = Coord::singleton(lua)?;
+        for ucid in ucids {
+            let player = maybe!(self.persisted.players, ucid, "player")?;
+            if let Some((_, Some(inst))) = &player.current_slot {
+                let tags = self
+                    .ephemeral
+                    .cfg
+                    .unit_classification
+                    .get(&inst.typ)
+                    .copied()
+                    .unwrap_or_default();
+                let typ = if tags.contains(UnitTag::Helicopter) {
+                    AcmiType::Rotorcraft
+                } else {
+                    AcmiType::FixedWing
+                };
+                let props = Props {
+                    typ,
+                    name: inst.typ.0.clone(),
+                    pilot: Some(player.name.clone()),
+                    group: None,
+                    side: player.side,
+                };
+                let record =
+                    Record::sample(&coord, now, AcmiKey::Player(*ucid), &inst.position, props)?;
+                self.ephemeral.acmi(record)
+            }
+        }
+        Ok(())
+    }
+
     pub fn update_player_positions_incremental(
         &mut self,
         lua: MizLua,
@@ -630,6 +743,13 @@ impl Db {
         if let Err(e) = adjust_warehouse() {
             error!("couldn't adjust warehouse {:?}", e)
         }
+        let typ = Vehicle::from(unit.get_type_name()?);
+        self.ephemeral.stat(StatKind::Slot {
+            ucid,
+            slot,
+            aircraft: Some(typ.clone()),
+        });
+        self.career_mut(&ucid).sorties += 1;
         let player = maybe_mut!(self.persisted.players, ucid, "player")?;
         let position = unit.get_position()?;
         let point = Vector2::new(position.p.x, position.p.z);
@@ -645,9 +765,10 @@ impl Db {
                 position,
                 velocity: unit.get_velocity()?.0,
                 in_air: unit.in_air()?,
-                typ: Vehicle::from(unit.get_type_name()?),
+                typ,
                 landed_at_objective,
                 moved: None,
+                took_off: None,
             }),
         ));
         player.changing_slots = false;
@@ -734,6 +855,27 @@ impl Db {
         self.player_deslot(ucid);
     }
 
+    /// record an automated punishment of target in the audit journal
+    pub(crate) fn audit_punishment(
+        &mut self,
+        action: &str,
+        target: &Ucid,
+        args: &str,
+        result: &str,
+    ) {
+        let mut entry = AuditEntry::new(AuditKind::Punishment, action);
+        let name = self
+            .persisted
+            .players
+            .get(target)
+            .map(|p| p.name.clone())
+            .unwrap_or_default();
+        entry.target = Some((*target, name));
+        entry.args = args.into();
+        entry.result = Ok(result.replace('\n', " ").trim().into());
+        self.ephemeral.audit(entry)
+    }
+
     fn apply_teamkill_penalty(
         &mut self,
         shooter: Ucid,
@@ -832,14 +974,23 @@ impl Db {
                     write!(msg, "have a nice day").unwrap();
                     self.ephemeral
                         .force_player_to_spectators_at(&shooter, now + Duration::seconds(30));
+                    self.audit_punishment(
+                        "force-spectator",
+                        &shooter,
+                        victim,
+                        "repeated team kills",
+                    );
                 }
                 msg
             }
         }
     }
 
-    pub fn award_kill_points(&mut self, cfg: PointsCfg, dead: Dead) {
-        let mut hit_by: SmallVec<[&Ucid; 16]> = smallvec![];
+    /// Credit the players who hit the victim with the kill in their
+    /// careers, and award points for it if points are enabled
+    pub fn player_kill(&mut self, dead: Dead) {
+        let points_cfg = self.ephemeral.cfg.points;
+        let mut hit_by: SmallVec<[Ucid; 16]> = smallvec![];
         let non_self_shots = || {
             dead.shots.iter().filter(|shot| {
                 (shot.target_gid.is_none() || shot.target_gid != shot.shooter_gid)
@@ -850,28 +1001,74 @@ impl Db {
             })
         };
         for shot in non_self_shots() {
-            if shot.hit && !hit_by.contains(&&shot.shooter_ucid) {
-                hit_by.push(&shot.shooter_ucid);
+            if shot.hit && !hit_by.contains(&shot.shooter_ucid) {
+                hit_by.push(shot.shooter_ucid);
             }
         }
         if hit_by.is_empty() {
             for shot in non_self_shots() {
                 if dead.time - shot.time <= Duration::minutes(3)
-                    && !hit_by.contains(&&shot.shooter_ucid)
+                    && !hit_by.contains(&shot.shooter_ucid)
                 {
-                    hit_by.push(&shot.shooter_ucid);
+                    hit_by.push(shot.shooter_ucid);
                 }
             }
         }
         if !hit_by.is_empty() {
+            let victim_typ = (&dead.shots)
+                .into_iter()
+                .find(|s| s.target_typ.trim() != "")
+                .map(|s| &s.target_typ);
+            let victim_tags = victim_typ
+                .and_then(|typ| self.ephemeral.cfg.unit_classification.get(typ.as_str()))
+                .copied();
+            let victim_info = dead
+                .victim_ucid
+                .as_ref()
+                .and_then(|i| self.persisted.players.get(i).map(|p| (*i, p)))
+                .map(|(i, p)| (i, p.name.clone(), p.airborne));
+            // a player in a ground vehicle is a ground kill, if the type
+            // is unknown go by whether the player was flying
+            let air = match victim_tags {
+                Some(tags) => {
+                    tags.contains(UnitTag::Aircraft) || tags.contains(UnitTag::Helicopter)
+                }
+                None => matches!(&victim_info, Some((_, _, Some(_)))),
+            };
+            for ucid in &hit_by {
+                let enemy = self
+                    .persisted
+                    .players
+                    .get(ucid)
+                    .map(|p| p.side != dead.victim_side)
+                    .unwrap_or(false);
+                if enemy {
+                    let weapon = dead
+                        .shots
+                        .iter()
+                        .rev()
+                        .filter(|s| &s.shooter_ucid == ucid)
+                        .find(|s| s.hit)
+                        .or_else(|| dead.shots.iter().rev().find(|s| &s.shooter_ucid == ucid))
+                        .and_then(|s| s.weapon_name.as_ref());
+                    self.record_kill(ucid, air, weapon, victim_typ);
+                }
+            }
+            let cfg = match points_cfg {
+                Some(cfg) => cfg,
+                None => {
+                    self.ephemeral.stat(StatKind::Kill {
+                        shots: dead,
+                        team_kill: false,
+                        points: smallvec![],
+                    });
+                    return;
+                }
+            };
             let total_points = if dead.victim_ucid.is_some() {
                 cfg.air_kill
             } else {
-                (&dead.shots)
-                    .into_iter()
-                    .find(|s| s.target_typ.trim() != "")
-                    .map(|s| &s.target_typ)
-                    .and_then(|typ| self.ephemeral.cfg.unit_classification.get(typ.as_str()))
+                victim_tags
                     .map(|tags| {
                         if tags.contains(UnitTag::LR | UnitTag::TrackRadar | UnitTag::SAM) {
                             cfg.ground_kill + cfg.lr_sam_bonus
@@ -886,14 +1083,12 @@ impl Db {
                     .unwrap_or(cfg.ground_kill)
             };
             let pps = (total_points as f32 / hit_by.len() as f32).ceil() as i32;
-            let victim_info = dead
-                .victim_ucid
-                .as_ref()
-                .and_then(|i| self.persisted.players.get(i).map(|p| (*i, p)))
-                .map(|(i, p)| (i, p.name.clone(), p.airborne));
-            for ucid in hit_by {
+            let mut team_kill = false;
+            let mut points: SmallVec<[(Ucid, usize); 2]> = smallvec![];
+            for ucid in &hit_by {
                 if let Some(player) = self.persisted.players.get_mut_cow(ucid) {
                     let msg = if player.side != dead.victim_side {
+                        points.push((*ucid, pps as usize));
                         player.points += pps;
                         let tp = player.points;
                         match &victim_info {
@@ -903,13 +1098,25 @@ impl Db {
                             }
                         }
                     } else {
-                        self.apply_teamkill_penalty(*ucid, total_points, &victim_info)
+                        team_kill = true;
+                        let msg = self.apply_teamkill_penalty(*ucid, total_points, &victim_info);
+                        let victim = match &victim_info {
+                            Some((_, victim, _)) => victim.as_str(),
+                            None => "ai unit",
+                        };
+                        self.audit_punishment("teamkill-penalty", ucid, victim, &msg);
+                        msg
                     };
                     debug!("{ucid} kill message: {msg}");
                     self.ephemeral
                         .panel_to_player(&self.persisted, 10, &ucid, msg)
                 }
             }
+            self.ephemeral.stat(StatKind::Kill {
+                shots: dead,
+                team_kill,
+                points,
+            })
         }
     }
 
@@ -0,0 +1,209 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::{
+    career::Career, objective::ObjectiveKind, read_persisted, squadron::Squadron, Db, Map,
+};
+use crate::{cfg::WinCfg, stats::StatKind};
+use anyhow::{anyhow, Result};
+use chrono::{prelude::*, Duration};
+use compact_str::format_compact;
+use dcso3::{coalition::Side, net::Ucid, String};
+use fxhash::FxHashMap;
+use log::info;
+use smallvec::{smallvec, SmallVec};
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SIDES: [Side; 2] = [Side::Red, Side::Blue];
+
+/// The path where the final state of a round is archived
+pub fn archive_path(state: &Path, round: u32) -> Result<PathBuf> {
+    let name = state
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow!("save file with no name"))?;
+    Ok(state.with_file_name(format_compact!("{name}_round{round}").as_str()))
+}
+
+/// The number of the next round, one more than the last archived round
+pub fn next_round(state: &Path) -> Result<u32> {
+    let name = state
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow!("save file with no name"))?;
+    let dir = state
+        .parent()
+        .ok_or_else(|| anyhow!("path has no parent dir"))?;
+    let mut next = 0;
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        let fname = file.file_name();
+        let round = fname
+            .to_str()
+            .and_then(|f| f.strip_prefix(name))
+            .and_then(|f| f.strip_prefix("_round"))
+            .and_then(|n| n.parse::<u32>().ok());
+        if let Some(round) = round {
+            next = std::cmp::max(next, round + 1)
+        }
+    }
+    Ok(next)
+}
+
+/// The careers and squadrons as they were at the end of the last
+/// archived round
+pub fn last_carryover(state: &Path) -> Result<(Map<Ucid, Career>, Map<String, Squadron>)> {
+    match next_round(state)? {
+        0 => Ok((Map::new(), Map::new())),
+        n => {
+            let path = archive_path(state, n - 1)?;
+            if !path.exists() {
+                return Ok((Map::new(), Map::new()));
+            }
+            let persisted = read_persisted(&path)?;
+            Ok((persisted.careers, persisted.squadrons))
+        }
+    }
+}
+
+impl Db {
+    fn owns_all_airbases(&self, side: Side) -> bool {
+        let mut airbases = self
+            .objectives()
+            .filter(|(_, obj)| obj.is_airbase())
+            .peekable();
+        airbases.peek().is_some() && airbases.all(|(_, obj)| obj.owner == side)
+    }
+
+    /// A side with no logistics hubs at all isn't exhausted, it may
+    /// just be a mission without hubs for that side
+    fn supply_exhausted(&self, side: Side) -> bool {
+        let mut hubs = self
+            .persisted
+            .logistics_hubs
+            .into_iter()
+            .filter_map(|oid| self.persisted.objectives.get(oid))
+            .filter(|obj| obj.owner == side)
+            .peekable();
+        hubs.peek().is_some()
+            && hubs.all(|obj| {
+                let wh = &obj.warehouse;
+                wh.equipment.into_iter().all(|(_, inv)| inv.stored == 0)
+                    && wh.liquids.into_iter().all(|(_, inv)| inv.stored == 0)
+            })
+    }
+
+    /// update how long each side has held the share of objectives
+    /// needed to win, and return the side that has held it long
+    /// enough. Farps come and go with the players, so they don't
+    /// count. If both sides have held it long enough the one that has
+    /// held it longer wins, then the one that owns more, and if that
+    /// is also the same nobody wins yet.
+    fn check_hold(&mut self, percent: u8, hours: u32, now: DateTime<Utc>) -> Option<Side> {
+        let mut total = 0;
+        let mut owned: FxHashMap<Side, usize> = FxHashMap::default();
+        for (_, obj) in self.objectives() {
+            match obj.kind {
+                ObjectiveKind::Farp { .. } => (),
+                ObjectiveKind::Airbase | ObjectiveKind::Fob | ObjectiveKind::Logistics => {
+                    total += 1;
+                    *owned.entry(obj.owner).or_default() += 1;
+                }
+            }
+        }
+        if total == 0 {
+            return None;
+        }
+        let mut held: SmallVec<[(DateTime<Utc>, usize, Side); 2]> = smallvec![];
+        for side in SIDES {
+            let owned = owned.get(&side).copied().unwrap_or(0);
+            if owned * 100 >= total * percent as usize {
+                let since = match self.persisted.holding.get(&side) {
+                    Some(ts) => *ts,
+                    None => {
+                        self.persisted.holding.insert_cow(side, now);
+                        self.ephemeral.dirty();
+                        now
+                    }
+                };
+                if now - since >= Duration::hours(hours as i64) {
+                    held.push((since, owned, side))
+                }
+            } else if self.persisted.holding.remove_cow(&side).is_some() {
+                self.ephemeral.dirty()
+            }
+        }
+        match &held[..] {
+            [] => None,
+            [(_, _, side)] => Some(*side),
+            [a, b, ..] => match a.0.cmp(&b.0).then(b.1.cmp(&a.1)) {
+                Ordering::Less => Some(a.2),
+                Ordering::Greater => Some(b.2),
+                Ordering::Equal => None,
+            },
+        }
+    }
+
+    /// the side that meets a win condition when only one of them does
+    fn sole_winner(won: impl Fn(Side) -> bool) -> Option<Side> {
+        match SIDES.map(won) {
+            [true, false] => Some(SIDES[0]),
+            [false, true] => Some(SIDES[1]),
+            [false, false] | [true, true] => None,
+        }
+    }
+
+    /// Check the configured win conditions. The first time one of
+    /// them is met the round ends and the winner is returned. The
+    /// conditions are checked in order, all airbases, hold, then
+    /// supply exhausted, and the first one that decides a winner
+    /// ends the round. A condition that both sides meet at once
+    /// doesn't decide anything, except hold, see check_hold.
+    pub fn check_round_end(&mut self, now: DateTime<Utc>) -> Option<Side> {
+        if self.persisted.winner.is_some() {
+            return None;
+        }
+        let WinCfg {
+            all_airbases,
+            hold,
+            supply_exhausted,
+        } = self.ephemeral.cfg.win?;
+        let mut winner = None;
+        if all_airbases {
+            winner = Self::sole_winner(|side| self.owns_all_airbases(side));
+        }
+        if let Some(hold) = hold {
+            let holder = self.check_hold(hold.percent, hold.hours, now);
+            winner = winner.or(holder);
+        }
+        // without warehouses nothing is ever in stock
+        if supply_exhausted && winner.is_none() && self.ephemeral.cfg.warehouse.is_some() {
+            winner = Self::sole_winner(|side| self.supply_exhausted(side.opposite()));
+        }
+        let winner = winner?;
+        info!("round {} won by {winner}", self.persisted.round);
+        self.persisted.winner = Some(winner);
+        self.ephemeral.stat(StatKind::RoundEnd {
+            winner: Some(winner),
+        });
+        self.ephemeral.dirty();
+        Some(winner)
+    }
+}
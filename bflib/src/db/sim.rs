@@ -0,0 +1,427 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Run the campaign logic without DCS. A Sim owns a Db that is built
+//! up by hand instead of from a miz, and steps it through the same
+//! periodic tasks the mission runs, so supply, repair, and capture
+//! behavior can be exercised from tests or tools.
+
+use super::{
+    backend::Backend,
+    ephemeral::{Equipment, LogiStage, Production},
+    group::{DeployKind, GroupId, SpawnedGroup, SpawnedUnit, UnitId},
+    objective::{ObjGroupClass, Objective, ObjectiveId, ObjectiveKind, Zone},
+    Db, Set,
+};
+use crate::{
+    cfg::{Cfg, UnitTags, Vehicle},
+    objective_mut,
+    perf::Perf,
+    stats::Stat,
+};
+use anyhow::{anyhow, Result};
+use chrono::{prelude::*, Duration};
+use compact_str::format_compact;
+use dcso3::{
+    airbase::ClassAirbase,
+    coalition::Side,
+    group::{ClassGroup, GroupCategory},
+    net::Ucid,
+    object::DcsOid,
+    warehouse::{LiquidType, WSCategory},
+    Position3, String, Vector2, Vector3,
+};
+use enumflags2::BitFlags;
+use smallvec::SmallVec;
+use std::sync::Arc;
+
+/// A flat world with no AI, where the objectives are the source of
+/// truth for their warehouses.
+#[derive(Debug, Clone, Default)]
+pub struct SimBackend {
+    /// when set, terrain blocks line of sight everywhere
+    pub terrain_masking: bool,
+}
+
+impl Backend for SimBackend {
+    fn is_visible(&mut self, _dist: f64, _p0: Vector3, _p1: Vector3) -> Result<bool> {
+        Ok(!self.terrain_masking)
+    }
+
+    fn set_ai_enabled(&mut self, _group: &DcsOid<ClassGroup>, _enabled: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_airbase_coalition(
+        &mut self,
+        _airbase: Option<&DcsOid<ClassAirbase>>,
+        _side: Side,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_warehouse_to_objective(
+        &mut self,
+        _airbase: Option<&DcsOid<ClassAirbase>>,
+        _obj: &mut Objective,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_objective_to_warehouse(
+        &mut self,
+        _airbase: Option<&DcsOid<ClassAirbase>>,
+        _obj: &Objective,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// What happened during one step of the simulation
+#[derive(Debug, Default)]
+pub struct StepReport {
+    pub captured: SmallVec<[(Side, ObjectiveId); 1]>,
+    pub threatened: SmallVec<[ObjectiveId; 4]>,
+    pub cleared: SmallVec<[ObjectiveId; 4]>,
+    /// groups the mission would have spawned
+    pub spawned: usize,
+    /// groups the mission would have despawned
+    pub despawned: usize,
+    /// the side that won the round in this step, if any
+    pub winner: Option<Side>,
+    pub stats: Vec<Stat>,
+}
+
+pub struct Sim {
+    pub db: Db,
+    pub backend: SimBackend,
+    now: DateTime<Utc>,
+    perf: Perf,
+}
+
+impl Sim {
+    pub fn new(cfg: Cfg, start: DateTime<Utc>) -> Self {
+        let mut db = Db::default();
+        db.ephemeral.cfg = Arc::new(cfg);
+        Self {
+            db,
+            backend: SimBackend::default(),
+            now: start,
+            perf: Perf::default(),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    pub fn add_objective(
+        &mut self,
+        name: &str,
+        kind: ObjectiveKind,
+        owner: Side,
+        pos: Vector2,
+        radius: f64,
+    ) -> ObjectiveId {
+        let zone = Zone::Circle { pos, radius };
+        self.db.add_objective(String::from(name), kind, owner, zone)
+    }
+
+    fn add_group(
+        &mut self,
+        side: Side,
+        template: &str,
+        origin: DeployKind,
+        units: &[(&str, Vector2)],
+    ) -> Result<GroupId> {
+        let gid = GroupId::new();
+        let name = String::from(format_compact!("{template}-{gid}"));
+        let mut group = SpawnedGroup {
+            id: gid,
+            name: name.clone(),
+            template_name: String::from(template),
+            side,
+            kind: Some(GroupCategory::Ground),
+            class: ObjGroupClass::from(template),
+            origin,
+            units: Set::new(),
+            tags: UnitTags(BitFlags::empty()),
+        };
+        for (typ, pos) in units {
+            let uid = UnitId::new();
+            let tags = *self
+                .db
+                .ephemeral
+                .cfg
+                .unit_classification
+                .get(*typ)
+                .ok_or_else(|| anyhow!("unit type not classified {typ}"))?;
+            group.tags.0.insert(tags.0);
+            let position = {
+                let mut p = Position3::default();
+                p.p.x = pos.x;
+                p.p.z = pos.y;
+                p
+            };
+            let unit_name = String::from(format_compact!("{name}-{uid}"));
+            let unit = SpawnedUnit {
+                name: unit_name.clone(),
+                id: uid,
+                group: gid,
+                side,
+                typ: Vehicle::from(String::from(*typ)),
+                tags,
+                template_name: String::from(template),
+                spawn_pos: *pos,
+                spawn_position: position,
+                pos: *pos,
+                position,
+                ..SpawnedUnit::default()
+            };
+            group.units.insert_cow(uid);
+            self.db.persisted.units.insert_cow(uid, unit);
+            self.db.persisted.units_by_name.insert_cow(unit_name, uid);
+        }
+        self.db.insert_group(group)
+    }
+
+    /// Add a group of units, given as (type, position), that defends
+    /// an objective. The template name determines the group's class
+    /// the same way it does in the miz, e.g. RLOGI is a red logistics
+    /// group. As in the miz, an objective needs groups for every side
+    /// that may capture it.
+    pub fn add_objective_group(
+        &mut self,
+        oid: ObjectiveId,
+        side: Side,
+        template: &str,
+        units: &[(&str, Vector2)],
+    ) -> Result<GroupId> {
+        let gid = self.add_group(side, template, DeployKind::Objective, units)?;
+        objective_mut!(self.db, oid)?
+            .groups
+            .get_or_default_cow(side)
+            .insert_cow(gid);
+        self.db.persisted.objectives_by_group.insert_cow(gid, oid);
+        Ok(gid)
+    }
+
+    /// Set what a side produces each delivery
+    pub fn set_production(
+        &mut self,
+        side: Side,
+        equipment: &[(&str, u32)],
+        liquids: &[(LiquidType, u32)],
+    ) {
+        let production = Production {
+            equipment: equipment
+                .iter()
+                .map(|(name, production)| {
+                    let eq = Equipment {
+                        category: WSCategory::Vehicles,
+                        production: *production,
+                    };
+                    (String::from(*name), eq)
+                })
+                .collect(),
+            liquids: liquids.iter().copied().collect(),
+        };
+        self.db
+            .ephemeral
+            .production_by_side
+            .insert(side, Arc::new(production));
+    }
+
+    /// Set how much of an equipment item is stored at an objective,
+    /// e.g. to model it being consumed by players
+    pub fn set_stored(&mut self, oid: ObjectiveId, name: &str, stored: u32) -> Result<()> {
+        objective_mut!(self.db, oid)?
+            .warehouse
+            .equipment
+            .get_or_default_cow(String::from(name))
+            .stored = stored;
+        Ok(())
+    }
+
+    /// Finish setting up the campaign once the objectives, their
+    /// groups, and production are in place.
+    pub fn start(&mut self) -> Result<()> {
+        let oids = self
+            .db
+            .persisted
+            .objectives
+            .into_iter()
+            .map(|(oid, _)| *oid)
+            .collect::<SmallVec<[ObjectiveId; 64]>>();
+        for oid in oids {
+            self.db.update_objective_status(&oid, self.now)?
+        }
+        self.db.fill_warehouses();
+        self.db.update_front_line();
+        self.db.setup_supply_lines()
+    }
+
+    /// Deploy the named troops from the player's side config at the
+    /// given unit positions as if the player had unloaded them.
+    pub fn deploy_troops(
+        &mut self,
+        ucid: Ucid,
+        name: &str,
+        origin: Option<ObjectiveId>,
+        units: &[(&str, Vector2)],
+    ) -> Result<GroupId> {
+        let side = self
+            .db
+            .player(&ucid)
+            .ok_or_else(|| anyhow!("unknown player {ucid}"))?
+            .side;
+        let spec = self
+            .db
+            .ephemeral
+            .cfg
+            .troops
+            .get(&side)
+            .and_then(|troops| troops.iter().find(|t| t.name.as_str() == name))
+            .ok_or_else(|| anyhow!("{side} has no troops named {name}"))?
+            .clone();
+        let template = spec.template.clone();
+        let origin = DeployKind::Troop {
+            player: ucid,
+            origin,
+            moved_by: None,
+            spec,
+        };
+        self.add_group(side, &template, origin, units)
+    }
+
+    /// Deploy the deployable from the player's side config whose menu
+    /// path ends in name at the given unit positions as if the player
+    /// had unpacked its crates.
+    pub fn deploy(&mut self, ucid: Ucid, name: &str, units: &[(&str, Vector2)]) -> Result<GroupId> {
+        let side = self
+            .db
+            .player(&ucid)
+            .ok_or_else(|| anyhow!("unknown player {ucid}"))?
+            .side;
+        let spec = self
+            .db
+            .ephemeral
+            .cfg
+            .deployables
+            .get(&side)
+            .and_then(|deps| {
+                deps.iter()
+                    .find(|d| d.path.last().map(|n| n.as_str()) == Some(name))
+            })
+            .ok_or_else(|| anyhow!("{side} has no deployable named {name}"))?
+            .clone();
+        let template = spec.template.clone();
+        let origin = DeployKind::Deployed {
+            player: ucid,
+            moved_by: None,
+            spec,
+        };
+        self.add_group(side, &template, origin, units)
+    }
+
+    pub fn kill_unit(&mut self, uid: UnitId) -> Result<()> {
+        self.db.unit_died(uid, self.now)
+    }
+
+    /// Put the convoys loaded by the last logistics tick on the road
+    /// as groups of the given unit types at their supplier.
+    pub fn spawn_convoys(&mut self, units: &[&str]) -> Result<Vec<GroupId>> {
+        let mut res = vec![];
+        for (from, to, cargo) in self.db.take_pending_convoys() {
+            let obj = self.db.objective(&from)?;
+            let (side, pos) = (obj.owner, obj.zone.pos());
+            let units = units.iter().map(|typ| (*typ, pos)).collect::<Vec<_>>();
+            let origin = DeployKind::Convoy {
+                from,
+                to,
+                cargo,
+                time: self.now,
+            };
+            res.push(self.add_group(side, "CONVOY", origin, &units)?);
+        }
+        Ok(res)
+    }
+
+    /// Launch the attacks planned by the last logistics tick as
+    /// groups of the given unit types at the attacking objective.
+    pub fn spawn_offensives(&mut self, units: &[&str]) -> Result<Vec<GroupId>> {
+        let mut res = vec![];
+        for (from, to, groups) in self.db.take_pending_offensives() {
+            let obj = self.db.objective(&from)?;
+            let (side, pos) = (obj.owner, obj.zone.pos());
+            let units = units.iter().map(|typ| (*typ, pos)).collect::<Vec<_>>();
+            for _ in 0..groups {
+                let origin = DeployKind::Offensive {
+                    from,
+                    to,
+                    time: self.now,
+                };
+                res.push(self.add_group(side, "ATTACK", origin, &units)?);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Teleport every unit in the group to pos
+    pub fn move_group(&mut self, gid: GroupId, pos: Vector2) -> Result<()> {
+        let uids = self.db.group(&gid)?.units.clone();
+        for uid in &uids {
+            let unit = self
+                .db
+                .persisted
+                .units
+                .get_mut_cow(uid)
+                .ok_or_else(|| anyhow!("missing unit {uid}"))?;
+            unit.pos = pos;
+            unit.position.p.x = pos.x;
+            unit.position.p.z = pos.y;
+        }
+        Ok(())
+    }
+
+    /// Advance the clock by dt and run the campaign logic once. The
+    /// logistics state machine runs to completion instead of
+    /// advancing one objective at a time.
+    pub fn step(&mut self, dt: Duration) -> Result<StepReport> {
+        self.now += dt;
+        let now = self.now;
+        let mut report = StepReport::default();
+        self.db.maybe_do_repairs(now)?;
+        let (threatened, cleared) = self.db.cull_or_respawn_objectives(&mut self.backend, now)?;
+        report.threatened = threatened;
+        report.cleared = cleared;
+        report.captured = self.db.check_capture(&mut self.backend, now)?;
+        let perf = Arc::make_mut(&mut self.perf.inner);
+        self.db.logistics_step(&mut self.backend, perf, now)?;
+        while !matches!(
+            self.db.ephemeral.logistics_stage,
+            LogiStage::Complete { .. }
+        ) {
+            self.db.logistics_step(&mut self.backend, perf, now)?;
+        }
+        self.db.check_convoys(now)?;
+        self.db.check_offensives(now)?;
+        report.winner = self.db.check_round_end(now);
+        (report.spawned, report.despawned) = self.db.ephemeral.clear_spawn_queues();
+        report.stats = self.db.ephemeral.take_stats();
+        Ok(report)
+    }
+}
@@ -0,0 +1,330 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Player squadrons. A squadron has a tag, players whose name starts
+//! with the tag may join it. Officers manage the roster and may open
+//! a shared points pool that members draw on when their own points
+//! don't cover an action. Squadrons carry over into the next round,
+//! and can be named in the rules.
+
+use super::{Db, Map, Set};
+use crate::cfg::Rule;
+use anyhow::{anyhow, bail, Result};
+use compact_str::{format_compact, CompactString};
+use dcso3::{net::Ucid, String};
+use serde_derive::{Deserialize, Serialize};
+use std::{cmp::min, fmt::Write};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Squadron {
+    /// the prefix a player's name must start with to join
+    pub tag: String,
+    /// members and their name when they joined
+    pub members: Map<Ucid, String>,
+    /// members who may manage the roster and the pool
+    pub officers: Set<Ucid>,
+    /// the shared points pool, if the officers have opened one
+    #[serde(default)]
+    pub pool: Option<u32>,
+}
+
+impl Squadron {
+    /// a short summary suitable for the chat window
+    pub fn summary(&self, name: &str) -> Vec<CompactString> {
+        let mut officers = CompactString::new("");
+        let mut members = CompactString::new("");
+        for (ucid, pname) in &self.members {
+            let s = if self.officers.contains(ucid) {
+                &mut officers
+            } else {
+                &mut members
+            };
+            if !s.is_empty() {
+                s.push_str(", ");
+            }
+            let _ = write!(s, "{pname}");
+        }
+        let mut res = vec![format_compact!(
+            "{name} [{}] {} members",
+            self.tag,
+            self.members.len()
+        )];
+        res.push(format_compact!("officers: {officers}"));
+        if !members.is_empty() {
+            res.push(format_compact!("members: {members}"));
+        }
+        match self.pool {
+            None => res.push(format_compact!("no shared points pool")),
+            Some(pool) => res.push(format_compact!("shared points pool: {pool}")),
+        }
+        res
+    }
+}
+
+impl Db {
+    pub fn squadrons(&self) -> impl Iterator<Item = (&String, &Squadron)> {
+        self.persisted.squadrons.into_iter()
+    }
+
+    /// the squadron the player belongs to, if any
+    pub fn squadron_of(&self, ucid: &Ucid) -> Option<(&String, &Squadron)> {
+        self.persisted
+            .squadrons
+            .into_iter()
+            .find(|(_, sq)| sq.members.get(ucid).is_some())
+    }
+
+    /// look up a squadron name ignoring case
+    pub fn squadron_name(&self, name: &str) -> Result<String> {
+        self.persisted
+            .squadrons
+            .into_iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(n, _)| n.clone())
+            .ok_or_else(|| anyhow!("no squadron named {name}"))
+    }
+
+    /// check a rule taking into account the player's squadron
+    pub fn check_rule(&self, rule: &Rule, ucid: &Ucid) -> bool {
+        rule.check(ucid, self.squadron_of(ucid).map(|(name, _)| name))
+    }
+
+    fn officer_of(&self, ucid: &Ucid) -> Result<String> {
+        match self.squadron_of(ucid) {
+            None => bail!("you are not in a squadron"),
+            Some((_, sq)) if !sq.officers.contains(ucid) => {
+                bail!("you are not an officer of your squadron")
+            }
+            Some((name, _)) => Ok(name.clone()),
+        }
+    }
+
+    fn squadron_mut(&mut self, name: &String) -> Result<&mut Squadron> {
+        self.persisted
+            .squadrons
+            .get_mut_cow(name)
+            .ok_or_else(|| anyhow!("no squadron named {name}"))
+    }
+
+    fn player_name(&self, ucid: &Ucid) -> Result<String> {
+        self.persisted
+            .players
+            .get(ucid)
+            .map(|p| p.name.clone())
+            .ok_or_else(|| anyhow!("you must join a side first"))
+    }
+
+    pub fn create_squadron(&mut self, ucid: &Ucid, name: &str, tag: &str) -> Result<()> {
+        let pname = self.player_name(ucid)?;
+        let name = name.trim();
+        let tag = tag.trim();
+        if name.is_empty() || tag.is_empty() {
+            bail!("a squadron needs a name and a tag")
+        }
+        if let Some((sq, _)) = self.squadron_of(ucid) {
+            bail!("you are already in {sq}")
+        }
+        if self.squadron_name(name).is_ok() {
+            bail!("there is already a squadron named {name}")
+        }
+        if let Some((sq, _)) = self
+            .squadrons()
+            .find(|(_, sq)| sq.tag.starts_with(tag) || tag.starts_with(sq.tag.as_str()))
+        {
+            bail!("the tag {tag} overlaps with the tag of {sq}")
+        }
+        if !pname.starts_with(tag) {
+            bail!("your name must start with the squadron tag {tag}")
+        }
+        let name = String::from(name);
+        self.persisted.squadrons.insert_cow(
+            name.clone(),
+            Squadron {
+                tag: String::from(tag),
+                members: Map::from_iter([(*ucid, pname.clone())]),
+                officers: Set::from_iter([*ucid]),
+                pool: None,
+            },
+        );
+        self.ephemeral.msgs().panel_to_all(
+            10,
+            false,
+            format_compact!("{pname} has founded the squadron {name}"),
+        );
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    pub fn join_squadron(&mut self, ucid: &Ucid, name: &str) -> Result<()> {
+        let pname = self.player_name(ucid)?;
+        if let Some((sq, _)) = self.squadron_of(ucid) {
+            bail!("you are already in {sq}, leave it first")
+        }
+        let name = self.squadron_name(name)?;
+        let sq = self.squadron_mut(&name)?;
+        if !pname.starts_with(sq.tag.as_str()) {
+            bail!("your name must start with the squadron tag {}", sq.tag)
+        }
+        sq.members.insert_cow(*ucid, pname);
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// Leave the player's squadron. When the last officer leaves
+    /// another member is promoted, when the last member leaves the
+    /// squadron is disbanded and the pool is returned to them.
+    pub fn leave_squadron(&mut self, ucid: &Ucid) -> Result<()> {
+        let name = match self.squadron_of(ucid) {
+            Some((name, _)) => name.clone(),
+            None => bail!("you are not in a squadron"),
+        };
+        let sq = self.squadron_mut(&name)?;
+        sq.members.remove_cow(ucid);
+        sq.officers.remove_cow(ucid);
+        if sq.members.len() == 0 {
+            let pool = sq.pool.unwrap_or(0);
+            self.persisted.squadrons.remove_cow(&name);
+            if pool > 0 {
+                self.adjust_points(ucid, pool as i32, &format!("disbanded {name}"));
+            }
+        } else if sq.officers.len() == 0 {
+            if let Some(next) = sq.members.into_iter().next().map(|(u, _)| *u) {
+                sq.officers.insert_cow(next);
+            }
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    pub fn kick_from_squadron(&mut self, officer: &Ucid, target: &Ucid) -> Result<()> {
+        let name = self.officer_of(officer)?;
+        if officer == target {
+            bail!("use leave to leave your squadron")
+        }
+        let sq = self.squadron_mut(&name)?;
+        if sq.members.remove_cow(target).is_none() {
+            bail!("they are not a member of {name}")
+        }
+        sq.officers.remove_cow(target);
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    pub fn set_squadron_officer(
+        &mut self,
+        officer: &Ucid,
+        target: &Ucid,
+        promote: bool,
+    ) -> Result<()> {
+        let name = self.officer_of(officer)?;
+        let sq = self.squadron_mut(&name)?;
+        if sq.members.get(target).is_none() {
+            bail!("they are not a member of {name}")
+        }
+        if promote {
+            sq.officers.insert_cow(*target);
+        } else {
+            if sq.officers.len() == 1 && sq.officers.contains(target) {
+                bail!("a squadron must have at least one officer")
+            }
+            sq.officers.remove_cow(target);
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// open or close the shared points pool, only an empty pool may be closed
+    pub fn set_squadron_pool(&mut self, officer: &Ucid, open: bool) -> Result<()> {
+        let name = self.officer_of(officer)?;
+        let sq = self.squadron_mut(&name)?;
+        match (open, sq.pool) {
+            (true, None) => sq.pool = Some(0),
+            (true, Some(_)) => bail!("{name} already has a pool"),
+            (false, None) => bail!("{name} doesn't have a pool"),
+            (false, Some(0)) => sq.pool = None,
+            (false, Some(n)) => bail!("the pool still has {n} points, spend them first"),
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    pub fn deposit_to_squadron(&mut self, ucid: &Ucid, amount: u32) -> Result<()> {
+        let name = match self.squadron_of(ucid) {
+            Some((name, sq)) if sq.pool.is_some() => name.clone(),
+            Some((name, _)) => bail!("{name} doesn't have a pool"),
+            None => bail!("you are not in a squadron"),
+        };
+        let points = self
+            .persisted
+            .players
+            .get(ucid)
+            .map(|p| p.points)
+            .ok_or_else(|| anyhow!("you must join a side first"))?;
+        if points < amount as i32 {
+            bail!("insufficient balance, you have {points}, you requested {amount}")
+        }
+        if let Some(pool) = self.squadron_mut(&name)?.pool.as_mut() {
+            *pool += amount
+        }
+        self.adjust_points(ucid, -(amount as i32), &format!("deposited to {name}"));
+        Ok(())
+    }
+
+    /// the points the player can spend, including their squadron pool
+    pub fn spendable_points(&self, ucid: &Ucid) -> i32 {
+        let points = self
+            .persisted
+            .players
+            .get(ucid)
+            .map(|p| p.points)
+            .unwrap_or(0);
+        let pool = self
+            .squadron_of(ucid)
+            .and_then(|(_, sq)| sq.pool)
+            .unwrap_or(0);
+        points + pool as i32
+    }
+
+    /// Spend points on behalf of the player. Their own points are
+    /// used first, and the remainder is drawn from the squadron pool.
+    pub fn spend_points(&mut self, ucid: &Ucid, cost: u32, why: &str) {
+        let points = self
+            .persisted
+            .players
+            .get(ucid)
+            .map(|p| p.points)
+            .unwrap_or(0);
+        let short = cost.saturating_sub(points.max(0) as u32);
+        let drawn = match self.squadron_of(ucid) {
+            Some((name, sq)) if short > 0 && sq.pool.is_some() => {
+                let name = name.clone();
+                let drawn = min(short, sq.pool.unwrap_or(0));
+                if let Ok(sq) = self.squadron_mut(&name) {
+                    sq.pool = sq.pool.map(|p| p - drawn);
+                }
+                if drawn > 0 {
+                    let msg = format_compact!("{drawn} points drawn from the {name} pool");
+                    self.ephemeral
+                        .panel_to_player(&self.persisted, 10, ucid, msg);
+                    self.ephemeral.dirty();
+                }
+                drawn
+            }
+            Some(_) | None => 0,
+        };
+        self.adjust_points(ucid, -((cost - drawn) as i32), why)
+    }
+}
@@ -15,6 +15,7 @@ for more details.
 */
 
 use crate::{
+    cfg::{DeployableEwr, RcsClass},
     db::{
         group::GroupId,
         player::{InstancedPlayer, Player},
@@ -23,7 +24,7 @@ use crate::{
     landcache::LandCache,
 };
 use anyhow::Result;
-use chrono::prelude::*;
+use chrono::{prelude::*, Duration};
 use dcso3::{
     azumith2d_to, azumith3d, coalition::Side, land::Land, net::Ucid, radians_to_degrees, MizLua,
     Position3, Vector2, Vector3,
@@ -32,12 +33,63 @@ use fxhash::FxHashMap;
 use smallvec::{smallvec, SmallVec};
 use std::fmt;
 
+/// The radius of the earth scaled by 4/3 to account for the
+/// standard atmosphere bending radar waves over the horizon (Meters)
+const EFFECTIVE_EARTH_RADIUS: f64 = 6_371_000. * 4. / 3.;
+
+/// The greatest distance at which an antenna at altitude h0 can see
+/// a target at altitude h1 over a smooth earth (Meters)
+pub fn radar_horizon(h0: f64, h1: f64) -> f64 {
+    let d = |h: f64| (2. * EFFECTIVE_EARTH_RADIUS * h.max(0.)).sqrt();
+    d(h0) + d(h1)
+}
+
+/// Can the radar at radar_pos detect a target of the given radar
+/// cross section class at pos moving with velocity. los is called
+/// last, only if every other check passes, with the distance, the
+/// antenna position, and the target position, and should return
+/// true if terrain does not mask the target.
+pub fn detect<F>(
+    ewr: &DeployableEwr,
+    radar_pos: Vector3,
+    pos: Vector3,
+    velocity: Vector3,
+    rcs: RcsClass,
+    los: F,
+) -> Result<bool>
+where
+    F: FnOnce(f64, Vector3, Vector3) -> Result<bool>,
+{
+    let mut antenna = radar_pos;
+    antenna.y += ewr.antenna_height as f64;
+    let dist = na::distance(&antenna.into(), &pos.into());
+    if dist > ewr.range as f64 * rcs.range_factor() {
+        return Ok(false);
+    }
+    if dist > radar_horizon(antenna.y, pos.y) {
+        return Ok(false);
+    }
+    // looking down the target is against the ground, and a doppler
+    // radar can't tell it from clutter unless it is moving toward or
+    // away from the radar
+    if ewr.notch > 0 && pos.y <= antenna.y && dist > 0. {
+        let radial = velocity.dot(&((pos - antenna) / dist)).abs();
+        if radial < ewr.notch as f64 {
+            return Ok(false);
+        }
+    }
+    los(dist, antenna, pos)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum TrackId {
     Player(Ucid),
     Group(GroupId),
 }
 
+/// An aircraft a radar may detect
+type Contact = (TrackId, Side, Position3, Vector3, RcsClass);
+
 #[derive(Debug, Clone, Copy)]
 pub struct GibBraa {
     pub bearing: u16,
@@ -139,15 +191,26 @@ pub struct Ewr {
 }
 
 impl Ewr {
+    /// Update the radar picture of each side. extra are radars that
+    /// aren't ewrs, such as the search radars of emitting SAM sites.
     pub fn update_tracks(
         &mut self,
         lua: MizLua,
         landcache: &mut LandCache,
         db: &Db,
+        extra: &[(Vector3, Side, DeployableEwr)],
         now: DateTime<Utc>,
     ) -> Result<()> {
         let land = Land::singleton(lua)?;
-        let aircraft: SmallVec<[(TrackId, Side, Position3, Vector3); 128]> = {
+        let rcs = |typ| {
+            db.ephemeral
+                .cfg
+                .radar_cross_section
+                .get(typ)
+                .copied()
+                .unwrap_or_default()
+        };
+        let aircraft: SmallVec<[Contact; 128]> = {
             let players = db
                 .instanced_players()
                 .filter(|(_, _, inst)| inst.in_air)
@@ -157,6 +220,7 @@ impl Ewr {
                         player.side,
                         inst.position,
                         inst.velocity,
+                        rcs(&inst.typ),
                     )
                 });
             let actions = db
@@ -169,27 +233,25 @@ impl Ewr {
                         .into_iter()
                         .filter_map(|uid| db.persisted.units.get(uid))
                         .filter_map(|su| {
-                            su.airborne_velocity
-                                .map(|v| (TrackId::Group(sg.id), sg.side, su.position, v))
+                            su.airborne_velocity.map(|v| {
+                                (TrackId::Group(sg.id), sg.side, su.position, v, rcs(&su.typ))
+                            })
                         })
                 });
             players.chain(actions).collect()
         };
-        for (mut ewr_pos, side, ewr) in db.ewrs() {
-            let range = (ewr.range as f64).powi(2);
+        let extra = extra.iter().map(|(pos, side, ewr)| (*pos, *side, ewr));
+        for (ewr_pos, side, ewr) in db.ewrs().chain(extra) {
             let tracks = self.tracks.entry(side).or_default();
-            ewr_pos.y += 10.; // factor in antenna height
-            for (id, side, pos, velocity) in &aircraft {
+            for (id, side, pos, velocity, rcs) in &aircraft {
                 let track = tracks.entry(*id).or_default();
                 if track.last != now {
-                    let dist = na::distance_squared(&ewr_pos.into(), &pos.p.0.into());
-                    if dist <= range {
-                        if landcache.is_visible(&land, dist.sqrt(), ewr_pos, pos.p.0)? {
-                            track.pos = *pos;
-                            track.velocity = *velocity;
-                            track.last = now;
-                            track.side = *side;
-                        }
+                    let los = |d, p0, p1| landcache.is_visible(&land, d, p0, p1);
+                    if detect(ewr, ewr_pos, pos.p.0, *velocity, *rcs, los)? {
+                        track.pos = *pos;
+                        track.velocity = *velocity;
+                        track.last = now;
+                        track.side = *side;
                     }
                 }
             }
@@ -197,6 +259,22 @@ impl Ewr {
         Ok(())
     }
 
+    /// The positions of the hostile aircraft in side's radar picture
+    /// that have been seen within max_age of now
+    pub fn hostile_tracks(
+        &self,
+        side: Side,
+        now: DateTime<Utc>,
+        max_age: Duration,
+    ) -> impl Iterator<Item = Vector3> + '_ {
+        self.tracks
+            .get(&side)
+            .into_iter()
+            .flat_map(|tracks| tracks.values())
+            .filter(move |t| t.side != side && now - t.last <= max_age)
+            .map(|t| t.pos.p.0)
+    }
+
     pub fn toggle(&mut self, ucid: &Ucid) -> bool {
         let st = self.player_state.entry(ucid.clone()).or_default();
         st.enabled = !st.enabled;
@@ -0,0 +1,259 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Integrated air defense. SAM sites stay dark until their side's
+//! radar picture shows a hostile within engagement range, launchers
+//! without a search radar are cued by nearby emitting search radars,
+//! and a site that has an anti radiation missile fired at it shuts
+//! down for a while.
+
+use crate::{
+    cfg::{DeployableEwr, IadsCfg, UnitTag},
+    db::{
+        group::{DeployKind, GroupId},
+        Db,
+    },
+    ewr::Ewr,
+};
+use anyhow::{Context, Result};
+use chrono::{prelude::*, Duration};
+use compact_str::format_compact;
+use dcso3::{coalition::Side, group::Group, MizLua, Vector2, Vector3};
+use fxhash::FxHashMap;
+use log::{error, info};
+use smallvec::{smallvec, SmallVec};
+
+/// A SAM site as the network sees it
+#[derive(Debug, Clone, Copy)]
+pub struct SiteState {
+    pub side: Side,
+    pub pos: Vector2,
+    /// the site has a search radar of its own
+    pub search: bool,
+    /// the site is hiding from an anti radiation missile
+    pub suppressed: bool,
+}
+
+/// Decide which sites should be emitting. hostile holds the
+/// position of every aircraft in a side's radar picture that is
+/// hostile to that side.
+pub fn plan(
+    cfg: &IadsCfg,
+    sites: &[SiteState],
+    hostile: &[(Side, Vector2)],
+) -> SmallVec<[bool; 64]> {
+    let within = |a: Vector2, b: Vector2, r: u32| {
+        na::distance_squared(&a.into(), &b.into()) <= (r as f64).powi(2)
+    };
+    let engaged: SmallVec<[bool; 64]> = sites
+        .iter()
+        .map(|site| {
+            !site.suppressed
+                && hostile.iter().any(|(side, pos)| {
+                    *side == site.side && within(site.pos, *pos, cfg.engagement_range)
+                })
+        })
+        .collect();
+    sites
+        .iter()
+        .zip(engaged.iter())
+        .map(|(site, in_range)| {
+            *in_range
+                || (!site.suppressed
+                    && !site.search
+                    && sites.iter().zip(engaged.iter()).any(|(radar, engaged)| {
+                        *engaged
+                            && radar.search
+                            && radar.side == site.side
+                            && within(site.pos, radar.pos, cfg.share_range)
+                    }))
+        })
+        .collect()
+}
+
+fn set_emission(lua: MizLua, db: &Db, gid: &GroupId, on: bool) -> Result<()> {
+    let name = &db.group(gid)?.name;
+    Group::get_by_name(lua, name)
+        .with_context(|| format_compact!("getting group {name}"))?
+        .enable_emission(on)
+        .with_context(|| format_compact!("setting emission of {name} to {on}"))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Site {
+    emitting: bool,
+    dark_until: Option<DateTime<Utc>>,
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        // sites spawn with their radars on
+        Self {
+            emitting: true,
+            dark_until: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Iads {
+    sites: FxHashMap<GroupId, Site>,
+}
+
+impl Iads {
+    /// The spawned objective and deployed groups with air defense
+    /// radars or launchers. Culled groups aren't in the mission, so
+    /// there is nothing to turn on or off.
+    fn sites(db: &Db) -> SmallVec<[(GroupId, Side, Vector2, bool); 64]> {
+        let air_defense = UnitTag::SAM | UnitTag::SearchRadar | UnitTag::TrackRadar;
+        db.groups()
+            .filter_map(|(gid, group)| {
+                match group.origin {
+                    DeployKind::Objective | DeployKind::Deployed { .. } => (),
+                    _ => return None,
+                }
+                if group.side == Side::Neutral || !group.tags.0.intersects(air_defense) {
+                    return None;
+                }
+                let spawned = group.units.into_iter().any(|uid| {
+                    db.unit(uid).map(|u| !u.dead).unwrap_or(false)
+                        && db.ephemeral.get_object_id_by_uid(uid).is_some()
+                });
+                if !spawned {
+                    return None;
+                }
+                let pos = db.group_center(gid).ok()?;
+                let search = group.tags.contains(UnitTag::SearchRadar);
+                Some((*gid, group.side, pos, search))
+            })
+            .collect()
+    }
+
+    /// The search radars of the emitting sites. They add to their
+    /// side's radar picture like an ewr.
+    pub fn search_radars(&self, db: &Db) -> SmallVec<[(Vector3, Side, DeployableEwr); 16]> {
+        let cfg = match &db.ephemeral.cfg.iads {
+            Some(cfg) => cfg,
+            None => return smallvec![],
+        };
+        self.sites
+            .iter()
+            .filter(|(_, site)| site.emitting)
+            .filter_map(|(gid, _)| {
+                let group = db.group(gid).ok()?;
+                if !group.tags.contains(UnitTag::SearchRadar) {
+                    return None;
+                }
+                let ewr = DeployableEwr {
+                    range: cfg.search_range,
+                    ..DeployableEwr::default()
+                };
+                Some((db.group_center3(gid).ok()?, group.side, ewr))
+            })
+            .collect()
+    }
+
+    /// An anti radiation missile was fired at gid. If it is a site
+    /// shut it down right away.
+    pub fn arm_launched(
+        &mut self,
+        lua: MizLua,
+        db: &Db,
+        gid: &GroupId,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let cfg = match &db.ephemeral.cfg.iads {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        if let Some(site) = self.sites.get_mut(gid) {
+            site.dark_until = Some(now + Duration::seconds(cfg.arm_shutdown as i64));
+            if site.emitting {
+                info!("iads site {gid} shutting down for an anti radiation missile");
+                site.emitting = false;
+                set_emission(lua, db, gid, false)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn each site's radars on or off according to the current
+    /// radar picture. Tracks older than max_age are ignored.
+    pub fn update(
+        &mut self,
+        lua: MizLua,
+        db: &Db,
+        ewr: &Ewr,
+        now: DateTime<Utc>,
+        max_age: Duration,
+    ) -> Result<()> {
+        let cfg = match &db.ephemeral.cfg.iads {
+            Some(cfg) => cfg,
+            None => {
+                // the network was turned off, every site goes back to
+                // emitting all the time
+                for (gid, site) in self.sites.drain() {
+                    if !site.emitting {
+                        if let Err(e) = set_emission(lua, db, &gid, true) {
+                            error!("could not turn on site {gid} {e:?}")
+                        }
+                    }
+                }
+                return Ok(());
+            }
+        };
+        // a site that is culled drops out here, when it spawns again
+        // it starts over as a new site with it's radars on
+        let found = Self::sites(db);
+        self.sites
+            .retain(|gid, _| found.iter().any(|(id, ..)| id == gid));
+        let states: SmallVec<[SiteState; 64]> = found
+            .iter()
+            .map(|(gid, side, pos, search)| SiteState {
+                side: *side,
+                pos: *pos,
+                search: *search,
+                suppressed: self
+                    .sites
+                    .get(gid)
+                    .and_then(|s| s.dark_until)
+                    .map(|t| t > now)
+                    .unwrap_or(false),
+            })
+            .collect();
+        let hostile: SmallVec<[(Side, Vector2); 64]> = [Side::Red, Side::Blue]
+            .into_iter()
+            .flat_map(|side| {
+                ewr.hostile_tracks(side, now, max_age)
+                    .map(move |p| (side, Vector2::new(p.x, p.z)))
+            })
+            .collect();
+        let plan = plan(cfg, &states, &hostile);
+        for ((gid, ..), on) in found.iter().zip(plan) {
+            let site = self.sites.entry(*gid).or_default();
+            if site.dark_until.map(|t| t <= now).unwrap_or(false) {
+                site.dark_until = None
+            }
+            if site.emitting != on {
+                site.emitting = on;
+                if let Err(e) = set_emission(lua, db, gid, on) {
+                    error!("could not set emission of site {gid} to {on} {e:?}")
+                }
+            }
+        }
+        Ok(())
+    }
+}
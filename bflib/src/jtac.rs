@@ -20,7 +20,7 @@ use crate::{
         group::{GroupId, SpawnedUnit, UnitId},
         objective::ObjectiveId,
         player::InstancedPlayer,
-        Db, JtDesc,
+        Db, JtDesc, JtacSettings,
     },
     landcache::LandCache,
 };
@@ -28,8 +28,10 @@ use anyhow::{anyhow, bail, Context, Result};
 use chrono::{prelude::*, Duration};
 use compact_str::{format_compact, CompactString};
 use dcso3::{
+    azumith2d_to,
     coalition::Side,
     controller::{ActionTyp, AltType, MissionPoint, PointType, Task, VehicleFormation},
+    coord::Coord,
     cvt_err, err,
     group::Group,
     land::Land,
@@ -64,6 +66,34 @@ impl fmt::Display for CtId {
     }
 }
 
+impl<'lua> FromLua<'lua> for CtId {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        let tbl: Table = FromLua::from_lua(value, lua)?;
+        match tbl.raw_get::<_, i64>("kind")? {
+            0 => Ok(Self::Unit(tbl.raw_get("id")?)),
+            1 => Ok(Self::Player(tbl.raw_get("id")?)),
+            n => Err(err(&format_compact!("invalid ctid {n}"))),
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for CtId {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<Value<'lua>> {
+        let tbl = lua.create_table()?;
+        match self {
+            Self::Unit(id) => {
+                tbl.raw_set("kind", 0)?;
+                tbl.raw_set("id", id)?
+            }
+            Self::Player(id) => {
+                tbl.raw_set("kind", 1)?;
+                tbl.raw_set("id", id)?;
+            }
+        }
+        Ok(Value::Table(tbl))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum JtId {
     Group(GroupId),
@@ -149,6 +179,87 @@ impl ArtilleryAdjustment {
     }
 }
 
+/// a contact must be this close to a lase mark to be selected by it (Meters)
+const LASE_MARK_RADIUS: f64 = 300.;
+
+fn contact_typ(db: &Db, id: &CtId) -> Result<Vehicle> {
+    Ok(match id {
+        CtId::Unit(uid) => db.unit(uid)?.typ.clone(),
+        CtId::Player(ucid) => db
+            .player(ucid)
+            .and_then(|p| p.current_slot.as_ref())
+            .and_then(|(_, i)| i.as_ref())
+            .map(|i| i.typ.clone())
+            .ok_or_else(|| anyhow!("player {ucid} isn't instanced"))?,
+    })
+}
+
+/// Task the artillery group gid to fire n rounds at pos from where it
+/// is now
+pub(crate) fn fire_mission(db: &Db, lua: MizLua, gid: &GroupId, pos: Vector2, n: u8) -> Result<()> {
+    let land = Land::singleton(lua)?;
+    let name = db.group(gid)?.name.clone();
+    let apos = db.group_center(gid)?;
+    let task = Task::FireAtPoint {
+        point: LuaVec2(pos),
+        radius: None,
+        expend_qty: Some(n as i64),
+        weapon_type: None,
+        altitude: Some(land.get_height(LuaVec2(pos))?),
+        altitude_type: Some(AltType::BARO),
+    };
+    let task = Task::Mission {
+        airborne: Some(false),
+        route: vec![MissionPoint {
+            action: Some(ActionTyp::Ground(VehicleFormation::OffRoad)),
+            typ: PointType::TurningPoint,
+            airdrome_id: None,
+            helipad: None,
+            time_re_fu_ar: None,
+            link_unit: None,
+            pos: LuaVec2(apos),
+            alt: land.get_height(LuaVec2(apos))?,
+            alt_typ: Some(AltType::BARO),
+            speed: 0.,
+            speed_locked: None,
+            eta: None,
+            eta_locked: None,
+            name: None,
+            task: Box::new(task),
+        }],
+    };
+    let group = Group::get_by_name(lua, &name)
+        .with_context(|| format_compact!("getting group {}", name))?;
+    let con = group.get_controller().context("getting controller")?;
+    con.set_task(task)?;
+    Ok(())
+}
+
+/// the points of the compass nearest to a bearing in radians
+fn cardinal(bearing: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    let i = (radians_to_degrees(bearing) / 45.).round() as usize;
+    POINTS[i % 8]
+}
+
+/// the broad kind of target to use in a brief
+fn describe(tags: UnitTags) -> &'static str {
+    [
+        (UnitTag::SAM, "SAM"),
+        (UnitTag::AAA, "AAA"),
+        (UnitTag::Artillery, "artillery"),
+        (UnitTag::Armor, "armor"),
+        (UnitTag::APC, "APC"),
+        (UnitTag::EWR, "radar"),
+        (UnitTag::Logistics, "logistics"),
+        (UnitTag::Infantry, "infantry"),
+    ]
+    .into_iter()
+    .find(|(tag, _)| tags.0.contains(*tag))
+    .map(|(_, name)| name)
+    .unwrap_or("vehicle")
+}
+
 type LocByCode = FxHashMap<Side, FxHashMap<ObjectiveId, FxHashMap<u16, FxHashSet<JtId>>>>;
 
 #[derive(Debug, Clone, Default)]
@@ -261,6 +372,9 @@ pub struct Jtac {
     nearby_artillery: SmallVec<[GroupId; 8]>,
     menu_dirty: bool,
     air: bool,
+    /// a manually selected target to pick up again once it is seen
+    pending_target: Option<CtId>,
+    restored: bool,
 }
 
 impl Jtac {
@@ -287,22 +401,42 @@ impl Jtac {
             nearby_artillery: smallvec![],
             menu_dirty: false,
             air,
+            pending_target: None,
+            restored: false,
+        }
+    }
+
+    fn settings(&self) -> JtacSettings {
+        let target = match (&self.autoshift, &self.target) {
+            (
+                Some(_),
+                Some(JtacTarget {
+                    id: CtId::Unit(uid),
+                    ..
+                }),
+            ) => Some(*uid),
+            (Some(_), Some(_)) | (Some(_), None) | (None, _) => None,
+        };
+        JtacSettings {
+            code: self.code,
+            filter: self.filter.into(),
+            autoshift: self.autoshift.is_none(),
+            ir_pointer: self.ir_pointer,
+            target,
         }
     }
 
+    fn restore(&mut self, settings: &JtacSettings) {
+        self.code = settings.code;
+        self.filter = settings.filter.0;
+        self.autoshift = if settings.autoshift { None } else { Some(0) };
+        self.ir_pointer = settings.ir_pointer;
+        self.pending_target = settings.target.map(CtId::Unit);
+        self.restored = true;
+    }
+
     pub fn status(&self, db: &Db, loc_by_code: &LocByCode) -> Result<CompactString> {
         use std::fmt::Write;
-        fn get_typ(db: &Db, id: &CtId) -> Result<Vehicle> {
-            Ok(match id {
-                CtId::Unit(uid) => db.unit(uid)?.typ.clone(),
-                CtId::Player(ucid) => db
-                    .player(ucid)
-                    .and_then(|p| p.current_slot.as_ref())
-                    .and_then(|(_, i)| i.as_ref())
-                    .map(|i| i.typ.clone())
-                    .ok_or_else(|| anyhow!("player {ucid} isn't instanced"))?,
-            })
-        }
         let mut msg = CompactString::new("");
         write!(msg, "JTAC {} status\n", self.gid)?;
         match &self.target {
@@ -310,7 +444,7 @@ impl Jtac {
                 write!(msg, "no target\n")?;
             }
             Some(target) => {
-                let unit_typ = get_typ(db, &target.id)?;
+                let unit_typ = contact_typ(db, &target.id)?;
                 let mid = match target.mark {
                     None => format_compact!("none"),
                     Some(mid) => format_compact!("{mid}"),
@@ -357,7 +491,7 @@ impl Jtac {
         } else {
             let mut counts: IndexMap<Vehicle, usize, FxBuildHasher> = IndexMap::default();
             for id in self.contacts.keys() {
-                let typ = get_typ(db, id)?;
+                let typ = contact_typ(db, id)?;
                 *counts.entry(typ).or_insert(0) += 1;
             }
             write!(msg, "Visual On: ")?;
@@ -383,6 +517,9 @@ impl Jtac {
             self.autoshift.is_none(),
             self.ir_pointer
         )?;
+        if self.restored {
+            write!(msg, " (restored after restart)")?;
+        }
         write!(msg, "\nfilter: [")?;
         let len = self.filter.len();
         for (i, tag) in self.filter.iter().enumerate() {
@@ -406,6 +543,147 @@ impl Jtac {
         Ok(msg)
     }
 
+    /// A 9-line CAS brief for the current target followed by a short
+    /// talk-on. The IP is the closest friendly objective to the target.
+    pub fn nine_line(&self, db: &Db, lua: MizLua) -> Result<CompactString> {
+        use std::fmt::Write;
+        let target = self.target.as_ref().ok_or_else(|| anyhow!("no target"))?;
+        let tpos = Vector2::new(target.pos.x, target.pos.z);
+        let (ip_dist, ip_bearing, ip) =
+            Db::objective_near_point(&db.persisted.objectives, tpos, |o| o.owner == self.side)
+                .ok_or_else(|| anyhow!("no friendly objective to use as an IP"))?;
+        let elevation = Land::singleton(lua)?.get_height(LuaVec2(tpos))?;
+        let coord = Coord::singleton(lua)?;
+        let ll = coord.lo_to_ll(LuaVec3(Vector3::new(tpos.x, elevation, tpos.y)))?;
+        let mgrs = coord.ll_to_mgrs(ll.latitude, ll.longitude)?;
+        let tags = self
+            .contacts
+            .get(&target.id)
+            .map(|ct| ct.tags)
+            .unwrap_or_default();
+        let near_target = |ct: &&Contact| {
+            let pos = Vector2::new(ct.pos.x, ct.pos.z);
+            na::distance(&pos.into(), &tpos.into()) <= 1000.
+        };
+        let same_typ = self
+            .contacts
+            .values()
+            .filter(near_target)
+            .filter(|ct| ct.typ == target.typ)
+            .count();
+        let others = self.contacts.values().filter(near_target).count() - same_typ;
+        let same_typ = same_typ.max(1);
+        let mut mark = format_compact!("laser {}", self.code);
+        if self.ir_pointer {
+            mark.push_str(", IR pointer");
+        }
+        if Utc::now() - self.last_smoke < Duration::minutes(5) {
+            write!(mark, ", {:?} smoke", self.smoke_color())?;
+        }
+        let friendlies = db
+            .persisted
+            .units
+            .into_iter()
+            .filter(|(_, u)| {
+                !u.dead
+                    && u.side == self.side
+                    && !u.tags.0.intersects(UnitTag::Aircraft | UnitTag::Helicopter)
+            })
+            .map(|(_, u)| {
+                let dist = na::distance(&u.pos.into(), &tpos.into());
+                (dist, azumith2d_to(tpos, u.pos))
+            })
+            .min_by(|(d0, _), (d1, _)| d0.total_cmp(d1));
+        let mut msg = CompactString::new("");
+        writeln!(msg, "JTAC {} 9-line", self.gid)?;
+        writeln!(msg, "1. IP: {}", ip.name)?;
+        writeln!(
+            msg,
+            "2. Heading: {:03}",
+            radians_to_degrees(ip_bearing) as u32
+        )?;
+        writeln!(msg, "3. Distance: {:.1}nm", ip_dist / 1852.)?;
+        writeln!(msg, "4. Elevation: {}ft MSL", (elevation * 3.28084) as i64)?;
+        writeln!(
+            msg,
+            "5. Target: {same_typ}x {} ({})",
+            target.typ,
+            describe(tags)
+        )?;
+        writeln!(
+            msg,
+            "6. Location: {} {} {:05} {:05}",
+            mgrs.utm_zone, mgrs.mgrs_digraph, mgrs.easting as u32, mgrs.northing as u32
+        )?;
+        writeln!(msg, "7. Mark: {mark}")?;
+        match friendlies {
+            None => writeln!(msg, "8. Friendlies: none")?,
+            Some((dist, bearing)) => {
+                writeln!(msg, "8. Friendlies: {} {}m", cardinal(bearing), dist as u32)?
+            }
+        }
+        let egress = azumith2d_to(tpos, ip.zone().pos());
+        writeln!(
+            msg,
+            "9. Egress: {:03} back to {}",
+            radians_to_degrees(egress) as u32,
+            ip.name
+        )?;
+        if others > 0 {
+            writeln!(
+                msg,
+                "Remarks: {others} more enemies within 1km of the target"
+            )?;
+        }
+        let (dist, bearing, landmark) =
+            Db::objective_near_point(&db.persisted.objectives, tpos, |_| true)
+                .ok_or_else(|| anyhow!("no objective to use as a landmark"))?;
+        write!(
+            msg,
+            "Talk-on: {} {:.1}km {} of {}, {:.1}km {} of my position",
+            target.typ,
+            dist / 1000.,
+            cardinal(bearing),
+            landmark.name,
+            na::distance(&tpos.into(), &self.location.pos.into()) / 1000.,
+            cardinal(azumith2d_to(self.location.pos, tpos)),
+        )?;
+        Ok(msg)
+    }
+
+    /// The contacts in priority order with their type
+    pub fn contact_list(&self, db: &Db) -> SmallVec<[(CtId, Vehicle); 32]> {
+        self.contacts
+            .keys()
+            .filter_map(|id| Some((*id, contact_typ(db, id).ok()?)))
+            .collect()
+    }
+
+    /// The current bearing (Degrees) and range (Meters) from the jtac
+    /// to a contact
+    pub fn contact_bearing_range(&self, id: &CtId) -> Option<(u32, f64)> {
+        let ct = self.contacts.get(id)?;
+        let pos = Vector2::new(ct.pos.x, ct.pos.z);
+        let bearing = radians_to_degrees(azumith2d_to(self.location.pos, pos)) as u32;
+        Some((
+            bearing,
+            na::distance(&self.location.pos.into(), &pos.into()),
+        ))
+    }
+
+    /// Lase a specific contact. Auto shift is turned off, so it stays
+    /// the target until it dies or the jtac loses sight of it.
+    pub fn select_contact(&mut self, db: &Db, lua: MizLua, id: &CtId) -> Result<()> {
+        let i = self
+            .contacts
+            .get_index_of(id)
+            .ok_or_else(|| anyhow!("jtac {} can't see that contact", self.gid))?;
+        self.autoshift = Some(i);
+        self.pending_target = None;
+        self.set_target(db, lua, i).context("setting target")?;
+        Ok(())
+    }
+
     fn add_unit_contact(&mut self, unit: &SpawnedUnit) {
         let ct = self.contacts.entry(CtId::Unit(unit.id)).or_default();
         ct.pos = unit.position.p.0;
@@ -609,12 +887,30 @@ impl Jtac {
         };
         self.contacts
             .sort_by(|_, ct0, _, ct1| priority(ct0.tags).cmp(&priority(ct1.tags)));
+        if let Some(i) = self
+            .pending_target
+            .and_then(|id| self.contacts.get_index_of(&id))
+        {
+            self.pending_target = None;
+            if self.autoshift.is_some() {
+                self.autoshift = Some(i);
+                return self.set_target(db, lua, i).context("restoring target");
+            }
+        }
         if self.autoshift.is_none() && !self.contacts.is_empty() {
             return self.set_target(db, lua, 0).context("setting target");
         }
         Ok(false)
     }
 
+    fn smoke_color(&self) -> SmokeColor {
+        match self.side {
+            Side::Blue => SmokeColor::Red,
+            Side::Red => SmokeColor::Blue,
+            Side::Neutral => SmokeColor::Green,
+        }
+    }
+
     pub fn smoke_target(&mut self, lua: MizLua) -> Result<()> {
         if let Some(target) = &self.target {
             if let Some(ct) = self.contacts.get(&target.id) {
@@ -632,12 +928,8 @@ impl Jtac {
                     ct.pos.z + rng.gen_range(0. ..10.),
                 );
                 let pos = Vector3::new(pos.x, land.get_height(LuaVec2(pos))?, pos.y);
-                let color = match self.side {
-                    Side::Blue => SmokeColor::Red,
-                    Side::Red => SmokeColor::Blue,
-                    Side::Neutral => SmokeColor::Green,
-                };
-                act.smoke(LuaVec3(pos), color).context("creating smoke")?;
+                act.smoke(LuaVec3(pos), self.smoke_color())
+                    .context("creating smoke")?;
             }
         }
         Ok(())
@@ -661,49 +953,15 @@ impl Jtac {
         gid: &GroupId,
         n: u8,
     ) -> Result<()> {
-        match self.target.as_mut() {
+        match self.target.as_ref() {
             None => bail!("no target"),
             Some(target) => {
-                let land = Land::singleton(lua)?;
-                let name = db.group(gid)?.name.clone();
                 let apos = db.group_center(gid)?;
                 let pos = Vector2::new(target.pos.x, target.pos.z);
                 let pos = adjustment.compute_final_solution(apos, pos);
-                let task = Task::FireAtPoint {
-                    point: LuaVec2(pos),
-                    radius: None,
-                    expend_qty: Some(n as i64),
-                    weapon_type: None,
-                    altitude: Some(land.get_height(LuaVec2(pos))?),
-                    altitude_type: Some(AltType::BARO),
-                };
-                let task = Task::Mission {
-                    airborne: Some(false),
-                    route: vec![MissionPoint {
-                        action: Some(ActionTyp::Ground(VehicleFormation::OffRoad)),
-                        typ: PointType::TurningPoint,
-                        airdrome_id: None,
-                        helipad: None,
-                        time_re_fu_ar: None,
-                        link_unit: None,
-                        pos: LuaVec2(apos),
-                        alt: land.get_height(LuaVec2(apos))?,
-                        alt_typ: Some(AltType::BARO),
-                        speed: 0.,
-                        speed_locked: None,
-                        eta: None,
-                        eta_locked: None,
-                        name: None,
-                        task: Box::new(task),
-                    }],
-                };
-                let group = Group::get_by_name(lua, &name)
-                    .with_context(|| format_compact!("getting group {}", name))?;
-                let con = group.get_controller().context("getting controller")?;
-                con.set_task(task)?;
+                fire_mission(db, lua, gid, pos, n)
             }
         }
-        Ok(())
     }
 
     fn update_target_position(&mut self, lua: MizLua, db: &Db) -> Result<()> {
@@ -878,6 +1136,38 @@ impl Jtacs {
         Ok(())
     }
 
+    /// Lase the contact closest to pos that one of the side's jtacs
+    /// can see, using only the specified jtac if there is one. Returns
+    /// the jtac that was tasked and the type of the target.
+    pub fn select_contact_near(
+        &mut self,
+        db: &Db,
+        lua: MizLua,
+        side: Side,
+        pos: Vector2,
+        jtac: Option<JtId>,
+    ) -> Result<(JtId, Vehicle)> {
+        let mut best: Option<(f64, JtId, CtId)> = None;
+        for jt in self.jtacs.get(&side).into_iter().flat_map(|j| j.values()) {
+            if jtac.map(|id| id != jt.gid).unwrap_or(false) {
+                continue;
+            }
+            for (id, ct) in &jt.contacts {
+                let cpos = Vector2::new(ct.pos.x, ct.pos.z);
+                let dist = na::distance(&pos.into(), &cpos.into());
+                if dist <= LASE_MARK_RADIUS && best.map(|(d, _, _)| dist < d).unwrap_or(true) {
+                    best = Some((dist, jt.gid, *id))
+                }
+            }
+        }
+        let (_, gid, id) = best.ok_or_else(|| match jtac {
+            Some(gid) => anyhow!("jtac {gid} can't see anything near that mark"),
+            None => anyhow!("no jtac can see anything near that mark"),
+        })?;
+        self.get_mut(&gid)?.select_contact(db, lua, &id)?;
+        Ok((gid, contact_typ(db, &id)?))
+    }
+
     pub fn jtac_targets<'a>(&'a self) -> impl Iterator<Item = CtId> + 'a {
         self.jtacs.values().flat_map(|j| {
             j.values()
@@ -1083,7 +1373,7 @@ impl Jtacs {
                 .or_default()
                 .entry(id)
                 .or_insert_with(|| {
-                    let jt = Jtac::new(
+                    let mut jt = Jtac::new(
                         db,
                         id,
                         side,
@@ -1091,6 +1381,12 @@ impl Jtacs {
                         pos,
                         air,
                     );
+                    if let JtId::Group(gid) = &id {
+                        if let Some(settings) = db.jtac_settings(gid) {
+                            info!("restoring jtac {id} settings {settings:?}");
+                            jt.restore(settings);
+                        }
+                    }
                     self.menu_dirty
                         .entry(side)
                         .or_default()
@@ -1269,6 +1565,14 @@ impl Jtacs {
         for (side, msg) in msgs {
             db.ephemeral.msgs().panel_to_side(10, false, side, msg);
         }
+        for jt in self.jtacs.values().flat_map(|jtx| jtx.values()) {
+            if let JtId::Group(gid) = jt.gid {
+                let settings = jt.settings();
+                if db.jtac_settings(&gid) != Some(&settings) {
+                    db.save_jtac_settings(gid, settings)
+                }
+            }
+        }
         for (side, jtx) in self.jtacs.iter_mut() {
             for jt in jtx.values_mut() {
                 if jt.menu_dirty {
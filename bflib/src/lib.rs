@@ -14,30 +14,37 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
+pub mod acmi;
 mod admin;
+pub mod audit;
 mod bg;
-mod cfg;
+pub mod cfg;
 mod chatcmd;
-mod db;
-mod ewr;
+pub mod counterbattery;
+pub mod db;
+pub mod ewr;
+pub mod iads;
 mod jtac;
 mod landcache;
 mod menu;
 mod msgq;
 mod perf;
-mod shots;
+pub mod shots;
 mod spawnctx;
 pub mod stats;
+pub mod status;
 
 extern crate nalgebra as na;
 use crate::{cfg::Cfg, db::player::SlotAuth, perf::record_perf};
 use admin::{run_admin_commands, AdminCommand};
 use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
+use audit::{AuditEntry, AuditKind};
 use cfg::LifeType;
 use chatcmd::run_action_commands;
 use chrono::{prelude::*, Duration};
 use compact_str::{format_compact, CompactString};
-use db::{objective::ObjectiveId, player::TakeoffRes, Db};
+use counterbattery::CounterBattery;
+use db::{backend::DcsBackend, objective::ObjectiveId, player::TakeoffRes, Db};
 use dcso3::{
     coalition::Side,
     env::{
@@ -58,6 +65,7 @@ use dcso3::{
 };
 use ewr::Ewr;
 use fxhash::{FxBuildHasher, FxHashMap, FxHashSet};
+use iads::Iads;
 use indexmap::IndexSet;
 use jtac::{JtId, Jtacs};
 use landcache::LandCache;
@@ -68,7 +76,14 @@ use perf::{Perf, PerfInner};
 use shots::ShotDb;
 use smallvec::{smallvec, SmallVec};
 use spawnctx::SpawnCtx;
-use std::{path::PathBuf, sync::Arc};
+use stats::StatKind;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use tokio::sync::mpsc::UnboundedSender;
 
 #[derive(Debug)]
@@ -215,12 +230,18 @@ struct Context {
     miz_state_path: PathBuf,
     shutdown: Option<AutoShutdown>,
     last_perf_log: DateTime<Utc>,
+    last_status: DateTime<Utc>,
     load_state: LoadState,
     idx: env::miz::MizIndex,
     db: Db,
     admin_commands: Vec<(PlayerId, AdminCommand)>,
     action_commands: Vec<(PlayerId, String)>,
     to_background: Option<UnboundedSender<bg::Task>>,
+    cfg_changed: Arc<AtomicBool>,
+    audit_replies: bg::AuditReplies,
+    /// the config as it is on disk when it contains changes that
+    /// can't be applied until the next restart
+    pending_cfg: Option<Cfg>,
     recently_landed: FxHashMap<DcsOid<ClassUnit>, DateTime<Utc>>,
     airborne: FxHashSet<DcsOid<ClassUnit>>,
     captureable: FxHashMap<ObjectiveId, usize>,
@@ -236,6 +257,8 @@ struct Context {
     landcache: LandCache,
     ewr: Ewr,
     jtac: Jtacs,
+    counter_battery: CounterBattery,
+    iads: Iads,
 }
 
 impl Context {
@@ -287,6 +310,21 @@ impl Context {
             .respawn_after_load(perf, &self.idx, miz, &mut self.landcache, &spctx)
     }
 
+    fn flush_stats(&mut self) {
+        let stats = self.db.ephemeral.take_stats();
+        if !stats.is_empty() {
+            self.do_bg_task(bg::Task::Stats(stats))
+        }
+        let records = self.db.ephemeral.take_acmi();
+        if !records.is_empty() {
+            self.do_bg_task(bg::Task::Acmi(records))
+        }
+        let audit = self.db.ephemeral.take_audit();
+        if !audit.is_empty() {
+            self.do_bg_task(bg::Task::Audit(audit))
+        }
+    }
+
     fn log_perf(&mut self, now: DateTime<Utc>) {
         if now - self.last_perf_log > Duration::seconds(60) {
             self.last_perf_log = now;
@@ -294,6 +332,17 @@ impl Context {
             info!("landcache {}", self.landcache.stats())
         }
     }
+
+    fn send_status(&mut self, now: DateTime<Utc>) {
+        if self.db.ephemeral.cfg.status_port.is_some()
+            && now - self.last_status > Duration::seconds(10)
+        {
+            self.last_status = now;
+            let perf = unsafe { Perf::get_mut() }.clone();
+            let st = status::Status::new(self, perf, now);
+            self.do_bg_task(bg::Task::Status(Box::new(st)))
+        }
+    }
 }
 
 fn on_player_try_connect(
@@ -332,6 +381,9 @@ fn on_player_try_connect(
                     let cfg = Arc::make_mut(&mut ctx.db.ephemeral.cfg);
                     cfg.banned.remove(&ucid);
                 }
+                let mut entry = AuditEntry::new(AuditKind::System, "ban-expired");
+                entry.target = Some((ucid, name.clone()));
+                ctx.db.ephemeral.audit(entry);
                 let cfg = Arc::clone(&ctx.db.ephemeral.cfg);
                 ctx.do_bg_task(bg::Task::SaveConfig(path, cfg))
             }
@@ -466,12 +518,12 @@ fn unit_killed(
     lua: MizLua,
     ctx: &mut Context,
     id: DcsOid<ClassUnit>,
+    ejected: bool,
     now: DateTime<Utc>,
 ) -> Result<()> {
     ctx.recently_landed.remove(&id);
-    if ctx.db.ephemeral.cfg.points.is_some() {
-        ctx.shots_out.dead(id.clone(), now);
-    }
+    ctx.db.player_lost(&id, ejected, now);
+    ctx.shots_out.dead(id.clone(), now);
     if let Err(e) = ctx.jtac.unit_dead(lua, &mut ctx.db, &id) {
         error!("jtac unit dead failed for {:?} {:?}", id, e)
     }
@@ -481,6 +533,42 @@ fn unit_killed(
     Ok(())
 }
 
+/// A mark whose text starts with "lase", optionally followed by a
+/// jtac id, tasks a jtac with lasing the contact closest to the mark.
+fn lase_mark(ctx: &mut Context, lua: MizLua, ucid: &Ucid, text: &str, pos: Vector2) {
+    let mut words = text.split_whitespace();
+    match words.next() {
+        Some(w) if w.eq_ignore_ascii_case("lase") => (),
+        Some(_) | None => return,
+    }
+    let (side, name) = match ctx.db.player(ucid) {
+        Some(p) => (p.side, p.name.clone()),
+        None => return,
+    };
+    let res = words
+        .next()
+        .map(|id| {
+            id.parse::<JtId>()
+                .with_context(|| format_compact!("invalid jtac {id}"))
+        })
+        .transpose()
+        .and_then(|jtac| ctx.jtac.select_contact_near(&ctx.db, lua, side, pos, jtac));
+    match res {
+        Ok((jtac, typ)) => {
+            let msg = format_compact!(
+                "JTAC NOW TARGETING {typ}\nauto shift is now disabled\njtac {jtac} lasing a mark\nrequested by {name}"
+            );
+            ctx.db.ephemeral.msgs().panel_to_side(10, false, side, msg)
+        }
+        Err(e) => {
+            let msg = format_compact!("COULD NOT LASE MARK\n{e}");
+            ctx.db
+                .ephemeral
+                .panel_to_player(&ctx.db.persisted, 10, ucid, msg)
+        }
+    }
+}
+
 fn on_event(lua: MizLua, ev: Event) -> Result<()> {
     let start_ts = Utc::now();
     match &ev {
@@ -513,13 +601,11 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
         Event::PlayerLeaveUnit(e) => {
             if let Some(unit) = e.initiator.and_then(|u| u.as_unit().ok()) {
                 let oid = unit.object_id()?;
-                if ctx.db.ephemeral.cfg.points.is_some() {
-                    if let Some(ucid) = ctx.db.player_in_unit(false, &oid) {
-                        if let Some(player) = ctx.db.player(&ucid) {
-                            if let Some((_, Some(inst))) = player.current_slot.as_ref() {
-                                if inst.landed_at_objective.is_none() {
-                                    ctx.shots_out.dead(oid, start_ts)
-                                }
+                if let Some(ucid) = ctx.db.player_in_unit(false, &oid) {
+                    if let Some(player) = ctx.db.player(&ucid) {
+                        if let Some((_, Some(inst))) = player.current_slot.as_ref() {
+                            if inst.landed_at_objective.is_none() {
+                                ctx.shots_out.dead(oid, start_ts)
                             }
                         }
                     }
@@ -532,22 +618,16 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
         Event::Hit(e) | Event::Kill(e) => {
             if let Some(target) = e.target.as_ref().and_then(|t| t.as_unit().ok()) {
                 let dead = target.get_life()? < 1;
-                if ctx.db.ephemeral.cfg.points.is_some() {
-                    if let Some(shooter) = e.initiator.and_then(|u| u.as_unit().ok()) {
-                        if let Err(e) = ctx.shots_out.hit(
-                            &ctx.db,
-                            start_ts,
-                            dead,
-                            &target,
-                            &shooter,
-                            e.weapon_name,
-                        ) {
-                            error!("error processing hit event {:?}", e)
-                        }
+                if let Some(shooter) = e.initiator.and_then(|u| u.as_unit().ok()) {
+                    if let Err(e) =
+                        ctx.shots_out
+                            .hit(&ctx.db, start_ts, dead, &target, &shooter, e.weapon_name)
+                    {
+                        error!("error processing hit event {:?}", e)
                     }
                 }
                 if dead {
-                    if let Err(e) = unit_killed(lua, ctx, target.object_id()?, start_ts) {
+                    if let Err(e) = unit_killed(lua, ctx, target.object_id()?, false, start_ts) {
                         error!("0 unit killed failed {:?}", e)
                     }
                 }
@@ -560,16 +640,34 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
             }
         }
         Event::Shot(e) => {
-            if ctx.db.ephemeral.cfg.points.is_some() {
-                if let Err(e) = ctx.shots_out.shot(&ctx.db, start_ts, e) {
-                    error!("error processing shot event {:?}", e)
+            if ctx.db.ephemeral.cfg.acmi {
+                if let Err(e) = ctx.shots_out.launched(&ctx.db, &e) {
+                    error!("error tracking weapon {:?}", e)
                 }
             }
+            if let Err(e) = ctx
+                .counter_battery
+                .shot(lua, &mut ctx.db, start_ts, &e.initiator)
+            {
+                error!("error locating artillery {:?}", e)
+            }
+            match ctx.shots_out.anti_radiation(&ctx.db, &e) {
+                Err(e) => error!("error checking for anti radiation missiles {:?}", e),
+                Ok(None) => (),
+                Ok(Some(gid)) => {
+                    if let Err(e) = ctx.iads.arm_launched(lua, &ctx.db, &gid, start_ts) {
+                        error!("error shutting down iads site {gid} {:?}", e)
+                    }
+                }
+            }
+            if let Err(e) = ctx.shots_out.shot(&ctx.db, start_ts, e) {
+                error!("error processing shot event {:?}", e)
+            }
         }
         Event::Dead(e) | Event::UnitLost(e) | Event::PilotDead(e) => {
             if let Some(unit) = e.initiator.as_ref().and_then(|u| u.as_unit().ok()) {
                 let id = unit.object_id()?;
-                if let Err(e) = unit_killed(lua, ctx, id, start_ts) {
+                if let Err(e) = unit_killed(lua, ctx, id, false, start_ts) {
                     error!("1 unit killed failed {:?}", e)
                 }
             } else if let Some(st) = e.initiator.as_ref().and_then(|s| s.as_static().ok()) {
@@ -581,11 +679,32 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
         Event::Ejection(e) => {
             if let Ok(unit) = e.initiator.as_unit() {
                 let id = unit.object_id()?;
-                if let Err(e) = unit_killed(lua, ctx, id, start_ts) {
+                if let Ok(pilot) = e.target.object_id() {
+                    ctx.db.pilot_ejected(&id, pilot);
+                }
+                if let Err(e) = unit_killed(lua, ctx, id, true, start_ts) {
                     error!("2 unit killed failed {}", e)
                 }
             }
         }
+        Event::LandingAfterEjection(e) => {
+            if let Some(pilot) = e.initiator {
+                let id = pilot.object_id()?;
+                let pos = pilot.get_point()?;
+                let pos = Vector2::new(pos.x, pos.z);
+                let res = SpawnCtx::new(lua)
+                    .and_then(|spctx| ctx.db.pilot_landed(&spctx, &ctx.idx, &id, pos, start_ts));
+                match res {
+                    Ok(None) => (),
+                    Ok(Some(_)) => {
+                        if let Err(e) = pilot.destroy() {
+                            error!("failed to destroy ejected pilot {e:?}")
+                        }
+                    }
+                    Err(e) => error!("could not put downed pilot in the field {e:?}"),
+                }
+            }
+        }
         Event::Takeoff(e) | Event::PostponedTakeoff(e) => {
             if let Ok(unit) = e.initiator.as_unit() {
                 let id = unit.object_id()?;
@@ -623,6 +742,8 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
         }
         Event::MarkAdded(MarkPanel {
             initiator: Some(unit),
+            text,
+            pos,
             ..
         }) => {
             let oid = unit.object_id()?;
@@ -637,6 +758,21 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
                             error!("failed to init action menu for {ucid} {slot} {e:?}")
                         }
                     }
+                    lase_mark(ctx, lua, &ucid, &text, Vector2::new(pos.x, pos.z))
+                }
+            }
+        }
+        Event::MarkChange(MarkPanel {
+            initiator: Some(unit),
+            text,
+            pos,
+            ..
+        }) => {
+            let oid = unit.object_id()?;
+            if let Some(slot) = ctx.db.ephemeral.get_slot_by_object_id(&oid) {
+                if let Some(ucid) = ctx.db.ephemeral.player_in_slot(slot) {
+                    let ucid = *ucid;
+                    lase_mark(ctx, lua, &ucid, &text, Vector2::new(pos.x, pos.z))
                 }
             }
         }
@@ -712,7 +848,10 @@ fn return_lives(lua: MizLua, ctx: &mut Context, ts: DateTime<Utc>) {
             let unit = or_false!(Unit::get_instance(lua, id));
             let pos = or_false!(unit.get_ground_position());
             let slot = or_false!(unit.slot());
-            if let Some(typ) = db.land(slot.clone(), pos.0) {
+            if let Err(e) = db.deliver_pilots(lua, &slot, pos.0) {
+                error!("could not deliver pilots carried by {slot:?} {e:?}")
+            }
+            if let Some(typ) = db.land(slot.clone(), pos.0, *landed_ts) {
                 returned.push((typ, slot));
                 return false;
             }
@@ -741,7 +880,8 @@ fn advise_captureable(ctx: &mut Context) -> Result<()> {
 }
 
 fn advise_captured(ctx: &mut Context, lua: MizLua, ts: DateTime<Utc>) -> Result<()> {
-    for (side, oid) in ctx.db.check_capture(lua, ts)? {
+    let mut backend = DcsBackend::new(lua, &mut ctx.landcache)?;
+    for (side, oid) in ctx.db.check_capture(&mut backend, ts)? {
         let name = ctx.db.objective(&oid)?.name();
         let mcap = format_compact!("our forces have captured {}", name);
         let mlost = format_compact!("we have lost {}", name);
@@ -783,6 +923,17 @@ fn generate_ewr_reports(ctx: &mut Context, now: DateTime<Utc>) -> Result<()> {
     Ok(())
 }
 
+fn end_round(ctx: &mut Context, path: &Path, winner: Side) {
+    let msg = format_compact!(
+        "{winner} has won the round! A new round will begin when the server restarts"
+    );
+    ctx.db.ephemeral.msgs().panel_to_all(120, true, msg);
+    match db::round::archive_path(path, ctx.db.persisted.round) {
+        Err(e) => error!("could not archive round {e:?}"),
+        Ok(archive) => ctx.do_bg_task(bg::Task::ArchiveState(archive, ctx.db.persisted.clone())),
+    }
+}
+
 fn check_auto_shutdown(ctx: &mut Context, lua: MizLua, now: DateTime<Utc>) {
     if let Some(asd) = ctx.shutdown.as_mut() {
         if asd.when - now <= Duration::minutes(30) && !asd.thirty_minute_warning {
@@ -815,7 +966,7 @@ fn check_auto_shutdown(ctx: &mut Context, lua: MizLua, now: DateTime<Utc>) {
                 .panel_to_all(60, true, "The server will restart in one minute")
         }
         if now > asd.when {
-            let _ = admin::admin_shutdown(ctx, lua, false);
+            let _ = admin::admin_shutdown(ctx, lua, false, &mut None);
         }
     }
 }
@@ -917,6 +1068,9 @@ fn run_slow_timed_events(
     if ts - ctx.last_slow_timed_events >= freq {
         ctx.last_slow_timed_events = ts;
         check_auto_shutdown(ctx, lua, ts);
+        if ctx.cfg_changed.swap(false, Ordering::Relaxed) {
+            admin::reload_config_and_notify(ctx, lua)
+        }
         for (oid, vh) in ctx.db.ephemeral.warehouses_to_sync() {
             if let Err(e) = ctx.db.sync_vehicle_at_obj(lua, oid, vh.clone()) {
                 error!(
@@ -926,35 +1080,55 @@ fn run_slow_timed_events(
             }
         }
         return_lives(lua, ctx, ts);
-        if let Some(points) = ctx.db.ephemeral.cfg.points {
-            for dead in ctx.shots_out.bring_out_your_dead(ts) {
-                info!("kill {:?}", dead);
-                ctx.db.award_kill_points(points, dead)
-            }
+        for dead in ctx.shots_out.bring_out_your_dead(ts) {
+            info!("kill {:?}", dead);
+            ctx.db.player_kill(dead)
         }
         let start_ts = Utc::now();
         if let Err(e) = ctx.db.maybe_do_repairs(ts) {
             error!("error doing repairs {:?}", e)
         }
         record_perf(&mut perf.do_repairs, start_ts);
+        if let Err(e) = ctx.db.expire_pilots(start_ts) {
+            error!("error expiring downed pilots {e:?}")
+        }
+        let res = SpawnCtx::new(lua).and_then(|spctx| {
+            ctx.db.spawn_convoys(perf, &spctx, &ctx.idx, start_ts)?;
+            ctx.db.spawn_offensives(perf, &spctx, &ctx.idx, start_ts)
+        });
+        if let Err(e) = res {
+            error!("error spawning convoys and offensives {e:?}")
+        }
+        if let Err(e) = ctx.db.check_convoys(start_ts) {
+            error!("error checking supply convoys {e:?}")
+        }
+        if let Err(e) = ctx.db.check_offensives(start_ts) {
+            error!("error checking offensives {e:?}")
+        }
         if let Err(e) = ctx.db.advance_actions(lua, &ctx.idx, &ctx.jtac, start_ts) {
             error!("could not advance actions {e:?}")
         }
         let ts = Utc::now();
-        if let Err(e) = ctx.ewr.update_tracks(lua, &mut ctx.landcache, &ctx.db, ts) {
+        let search_radars = ctx.iads.search_radars(&ctx.db);
+        if let Err(e) = ctx
+            .ewr
+            .update_tracks(lua, &mut ctx.landcache, &ctx.db, &search_radars, ts)
+        {
             error!("could not update ewr tracks {e}")
         }
         record_perf(&mut perf.ewr_tracks, ts);
+        if let Err(e) = ctx.iads.update(lua, &ctx.db, &ctx.ewr, ts, freq * 2) {
+            error!("could not update iads {e}")
+        }
         let ts = Utc::now();
         if let Err(e) = generate_ewr_reports(ctx, ts) {
             error!("could not generate ewr reports {e}")
         }
         record_perf(&mut perf.ewr_reports, ts);
         let ts = Utc::now();
-        match ctx
-            .db
-            .cull_or_respawn_objectives(lua, &mut ctx.landcache, ts)
-        {
+        let res = DcsBackend::new(lua, &mut ctx.landcache)
+            .and_then(|mut backend| ctx.db.cull_or_respawn_objectives(&mut backend, ts));
+        match res {
             Err(e) => error!("could not cull or respawn objectives {e}"),
             Ok((threatened, cleared)) => {
                 for oid in threatened {
@@ -972,6 +1146,9 @@ fn run_slow_timed_events(
             }
         }
         record_perf(&mut perf.unit_culling, ts);
+        if let Some(winner) = ctx.db.check_round_end(ts) {
+            end_round(ctx, path, winner)
+        }
         let ts = Utc::now();
         if let Err(e) = ctx.db.update_objectives_markup() {
             error!("could not remark objectives {e}")
@@ -980,6 +1157,7 @@ fn run_slow_timed_events(
         let ts = Utc::now();
         update_jtac_contacts(ctx, lua);
         record_perf(&mut perf.update_jtac_contacts, ts);
+        ctx.counter_battery.expire(&mut ctx.db, ts);
         let now = Utc::now();
         if let Some(snap) = ctx.db.maybe_snapshot() {
             ctx.do_bg_task(bg::Task::SaveState(path.clone(), snap));
@@ -1005,7 +1183,7 @@ fn run_timed_events(lua: MizLua, path: &PathBuf) -> Result<()> {
         Ok((i, dead)) => {
             ctx.last_unit_position = i;
             for id in dead {
-                if let Err(e) = unit_killed(lua, ctx, id.clone(), ts) {
+                if let Err(e) = unit_killed(lua, ctx, id.clone(), false, ts) {
                     error!("unit killed failed {:?} {:?}", id, e)
                 }
             }
@@ -1021,13 +1199,16 @@ fn run_timed_events(lua: MizLua, path: &PathBuf) -> Result<()> {
         Ok((i, dead)) => {
             ctx.last_player_position = i;
             for id in dead {
-                if let Err(e) = unit_killed(lua, ctx, id.clone(), ts) {
+                if let Err(e) = unit_killed(lua, ctx, id.clone(), false, ts) {
                     error!("unit killed failed {:?} {:?}", id, e)
                 }
             }
         }
     }
     record_perf(&mut perf.player_positions, ts);
+    if let Err(e) = ctx.shots_out.track_weapons(lua, &mut ctx.db, ts) {
+        error!("could not track weapons {e:?}")
+    }
     if let Err(e) = run_slow_timed_events(lua, ctx, perf, path, ts) {
         error!("error running slow timed events {:?}", e)
     }
@@ -1061,7 +1242,7 @@ fn run_timed_events(lua: MizLua, path: &PathBuf) -> Result<()> {
         Err(e) => error!("error updating jtac target positions {:?}", e),
         Ok(dead) => {
             for id in dead {
-                if let Err(e) = unit_killed(lua, ctx, id.clone(), now) {
+                if let Err(e) = unit_killed(lua, ctx, id.clone(), false, now) {
                     error!("unit killed failed {:?} {:?}", id, e)
                 }
             }
@@ -1072,7 +1253,9 @@ fn run_timed_events(lua: MizLua, path: &PathBuf) -> Result<()> {
     let max_rate = ctx.db.ephemeral.cfg.max_msgs_per_second;
     ctx.db.ephemeral.msgs().process(max_rate, &net, &act);
     record_perf(&mut perf.process_messages, now);
-    if let Err(e) = ctx.db.logistics_step(lua, perf, ts) {
+    let res = DcsBackend::new(lua, &mut ctx.landcache)
+        .and_then(|mut backend| ctx.db.logistics_step(&mut backend, perf, ts));
+    if let Err(e) = res {
         error!("error running logistics events {e:?}")
     }
     if let Err(e) = run_admin_commands(ctx, lua) {
@@ -1081,9 +1264,11 @@ fn run_timed_events(lua: MizLua, path: &PathBuf) -> Result<()> {
     if let Err(e) = run_action_commands(ctx, perf, lua) {
         error!("failed to run action commands {e:?}")
     }
+    ctx.flush_stats();
     ctx.load_state.step();
     record_perf(&mut perf.timed_events, ts);
     ctx.log_perf(now);
+    ctx.send_status(now);
     Ok(())
 }
 
@@ -1125,14 +1310,35 @@ fn delayed_init_miz(lua: MizLua) -> Result<()> {
         ctx.miz_state_path.clone()
     };
     debug!("path to saved state is {:?}", path);
+    ctx.do_bg_task(bg::Task::WatchConfig(
+        Cfg::path(&path),
+        Arc::clone(&ctx.cfg_changed),
+    ));
     info!("initializing db");
-    if !path.exists() {
+    let saved = if !path.exists() {
         debug!("saved state doesn't exist, starting from default");
-        let cfg = Cfg::load(&path)?;
-        ctx.db = Db::init(lua, cfg, &ctx.idx, &miz).context("initalizing the mission")?;
+        None
     } else {
         debug!("saved state exists, loading it");
-        ctx.db = Db::load(&miz, &ctx.idx, &path).context("loading the saved state")?;
+        Some(Db::load(&miz, &ctx.idx, &path).context("loading the saved state")?)
+    };
+    match saved {
+        Some(db) if db.persisted.winner.is_none() => ctx.db = db,
+        saved => {
+            let (careers, squadrons) = match saved {
+                Some(db) => {
+                    info!("round {} is over, starting a new round", db.persisted.round);
+                    (db.persisted.careers, db.persisted.squadrons)
+                }
+                None => db::round::last_carryover(&path).context("loading careers")?,
+            };
+            let cfg = Cfg::load(&path)?;
+            ctx.db = Db::init(lua, cfg, &ctx.idx, &miz).context("initalizing the mission")?;
+            ctx.db.persisted.round = db::round::next_round(&path).context("numbering round")?;
+            ctx.db.persisted.careers = careers;
+            ctx.db.persisted.squadrons = squadrons;
+            ctx.db.ephemeral.stat(StatKind::NewRound);
+        }
     }
     ctx.shutdown = ctx
         .db
@@ -1140,6 +1346,18 @@ fn delayed_init_miz(lua: MizLua) -> Result<()> {
         .cfg
         .shutdown
         .map(|hrs| AutoShutdown::new(Utc::now() + Duration::hours(hrs as i64)));
+    if let Some(port) = ctx.db.ephemeral.cfg.status_port {
+        ctx.do_bg_task(bg::Task::ServeStatus(port))
+    }
+    let stop_time = ctx
+        .shutdown
+        .as_ref()
+        .map(|asd| asd.when)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    ctx.db.ephemeral.stat(StatKind::SessionStart { stop_time });
+    ctx.db
+        .stat_objectives(lua)
+        .context("recording objective stats")?;
     info!("spawning units");
     ctx.respawn_groups(lua, &miz)
         .context("setting up the mission after load")?;
@@ -1233,7 +1451,8 @@ fn init_miz(lua: MizLua) -> Result<()> {
     Ok(())
 }
 
-#[mlua::lua_module]
+#[cfg_attr(feature = "module", mlua::lua_module)]
+#[cfg_attr(not(feature = "module"), allow(dead_code))]
 fn bflib(lua: &Lua) -> LuaResult<LuaTable> {
     unsafe { Context::get_mut() }
         .init_async_bg(lua.inner())
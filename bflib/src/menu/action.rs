@@ -425,7 +425,11 @@ fn add_action_menu(lua: MizLua, arg: ArgTriple<Ucid, GroupId, SlotId>) -> Result
                         None
                     }
                 }
-                DeployKind::Crate { .. } | DeployKind::Objective => None,
+                DeployKind::Crate { .. }
+                | DeployKind::Objective
+                | DeployKind::Pilot { .. }
+                | DeployKind::Convoy { .. }
+                | DeployKind::Offensive { .. } => None,
             };
             if let Some(key) = key {
                 let root = mc.add_submenu_for_group(
@@ -138,6 +138,7 @@ pub(crate) fn list_cargo_for_slot(lua: MizLua, ctx: &mut Context, slot: &SlotId)
         cargo.num_troops(),
         capacity.troop_slots
     ));
+    msg.push_str(&format_compact!("pilots: {}\n", cargo.num_pilots()));
     msg.push_str(&format_compact!(
         "crates: {} of {}\n",
         cargo.num_crates(),
@@ -166,6 +167,18 @@ pub(crate) fn list_cargo_for_slot(lua: MizLua, ctx: &mut Context, slot: &SlotId)
         ));
         total += tr.weight
     }
+    for pilot in &cargo.pilots {
+        let name = ctx
+            .db
+            .player(&pilot.player)
+            .map(|p| p.name.clone())
+            .unwrap_or_default();
+        msg.push_str(&format_compact!(
+            "pilot {name} weighing {} kg\n",
+            pilot.weight
+        ));
+        total += pilot.weight
+    }
     if total > 0 {
         msg.push_str("----------------------------\n");
     }
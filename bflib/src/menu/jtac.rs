@@ -23,7 +23,7 @@ use crate::{
         objective::ObjectiveId,
         Db,
     },
-    jtac::{AdjustmentDir, JtId, Jtac, Jtacs},
+    jtac::{AdjustmentDir, CtId, JtId, Jtac, Jtacs},
     perf::Perf,
     spawnctx::SpawnCtx,
     Context,
@@ -42,6 +42,9 @@ use log::error;
 use smallvec::{smallvec, SmallVec};
 use std::sync::Arc;
 
+/// the most contacts that will be listed in a jtac's contacts menu
+const MAX_CONTACTS: usize = 36;
+
 fn jtac_status(_: MizLua, arg: ArgTuple<Option<Ucid>, JtId>) -> Result<()> {
     let ctx = unsafe { Context::get_mut() };
     let jtac = ctx
@@ -65,6 +68,18 @@ fn jtac_status(_: MizLua, arg: ArgTuple<Option<Ucid>, JtId>) -> Result<()> {
     Ok(())
 }
 
+fn jtac_nine_line(lua: MizLua, arg: ArgTuple<Ucid, JtId>) -> Result<()> {
+    let ctx = unsafe { Context::get_mut() };
+    let msg = match get_jtac(&ctx.jtac, &arg.snd)?.nine_line(&ctx.db, lua) {
+        Ok(msg) => msg,
+        Err(e) => format_compact!("{e}"),
+    };
+    ctx.db
+        .ephemeral
+        .panel_to_player(&ctx.db.persisted, 30, &arg.fst, msg);
+    Ok(())
+}
+
 fn change_info(jtac: &Jtac, db: &Db, ucid: &Ucid) -> (String, String) {
     let near = db
         .objective(&jtac.location().oid)
@@ -173,6 +188,84 @@ fn jtac_shift(lua: MizLua, arg: ArgTuple<Ucid, JtId>) -> Result<()> {
     Ok(())
 }
 
+fn jtac_select_contact(lua: MizLua, arg: ArgTriple<JtId, CtId, Ucid>) -> Result<()> {
+    let ctx = unsafe { Context::get_mut() };
+    let jtac = get_jtac_mut(&mut ctx.jtac, &arg.fst)?;
+    let (near, name) = change_info(jtac, &ctx.db, &arg.trd);
+    let msg = match jtac.select_contact(&ctx.db, lua, &arg.snd) {
+        Err(e) => format_compact!("COULD NOT SELECT TARGET\njtac {}\n{e}", arg.fst),
+        Ok(()) => {
+            let target = jtac
+                .target()
+                .as_ref()
+                .map(|t| t.typ.clone())
+                .unwrap_or("no target".into());
+            // the contact may have moved since the menu was built
+            let (bearing, range) = jtac.contact_bearing_range(&arg.snd).unwrap_or((0, 0.));
+            format_compact!(
+                "JTAC NOW TARGETING {}\nbearing {:03} range {:.1}km from the jtac\nauto shift is now disabled\njtac {} near {}\nrequested by {}",
+                target,
+                bearing,
+                range / 1000.,
+                arg.fst,
+                near,
+                name
+            )
+        }
+    };
+    ctx.db
+        .ephemeral
+        .msgs()
+        .panel_to_side(10, false, jtac.side(), msg);
+    Ok(())
+}
+
+fn add_contacts_for_jtac(
+    lua: MizLua,
+    arg: ArgQuad<Ucid, GroupId, JtId, GroupSubMenu>,
+) -> Result<()> {
+    let ctx = unsafe { Context::get_mut() };
+    let jtac = get_jtac(&ctx.jtac, &arg.trd)?;
+    let mc = MissionCommands::singleton(lua)?;
+    let mut cmd: Vec<String> = arg.fth.clone().into();
+    cmd.push("Contacts>>".into());
+    mc.remove_command_for_group(arg.snd, cmd.into())?;
+    let mut menu: Vec<String> = arg.fth.clone().into();
+    menu.push("Contacts".into());
+    mc.remove_submenu_for_group(arg.snd, menu.into())?;
+    let mut root = mc.add_submenu_for_group(arg.snd, "Contacts".into(), Some(arg.fth.clone()))?;
+    mc.add_command_for_group(
+        arg.snd,
+        "Refresh".into(),
+        Some(root.clone()),
+        add_contacts_for_jtac,
+        ArgQuad {
+            fst: arg.fst,
+            snd: arg.snd,
+            trd: arg.trd,
+            fth: arg.fth.clone(),
+        },
+    )?;
+    let contacts = jtac.contact_list(&ctx.db);
+    for (i, (id, typ)) in contacts.into_iter().take(MAX_CONTACTS).enumerate() {
+        if i > 0 && i % 8 == 0 {
+            root = mc.add_submenu_for_group(arg.snd, "Next>>".into(), Some(root.clone()))?;
+        }
+        mc.add_command_for_group(
+            arg.snd,
+            format_compact!("{} {typ}", i + 1).into(),
+            Some(root.clone()),
+            jtac_select_contact,
+            ArgTriple {
+                fst: arg.trd,
+                snd: id,
+                trd: arg.fst,
+            },
+        )?;
+    }
+    Ok(())
+}
+
 fn jtac_artillery_mission(lua: MizLua, arg: ArgQuad<JtId, DbGid, u8, Ucid>) -> Result<()> {
     let ctx = unsafe { Context::get_mut() };
     let adjustment = ctx.jtac.get_artillery_adjustment(&arg.snd);
@@ -541,7 +634,11 @@ pub(super) fn add_menu_for_jtac(
                     Some(player) => format_compact!("{gid}({} {})", spec.name, player.name),
                     None => format_compact!("{gid}({})", spec.name),
                 },
-                DeployKind::Objective | DeployKind::Crate { .. } => format_compact!("{gid}"),
+                DeployKind::Convoy { .. } => format_compact!("{gid}(supply convoy)"),
+                DeployKind::Offensive { .. } => format_compact!("{gid}(attack group)"),
+                DeployKind::Objective | DeployKind::Crate { .. } | DeployKind::Pilot { .. } => {
+                    format_compact!("{gid}")
+                }
             },
         },
         JtId::Slot(sl) => {
@@ -585,6 +682,16 @@ pub(super) fn add_menu_for_jtac(
             snd: jtac.gid(),
         },
     )?;
+    mc.add_command_for_group(
+        mizgid,
+        "9-Line".into(),
+        Some(root.clone()),
+        jtac_nine_line,
+        ArgTuple {
+            fst: *ucid,
+            snd: jtac.gid(),
+        },
+    )?;
     mc.add_command_for_group(
         mizgid,
         "Toggle Auto Shift".into(),
@@ -625,6 +732,20 @@ pub(super) fn add_menu_for_jtac(
             snd: jtac.gid(),
         },
     )?;
+    // contacts come and go all the time, so the list is only built
+    // when the player asks for it
+    mc.add_command_for_group(
+        mizgid,
+        "Contacts>>".into(),
+        Some(root.clone()),
+        add_contacts_for_jtac,
+        ArgQuad {
+            fst: *ucid,
+            snd: mizgid,
+            trd: jtac.gid(),
+            fth: root.clone(),
+        },
+    )?;
     let mut filter_root = mc.add_submenu_for_group(mizgid, "Filter".into(), Some(root.clone()))?;
     mc.add_command_for_group(
         mizgid,
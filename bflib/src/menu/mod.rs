@@ -267,16 +267,19 @@ pub(super) fn init_for_slot(ctx: &mut Context, lua: MizLua, slot: &SlotId) -> Re
             mc.remove_submenu_for_group(si.miz_gid, GroupSubMenu::from(vec!["Actions".into()]))?;
             ewr::add_ewr_menu_for_group(&mc, si.miz_gid)?;
             let cap = CarryCap::from_typ(&cfg, si.typ.as_str());
-            if cap.crates && ctx.db.ephemeral.cfg.rules.cargo.check(&ucid) {
+            if cap.crates && ctx.db.check_rule(&ctx.db.ephemeral.cfg.rules.cargo, &ucid) {
                 cargo::add_cargo_menu_for_group(&cfg, &mc, &si.side, si.miz_gid)?
             }
-            if cap.troops && ctx.db.ephemeral.cfg.rules.troops.check(&ucid) {
+            if cap.troops && ctx.db.check_rule(&ctx.db.ephemeral.cfg.rules.troops, &ucid) {
                 troop::add_troops_menu_for_group(&cfg, &mc, &si.side, si.miz_gid)?
             }
-            if ctx.db.ephemeral.cfg.rules.jtac.check(&ucid) {
+            if ctx.db.check_rule(&ctx.db.ephemeral.cfg.rules.jtac, &ucid) {
                 jtac::init_jtac_menu_for_slot(ctx, lua, slot)?
             }
-            if ctx.db.ephemeral.cfg.rules.actions.check(&ucid) {
+            if ctx
+                .db
+                .check_rule(&ctx.db.ephemeral.cfg.rules.actions, &ucid)
+            {
                 action::init_action_menu_for_slot(ctx, lua, slot, &ucid)?
             }
             Ok(())
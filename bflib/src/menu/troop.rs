@@ -123,6 +123,28 @@ fn return_troops(lua: MizLua, gid: GroupId) -> Result<()> {
     Ok(())
 }
 
+fn pickup_pilot(lua: MizLua, gid: GroupId) -> Result<()> {
+    let ctx = unsafe { Context::get_mut() };
+    let (side, slot) = slot_for_group(lua, ctx, &gid).context("getting slot for group")?;
+    match ctx.db.pickup_pilot(lua, &ctx.idx, &slot) {
+        Ok((pilot, friendly)) => {
+            let player = player_name(&ctx.db, &slot);
+            let msg = if friendly {
+                format_compact!("{player} picked up {pilot}")
+            } else {
+                format_compact!("{player} picked up enemy pilot {pilot}")
+            };
+            ctx.db.ephemeral.msgs().panel_to_side(10, false, side, msg)
+        }
+        Err(e) => ctx
+            .db
+            .ephemeral
+            .msgs()
+            .panel_to_group(10, false, gid, format_compact!("{e}")),
+    }
+    Ok(())
+}
+
 pub(super) fn add_troops_menu_for_group(
     cfg: &Cfg,
     mc: &MissionCommands,
@@ -159,6 +181,15 @@ pub(super) fn add_troops_menu_for_group(
             return_troops,
             group,
         )?;
+        if cfg.csar.is_some() {
+            mc.add_command_for_group(
+                group,
+                "Pick Up Pilot".into(),
+                Some(root.clone()),
+                pickup_pilot,
+                group,
+            )?;
+        }
         let root = mc.add_submenu_for_group(group, "Squads".into(), Some(root))?;
         for sq in squads {
             let item = if sq.cost > 0 {
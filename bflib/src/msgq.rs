@@ -18,7 +18,9 @@ use dcso3::{
     coalition::Side,
     env::miz::{GroupId, UnitId},
     net::{Net, PlayerId},
-    trigger::{Action, ArrowSpec, CircleSpec, MarkId, QuadSpec, RectSpec, SideFilter, TextSpec},
+    trigger::{
+        Action, ArrowSpec, CircleSpec, LineSpec, MarkId, QuadSpec, RectSpec, SideFilter, TextSpec,
+    },
     Color, LuaVec3, String, Vector2, Vector3,
 };
 use log::error;
@@ -63,6 +65,12 @@ pub enum Msg {
         typ: MsgTyp,
         text: String,
     },
+    Line {
+        id: MarkId,
+        to: SideFilter,
+        spec: LineSpec,
+        message: Option<String>,
+    },
     Circle {
         id: MarkId,
         to: SideFilter,
@@ -144,7 +152,8 @@ impl MsgQ {
                 Cmd::DeleteMark(_) => true,
                 Cmd::Send(msg) => match msg {
                     Msg::Message { .. } => true,
-                    Msg::Circle { id, .. }
+                    Msg::Line { id, .. }
+                    | Msg::Circle { id, .. }
                     | Msg::Rect { id, .. }
                     | Msg::Quad { id, .. }
                     | Msg::Text { id, .. }
@@ -301,6 +310,21 @@ impl MsgQ {
         )
     }
 
+    pub fn line_to_all(
+        &mut self,
+        to: SideFilter,
+        id: MarkId,
+        spec: LineSpec,
+        message: Option<String>,
+    ) {
+        self.0[2].push_back(Cmd::Send(Msg::Line {
+            id,
+            to,
+            spec,
+            message,
+        }))
+    }
+
     pub fn circle_to_all(
         &mut self,
         to: SideFilter,
@@ -429,6 +453,12 @@ impl MsgQ {
                         }
                     },
                 },
+                Cmd::Send(Msg::Line {
+                    id,
+                    to,
+                    spec,
+                    message,
+                }) => act.line_to_all(to, id, spec, message),
                 Cmd::Send(Msg::Circle {
                     id,
                     to,
@@ -17,22 +17,30 @@ for more details.
 use std::collections::hash_map::Entry;
 
 /// Lets not bicker and argue about oo killed oo
-use crate::db::{group::GroupId, Db};
+use crate::{
+    acmi::{AcmiKey, AcmiType, Props, Record, WEAPON_SAMPLE_INTERVAL},
+    db::{group::GroupId, Db},
+};
 use anyhow::Result;
 use chrono::{prelude::*, Duration};
 use dcso3::{
     coalition::Side,
+    coord::Coord,
     event::Shot as ShotEvent,
     net::Ucid,
     object::{DcsObject, DcsOid},
     unit::{ClassUnit, Unit},
-    weapon::ClassWeapon,
-    String,
+    weapon::{ClassWeapon, Weapon},
+    MizLua, String,
 };
 use fxhash::FxHashMap;
 use smallvec::SmallVec;
 use serde::{Serialize, Deserialize};
 
+/// Weapon.GuidanceType.RADAR_PASSIVE, the guidance of anti radiation
+/// missiles
+const GUIDANCE_RADAR_PASSIVE: i64 = 5;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dead {
     pub victim: DcsOid<ClassUnit>,
@@ -64,6 +72,8 @@ pub struct ShotDb {
     by_target: FxHashMap<DcsOid<ClassUnit>, SmallVec<[Shot; 8]>>,
     dead: FxHashMap<DcsOid<ClassUnit>, DateTime<Utc>>,
     recently_dead: FxHashMap<DcsOid<ClassUnit>, DateTime<Utc>>,
+    in_flight: FxHashMap<DcsOid<ClassWeapon>, Props>,
+    last_weapon_sample: DateTime<Utc>,
     last_gc: DateTime<Utc>,
 }
 
@@ -112,6 +122,73 @@ impl ShotDb {
         }
     }
 
+    /// Start tracking a weapon for the acmi recording
+    pub fn launched(&mut self, db: &Db, e: &ShotEvent) -> Result<()> {
+        let (side, _) = side_and_gid(db, &e.initiator.object_id()?);
+        let props = Props {
+            typ: AcmiType::Missile,
+            name: e.weapon_name.clone(),
+            pilot: None,
+            group: None,
+            side,
+        };
+        self.in_flight.insert(e.weapon.object_id()?, props);
+        Ok(())
+    }
+
+    /// Record the position of every weapon in flight in the acmi,
+    /// and stop tracking the ones that no longer exist. Weapons are
+    /// sampled at most once every WEAPON_SAMPLE_INTERVAL.
+    pub fn track_weapons(&mut self, lua: MizLua, db: &mut Db, now: DateTime<Utc>) -> Result<()> {
+        if !db.ephemeral.cfg.acmi {
+            // recording was turned off, nothing will ever remove these
+            self.in_flight.clear();
+            return Ok(());
+        }
+        if self.in_flight.is_empty()
+            || now - self.last_weapon_sample < Duration::milliseconds(WEAPON_SAMPLE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_weapon_sample = now;
+        let coord = Coord::singleton(lua)?;
+        let mut gone: SmallVec<[DcsOid<ClassWeapon>; 16]> = SmallVec::new();
+        for (id, props) in &self.in_flight {
+            let pos = Weapon::get_instance(lua, id)
+                .and_then(|w| w.as_object())
+                .and_then(|o| o.get_position());
+            match pos {
+                Ok(pos) => {
+                    let key = AcmiKey::Weapon(id.clone());
+                    db.ephemeral
+                        .acmi(Record::sample(&coord, now, key, &pos, props.clone())?)
+                }
+                Err(_) => gone.push(id.clone()),
+            }
+        }
+        for id in gone {
+            self.in_flight.remove(&id);
+            db.ephemeral.acmi(Record::Remove {
+                time: now,
+                key: AcmiKey::Weapon(id),
+            })
+        }
+        Ok(())
+    }
+
+    /// If the shot is an anti radiation missile fired at a ground
+    /// unit return the group it is going after
+    pub fn anti_radiation(&self, db: &Db, e: &ShotEvent) -> Result<Option<GroupId>> {
+        let guidance: Option<i64> = e.weapon.get_desc()?.raw_get("guidance")?;
+        if guidance != Some(GUIDANCE_RADAR_PASSIVE) {
+            return Ok(None);
+        }
+        match e.weapon.get_target()?.and_then(|t| t.as_unit().ok()) {
+            None => Ok(None),
+            Some(target) => Ok(gid_by_oid(db, &target.object_id()?)),
+        }
+    }
+
     pub fn shot(&mut self, db: &Db, now: DateTime<Utc>, e: ShotEvent) -> Result<()> {
         let target = ok!(some!(e.weapon.get_target()?).as_unit());
         let target_oid = target.object_id()?;
@@ -26,6 +26,8 @@ pub enum StatKind {
     SessionEnd,
     Objective {
         id: ObjectiveId,
+        #[serde(default)]
+        name: String,
         pos: LLPos,
         owner: Side,
         kind: ObjectiveKind,
@@ -89,3 +91,19 @@ pub enum StatKind {
         points: SmallVec<[(Ucid, usize); 2]>,
     },
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stat {
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: StatKind,
+}
+
+impl Stat {
+    pub fn new(kind: StatKind) -> Self {
+        Self {
+            time: Utc::now(),
+            kind,
+        }
+    }
+}
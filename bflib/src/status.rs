@@ -0,0 +1,376 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! A read only view of the mission, served as JSON over http on
+//! localhost from the background thread. The mission thread
+//! periodically sends a Status, and requests are answered from the
+//! latest one.
+
+use crate::{
+    cfg::Vehicle,
+    db::{
+        group::{DeployKind, GroupId},
+        objective::{ObjectiveId, ObjectiveKind},
+        persisted::Persisted,
+    },
+    jtac::Jtac,
+    perf::Perf,
+    Context,
+};
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+use chrono::prelude::*;
+use dcso3::{coalition::Side, net::Ucid, String};
+use hdrhistogram::Histogram;
+use log::{debug, error, info};
+use parking_lot::Mutex;
+use serde_derive::Serialize;
+use serde_json::json;
+use std::{net::Ipv4Addr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PlayerStatus {
+    ucid: Ucid,
+    name: String,
+    side: Side,
+    slot: Option<String>,
+    points: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct JtacTargetStatus {
+    typ: Vehicle,
+    x: f64,
+    z: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct JtacStatus {
+    id: String,
+    side: Side,
+    code: u16,
+    near: ObjectiveId,
+    bearing: f64,
+    distance: f64,
+    autoshift: bool,
+    ir_pointer: bool,
+    target: Option<JtacTargetStatus>,
+}
+
+impl From<&Jtac> for JtacStatus {
+    fn from(jt: &Jtac) -> Self {
+        let loc = jt.location();
+        Self {
+            id: String::from(jt.gid().to_string()),
+            side: jt.side(),
+            code: jt.code(),
+            near: loc.oid,
+            bearing: loc.bearing,
+            distance: loc.distance,
+            autoshift: jt.autoshift(),
+            ir_pointer: jt.ir_pointer(),
+            target: jt.target().as_ref().map(|t| JtacTargetStatus {
+                typ: t.typ.clone(),
+                x: t.pos.x,
+                z: t.pos.z,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ObjectiveStatus<'a> {
+    id: ObjectiveId,
+    name: &'a str,
+    kind: &'static str,
+    owner: Side,
+    health: u8,
+    logi: u8,
+    supply: u8,
+    fuel: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct GroupStatus<'a> {
+    id: GroupId,
+    name: &'a str,
+    side: Side,
+    kind: &'static str,
+    template: &'a str,
+    player: Option<Ucid>,
+    alive: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HistogramStatus {
+    n: u64,
+    p25: u64,
+    p50: u64,
+    p90: u64,
+    p99: u64,
+}
+
+impl From<&Histogram<u64>> for HistogramStatus {
+    // microseconds, the same as the perf log
+    fn from(h: &Histogram<u64>) -> Self {
+        Self {
+            n: h.len(),
+            p25: h.value_at_quantile(0.25) / 1000,
+            p50: h.value_at_quantile(0.5) / 1000,
+            p90: h.value_at_quantile(0.9) / 1000,
+            p99: h.value_at_quantile(0.99) / 1000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Status {
+    ts: DateTime<Utc>,
+    persisted: Persisted,
+    players: Vec<PlayerStatus>,
+    jtacs: Vec<JtacStatus>,
+    perf: Perf,
+}
+
+impl Status {
+    pub(crate) fn new(ctx: &Context, perf: Perf, ts: DateTime<Utc>) -> Self {
+        let players = ctx
+            .connected
+            .info_by_player_id
+            .values()
+            .filter_map(|ifo| {
+                let player = ctx.db.player(&ifo.ucid)?;
+                Some(PlayerStatus {
+                    ucid: ifo.ucid,
+                    name: ifo.name.clone(),
+                    side: player.side,
+                    slot: player
+                        .current_slot
+                        .as_ref()
+                        .map(|(slot, _)| String::from(slot.to_string())),
+                    points: player.points,
+                })
+            })
+            .collect();
+        Self {
+            ts,
+            persisted: ctx.db.persisted.clone(),
+            players,
+            jtacs: ctx.jtac.jtacs().map(JtacStatus::from).collect(),
+            perf,
+        }
+    }
+
+    fn objectives(&self) -> Vec<ObjectiveStatus<'_>> {
+        self.persisted
+            .objectives
+            .into_iter()
+            .map(|(id, obj)| ObjectiveStatus {
+                id: *id,
+                name: obj.name(),
+                kind: match obj.kind() {
+                    ObjectiveKind::Airbase => "airbase",
+                    ObjectiveKind::Fob => "fob",
+                    ObjectiveKind::Logistics => "logistics",
+                    ObjectiveKind::Farp { .. } => "farp",
+                },
+                owner: obj.owner(),
+                health: obj.health(),
+                logi: obj.logi(),
+                supply: obj.supply(),
+                fuel: obj.fuel(),
+            })
+            .collect()
+    }
+
+    fn groups(&self) -> Vec<GroupStatus<'_>> {
+        self.persisted
+            .deployed
+            .into_iter()
+            .chain(&self.persisted.troops)
+            .chain(&self.persisted.pilots)
+            .chain(&self.persisted.convoys)
+            .chain(&self.persisted.offensives)
+            .filter_map(|gid| self.persisted.groups.get(gid))
+            .map(|group| {
+                let (kind, player) = match &group.origin {
+                    DeployKind::Deployed { player, .. } => ("deployed", Some(*player)),
+                    DeployKind::Troop { player, .. } => ("troop", Some(*player)),
+                    DeployKind::Crate { player, .. } => ("crate", Some(*player)),
+                    DeployKind::Action { player, .. } => ("action", *player),
+                    DeployKind::Pilot { player, .. } => ("pilot", Some(*player)),
+                    DeployKind::Convoy { .. } => ("convoy", None),
+                    DeployKind::Offensive { .. } => ("offensive", None),
+                    DeployKind::Objective => ("objective", None),
+                };
+                let alive = group
+                    .units
+                    .into_iter()
+                    .filter_map(|uid| self.persisted.units.get(uid))
+                    .filter(|u| !u.dead)
+                    .count();
+                GroupStatus {
+                    id: group.id,
+                    name: group.name.as_str(),
+                    side: group.side,
+                    kind,
+                    template: group.template_name.as_str(),
+                    player,
+                    alive,
+                }
+            })
+            .collect()
+    }
+
+    fn perf(&self) -> Vec<(&'static str, HistogramStatus)> {
+        let p = &self.perf.inner;
+        [
+            ("timed_events", &p.timed_events),
+            ("slow_timed", &p.slow_timed),
+            ("dcs_events", &p.dcs_events),
+            ("dcs_hooks", &p.dcs_hooks),
+            ("unit_positions", &p.unit_positions),
+            ("player_positions", &p.player_positions),
+            ("ewr_tracks", &p.ewr_tracks),
+            ("ewr_reports", &p.ewr_reports),
+            ("unit_culling", &p.unit_culling),
+            ("remark_objectives", &p.remark_objectives),
+            ("update_jtac_contacts", &p.update_jtac_contacts),
+            ("do_repairs", &p.do_repairs),
+            ("spawn_queue", &p.spawn_queue),
+            ("spawn", &p.spawn),
+            ("despawn", &p.despawn),
+            ("advise_captured", &p.advise_captured),
+            ("advise_capturable", &p.advise_capturable),
+            ("jtac_target_positions", &p.jtac_target_positions),
+            ("process_messages", &p.process_messages),
+            ("snapshot", &p.snapshot),
+            ("logistics", &p.logistics),
+            ("logistics_distribute", &p.logistics_distribute),
+            ("logistics_deliver", &p.logistics_deliver),
+            ("logistics_sync_from", &p.logistics_sync_from),
+            ("logistics_sync_to", &p.logistics_sync_to),
+            ("frame", &*self.perf.frame),
+        ]
+        .into_iter()
+        .map(|(name, h)| (name, HistogramStatus::from(h)))
+        .collect()
+    }
+
+    fn view<T: serde::Serialize>(&self, data: T) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&json!({ "ts": self.ts, "data": data }))?)
+    }
+}
+
+pub type Latest = Arc<Mutex<Option<Arc<Status>>>>;
+
+const PATHS: [&str; 5] = ["/objectives", "/players", "/jtacs", "/groups", "/perf"];
+
+/// answer a request from the latest status, returning the http status
+/// line and the body
+pub fn route(latest: &Latest, method: &str, path: &str) -> Result<(&'static str, Vec<u8>)> {
+    if method != "GET" {
+        let body = serde_json::to_vec("only GET is supported")?;
+        return Ok(("405 Method Not Allowed", body));
+    }
+    if path == "/" {
+        return Ok(("200 OK", serde_json::to_vec(&PATHS)?));
+    }
+    if !PATHS.contains(&path) {
+        return Ok(("404 Not Found", serde_json::to_vec("not found")?));
+    }
+    let status = match latest.lock().clone() {
+        Some(status) => status,
+        None => {
+            let body = serde_json::to_vec("the mission hasn't started yet")?;
+            return Ok(("503 Service Unavailable", body));
+        }
+    };
+    let body = match path {
+        "/objectives" => status.view(status.objectives())?,
+        "/players" => status.view(&status.players)?,
+        "/jtacs" => status.view(&status.jtacs)?,
+        "/groups" => status.view(status.groups())?,
+        "/perf" => status.view(status.perf())?,
+        _ => unreachable!(),
+    };
+    Ok(("200 OK", body))
+}
+
+async fn read_request(sock: &mut TcpStream) -> Result<(String, String)> {
+    let mut buf = BytesMut::with_capacity(1024);
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() > 8192 {
+            bail!("request too large")
+        }
+        if sock.read_buf(&mut buf).await? == 0 {
+            bail!("connection closed")
+        }
+    }
+    let head = std::str::from_utf8(&buf)?;
+    let mut line = head.lines().next().unwrap_or("").split(' ');
+    match (line.next(), line.next()) {
+        (Some(method), Some(path)) => Ok((String::from(method), String::from(path))),
+        _ => bail!("invalid request line"),
+    }
+}
+
+async fn handle(mut sock: TcpStream, latest: Latest) -> Result<()> {
+    let (method, path) = time::timeout(Duration::from_secs(5), read_request(&mut sock)).await??;
+    let path = path.split('?').next().unwrap_or("");
+    let (code, body) = route(&latest, &method, path)?;
+    let head = format!(
+        "HTTP/1.1 {code}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    sock.write_all(head.as_bytes()).await?;
+    sock.write_all(&body).await?;
+    sock.shutdown().await?;
+    Ok(())
+}
+
+/// serve the status api on localhost:port until the process exits
+pub(crate) async fn serve(port: u16, latest: Latest) {
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("could not listen for status requests on port {port}, {e:?}");
+            return;
+        }
+    };
+    info!("serving status on 127.0.0.1:{port}");
+    loop {
+        match listener.accept().await {
+            Err(e) => {
+                error!("could not accept status connection {e:?}");
+                time::sleep(Duration::from_secs(1)).await
+            }
+            Ok((sock, _)) => {
+                let latest = Arc::clone(&latest);
+                tokio::spawn(async move {
+                    if let Err(e) = handle(sock, latest).await {
+                        debug!("status request failed {e:?}")
+                    }
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::Result;
+use bflib::{
+    acmi::{escape, AcmiKey, AcmiLog, AcmiType, Props, Record},
+    db::objective::ObjectiveKind,
+    stats::{Stat, StatKind},
+};
+use chrono::{prelude::*, Duration};
+use dcso3::{coalition::Side, coord::LLPos, String};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+fn at(secs: i64) -> DateTime<Utc> {
+    start() + Duration::seconds(secs)
+}
+
+fn write_dir(name: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("bflib-acmi-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("Logs"))?;
+    Ok(dir)
+}
+
+fn read_recording(dir: &Path) -> Result<Vec<std::string::String>> {
+    let file = fs::File::open(dir.join("Logs/bftrack.txt.acmi.zst"))?;
+    let mut s = std::string::String::new();
+    zstd::stream::Decoder::new(file)?.read_to_string(&mut s)?;
+    Ok(s.lines().map(|l| l.to_owned()).collect())
+}
+
+fn pos(latitude: f64, longitude: f64, altitude: f64) -> LLPos {
+    LLPos {
+        latitude,
+        longitude,
+        altitude,
+    }
+}
+
+fn stat(secs: i64, kind: StatKind) -> Stat {
+    Stat {
+        time: at(secs),
+        kind,
+    }
+}
+
+fn session_start(secs: i64) -> Stat {
+    stat(
+        secs,
+        StatKind::SessionStart {
+            stop_time: at(secs) + Duration::hours(4),
+        },
+    )
+}
+
+fn props(name: &str) -> Props {
+    Props {
+        typ: AcmiType::FixedWing,
+        name: String::from(name),
+        pilot: Some(String::from("Viper 1-1, lead")),
+        group: None,
+        side: Side::Blue,
+    }
+}
+
+fn sample(secs: i64, key: &AcmiKey, props: Props) -> Record {
+    Record::Sample {
+        time: at(secs),
+        key: key.clone(),
+        pos: pos(42.1, 41.7, 1500.),
+        heading: 90.,
+        props,
+    }
+}
+
+#[test]
+fn escaping() {
+    assert_eq!(escape("Batumi"), "Batumi");
+    assert_eq!(escape("a,b"), "a\\,b");
+    assert_eq!(escape("a\\b"), "a\\\\b");
+    assert_eq!(escape("two\nlines"), "two lines");
+}
+
+#[test]
+fn samples_are_written_as_frames() -> Result<()> {
+    let dir = write_dir("samples")?;
+    let mut log = AcmiLog::new(&dir);
+    log.stats(&[session_start(0)])?;
+    let key = AcmiKey::Unit("7".parse()?);
+    log.write(vec![
+        sample(1, &key, props("F-16C_50")),
+        // same props, only the position is written
+        sample(2, &key, props("F-16C_50")),
+        // changed props are written again
+        sample(3, &key, props("F-16C_50,block 50")),
+        Record::Remove {
+            time: at(4),
+            key: key.clone(),
+        },
+        // removing an object that isn't recorded is ignored
+        Record::Remove { time: at(5), key },
+    ])?;
+    log.finish()?;
+    let lines = read_recording(&dir)?;
+    assert_eq!(
+        &lines[..4],
+        [
+            "FileType=text/acmi/tacview",
+            "FileVersion=2.2",
+            "0,ReferenceTime=2024-06-01T12:00:00Z",
+            "0,DataSource=bfnext",
+        ]
+    );
+    assert_eq!(
+        &lines[4..],
+        [
+            "#1.00",
+            "1,T=41.7000000|42.1000000|1500.0|||90.0,Type=Air+FixedWing,Name=F-16C_50,Coalition=blue,Color=Blue,Pilot=Viper 1-1\\, lead",
+            "#2.00",
+            "1,T=41.7000000|42.1000000|1500.0|||90.0",
+            "#3.00",
+            "1,T=41.7000000|42.1000000|1500.0|||90.0,Type=Air+FixedWing,Name=F-16C_50\\,block 50,Coalition=blue,Color=Blue,Pilot=Viper 1-1\\, lead",
+            "#4.00",
+            "-1",
+        ]
+    );
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn objectives_and_captures_come_from_the_stats() -> Result<()> {
+    let dir = write_dir("stats")?;
+    let mut log = AcmiLog::new(&dir);
+    let objective = |secs, id: &str, name: &str, kind| -> Result<Stat> {
+        Ok(stat(
+            secs,
+            StatKind::Objective {
+                id: id.parse()?,
+                name: String::from(name),
+                pos: pos(41.6, 41.6, 10.),
+                owner: Side::Red,
+                kind,
+            },
+        ))
+    };
+    let capture = |secs, side| -> Result<Stat> {
+        Ok(stat(
+            secs,
+            StatKind::Capture {
+                id: "0".parse()?,
+                ucid: "0123456789abcdef0123456789abcdef".parse()?,
+                side,
+                points: 10,
+            },
+        ))
+    };
+    log.stats(&[
+        session_start(0),
+        objective(0, "0", "Batumi", ObjectiveKind::Airbase)?,
+        // nothing is recorded yet, so this is only tracked
+        capture(1, Side::Blue)?,
+    ])?;
+    let key = AcmiKey::Unit("7".parse()?);
+    log.write(vec![sample(2, &key, props("F-16C_50"))])?;
+    log.stats(&[
+        objective(3, "1", "Kobuleti, FARP", ObjectiveKind::Fob)?,
+        // already owned by blue
+        capture(4, Side::Blue)?,
+        capture(5, Side::Red)?,
+    ])?;
+    log.finish()?;
+    let lines = read_recording(&dir)?;
+    assert_eq!(
+        &lines[4..],
+        [
+            "1,T=41.6000000|41.6000000|10.0,Type=Ground+Static+Aerodrome,Name=Batumi,Coalition=blue,Color=Blue",
+            "#2.00",
+            "2,T=41.7000000|42.1000000|1500.0|||90.0,Type=Air+FixedWing,Name=F-16C_50,Coalition=blue,Color=Blue,Pilot=Viper 1-1\\, lead",
+            "3,T=41.6000000|41.6000000|10.0,Type=Ground+Static+Building,Name=Kobuleti\\, FARP,Coalition=red,Color=Red",
+            "#5.00",
+            "1,Coalition=red,Color=Red",
+            "0,Event=Bookmark|Batumi captured by red",
+        ]
+    );
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn each_session_gets_a_file() -> Result<()> {
+    let dir = write_dir("sessions")?;
+    let mut log = AcmiLog::new(&dir);
+    let key = AcmiKey::Unit("7".parse()?);
+    for session in 0..3 {
+        let t = session * 100;
+        log.stats(&[session_start(t)])?;
+        log.write(vec![sample(t + 1, &key, props("F-16C_50"))])?;
+    }
+    log.finish()?;
+    // sessions started in the same second don't clobber each other
+    let files = fs::read_dir(dir.join("Logs"))?.count();
+    assert_eq!(files, 3);
+    let lines = read_recording(&dir)?;
+    assert_eq!(lines[2], "0,ReferenceTime=2024-06-01T12:03:20Z");
+    // ids and props start over in the new file
+    assert_eq!(
+        lines[5],
+        "1,T=41.7000000|42.1000000|1500.0|||90.0,Type=Air+FixedWing,Name=F-16C_50,Coalition=blue,Color=Blue,Pilot=Viper 1-1\\, lead"
+    );
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use anyhow::Result;
+use bflib::cfg::Cfg;
+use dcso3::net::Ucid;
+use fxhash::FxHashSet;
+
+#[test]
+fn admin_roles_limit_commands() -> Result<()> {
+    let mut cfg = common::cfg();
+    let [owner, moderator, player]: [Ucid; 3] = [
+        "00000000000000000000000000000001".parse()?,
+        "00000000000000000000000000000002".parse()?,
+        "00000000000000000000000000000003".parse()?,
+    ];
+    for ucid in [owner, moderator] {
+        cfg.admins.insert(ucid, "admin".into());
+    }
+    cfg.admin_roles.insert(
+        "moderator".into(),
+        FxHashSet::from_iter(["kick".into(), "ban".into()]),
+    );
+    cfg.admin_roles
+        .insert("campaign".into(), FxHashSet::from_iter(["*".into()]));
+    cfg.admin_assignments
+        .insert(owner, FxHashSet::from_iter(["campaign".into()]));
+    cfg.admin_assignments
+        .insert(moderator, FxHashSet::from_iter(["moderator".into()]));
+    cfg.validate()?;
+    assert!(cfg.admin_may(&owner, "reset"));
+    assert!(cfg.admin_may(&moderator, "kick"));
+    assert!(!cfg.admin_may(&moderator, "reset"));
+    assert!(!cfg.admin_may(&player, "kick"));
+    cfg.admin_assignments
+        .insert(moderator, FxHashSet::from_iter(["campaign".into()]));
+    assert!(cfg.admin_may(&moderator, "reset"));
+    Ok(())
+}
+
+#[test]
+fn unassigned_admins_hold_the_default_roles() -> Result<()> {
+    let mut cfg = common::cfg();
+    let admin: Ucid = "00000000000000000000000000000001".parse()?;
+    cfg.admins.insert(admin, "admin".into());
+    cfg.admin_roles.insert(
+        "moderator".into(),
+        FxHashSet::from_iter(["kick".into(), "ban".into()]),
+    );
+    cfg.admin_default_roles = Some(FxHashSet::from_iter(["moderator".into()]));
+    cfg.validate()?;
+    assert!(cfg.admin_may(&admin, "kick"));
+    assert!(!cfg.admin_may(&admin, "reset"));
+    Ok(())
+}
+
+#[test]
+fn configs_without_roles_let_admins_run_every_command() -> Result<()> {
+    let default = Cfg::default();
+    let (admin, _) = default.admins.iter().next().unwrap();
+    for cmd in ["reset", "add-admin", "reload", "kick"] {
+        assert!(default.admin_may(admin, cmd), "{cmd}");
+    }
+    let mut old = serde_json::to_value(&default)?;
+    let fields = old.as_object_mut().unwrap();
+    for field in ["admin_roles", "admin_assignments", "admin_default_roles"] {
+        fields.remove(field);
+    }
+    let old: Cfg = serde_json::from_value(old)?;
+    old.validate()?;
+    for cmd in ["reset", "add-admin", "reload", "kick"] {
+        assert!(old.admin_may(admin, cmd), "{cmd}");
+    }
+    Ok(())
+}
+
+#[test]
+fn admin_roles_are_validated() -> Result<()> {
+    let mut cfg = common::cfg();
+    let admin: Ucid = "00000000000000000000000000000001".parse()?;
+    Cfg::default().validate()?;
+    cfg.admin_roles.insert(
+        "moderator".into(),
+        FxHashSet::from_iter(["kick".into(), "nuke".into()]),
+    );
+    assert!(cfg.validate().is_err());
+    cfg.admin_roles.insert(
+        "moderator".into(),
+        FxHashSet::from_iter(["kick".into(), "log-desc".into()]),
+    );
+    cfg.validate()?;
+    cfg.admin_assignments
+        .insert(admin, FxHashSet::from_iter(["moderater".into()]));
+    assert!(cfg.validate().is_err());
+    cfg.admin_assignments
+        .insert(admin, FxHashSet::from_iter(["moderator".into()]));
+    cfg.validate()?;
+    cfg.admin_default_roles = Some(FxHashSet::from_iter(["owner".into()]));
+    assert!(cfg.validate().is_err());
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use bflib::audit::{self, AuditEntry, AuditKind, AuditQuery};
+use dcso3::net::Ucid;
+use std::{fs, io::Write};
+
+#[test]
+fn journal_queries_by_player_and_count() -> Result<()> {
+    let admin: Ucid = "00000000000000000000000000000001".parse()?;
+    let griefer: Ucid = "00000000000000000000000000000002".parse()?;
+    let mut ban = AuditEntry::new(AuditKind::Admin, "ban");
+    ban.issuer = Some((admin, "Admin".into()));
+    ban.target = Some((griefer, "Griefer".into()));
+    ban.args = "forever".into();
+    ban.result = Ok("Griefer banned until None".into());
+    let mut tk = AuditEntry::new(AuditKind::Punishment, "teamkill-penalty");
+    tk.target = Some((griefer, "Griefer".into()));
+    let mut save = AuditEntry::new(AuditKind::ConfigSave, "save-config");
+    save.result = Err("disk full".into());
+    let path = std::env::temp_dir().join(format!("bfaudit-{}.jsonl", std::process::id()));
+    let mut file = fs::File::create(&path)?;
+    let mut expired = AuditEntry::new(AuditKind::System, "ban-expired");
+    expired.target = Some((griefer, "Griefer".into()));
+    for entry in [&tk, &ban, &save] {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    // a line cut short by a crash must not hide the rest of the journal
+    let truncated = serde_json::to_string(&expired)?;
+    writeln!(file, "{}", &truncated[..truncated.len() / 2])?;
+    writeln!(file, "{}", truncated)?;
+    drop(file);
+    let griefer_entries = audit::query(&path, &AuditQuery::parse("griefer", 10))?;
+    assert_eq!(
+        griefer_entries
+            .iter()
+            .map(|e| e.action.as_str())
+            .collect::<Vec<_>>(),
+        vec!["teamkill-penalty", "ban", "ban-expired"]
+    );
+    let by_ucid = audit::query(&path, &AuditQuery::parse(&admin.to_string(), 10))?;
+    assert_eq!(by_ucid.len(), 1);
+    let last = audit::query(&path, &AuditQuery::parse("2", 10))?;
+    assert_eq!(last.len(), 2);
+    assert_eq!(last[1].kind, AuditKind::System);
+    let last = &last[..1];
+    assert!(last[0].summary().ends_with("failed disk full"));
+    assert!(ban
+        .summary()
+        .contains("Admin(00000000000000000000000000000001) ban Griefer"));
+    fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use anyhow::Result;
+use bflib::cfg::Cfg;
+
+#[test]
+fn merge_live_applies_live_fields_and_holds_back_the_rest() -> Result<()> {
+    let live = Cfg::default();
+    let (merged, report) = live.merge_live(Cfg::default())?;
+    assert!(report.is_empty());
+    assert_eq!(merged.repair_time, live.repair_time);
+    let new = Cfg {
+        repair_time: live.repair_time + 60,
+        shutdown: Some(live.shutdown.unwrap_or(0) + 1),
+        ..Cfg::default()
+    };
+    let (merged, report) = live.merge_live(new)?;
+    assert_eq!(report.applied, vec!["repair_time"]);
+    assert_eq!(report.needs_restart, vec!["shutdown"]);
+    assert!(report.applied("repair_time"));
+    assert_eq!(merged.repair_time, live.repair_time + 60);
+    // restart only fields keep their live value until the restart
+    assert_eq!(merged.shutdown, live.shutdown);
+    Ok(())
+}
+
+#[test]
+fn merge_live_rejects_missing_default_lives() {
+    let live = Cfg::default();
+    let mut new = Cfg::default();
+    let lt = *new.life_types.values().next().unwrap();
+    new.default_lives.remove(&lt);
+    assert!(live.merge_live(new).is_err());
+}
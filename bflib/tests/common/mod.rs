@@ -0,0 +1,132 @@
+//! Helpers shared by the integration tests
+
+use anyhow::{anyhow, Result};
+use bflib::{
+    cfg::Cfg,
+    db::{objective::ObjectiveKind, sim::Sim},
+};
+use chrono::{prelude::*, Duration};
+use dcso3::{coalition::Side, net::Ucid, Vector2};
+use serde_json::json;
+
+pub const TANK: &str = "M-1 Abrams";
+
+pub fn cfg() -> Cfg {
+    let troops = |side: &str| {
+        json!([{
+            "name": "Capture",
+            "template": format!("{side}TROOP"),
+            "persist": "Forever",
+            "can_capture": true,
+            "limit": 2,
+            "limit_enforce": "DeleteOldest",
+            "weight": 800,
+            "cost": 0,
+            "jtac": null
+        }])
+    };
+    let transfer_crate = json!({
+        "name": "Supply Transfer",
+        "weight": 1000,
+        "required": 1,
+        "pos_unit": null,
+        "max_drop_height_agl": 10,
+        "max_drop_speed": 13
+    });
+    serde_json::from_value(json!({
+        "points": {
+            "new_player_join": 0,
+            "air_kill": 5,
+            "ground_kill": 1,
+            "lr_sam_bonus": 5,
+            "logistics_repair": 5,
+            "logistics_transfer": 5,
+            "capture": 10
+        },
+        "repair_time": 1800,
+        "repair_crate": {},
+        "warehouse": {
+            "hub_max": 10,
+            "airbase_max": 2,
+            "tick": 10,
+            "ticks_per_delivery": 6,
+            "supply_transfer_crate": { "Blue": transfer_crate, "Red": transfer_crate },
+            "supply_transfer_size": 25,
+            "supply_source": {}
+        },
+        "logistics_exclusion": 10000,
+        "unit_cull_distance": 70000,
+        "ground_vehicle_cull_distance": 10000,
+        "slow_timed_events_freq": 10,
+        "threatened_distance": {},
+        "threatened_cooldown": 300,
+        "crate_load_distance": 50,
+        "crate_spread": 250,
+        "artillery_mission_range": 20000,
+        "life_types": {},
+        "default_lives": {},
+        "troops": { "Blue": troops("B"), "Red": troops("R") },
+        "unit_classification": {
+            "Ural-375": ["Logistics", "Unarmed"],
+            "Soldier M4": ["Infantry", "SmallArms"],
+            TANK: ["Armor", "HeavyCannon"]
+        },
+        "jtac_priority": []
+    }))
+    .unwrap()
+}
+
+pub fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+/// A small campaign with a hub, an airbase, a registered player, and
+/// some deployed troops, stepped far enough that most of the save
+/// state is populated.
+#[allow(dead_code)]
+pub fn campaign() -> Result<Sim> {
+    let mut sim = Sim::new(cfg(), start());
+    let hub = sim.add_objective(
+        "Kutaisi",
+        ObjectiveKind::Logistics,
+        Side::Blue,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Red,
+        Vector2::new(40000., 0.),
+        2000.,
+    );
+    for oid in [hub, base] {
+        sim.add_objective_group(
+            oid,
+            Side::Blue,
+            "BLOGI",
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+        sim.add_objective_group(
+            oid,
+            Side::Red,
+            "RLOGI",
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+    }
+    sim.set_production(Side::Blue, &[(TANK, 10)], &[]);
+    sim.set_production(Side::Red, &[(TANK, 10)], &[]);
+    sim.start()?;
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    sim.deploy_troops(
+        ucid,
+        "Capture",
+        Some(hub),
+        &[("Soldier M4", Vector2::new(20000., 0.))],
+    )?;
+    sim.step(Duration::minutes(10))?;
+    Ok(sim)
+}
@@ -0,0 +1,44 @@
+use bflib::{
+    cfg::IadsCfg,
+    iads::{plan, SiteState},
+};
+use dcso3::{coalition::Side, Vector2};
+
+#[test]
+fn iads_sites_light_up_for_hostiles_in_range() {
+    let cfg = IadsCfg {
+        engagement_range: 40_000,
+        search_range: 100_000,
+        share_range: 20_000,
+        arm_shutdown: 120,
+    };
+    let site = |x: f64, search: bool, suppressed: bool| SiteState {
+        side: Side::Red,
+        pos: Vector2::new(x, 0.),
+        search,
+        suppressed,
+    };
+    // a search radar, a launcher cued by it, and a launcher too far
+    // away to share its picture
+    let sites = [
+        site(0., true, false),
+        site(15_000., false, false),
+        site(-60_000., false, false),
+    ];
+    let none = plan(&cfg, &sites, &[]);
+    assert_eq!(&none[..], &[false, false, false]);
+    // hostiles in the other side's radar picture don't matter
+    let friendly = plan(&cfg, &sites, &[(Side::Blue, Vector2::new(30_000., 0.))]);
+    assert_eq!(&friendly[..], &[false, false, false]);
+    let inbound = plan(&cfg, &sites, &[(Side::Red, Vector2::new(-30_000., 0.))]);
+    assert_eq!(&inbound[..], &[true, true, true]);
+    let east = plan(&cfg, &sites, &[(Side::Red, Vector2::new(35_000., 0.))]);
+    assert_eq!(&east[..], &[true, true, false]);
+    // the search radar is hiding from an arm, so the launcher it
+    // was cueing only lights up if the hostile is in its own range
+    let sites = [site(0., true, true), site(15_000., false, false)];
+    let far = plan(&cfg, &sites, &[(Side::Red, Vector2::new(-30_000., 0.))]);
+    assert_eq!(&far[..], &[false, false]);
+    let near = plan(&cfg, &sites, &[(Side::Red, Vector2::new(30_000., 0.))]);
+    assert_eq!(&near[..], &[false, true]);
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use bflib::{
+    cfg::{DeployableCounterBattery, DeployableEwr, RcsClass},
+    counterbattery::locate,
+    ewr::{detect, radar_horizon},
+};
+use dcso3::{coalition::Side, Vector2, Vector3};
+
+fn ewr(range: u32, antenna_height: u32, notch: u32) -> DeployableEwr {
+    DeployableEwr {
+        range,
+        antenna_height,
+        notch,
+    }
+}
+
+fn clear(_: f64, _: Vector3, _: Vector3) -> Result<bool> {
+    Ok(true)
+}
+
+fn masked(_: f64, _: Vector3, _: Vector3) -> Result<bool> {
+    Ok(false)
+}
+
+fn unreachable(_: f64, _: Vector3, _: Vector3) -> Result<bool> {
+    panic!("line of sight should not be checked")
+}
+
+#[test]
+fn range_depends_on_radar_cross_section() -> Result<()> {
+    let ewr = ewr(100_000, 10, 0);
+    let radar = Vector3::new(0., 0., 0.);
+    let fast = Vector3::new(-250., 0., 0.);
+    let at = |x| Vector3::new(x, 10_000., 0.);
+    assert!(detect(
+        &ewr,
+        radar,
+        at(90_000.),
+        fast,
+        RcsClass::Medium,
+        clear
+    )?);
+    assert!(!detect(
+        &ewr,
+        radar,
+        at(110_000.),
+        fast,
+        RcsClass::Medium,
+        unreachable
+    )?);
+    assert!(detect(
+        &ewr,
+        radar,
+        at(110_000.),
+        fast,
+        RcsClass::Large,
+        clear
+    )?);
+    assert!(!detect(
+        &ewr,
+        radar,
+        at(50_000.),
+        fast,
+        RcsClass::Stealth,
+        unreachable
+    )?);
+    assert!(detect(
+        &ewr,
+        radar,
+        at(15_000.),
+        fast,
+        RcsClass::Stealth,
+        clear
+    )?);
+    Ok(())
+}
+
+#[test]
+fn low_targets_hide_below_the_horizon() -> Result<()> {
+    let ewr = ewr(400_000, 10, 0);
+    let radar = Vector3::new(0., 0., 0.);
+    let velocity = Vector3::new(-250., 0., 0.);
+    let horizon = radar_horizon(10., 100.);
+    assert!(horizon > 50_000. && horizon < 60_000.);
+    let low = Vector3::new(100_000., 100., 0.);
+    let high = Vector3::new(100_000., 10_000., 0.);
+    assert!(!detect(
+        &ewr,
+        radar,
+        low,
+        velocity,
+        RcsClass::Medium,
+        unreachable
+    )?);
+    assert!(detect(
+        &ewr,
+        radar,
+        high,
+        velocity,
+        RcsClass::Medium,
+        clear
+    )?);
+    Ok(())
+}
+
+#[test]
+fn terrain_masks_targets() -> Result<()> {
+    let ewr = ewr(100_000, 10, 0);
+    let radar = Vector3::new(0., 500., 0.);
+    let target = Vector3::new(20_000., 300., 0.);
+    let velocity = Vector3::new(-250., 0., 0.);
+    assert!(!detect(
+        &ewr,
+        radar,
+        target,
+        velocity,
+        RcsClass::Medium,
+        masked
+    )?);
+    assert!(detect(
+        &ewr,
+        radar,
+        target,
+        velocity,
+        RcsClass::Medium,
+        |d, p0, p1| {
+            assert_eq!(p0.y, 510.);
+            assert_eq!(p1, target);
+            assert!((d - 20_000.).abs() < 10.);
+            Ok(true)
+        }
+    )?);
+    Ok(())
+}
+
+#[test]
+fn beaming_targets_are_lost_in_the_notch() -> Result<()> {
+    let awacs = ewr(400_000, 0, 30);
+    let radar = Vector3::new(0., 9_000., 0.);
+    let low = Vector3::new(100_000., 300., 0.);
+    let hot = Vector3::new(-250., 0., 0.);
+    let beam = Vector3::new(0., 0., 250.);
+    assert!(detect(&awacs, radar, low, hot, RcsClass::Medium, clear)?);
+    assert!(!detect(
+        &awacs,
+        radar,
+        low,
+        beam,
+        RcsClass::Medium,
+        unreachable
+    )?);
+    // looking up there is no clutter to hide in
+    let high = Vector3::new(100_000., 10_000., 0.);
+    assert!(detect(&awacs, radar, high, beam, RcsClass::Medium, clear)?);
+    Ok(())
+}
+
+#[test]
+fn counter_battery_radar_locates_enemy_artillery_in_range() {
+    let cb = DeployableCounterBattery {
+        range: 20_000,
+        return_fire: 0,
+    };
+    let radars = [(Vector2::new(0., 0.), Side::Blue, &cb)];
+    let near = Vector2::new(15_000., 0.);
+    let far = Vector2::new(25_000., 0.);
+    assert_eq!(
+        locate(radars, Side::Red, near).map(|(s, _)| s),
+        Some(Side::Blue)
+    );
+    assert!(locate(radars, Side::Red, far).is_none());
+    // a radar doesn't report its own side's artillery
+    assert!(locate(radars, Side::Blue, near).is_none());
+}
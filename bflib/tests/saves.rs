@@ -0,0 +1,467 @@
+use anyhow::{anyhow, Result};
+use bflib::{
+    cfg::{Cfg, DeployableJtac, LifeType, UnitTag, UnitTags},
+    db::{
+        csar::Pilot,
+        migrate::{self, SchemaVersion, SCHEMA_VERSION},
+        objective::ObjectiveKind,
+        persisted::Persisted,
+        read_persisted,
+        sim::Sim,
+        JtacSettings,
+    },
+};
+use chrono::Duration;
+use dcso3::{coalition::Side, net::Ucid, Vector2};
+use proptest::{prelude::*, sample::Index};
+use serde_json::{json, Value};
+use std::{fs, io::Write, path::PathBuf};
+
+mod common;
+
+use common::{cfg, start, TANK};
+
+fn corpus() -> Result<Vec<PathBuf>> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/saves");
+    let mut saves = vec![];
+    for file in fs::read_dir(dir)? {
+        saves.push(file?.path())
+    }
+    saves.sort();
+    Ok(saves)
+}
+
+fn round_trip(persisted: &Persisted) -> Result<()> {
+    let encoded = serde_json::to_vec(persisted)?;
+    let decoded = migrate::decode(&encoded[..])?;
+    assert_eq!(
+        serde_json::to_value(persisted)?,
+        serde_json::to_value(&decoded)?
+    );
+    Ok(())
+}
+
+/// rewrite a current save the way version 0 stored it
+fn downgrade_to_v0(save: &mut Value) -> Result<()> {
+    let save = save
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("not an object"))?;
+    save.remove("version");
+    for obj in save["objectives"]
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("no objectives"))?
+        .values_mut()
+    {
+        let obj = obj
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("not an object"))?;
+        let zone = obj.remove("zone").ok_or_else(|| anyhow!("no zone"))?;
+        let circle = &zone["Circle"];
+        obj.insert("pos".into(), circle["pos"].clone());
+        obj.insert("radius".into(), circle["radius"].clone());
+    }
+    Ok(())
+}
+
+#[test]
+fn corpus_loads_round_trips_and_validates() -> Result<()> {
+    let saves = corpus()?;
+    assert!(!saves.is_empty());
+    for path in saves {
+        let persisted = read_persisted(&path)?;
+        assert_eq!(persisted.version, SchemaVersion(SCHEMA_VERSION), "{path:?}");
+        persisted
+            .validate()
+            .map_err(|e| anyhow!("{path:?} is inconsistent {e:?}"))?;
+        for (_, obj) in &persisted.objectives {
+            assert!(
+                obj.zone().radius() > 0.,
+                "{path:?} {} has no zone",
+                obj.name()
+            );
+        }
+        assert!(persisted.objectives.len() > 2, "{path:?} is too small");
+        assert!(
+            persisted.objectives.into_iter().any(|(_, obj)| obj
+                .warehouse()
+                .equipment()
+                .into_iter()
+                .any(|(_, e)| e.stored > 0)),
+            "{path:?} has no warehouse stock"
+        );
+        assert!(persisted.deployed.len() > 0, "{path:?} has no deployables");
+        assert!(persisted.players.len() > 1, "{path:?} has too few players");
+        round_trip(&persisted)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn newer_saves_are_rejected() {
+    let mut save = json!({ "version": SCHEMA_VERSION + 1 });
+    assert!(migrate::migrate(&mut save).is_err());
+}
+
+#[test]
+fn jtac_settings_survive_a_save() -> Result<()> {
+    let mut cfg = cfg();
+    for troop in cfg.troops.get_mut(&Side::Blue).unwrap() {
+        troop.jtac = Some(DeployableJtac {
+            range: 5000,
+            nolos: false,
+        });
+    }
+    let mut sim = Sim::new(cfg, start());
+    let oid = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Blue,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    sim.start()?;
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    let gid = sim.deploy_troops(
+        ucid,
+        "Capture",
+        Some(oid),
+        &[("Soldier M4", Vector2::new(100., 100.))],
+    )?;
+    let settings = JtacSettings {
+        code: 1511,
+        filter: UnitTags(UnitTag::Armor.into()),
+        autoshift: false,
+        ir_pointer: true,
+        target: None,
+    };
+    sim.db.save_jtac_settings(gid, settings.clone());
+    let persisted = &sim.db.persisted;
+    persisted.validate().map_err(|e| anyhow!("{e:?}"))?;
+    round_trip(persisted)?;
+    let decoded = migrate::decode(&serde_json::to_vec(persisted)?[..])?;
+    assert_eq!(decoded.jtac_settings.get(&gid), Some(&settings));
+    let uid = *sim.db.group(&gid)?.units.into_iter().next().unwrap();
+    sim.kill_unit(uid)?;
+    assert!(sim.db.jtac_settings(&gid).is_none());
+    Ok(())
+}
+
+#[test]
+fn carried_pilots_survive_a_save() -> Result<()> {
+    let mut sim = Sim::new(cfg(), start());
+    sim.start()?;
+    let rescuer: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    let downed: Ucid = "fedcba9876543210fedcba9876543210".parse()?;
+    for (ucid, name, side) in [
+        (rescuer, "rescuer", Side::Blue),
+        (downed, "downed", Side::Red),
+    ] {
+        sim.db
+            .register_player(ucid, name.into(), side)
+            .map_err(|_| anyhow!("failed to register player"))?;
+    }
+    let pilot = Pilot {
+        player: downed,
+        life_type: Some(LifeType::Standard),
+        weight: 100,
+    };
+    let persisted = &mut sim.db.persisted;
+    persisted
+        .carried_pilots
+        .insert_cow(rescuer, [pilot].into_iter().collect());
+    persisted.validate().map_err(|e| anyhow!("{e:?}"))?;
+    round_trip(persisted)?;
+    let decoded = migrate::decode(&serde_json::to_vec(&*persisted)?[..])?;
+    let carried = decoded.carried_pilots.get(&rescuer).unwrap();
+    assert_eq!(carried.len(), 1);
+    assert_eq!(carried[0].player, downed);
+    // pilots carried by a player missing from the save are an error
+    persisted.players.remove_cow(&rescuer);
+    assert!(persisted.validate().is_err());
+    Ok(())
+}
+
+/// The objectives of the Caucasus campaign in miz/test.miz as
+/// (name, kind, owner, x, y, radius), taken from its trigger zones
+#[rustfmt::skip]
+const CAUCASUS: [(&str, ObjectiveKind, Side, f64, f64, f64); 8] = [
+    ("Batumi", ObjectiveKind::Airbase, Side::Blue, -355757.07, 617359.63, 2133.6),
+    ("Kobuleti", ObjectiveKind::Airbase, Side::Red, -317913.28, 635762.59, 2133.6),
+    ("Kemalpasha", ObjectiveKind::Logistics, Side::Blue, -371607.72, 611631.39, 1219.2),
+    ("Kvedo Nasakirali", ObjectiveKind::Logistics, Side::Red, -311273.62, 651507.38, 1219.2),
+    ("Tbilisi", ObjectiveKind::Airbase, Side::Red, -315485.50, 896637.84, 1828.8),
+    ("Mukhrani", ObjectiveKind::Logistics, Side::Red, -293212.94, 863493.06, 1828.8),
+    ("Sukhumi", ObjectiveKind::Airbase, Side::Blue, -220544.28, 564363.70, 2743.2),
+    ("Gali", ObjectiveKind::Logistics, Side::Blue, -239148.59, 612377.85, 1828.8),
+];
+
+/// The test config with a jtac and an ewr that players can deploy
+fn corpus_cfg() -> Result<Cfg> {
+    let mut cfg = cfg();
+    let deployable = |template: &str, name: &str, ewr: Value, jtac: Value| {
+        json!({
+            "path": ["Ground", name],
+            "template": template,
+            "persist": "Forever",
+            "limit": 2,
+            "limit_enforce": "DeleteOldest",
+            "crates": [],
+            "repair_crate": null,
+            "logistics": null,
+            "ewr": ewr,
+            "jtac": jtac
+        })
+    };
+    for (side, s) in [(Side::Blue, "B"), (Side::Red, "R")] {
+        let deps = serde_json::from_value(json!([
+            deployable(
+                &format!("{s}DEPEWR"),
+                "EWR",
+                json!({ "range": 150000 }),
+                Value::Null
+            ),
+            deployable(
+                &format!("{s}DEPJTAC"),
+                "JTAC",
+                Value::Null,
+                json!({ "range": 10000, "nolos": false })
+            ),
+        ]))?;
+        cfg.deployables.insert(side, deps);
+    }
+    for (typ, tags) in [
+        ("FPS-117", UnitTag::EWR | UnitTag::SearchRadar),
+        ("Hummer", UnitTag::APC | UnitTag::Unarmed),
+    ] {
+        cfg.unit_classification.insert(typ.into(), UnitTags(tags));
+    }
+    Ok(cfg)
+}
+
+/// A trimmed down version of the Caucasus campaign a few hours into
+/// a round, with stocked and depleted warehouses, players on both
+/// sides, their troops and deployables, and some losses.
+fn corpus_campaign() -> Result<Sim> {
+    let mut sim = Sim::new(corpus_cfg()?, start());
+    let (mut oids, mut armor) = (vec![], vec![]);
+    for (name, kind, owner, x, y, radius) in CAUCASUS {
+        let pos = Vector2::new(x, y);
+        let oid = sim.add_objective(name, kind, owner, pos, radius);
+        let near = |dx: f64, dy: f64| Vector2::new(x + dx, y + dy);
+        for (side, logi, armor_template) in [
+            (Side::Blue, "BLOGI", "BARMOR"),
+            (Side::Red, "RLOGI", "RARMOR"),
+        ] {
+            sim.add_objective_group(
+                oid,
+                side,
+                logi,
+                &[("Ural-375", near(50., 50.)), ("Ural-375", near(60., 50.))],
+            )?;
+            let gid = sim.add_objective_group(
+                oid,
+                side,
+                armor_template,
+                &[(TANK, near(-300., 200.)), (TANK, near(-320., 220.))],
+            )?;
+            if side == owner {
+                armor.push(gid)
+            }
+        }
+        oids.push(oid);
+    }
+    sim.set_production(Side::Blue, &[(TANK, 10), ("Ural-375", 4)], &[]);
+    sim.set_production(Side::Red, &[(TANK, 10), ("Ural-375", 4)], &[]);
+    sim.start()?;
+    let players = [
+        ("0123456789abcdef0123456789abcd01", "Viper 1-1", Side::Blue),
+        ("0123456789abcdef0123456789abcd02", "Hawg 2-1", Side::Blue),
+        (
+            "0123456789abcdef0123456789abcd03",
+            "Frogfoot 1-1",
+            Side::Red,
+        ),
+        ("0123456789abcdef0123456789abcd04", "Hip 3-1", Side::Red),
+    ];
+    let mut ucids = vec![];
+    for (ucid, name, side) in players {
+        let ucid: Ucid = ucid.parse()?;
+        sim.db
+            .register_player(ucid, name.into(), side)
+            .map_err(|_| anyhow!("failed to register {name}"))?;
+        ucids.push(ucid);
+    }
+    let (batumi, kobuleti) = (oids[0], oids[1]);
+    let toward_kobuleti = Vector2::new(-330000., 630000.);
+    sim.deploy_troops(
+        ucids[1],
+        "Capture",
+        Some(batumi),
+        &[("Soldier M4", toward_kobuleti)],
+    )?;
+    sim.deploy(ucids[1], "JTAC", &[("Hummer", toward_kobuleti)])?;
+    sim.deploy(
+        ucids[0],
+        "EWR",
+        &[("FPS-117", Vector2::new(-350000., 615000.))],
+    )?;
+    sim.deploy(
+        ucids[3],
+        "EWR",
+        &[("FPS-117", Vector2::new(-312000., 650000.))],
+    )?;
+    sim.deploy_troops(
+        ucids[3],
+        "Capture",
+        Some(kobuleti),
+        &[("Soldier M4", Vector2::new(-320000., 634000.))],
+    )?;
+    // players have been flying stock out of the front line airbases
+    sim.set_stored(batumi, TANK, 3)?;
+    sim.set_stored(kobuleti, TANK, 0)?;
+    // and the defenders of Kobuleti took some losses
+    for gid in &armor[1..2] {
+        let uid = *sim.db.group(gid)?.units.into_iter().next().unwrap();
+        sim.kill_unit(uid)?;
+    }
+    for _ in 0..6 {
+        sim.step(Duration::minutes(10))?;
+        sim.spawn_convoys(&["Ural-375"])?;
+    }
+    Ok(sim)
+}
+
+/// The top level fields of a save before it was versioned
+const V0_FIELDS: [&str; 19] = [
+    "groups",
+    "units",
+    "groups_by_name",
+    "units_by_name",
+    "groups_by_side",
+    "deployed",
+    "farps",
+    "crates",
+    "troops",
+    "jtacs",
+    "ewrs",
+    "actions",
+    "objectives",
+    "objectives_by_name",
+    "objectives_by_group",
+    "players",
+    "logistics_hubs",
+    "nukes_used",
+    "logistics_ticks_since_delivery",
+];
+
+/// Regenerate the save corpus. The saves are written the way each
+/// version of bflib wrote them, so once written they should not be
+/// regenerated unless the campaign they hold needs to change.
+///
+/// cargo test -p bflib --test saves -- --ignored generate_corpus
+#[test]
+#[ignore]
+fn generate_corpus() -> Result<()> {
+    let sim = corpus_campaign()?;
+    let persisted = &sim.db.persisted;
+    persisted.validate().map_err(|e| anyhow!("{e:?}"))?;
+    let current = serde_json::to_value(persisted)?;
+    let mut v0 = current.clone();
+    downgrade_to_v0(&mut v0)?;
+    v0.as_object_mut()
+        .unwrap()
+        .retain(|k, _| V0_FIELDS.contains(&k.as_str()));
+    // saves written during the zone transition have zones that were
+    // never set next to the pos and radius
+    let mut transition = v0.clone();
+    let save = transition.as_object_mut().unwrap();
+    for obj in save["objectives"].as_object_mut().unwrap().values_mut() {
+        obj.as_object_mut().unwrap().insert(
+            "zone".into(),
+            json!({ "Circle": { "pos": [0., 0.], "radius": 0. } }),
+        );
+    }
+    for k in ["round", "winner", "holding"] {
+        save.insert(k.into(), current[k].clone());
+    }
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/saves");
+    for (name, save) in [
+        ("v1_campaign", &current),
+        ("v0_pos_radius", &v0),
+        ("v0_zone_transition", &transition),
+    ] {
+        let file = fs::File::create(dir.join(format!("{name}.json.zst")))?;
+        let mut enc = zstd::stream::Encoder::new(file, 19)?;
+        enc.write_all(&serde_json::to_vec(save)?)?;
+        enc.finish()?;
+    }
+    Ok(())
+}
+
+fn campaign(objectives: &[(u8, bool, f64, f64, f64)], kills: &[Index], steps: u32) -> Result<Sim> {
+    let mut sim = Sim::new(cfg(), start());
+    let mut first = None;
+    for (i, (kind, blue, x, y, radius)) in objectives.iter().enumerate() {
+        let kind = match kind {
+            0 => ObjectiveKind::Airbase,
+            1 => ObjectiveKind::Fob,
+            _ => ObjectiveKind::Logistics,
+        };
+        let owner = if *blue { Side::Blue } else { Side::Red };
+        let pos = Vector2::new(*x, *y);
+        let oid = sim.add_objective(&format!("obj{i}"), kind, owner, pos, *radius);
+        for (side, template) in [(Side::Blue, "BLOGI"), (Side::Red, "RLOGI")] {
+            sim.add_objective_group(oid, side, template, &[("Ural-375", pos)])?;
+        }
+        first.get_or_insert(oid);
+    }
+    sim.start()?;
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    sim.deploy_troops(ucid, "Capture", first, &[("Soldier M4", Vector2::zeros())])?;
+    let uids: Vec<_> = sim
+        .db
+        .persisted
+        .units
+        .into_iter()
+        .map(|(id, _)| *id)
+        .collect();
+    for idx in kills {
+        sim.kill_unit(*idx.get(&uids))?;
+    }
+    for _ in 0..steps {
+        sim.step(Duration::minutes(10))?;
+    }
+    Ok(sim)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn campaigns_round_trip(
+        objectives in prop::collection::vec(
+            (0u8..3, any::<bool>(), -1e5..1e5f64, -1e5..1e5f64, 500.0..5000.0f64),
+            1..6,
+        ),
+        kills in prop::collection::vec(any::<Index>(), 0..4),
+        steps in 0u32..4,
+    ) {
+        let sim = campaign(&objectives, &kills, steps).unwrap();
+        let persisted = &sim.db.persisted;
+        persisted.validate().unwrap();
+        round_trip(persisted).unwrap();
+        let mut v0 = serde_json::to_value(persisted).unwrap();
+        downgrade_to_v0(&mut v0).unwrap();
+        let upgraded = migrate::decode(&serde_json::to_vec(&v0).unwrap()[..]).unwrap();
+        prop_assert_eq!(
+            serde_json::to_value(persisted).unwrap(),
+            serde_json::to_value(&upgraded).unwrap()
+        );
+    }
+}
@@ -0,0 +1,588 @@
+use anyhow::{anyhow, Result};
+use bflib::{
+    cfg::{CaptureCfg, ConvoyCfg, FrontLineCfg, HoldCfg, OffensiveCfg, WinCfg},
+    db::{
+        objective::{ObjectiveId, ObjectiveKind},
+        sim::Sim,
+    },
+    shots::Dead,
+    stats::StatKind,
+};
+use chrono::Duration;
+use dcso3::{coalition::Side, net::Ucid, Vector2};
+use fxhash::FxHashMap;
+use serde_json::json;
+use std::sync::Arc;
+
+mod common;
+
+use common::{cfg, start, TANK};
+
+#[test]
+fn troops_capture_undefended_airbase() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.win = Some(WinCfg {
+        all_airbases: true,
+        ..WinCfg::default()
+    });
+    let mut sim = Sim::new(cfg, start());
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Red,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    let logi = sim.add_objective_group(
+        base,
+        Side::Red,
+        "RLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.add_objective_group(
+        base,
+        Side::Blue,
+        "BLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.start()?;
+    assert!(!sim.db.objective(&base)?.captureable());
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    let uids: Vec<_> = sim.db.group(&logi)?.units.into_iter().copied().collect();
+    for uid in uids {
+        sim.kill_unit(uid)?;
+    }
+    assert!(sim.db.objective(&base)?.captureable());
+    sim.deploy_troops(
+        ucid,
+        "Capture",
+        None,
+        &[("Soldier M4", Vector2::new(50., -50.))],
+    )?;
+    let report = sim.step(Duration::seconds(10))?;
+    assert_eq!(&report.captured[..], &[(Side::Blue, base)]);
+    assert_eq!(sim.db.objective(&base)?.owner(), Side::Blue);
+    assert_eq!(sim.db.player(&ucid).unwrap().points, 10);
+    assert_eq!(sim.db.career(&ucid).unwrap().captures, 1);
+    // it was the only airbase
+    assert_eq!(report.winner, Some(Side::Blue));
+    assert!(report.stats.iter().any(|st| matches!(
+        st.kind,
+        StatKind::Capture { id, side: Side::Blue, points: 10, .. } if id == base
+    )));
+    Ok(())
+}
+
+#[test]
+fn round_does_not_end_on_a_tie_or_without_hubs() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.win = Some(WinCfg {
+        hold: Some(HoldCfg {
+            percent: 50,
+            hours: 1,
+        }),
+        supply_exhausted: true,
+        ..WinCfg::default()
+    });
+    let mut sim = Sim::new(cfg.clone(), start());
+    for (name, side, x) in [("Kutaisi", Side::Blue, 0.), ("Senaki", Side::Red, 100000.)] {
+        let oid = sim.add_objective(
+            name,
+            ObjectiveKind::Airbase,
+            side,
+            Vector2::new(x, 0.),
+            2000.,
+        );
+        let template = if side == Side::Blue { "BLOGI" } else { "RLOGI" };
+        sim.add_objective_group(
+            oid,
+            side,
+            template,
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+    }
+    sim.start()?;
+    sim.step(Duration::seconds(10))?;
+    // both sides have held half the map equally long, and neither
+    // has any logistics hubs to run out of supply
+    let report = sim.step(Duration::hours(2))?;
+    assert_eq!(report.winner, None);
+    cfg.win.as_mut().unwrap().hold.as_mut().unwrap().percent = 101;
+    assert!(cfg.validate().is_err());
+    Ok(())
+}
+
+#[test]
+fn troops_do_not_capture_defended_airbase() -> Result<()> {
+    let mut sim = Sim::new(cfg(), start());
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Red,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    sim.add_objective_group(
+        base,
+        Side::Red,
+        "RLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.start()?;
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    sim.deploy_troops(
+        ucid,
+        "Capture",
+        None,
+        &[("Soldier M4", Vector2::new(50., -50.))],
+    )?;
+    let report = sim.step(Duration::seconds(10))?;
+    assert!(report.captured.is_empty());
+    assert_eq!(sim.db.objective(&base)?.owner(), Side::Red);
+    Ok(())
+}
+
+#[test]
+fn timed_capture_pauses_while_contested() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.capture = Some(CaptureCfg {
+        time: 300,
+        decay: 300,
+        warning: 60,
+    });
+    let mut sim = Sim::new(cfg, start());
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Red,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    let logi = sim.add_objective_group(
+        base,
+        Side::Red,
+        "RLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    let armor = sim.add_objective_group(
+        base,
+        Side::Red,
+        "RARMOR",
+        &[(TANK, Vector2::new(-100., 100.))],
+    )?;
+    sim.add_objective_group(
+        base,
+        Side::Blue,
+        "BLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.start()?;
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    let uids: Vec<_> = sim.db.group(&logi)?.units.into_iter().copied().collect();
+    for uid in uids {
+        sim.kill_unit(uid)?;
+    }
+    assert!(sim.db.objective(&base)?.captureable());
+    sim.deploy_troops(
+        ucid,
+        "Capture",
+        None,
+        &[("Soldier M4", Vector2::new(50., -50.))],
+    )?;
+    // the tank is still defending
+    for _ in 0..4 {
+        let report = sim.step(Duration::minutes(5))?;
+        assert!(report.captured.is_empty());
+    }
+    let capture = *sim.db.objective(&base)?.capture().unwrap();
+    assert_eq!(capture.side, Side::Blue);
+    assert!(capture.contested);
+    assert_eq!(capture.progress, 0.);
+    let uids: Vec<_> = sim.db.group(&armor)?.units.into_iter().copied().collect();
+    for uid in uids {
+        sim.kill_unit(uid)?;
+    }
+    let report = sim.step(Duration::minutes(4))?;
+    assert!(report.captured.is_empty());
+    let capture = *sim.db.objective(&base)?.capture().unwrap();
+    assert!(!capture.contested);
+    assert!(capture.warned);
+    let report = sim.step(Duration::minutes(1))?;
+    assert_eq!(&report.captured[..], &[(Side::Blue, base)]);
+    assert_eq!(sim.db.objective(&base)?.owner(), Side::Blue);
+    assert!(sim.db.objective(&base)?.capture().is_none());
+    Ok(())
+}
+
+#[test]
+fn hub_resupplies_airbase() -> Result<()> {
+    let mut sim = Sim::new(cfg(), start());
+    let hub = sim.add_objective(
+        "Kutaisi",
+        ObjectiveKind::Logistics,
+        Side::Blue,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Blue,
+        Vector2::new(40000., 0.),
+        2000.,
+    );
+    for oid in [hub, base] {
+        sim.add_objective_group(
+            oid,
+            Side::Blue,
+            "BLOGI",
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+    }
+    sim.set_production(Side::Blue, &[(TANK, 10)], &[]);
+    sim.start()?;
+    assert_eq!(sim.db.objective(&hub)?.get_equipment(TANK).stored, 100);
+    assert_eq!(sim.db.objective(&base)?.get_equipment(TANK).stored, 20);
+    sim.step(Duration::seconds(10))?;
+    sim.set_stored(base, TANK, 0)?;
+    // nothing moves until the next logistics tick
+    sim.step(Duration::minutes(5))?;
+    assert_eq!(sim.db.objective(&base)?.get_equipment(TANK).stored, 0);
+    sim.step(Duration::minutes(5))?;
+    let base_inv = sim.db.objective(&base)?.get_equipment(TANK);
+    let hub_inv = sim.db.objective(&hub)?.get_equipment(TANK);
+    assert!(base_inv.stored > 0);
+    assert_eq!(base_inv.stored + hub_inv.stored, 100);
+    Ok(())
+}
+
+/// a hub and a base far enough apart that supplies go by road,
+/// returns after the first convoy has been loaded
+fn convoy_sim() -> Result<(Sim, ObjectiveId, ObjectiveId)> {
+    let mut cfg = cfg();
+    cfg.convoy = Some(ConvoyCfg {
+        template: FxHashMap::default(),
+        min_distance: 10000,
+        speed: 15.,
+        max_age: 120,
+    });
+    let mut sim = Sim::new(cfg, start());
+    let hub = sim.add_objective(
+        "Kutaisi",
+        ObjectiveKind::Logistics,
+        Side::Blue,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Blue,
+        Vector2::new(40000., 0.),
+        2000.,
+    );
+    for oid in [hub, base] {
+        sim.add_objective_group(
+            oid,
+            Side::Blue,
+            "BLOGI",
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+    }
+    sim.set_production(Side::Blue, &[(TANK, 10)], &[]);
+    sim.start()?;
+    sim.step(Duration::seconds(10))?;
+    sim.set_stored(base, TANK, 0)?;
+    sim.step(Duration::minutes(10))?;
+    Ok((sim, hub, base))
+}
+
+#[test]
+fn convoy_delivers_what_survives() -> Result<()> {
+    let (mut sim, hub, base) = convoy_sim()?;
+    // the supplies left the hub but are still on the road
+    let hub_stored = sim.db.objective(&hub)?.get_equipment(TANK).stored;
+    assert!(hub_stored < 100);
+    assert_eq!(sim.db.objective(&base)?.get_equipment(TANK).stored, 0);
+    let convoys = sim.spawn_convoys(&["Ural-375", "Ural-375"])?;
+    assert_eq!(convoys.len(), 1);
+    let convoy = convoys[0];
+    let uid = *sim.db.group(&convoy)?.units.into_iter().next().unwrap();
+    sim.kill_unit(uid)?;
+    sim.move_group(convoy, Vector2::new(40000., 100.))?;
+    sim.step(Duration::seconds(10))?;
+    assert!(sim.db.group(&convoy).is_err());
+    let loaded = 100 - hub_stored;
+    assert_eq!(
+        sim.db.objective(&base)?.get_equipment(TANK).stored,
+        loaded / 2
+    );
+    Ok(())
+}
+
+#[test]
+fn convoy_that_never_arrives_is_lost() -> Result<()> {
+    let (mut sim, _, base) = convoy_sim()?;
+    // the loaded cargo is saved until the convoy is on the road
+    assert_eq!(sim.db.persisted.pending_convoys.len(), 1);
+    let convoys = sim.spawn_convoys(&["Ural-375"])?;
+    assert_eq!(convoys.len(), 1);
+    assert_eq!(sim.db.persisted.pending_convoys.len(), 0);
+    sim.step(Duration::minutes(121))?;
+    assert!(sim.db.group(&convoys[0]).is_err());
+    assert_eq!(sim.db.objective(&base)?.get_equipment(TANK).stored, 0);
+    Ok(())
+}
+
+#[test]
+fn convoys_on_the_road_are_delivered_when_disabled() -> Result<()> {
+    let (mut sim, hub, base) = convoy_sim()?;
+    let loaded = 100 - sim.db.objective(&hub)?.get_equipment(TANK).stored;
+    let convoys = sim.spawn_convoys(&["Ural-375"])?;
+    Arc::make_mut(&mut sim.db.ephemeral.cfg).convoy = None;
+    sim.step(Duration::seconds(10))?;
+    assert!(sim.db.group(&convoys[0]).is_err());
+    assert_eq!(sim.db.objective(&base)?.get_equipment(TANK).stored, loaded);
+    Ok(())
+}
+
+#[test]
+fn offensive_captures_undefended_objective() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.offensive = Some(OffensiveCfg {
+        template: FxHashMap::default(),
+        min_supply: 0,
+        min_logi: 50,
+        range: 20000,
+        freq: 5,
+        groups: 2,
+        cost: [(TANK.into(), 10)].into_iter().collect(),
+        speed: 10.,
+        lifetime: 60,
+        max_groups: 4,
+        population_scale: vec![],
+    });
+    let mut sim = Sim::new(cfg, start());
+    let hub = sim.add_objective(
+        "Kutaisi",
+        ObjectiveKind::Logistics,
+        Side::Blue,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    let base = sim.add_objective(
+        "Senaki",
+        ObjectiveKind::Airbase,
+        Side::Red,
+        Vector2::new(15000., 0.),
+        2000.,
+    );
+    for oid in [hub, base] {
+        sim.add_objective_group(
+            oid,
+            Side::Blue,
+            "BLOGI",
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+    }
+    let logi = sim.add_objective_group(
+        base,
+        Side::Red,
+        "RLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.set_production(Side::Blue, &[(TANK, 10)], &[]);
+    sim.start()?;
+    let uids: Vec<_> = sim.db.group(&logi)?.units.into_iter().copied().collect();
+    for uid in uids {
+        sim.kill_unit(uid)?;
+    }
+    sim.step(Duration::seconds(10))?;
+    // the first full tick starts the clock on the hub's attacks
+    sim.step(Duration::minutes(10))?;
+    sim.step(Duration::minutes(10))?;
+    let before = sim.db.objective(&hub)?.get_equipment(TANK).stored;
+    let groups = sim.spawn_offensives(&[TANK])?;
+    assert_eq!(groups.len(), 2);
+    // the cost of the attack was taken when it was planned
+    assert!(before < 100);
+    sim.move_group(groups[0], Vector2::new(15000., 100.))?;
+    let report = sim.step(Duration::seconds(10))?;
+    assert_eq!(&report.captured[..], &[(Side::Blue, base)]);
+    assert_eq!(sim.db.objective(&base)?.owner(), Side::Blue);
+    // the other group has nothing left to attack and goes home
+    sim.step(Duration::seconds(10))?;
+    for gid in groups {
+        assert!(sim.db.group(&gid).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn offensive_tiny_scale_does_not_panic() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.offensive = Some(OffensiveCfg {
+        template: FxHashMap::default(),
+        min_supply: 0,
+        min_logi: 0,
+        range: 20000,
+        freq: 5,
+        groups: 2,
+        cost: [(TANK.into(), 10)].into_iter().collect(),
+        speed: 10.,
+        lifetime: 60,
+        max_groups: 4,
+        population_scale: vec![(0, 1e-30)],
+    });
+    let mut sim = Sim::new(cfg, start());
+    let hub = sim.add_objective(
+        "Kutaisi",
+        ObjectiveKind::Logistics,
+        Side::Blue,
+        Vector2::new(0., 0.),
+        2000.,
+    );
+    sim.add_objective_group(
+        hub,
+        Side::Blue,
+        "BLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.set_production(Side::Blue, &[(TANK, 10)], &[]);
+    sim.start()?;
+    sim.step(Duration::minutes(10))?;
+    sim.step(Duration::minutes(10))?;
+    assert!(sim.spawn_offensives(&[TANK])?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn front_line_moves_with_captures() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.front_line = Some(FrontLineCfg {
+        resolution: 1000,
+        airbase: 5000,
+        fob: 2000,
+        farp: 2000,
+        logistics: 5000,
+        shade: true,
+        threatened_distance: None,
+    });
+    let mut sim = Sim::new(cfg, start());
+    let mut add = |name, side, x| {
+        sim.add_objective(
+            name,
+            ObjectiveKind::Airbase,
+            side,
+            Vector2::new(x, 0.),
+            2000.,
+        )
+    };
+    let home = add("Kutaisi", Side::Blue, 0.);
+    let base = add("Senaki", Side::Red, 40000.);
+    let rear = add("Kobuleti", Side::Red, 80000.);
+    for oid in [home, base, rear] {
+        sim.add_objective_group(
+            oid,
+            Side::Blue,
+            "BLOGI",
+            &[("Ural-375", Vector2::new(100., 100.))],
+        )?;
+    }
+    let logi = sim.add_objective_group(
+        base,
+        Side::Red,
+        "RLOGI",
+        &[("Ural-375", Vector2::new(100., 100.))],
+    )?;
+    sim.start()?;
+    let front = sim.db.front_line().unwrap();
+    assert!(front.distance(Vector2::new(20000., 0.)) < 1000.);
+    assert_eq!(front.side_at(Vector2::new(30000., 0.)), Side::Red);
+    assert!(!front.territory().is_empty());
+    // the front between two airbases is straight, it should be drawn
+    // as one line, not one mark per grid cell
+    assert_eq!(front.lines().len(), 1);
+    assert_eq!(front.lines()[0].len(), 2);
+    // the shading is a handful of rectangles, not one per row
+    assert!(front.territory().len() <= 4);
+    let ucid: Ucid = "0123456789abcdef0123456789abcdef".parse()?;
+    sim.db
+        .register_player(ucid, "pilot".into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register player"))?;
+    let uids: Vec<_> = sim.db.group(&logi)?.units.into_iter().copied().collect();
+    for uid in uids {
+        sim.kill_unit(uid)?;
+    }
+    sim.deploy_troops(
+        ucid,
+        "Capture",
+        None,
+        &[("Soldier M4", Vector2::new(40050., -50.))],
+    )?;
+    let report = sim.step(Duration::seconds(10))?;
+    assert_eq!(&report.captured[..], &[(Side::Blue, base)]);
+    let front = sim.db.front_line().unwrap();
+    assert!(front.distance(Vector2::new(60000., 0.)) < 1000.);
+    assert_eq!(front.side_at(Vector2::new(50000., 0.)), Side::Blue);
+    Ok(())
+}
+
+#[test]
+fn kills_are_recorded_without_points() -> Result<()> {
+    let mut cfg = cfg();
+    cfg.points = None;
+    let mut sim = Sim::new(cfg, start());
+    sim.start()?;
+    let [shooter, victim]: [Ucid; 2] = [
+        "0123456789abcdef0123456789abcdef".parse()?,
+        "fedcba9876543210fedcba9876543210".parse()?,
+    ];
+    for (ucid, side) in [(shooter, Side::Blue), (victim, Side::Red)] {
+        sim.db
+            .register_player(ucid, "pilot".into(), side)
+            .map_err(|_| anyhow!("failed to register player"))?;
+    }
+    let dead = |victim_ucid: Option<Ucid>, typ: &str| -> Result<Dead> {
+        let oid = |id: u64| json!({ "id": id, "class": "Unit" });
+        Ok(serde_json::from_value(json!({
+            "victim": oid(2),
+            "victim_ucid": victim_ucid,
+            "victim_side": "Red",
+            "victim_gid": null,
+            "time": start(),
+            "shots": [{
+                "weapon_name": "Mk-82",
+                "weapon": null,
+                "shooter": oid(1),
+                "shooter_ucid": shooter,
+                "shooter_gid": null,
+                "target": oid(2),
+                "target_side": "Red",
+                "target_ucid": victim_ucid,
+                "target_gid": null,
+                "target_typ": typ,
+                "time": start(),
+                "hit": true
+            }]
+        }))?)
+    };
+    sim.db.player_kill(dead(None, TANK)?);
+    // a player driving a tank is still a ground kill
+    sim.db.player_kill(dead(Some(victim), TANK)?);
+    let career = sim.db.career(&shooter).unwrap();
+    assert_eq!(career.ground_kills.total, 2);
+    assert_eq!(career.air_kills.total, 0);
+    assert_eq!(career.ground_kills.by_victim.get(TANK), Some(&2));
+    assert_eq!(sim.db.player(&shooter).unwrap().points, 0);
+    Ok(())
+}
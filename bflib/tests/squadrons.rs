@@ -0,0 +1,64 @@
+mod common;
+
+use anyhow::{anyhow, Result};
+use bflib::{
+    cfg::Rule,
+    db::{sim::Sim, Db},
+};
+use dcso3::{coalition::Side, net::Ucid};
+use fxhash::{FxHashMap, FxHashSet};
+
+fn register(db: &mut Db, n: u8, name: &str) -> Result<Ucid> {
+    let ucid: Ucid = format!("{n:032x}").parse()?;
+    db.register_player(ucid, name.into(), Side::Blue)
+        .map_err(|_| anyhow!("failed to register {name}"))?;
+    Ok(ucid)
+}
+
+#[test]
+fn squadron_roster_rules_and_pool() -> Result<()> {
+    let mut sim = Sim::new(common::cfg(), common::start());
+    sim.start()?;
+    let db = &mut sim.db;
+    let lead = register(db, 1, "=VF1= Lead")?;
+    let wing = register(db, 2, "=VF1= Wing")?;
+    let stranger = register(db, 3, "Stranger")?;
+    assert!(db.create_squadron(&stranger, "Wolfpack", "=VF1=").is_err());
+    db.create_squadron(&lead, "Wolfpack", "=VF1=")?;
+    assert!(db.create_squadron(&wing, "Other", "=VF").is_err());
+    assert!(db.join_squadron(&stranger, "wolfpack").is_err());
+    db.join_squadron(&wing, "wolfpack")?;
+    assert_eq!(
+        db.squadron_of(&wing).map(|(n, _)| n.as_str()),
+        Some("Wolfpack")
+    );
+    assert!(db.set_squadron_pool(&wing, true).is_err());
+    db.persisted.validate()?;
+
+    let rule = Rule::Whitelist {
+        allowed: FxHashMap::default(),
+        squadrons: FxHashSet::from_iter(["Wolfpack".into()]),
+    };
+    assert!(db.check_rule(&rule, &wing));
+    assert!(!db.check_rule(&rule, &stranger));
+
+    db.adjust_points(&lead, 30, "test");
+    db.adjust_points(&wing, 10, "test");
+    db.set_squadron_pool(&lead, true)?;
+    db.deposit_to_squadron(&lead, 30)?;
+    assert_eq!(db.spendable_points(&wing), 40);
+    db.spend_points(&wing, 25, "test");
+    assert_eq!(db.player(&wing).map(|p| p.points), Some(0));
+    assert_eq!(db.squadron_of(&wing).and_then(|(_, sq)| sq.pool), Some(15));
+    assert!(db.set_squadron_pool(&lead, false).is_err());
+
+    db.leave_squadron(&lead)?;
+    let (_, sq) = db
+        .squadron_of(&wing)
+        .ok_or_else(|| anyhow!("missing squadron"))?;
+    assert!(sq.officers.contains(&wing));
+    db.leave_squadron(&wing)?;
+    assert_eq!(db.squadrons().count(), 0);
+    assert_eq!(db.player(&wing).map(|p| p.points), Some(15));
+    Ok(())
+}
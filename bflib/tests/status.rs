@@ -0,0 +1,25 @@
+use anyhow::Result;
+use bflib::status::{route, Latest};
+
+#[test]
+fn routes() -> Result<()> {
+    let latest = Latest::default();
+    let (code, body) = route(&latest, "GET", "/")?;
+    assert_eq!(code, "200 OK");
+    let paths: Vec<String> = serde_json::from_slice(&body)?;
+    assert!(paths.iter().any(|p| p == "/objectives"));
+    assert_eq!(route(&latest, "GET", "/secrets")?.0, "404 Not Found");
+    assert_eq!(
+        route(&latest, "POST", "/objectives")?.0,
+        "405 Method Not Allowed"
+    );
+    // nothing can be served until the mission sends the first snapshot
+    for path in &paths {
+        assert_eq!(
+            route(&latest, "GET", path)?.0,
+            "503 Service Unavailable",
+            "{path}"
+        );
+    }
+    Ok(())
+}
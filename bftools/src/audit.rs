@@ -0,0 +1,29 @@
+use crate::AuditCmd;
+use anyhow::Result;
+use bflib::audit::{self, AuditQuery};
+use std::io::{self, BufWriter, Write};
+
+pub fn run(cmd: &AuditCmd) -> Result<()> {
+    let query = match &cmd.player {
+        None => AuditQuery::Last(usize::MAX),
+        Some(player) => AuditQuery::Player(player.as_str().into(), usize::MAX),
+    };
+    let mut entries = audit::query(&cmd.journal, &query)?;
+    if let Some(action) = &cmd.action {
+        entries.retain(|e| e.action.eq_ignore_ascii_case(action))
+    }
+    if let Some(n) = cmd.last {
+        entries = entries.split_off(entries.len().saturating_sub(n))
+    }
+    let mut out = BufWriter::new(io::stdout().lock());
+    for entry in entries {
+        if cmd.json {
+            serde_json::to_writer(&mut out, &entry)?;
+            writeln!(out)?
+        } else {
+            writeln!(out, "{}", entry.summary())?
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -1,9 +1,12 @@
 use anyhow::Result;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde_derive::Serialize;
 use std::path::PathBuf;
 
+mod audit;
 mod mission_edit;
+mod supply_lines;
+mod validate;
 
 #[derive(Args, Clone, Debug, Serialize)]
 struct MizCmd {
@@ -28,9 +31,81 @@ struct MizCmd {
     red_production_template: String
 }
 
+#[derive(Args, Clone, Debug, Serialize)]
+struct ValidateCmd {
+    /// the config file to check
+    #[clap(long)]
+    cfg: PathBuf,
+    /// the mission file the config will run with
+    #[clap(long)]
+    miz: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+enum SupplyFormat {
+    Dot,
+    Geojson,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+enum Theater {
+    Caucasus,
+    Syria,
+    PersianGulf,
+    Nevada,
+    Normandy,
+    MarianaIslands,
+    TheChannel,
+    SinaiMap,
+    Falklands,
+    Kola,
+    Afghanistan,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+struct SupplyLinesCmd {
+    /// the save file to read
+    #[clap(long)]
+    save: PathBuf,
+    /// the output format
+    #[clap(long, value_enum, default_value = "dot")]
+    format: SupplyFormat,
+    /// the map the save is from, needed to compute lat/lon for geojson
+    #[clap(long, value_enum)]
+    theater: Option<Theater>,
+    /// where to write the output, stdout if not specified
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+struct AuditCmd {
+    /// the audit journal, Logs/bfaudit.jsonl in the dcs write dir
+    #[clap(long)]
+    journal: PathBuf,
+    /// only entries issued by or targeting this player, a ucid or part of a name
+    #[clap(long)]
+    player: Option<String>,
+    /// only entries for this action, e.g. ban
+    #[clap(long)]
+    action: Option<String>,
+    /// only the last n matching entries
+    #[clap(long)]
+    last: Option<usize>,
+    /// print the entries as json instead of a summary
+    #[clap(long)]
+    json: bool,
+}
+
 #[derive(Subcommand, Clone, Debug, Serialize)]
 enum Tools {
     Miz(MizCmd),
+    /// check that a config file agrees with a mission file
+    Validate(ValidateCmd),
+    /// export the logistics network in a save file as dot or geojson
+    SupplyLines(SupplyLinesCmd),
+    /// search the admin audit journal
+    Audit(AuditCmd),
 }
 
 #[derive(Parser)]
@@ -45,6 +120,9 @@ fn main() -> Result<()> {
 
     match bftools_args.tool {
         Tools::Miz(cfg) => mission_edit::run(&cfg)?,
+        Tools::Validate(cfg) => validate::run(&cfg)?,
+        Tools::SupplyLines(cfg) => supply_lines::run(&cfg)?,
+        Tools::Audit(cfg) => audit::run(&cfg)?,
     };
     Ok(())
 }
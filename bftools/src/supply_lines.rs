@@ -0,0 +1,258 @@
+use crate::{SupplyFormat, SupplyLinesCmd, Theater};
+use anyhow::{anyhow, Context, Result};
+use bflib::db::{
+    logistics::Inventory,
+    objective::{Objective, ObjectiveId, ObjectiveKind},
+    persisted::Persisted,
+};
+use dcso3::{coalition::Side, Vector2};
+use serde_json::{json, Map, Value};
+use std::{
+    fmt::Write as FmtWrite,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+/// The transverse mercator projection DCS uses to map a theater
+/// onto it's flat x (north), z (east) coordinate system
+struct Projection {
+    central_meridian: f64,
+    false_easting: f64,
+    false_northing: f64,
+    scale_factor: f64,
+}
+
+impl Theater {
+    fn projection(&self) -> Projection {
+        let (central_meridian, false_easting, false_northing) = match self {
+            Self::Caucasus => (33., -99516.9999999732, -4998114.999999984),
+            Self::Syria => (39., 282801.00000003993, -3879865.9999999935),
+            Self::PersianGulf => (57., 75755.99999999645, -2894933.0000000377),
+            Self::Nevada => (-117., -193996.80999964548, -4410028.063999966),
+            Self::Normandy => (-3., -195526.00000000204, -5484812.999999951),
+            Self::MarianaIslands => (147., 238417.99999989968, -1491840.000000048),
+            Self::TheChannel => (3., 99376.00000000288, -5636889.00000001),
+            Self::SinaiMap => (33., 169221.9999999585, -3325312.9999999693),
+            Self::Falklands => (-57., 147639.99999997593, 5815417.000000032),
+            Self::Kola => (21., -62702.00000000087, -7543624.999999979),
+            Self::Afghanistan => (63., -300149.9999999864, -3759657.000000049),
+        };
+        Projection {
+            central_meridian,
+            false_easting,
+            false_northing,
+            scale_factor: 0.9996,
+        }
+    }
+}
+
+impl Projection {
+    /// convert a DCS map position to WGS84 (lon, lat) in degrees
+    fn to_lon_lat(&self, pos: Vector2) -> (f64, f64) {
+        const A: f64 = 6378137.;
+        const F: f64 = 1. / 298.257223563;
+        let e2 = F * (2. - F);
+        let ep2 = e2 / (1. - e2);
+        let k0 = self.scale_factor;
+        let easting = pos.y - self.false_easting;
+        let northing = pos.x - self.false_northing;
+        let m = northing / k0;
+        let mu = m / (A * (1. - e2 / 4. - 3. * e2.powi(2) / 64. - 5. * e2.powi(3) / 256.));
+        let e1 = (1. - (1. - e2).sqrt()) / (1. + (1. - e2).sqrt());
+        let phi1 = mu
+            + (3. * e1 / 2. - 27. * e1.powi(3) / 32.) * (2. * mu).sin()
+            + (21. * e1.powi(2) / 16. - 55. * e1.powi(4) / 32.) * (4. * mu).sin()
+            + (151. * e1.powi(3) / 96.) * (6. * mu).sin()
+            + (1097. * e1.powi(4) / 512.) * (8. * mu).sin();
+        let (sin1, cos1, tan1) = (phi1.sin(), phi1.cos(), phi1.tan());
+        let c1 = ep2 * cos1.powi(2);
+        let t1 = tan1.powi(2);
+        let n1 = A / (1. - e2 * sin1.powi(2)).sqrt();
+        let r1 = A * (1. - e2) / (1. - e2 * sin1.powi(2)).powf(1.5);
+        let d = easting / (n1 * k0);
+        let lat = phi1
+            - (n1 * tan1 / r1)
+                * (d.powi(2) / 2.
+                    - (5. + 3. * t1 + 10. * c1 - 4. * c1.powi(2) - 9. * ep2) * d.powi(4) / 24.
+                    + (61. + 90. * t1 + 298. * c1 + 45. * t1.powi(2)
+                        - 252. * ep2
+                        - 3. * c1.powi(2))
+                        * d.powi(6)
+                        / 720.);
+        let lon = (d - (1. + 2. * t1 + c1) * d.powi(3) / 6.
+            + (5. - 2. * c1 + 28. * t1 - 3. * c1.powi(2) + 8. * ep2 + 24. * t1.powi(2))
+                * d.powi(5)
+                / 120.)
+            / cos1;
+        (self.central_meridian + lon.to_degrees(), lat.to_degrees())
+    }
+}
+
+fn kind_name(obj: &Objective) -> &'static str {
+    match obj.kind() {
+        ObjectiveKind::Airbase => "airbase",
+        ObjectiveKind::Fob => "fob",
+        ObjectiveKind::Logistics => "logistics",
+        ObjectiveKind::Farp { .. } => "farp",
+    }
+}
+
+fn side_color(side: Side) -> &'static str {
+    match side {
+        Side::Blue => "blue",
+        Side::Red => "red",
+        Side::Neutral => "gray",
+    }
+}
+
+/// every (supplier, destination) pair in the network
+fn supply_lines(persisted: &Persisted) -> Vec<(ObjectiveId, ObjectiveId)> {
+    persisted
+        .objectives
+        .into_iter()
+        .flat_map(|(hub, obj)| {
+            obj.warehouse()
+                .destination()
+                .into_iter()
+                .map(move |dst| (*hub, *dst))
+        })
+        .collect()
+}
+
+fn dot_escape(s: &str) -> std::string::String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn dot(persisted: &Persisted, out: &mut impl Write) -> Result<()> {
+    writeln!(out, "digraph supply_lines {{")?;
+    writeln!(out, "    node [fontname=\"monospace\"];")?;
+    for (oid, obj) in &persisted.objectives {
+        let shape = match obj.kind() {
+            ObjectiveKind::Logistics => "box",
+            ObjectiveKind::Airbase => "ellipse",
+            ObjectiveKind::Fob => "diamond",
+            ObjectiveKind::Farp { .. } => "triangle",
+        };
+        let mut label = format!(
+            "{}\\l{} {}\\lsupply {}% fuel {}%\\l",
+            dot_escape(obj.name()),
+            side_color(obj.owner()),
+            kind_name(obj),
+            obj.supply(),
+            obj.fuel()
+        );
+        let stock = |label: &mut std::string::String, name: &str, inv: &Inventory| {
+            write!(
+                label,
+                "{} {}/{}\\l",
+                dot_escape(name),
+                inv.stored,
+                inv.capacity
+            )
+        };
+        for (name, inv) in obj.warehouse().equipment() {
+            stock(&mut label, name, inv)?
+        }
+        for (name, inv) in obj.warehouse().liquids() {
+            stock(&mut label, &format!("{name:?}"), inv)?
+        }
+        writeln!(
+            out,
+            "    \"{oid}\" [shape={shape}, color={}, label=\"{label}\"];",
+            side_color(obj.owner())
+        )?;
+    }
+    for (hub, dst) in supply_lines(persisted) {
+        let color = persisted
+            .objectives
+            .get(&hub)
+            .map(|o| side_color(o.owner()))
+            .unwrap_or("black");
+        writeln!(out, "    \"{hub}\" -> \"{dst}\" [color={color}];")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn inventory_json<'a, K: ToString + 'a>(
+    items: impl IntoIterator<Item = (K, &'a Inventory)>,
+) -> Value {
+    let items = items
+        .into_iter()
+        .map(|(name, inv)| {
+            let inv = json!({ "stored": inv.stored, "capacity": inv.capacity });
+            (name.to_string(), inv)
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(items)
+}
+
+fn geojson(persisted: &Persisted, theater: Theater, out: &mut impl Write) -> Result<()> {
+    let proj = theater.projection();
+    let point = |oid: &ObjectiveId| -> Result<Value> {
+        let obj = persisted
+            .objectives
+            .get(oid)
+            .ok_or_else(|| anyhow!("missing objective {oid}"))?;
+        let (lon, lat) = proj.to_lon_lat(obj.pos());
+        Ok(json!([lon, lat]))
+    };
+    let mut features = vec![];
+    for (oid, obj) in &persisted.objectives {
+        let wh = obj.warehouse();
+        let liquids = wh
+            .liquids()
+            .into_iter()
+            .map(|(name, inv)| (format!("{name:?}"), inv));
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": point(oid)? },
+            "properties": {
+                "id": oid.to_string(),
+                "name": obj.name(),
+                "kind": kind_name(obj),
+                "owner": side_color(obj.owner()),
+                "logi": obj.logi(),
+                "supply": obj.supply(),
+                "fuel": obj.fuel(),
+                "supplier": wh.supplier().map(|id| id.to_string()),
+                "equipment": inventory_json(wh.equipment()),
+                "liquids": inventory_json(liquids),
+            }
+        }))
+    }
+    for (hub, dst) in supply_lines(persisted) {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": [point(&hub)?, point(&dst)?] },
+            "properties": { "supplier": hub.to_string(), "destination": dst.to_string() }
+        }))
+    }
+    let fc = json!({ "type": "FeatureCollection", "features": features });
+    serde_json::to_writer_pretty(&mut *out, &fc)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub fn run(cmd: &SupplyLinesCmd) -> Result<()> {
+    let persisted = bfdb::read_snapshot(&cmd.save)?;
+    let mut out: Box<dyn Write> = match &cmd.output {
+        None => Box::new(io::stdout().lock()),
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("creating {path:?}"))?;
+            Box::new(file)
+        }
+    };
+    let mut out = BufWriter::new(&mut out);
+    match cmd.format {
+        SupplyFormat::Dot => dot(&persisted, &mut out)?,
+        SupplyFormat::Geojson => {
+            let theater = cmd
+                .theater
+                .ok_or_else(|| anyhow!("--theater is required to export geojson"))?;
+            geojson(&persisted, theater, &mut out)?
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use crate::{mission_edit::LoadedMiz, ValidateCmd};
+use anyhow::{bail, Context, Result};
+use bflib::cfg::{ActionKind, Cfg, Deployable, Vehicle};
+use compact_str::format_compact;
+use dcso3::{coalition::Side, env::miz::Group, Sequence, String};
+use log::info;
+use mlua::Lua;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs::File,
+};
+
+/// The names and types in the mission that the config may refer to
+#[derive(Debug, Default)]
+struct MizNames {
+    groups: HashMap<Side, HashSet<String>>,
+    statics: HashSet<String>,
+    unit_types: HashSet<String>,
+    objectives: HashSet<String>,
+}
+
+impl MizNames {
+    fn add_groups(&mut self, side: Side, statics: bool, groups: Sequence<Group>) -> Result<()> {
+        for group in groups {
+            let group = group?;
+            self.groups.entry(side).or_default().insert(group.name()?);
+            for unit in group.units()? {
+                let unit = unit?;
+                self.unit_types.insert(unit.typ()?);
+                if statics {
+                    self.statics.insert(unit.name()?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn new(miz: &LoadedMiz) -> Result<Self> {
+        let mut t = Self::default();
+        for side in Side::ALL {
+            let coa = miz
+                .mission
+                .coalition(side)
+                .with_context(|| format_compact!("getting coalition {side}"))?;
+            for country in coa.countries()? {
+                let country = country?;
+                t.add_groups(side, false, country.planes()?)?;
+                t.add_groups(side, false, country.helicopters()?)?;
+                t.add_groups(side, false, country.ships()?)?;
+                t.add_groups(side, false, country.vehicles()?)?;
+                t.add_groups(side, true, country.statics()?)?;
+            }
+        }
+        for zone in miz.mission.triggers()? {
+            let name = zone?.name()?;
+            // objective zones are named O{AB,FO,LO}{B,R,N}name
+            if let Some(name) = name.strip_prefix('O') {
+                if let Some(name) = name.get(3..) {
+                    t.objectives.insert(String::from(name));
+                }
+            }
+        }
+        Ok(t)
+    }
+}
+
+struct Validator<'a> {
+    names: &'a MizNames,
+    problems: Vec<std::string::String>,
+}
+
+impl<'a> Validator<'a> {
+    fn template(&mut self, side: Side, what: &str, name: &str) {
+        let found = self
+            .names
+            .groups
+            .get(&side)
+            .map(|g| g.contains(name))
+            .unwrap_or(false);
+        if !found {
+            self.problems.push(format!(
+                "{side} {what} template {name} is not a group in the miz"
+            ))
+        }
+    }
+
+    fn vehicle(&mut self, what: &str, vehicle: &Vehicle) {
+        if !self.names.unit_types.contains(&vehicle.0) {
+            self.problems.push(format!(
+                "{what} vehicle {vehicle} is not used by any unit in the miz"
+            ))
+        }
+    }
+
+    /// The cargo menu is built by looking up each path element by name
+    /// alone, so a name must always appear under the same parent, and
+    /// the name of a deployable must not also be a submenu.
+    fn deployable_paths(&mut self, side: Side, deployables: &[Deployable]) {
+        let mut parents: HashMap<&String, &[String]> = HashMap::new();
+        let mut leaves: HashSet<&String> = HashSet::new();
+        for dep in deployables {
+            let name = match dep.path.last() {
+                Some(name) => name,
+                None => {
+                    self.problems.push(format!(
+                        "{side} deployable {} has an empty path",
+                        dep.template
+                    ));
+                    continue;
+                }
+            };
+            if !leaves.insert(name) {
+                self.problems.push(format!(
+                    "{side} deployable {:?} has a duplicate name",
+                    dep.path
+                ))
+            }
+            for (i, p) in dep.path.iter().enumerate() {
+                match parents.entry(p) {
+                    Entry::Vacant(e) => {
+                        e.insert(&dep.path[..i]);
+                    }
+                    Entry::Occupied(e) => {
+                        if *e.get() != &dep.path[..i] {
+                            self.problems.push(format!(
+                                "{side} deployable {:?} collides in the menu with {:?}",
+                                dep.path,
+                                e.get().iter().chain([*e.key()]).collect::<Vec<_>>()
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        for dep in deployables {
+            for p in dep.path.iter().rev().skip(1) {
+                if leaves.contains(p) {
+                    self.problems.push(format!(
+                        "{side} deployable {:?} uses the deployable {p} as a submenu",
+                        dep.path
+                    ))
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, cfg: &Cfg) {
+        for (side, deployables) in &cfg.deployables {
+            for dep in deployables {
+                self.template(*side, "deployable", &dep.template);
+                if let Some(logi) = &dep.logistics {
+                    for t in [
+                        &logi.ammo_template,
+                        &logi.fuel_template,
+                        &logi.barracks_template,
+                    ]
+                    .into_iter()
+                    .chain(logi.pad_templates.iter())
+                    {
+                        self.template(*side, "logistics", t)
+                    }
+                }
+            }
+            self.deployable_paths(*side, deployables)
+        }
+        for (side, troops) in &cfg.troops {
+            for troop in troops {
+                self.template(*side, "troop", &troop.template)
+            }
+        }
+        for (side, template) in &cfg.crate_template {
+            self.template(*side, "crate", template)
+        }
+        for (side, actions) in &cfg.actions {
+            for (name, action) in actions {
+                let plane = match &action.kind {
+                    ActionKind::Tanker(p)
+                    | ActionKind::Fighters(p)
+                    | ActionKind::Attackers(p)
+                    | ActionKind::LogisticsRepair(p)
+                    | ActionKind::LogisticsTransfer(p) => p,
+                    ActionKind::Awacs(a) => &a.plane,
+                    ActionKind::Bomber(b) => &b.plane,
+                    ActionKind::Drone(d) => &d.plane,
+                    ActionKind::Paratrooper(d) | ActionKind::Deployable(d) => &d.plane,
+                    ActionKind::Nuke(_)
+                    | ActionKind::FighersWaypoint
+                    | ActionKind::AttackersWaypoint
+                    | ActionKind::DroneWaypoint
+                    | ActionKind::TankerWaypoint
+                    | ActionKind::AwacsWaypoint
+                    | ActionKind::Move(_) => continue,
+                };
+                self.template(*side, &format!("action {name}"), &plane.template)
+            }
+        }
+        if let Some(wh) = &cfg.warehouse {
+            for (side, source) in &wh.supply_source {
+                if !self.names.statics.contains(source) {
+                    self.problems.push(format!(
+                        "{side} supply source {source} is not a static object in the miz"
+                    ))
+                }
+            }
+        }
+        for name in &cfg.extra_fixed_wing_objectives {
+            if !self.names.objectives.contains(name) {
+                self.problems.push(format!(
+                    "extra fixed wing objective {name} is not an objective in the miz"
+                ))
+            }
+        }
+        for vehicle in cfg.life_types.keys() {
+            self.vehicle("life type", vehicle)
+        }
+        for vehicle in cfg.cargo.keys() {
+            self.vehicle("cargo", vehicle)
+        }
+        for vehicle in cfg.unit_classification.keys() {
+            self.vehicle("unit classification", vehicle)
+        }
+    }
+}
+
+pub fn run(cmd: &ValidateCmd) -> Result<()> {
+    let cfg: Cfg = {
+        let file =
+            File::open(&cmd.cfg).with_context(|| format_compact!("opening {:?}", cmd.cfg))?;
+        serde_json::from_reader(file).with_context(|| format_compact!("decoding {:?}", cmd.cfg))?
+    };
+    let lua = Box::leak(Box::new(Lua::new()));
+    lua.gc_stop();
+    let miz = LoadedMiz::new(lua, &cmd.miz).context("loading mission")?;
+    let names = MizNames::new(&miz).context("indexing mission")?;
+    let mut validator = Validator {
+        names: &names,
+        problems: vec![],
+    };
+    validator.run(&cfg);
+    if validator.problems.is_empty() {
+        info!("{:?} agrees with {:?}", cmd.cfg, cmd.miz);
+        return Ok(());
+    }
+    for problem in &validator.problems {
+        println!("{problem}")
+    }
+    bail!("found {} problems", validator.problems.len())
+}
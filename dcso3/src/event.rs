@@ -213,7 +213,7 @@ pub enum Event<'lua> {
     Kill(WeaponUse<'lua>),
     Score(UnitEvent<'lua>),
     UnitLost(UnitEvent<'lua>),
-    LandingAfterEjection,
+    LandingAfterEjection(UnitEvent<'lua>),
     ParatrooperLanding,
     DiscardChairAfterEjection,
     WeaponAdd(WeaponAdd<'lua>),
@@ -275,7 +275,7 @@ fn translate<'a, 'lua: 'a>(lua: &'lua Lua, id: i64, value: Value<'lua>) -> Resul
         28 => Event::Kill(WeaponUse::from_lua(value, lua)?),
         29 => Event::Score(UnitEvent::from_lua(value, lua)?),
         30 => Event::UnitLost(UnitEvent::from_lua(value, lua)?),
-        31 => Event::LandingAfterEjection,
+        31 => Event::LandingAfterEjection(UnitEvent::from_lua(value, lua)?),
         32 => Event::ParatrooperLanding,
         33 => Event::DiscardChairAfterEjection,
         34 => Event::WeaponAdd(WeaponAdd::from_lua(value, lua)?),